@@ -1,5 +1,5 @@
 use sesh_cli::Cli;
-use std::path::PathBuf;
+use std::{path::PathBuf, process::Command};
 
 const RED: &str = "\x1b[31m";
 const GREEN: &str = "\x1b[32m";
@@ -37,6 +37,7 @@ On MacOS, you can install it with:
     brew install protobuf
     {RESET}
 "    ))?;
+    build_info();
     let markdown: String = clap_markdown::help_markdown::<Cli>();
     let workspace_dir = std::env::var("CARGO_MANIFEST_DIR")?;
     std::fs::write(
@@ -45,3 +46,33 @@ On MacOS, you can install it with:
     )?;
     Ok(())
 }
+
+/// Passes the commit, compiler and target sesh was built from to the client, for
+/// `sesh version --verbose`. $GIT_HASH is used when set, for builds outside of a git checkout.
+fn build_info() {
+    let git_hash = std::env::var("GIT_HASH")
+        .ok()
+        .filter(|hash| !hash.is_empty())
+        .or_else(|| output("git", &["rev-parse", "--short", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_owned());
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+    let rustc_version = output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_owned());
+    let target = std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_owned());
+    println!("cargo:rustc-env=GIT_HASH={git_hash}");
+    println!("cargo:rustc-env=RUSTC_VERSION={rustc_version}");
+    println!("cargo:rustc-env=BUILD_TARGET={target}");
+}
+
+/// Runs a program and returns the first line it printed, if it succeeded
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    stdout
+        .lines()
+        .next()
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty())
+}
@@ -1,21 +1,66 @@
-use std::{fmt::Display, str::FromStr};
+use std::{fmt::Display, path::PathBuf, str::FromStr, time::Duration};
 
 use clap::{Args, Subcommand};
 
+pub mod theme;
+
 #[derive(Debug, clap::Parser)]
 #[clap(
     name = "sesh",
-    version = "0.1.8",
+    version,
+    disable_version_flag = true,
     author = "Will Hopkins <willothyh@gmail.com>"
 )]
 #[group(required = false, multiple = true)]
 /// A terminal session manager for unix systems. Run persistent, named tasks that you can
 /// detach from and attach to at any time - both on your local machine, and over SSH.
+///
+/// When the client leaves a session, it exits with the program's exit status (or 128 plus the
+/// signal that killed it), 10 after detaching, or 11 if the session was killed, from another
+/// client or with the prefix key.
+/// Usage errors exit with 2, and 3 means the server could not be reached.
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Command>,
     #[command(flatten)]
     pub args: CliArgs,
+    /// Don't print status messages like [detached]. Errors are still printed to stderr
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+    /// Don't color output. Also turned off by $NO_COLOR, or when stdout isn't a terminal
+    #[arg(long, global = true)]
+    pub no_color: bool,
+    /// Draw lists and tables with plain ASCII instead of Unicode and Nerd Font icons. Also used
+    /// when the locale isn't UTF-8. $SESH_THEME can change the icons and colors further
+    #[arg(long, global = true)]
+    pub ascii: bool,
+    /// Seconds to wait for a server started by this command to come up. Also set by
+    /// $SESH_START_TIMEOUT, and 5 if neither is given
+    #[arg(long, global = true, value_name = "SECS", value_parser = parse_seconds)]
+    pub wait_timeout: Option<Duration>,
+    /// Seconds to wait for the server to answer a request before giving up on it. Also set by
+    /// $SESH_TIMEOUT, and 5 if neither is given. Commands that wait on purpose, like
+    /// `sesh wait-for`, aren't cut short
+    #[arg(long, global = true, value_name = "SECS", value_parser = parse_seconds)]
+    pub timeout: Option<Duration>,
+    /// Connect to the server on this socket, and start one there if it isn't running. A server
+    /// started this way keeps its sessions' sockets in <PATH>.d/. Also set by
+    /// $SESH_SERVER_SOCKET, which sessions started through it inherit, so sesh commands run in
+    /// them use the same server. Give it before the command: `sesh -S /tmp/s.sock list`
+    #[arg(short = 'S', long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+    /// Keep the server's and sessions' sockets in this directory, instead of sesh/ in
+    /// $XDG_RUNTIME_DIR, and start a server there if it isn't running. Such a server keeps its
+    /// list of dead sessions there too. Also set by $SESH_RUNTIME_DIR, which sessions started
+    /// through it inherit
+    #[arg(long, global = true, value_name = "PATH")]
+    pub runtime_dir: Option<PathBuf>,
+    /// Print version (see `sesh version`)
+    #[arg(short = 'V', long)]
+    pub version: bool,
+    /// With --version, also print what sesh was built from
+    #[arg(long, requires = "version")]
+    pub verbose: bool,
 }
 
 #[derive(Debug, Clone, Args)]
@@ -24,8 +69,105 @@ pub struct CliArgs {
     pub args: Vec<String>,
     #[arg(short, long)]
     pub name: Option<String>,
+    #[command(flatten)]
+    pub attach: AttachArgs,
+    #[arg(long)]
+    pub no_alt_screen: bool,
+    #[arg(long)]
+    pub status: bool,
     #[arg(short, long)]
-    pub detached: bool,
+    pub login: bool,
+    #[arg(long)]
+    pub term: Option<String>,
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    pub force_size: Option<(u16, u16)>,
+    #[arg(long, value_name = "FILE")]
+    pub replay_input: Option<PathBuf>,
+    #[arg(long, value_name = "SESSION")]
+    pub here: Option<SessionSelector>,
+    #[arg(long)]
+    pub note: Option<String>,
+    #[arg(long = "tag", value_name = "TAG", value_parser = parse_tag)]
+    pub tags: Vec<String>,
+    #[arg(long)]
+    pub title: Option<String>,
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["program", "args"])]
+    pub script: Option<PathBuf>,
+    #[arg(long)]
+    pub expand: bool,
+    #[command(flatten)]
+    pub notify: NotifyArgs,
+    #[arg(long)]
+    pub keep: bool,
+    #[command(flatten)]
+    pub windows: WindowArgs,
+    #[command(flatten)]
+    pub termios: TermiosArgs,
+}
+
+/// Whether a new session is attached to once it's started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachPolicy {
+    Attach,
+    Detach,
+}
+
+/// --attach and --no-attach, of which the last one given wins
+#[derive(Debug, Clone, Args)]
+pub struct AttachArgs {
+    /// Attach to the session once it's started, which is the default
+    #[arg(long, overrides_with = "no_attach")]
+    attach: bool,
+    /// Leave the session running in the background instead of attaching to it
+    #[arg(
+        short = 'd',
+        long,
+        visible_alias = "detached",
+        overrides_with = "attach"
+    )]
+    no_attach: bool,
+}
+
+impl AttachArgs {
+    pub fn policy(&self) -> AttachPolicy {
+        match self.no_attach {
+            true => AttachPolicy::Detach,
+            false => AttachPolicy::Attach,
+        }
+    }
+}
+
+/// How a session is told about clients attaching and detaching
+#[derive(Debug, Clone, Default, Args)]
+pub struct NotifyArgs {
+    /// Signal to send the session's process group when a client attaches
+    #[arg(long, value_name = "SIGNAL")]
+    pub notify_attach: Option<String>,
+    /// Signal to send the session's process group when its client detaches
+    #[arg(long, value_name = "SIGNAL")]
+    pub notify_detach: Option<String>,
+    /// Keep attached=1 or attached=0 in a file the session finds at $SESH_STATUS_FILE
+    #[arg(long)]
+    pub attach_status: bool,
+}
+
+/// Changes to a new session's terminal settings, made before its program starts
+#[derive(Debug, Clone, Default, Args)]
+pub struct TermiosArgs {
+    /// Turn off XON/XOFF flow control, so Ctrl-S doesn't freeze the session
+    #[arg(long)]
+    pub no_flow_control: bool,
+    /// Turn off echoing input back to the terminal
+    #[arg(long)]
+    pub no_echo: bool,
+}
+
+/// More programs to run in a new session, each in a window of its own
+#[derive(Debug, Clone, Default, Args)]
+pub struct WindowArgs {
+    /// Another window to run a command in, can be given several times
+    #[arg(long = "window", value_name = "NAME:CMD", value_parser = parse_window)]
+    pub windows: Vec<(String, String)>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -41,87 +183,599 @@ pub enum Command {
     },
     /// Start a new session, optionally specifying a name [alias: s]
     ///
-    /// If no program is specified, the default shell will be used.
+    /// If no program is specified, $SESH_DEFAULT_PROGRAM will be used, or else your shell
+    /// ($SHELL, or the one in your passwd entry).
     /// If no name is specified, the name will be [program name]-[n-1] where n is the number of sessions
     /// with that program name.
-    /// If --detached / -d is present, the session will not be attached to the client on creation
-    /// and will run in the background.
+    /// Names can only have ASCII letters, digits and -_.+@ in them, can't start with - and can be
+    /// at most 64 bytes long. A program name with other characters has them replaced by _.
+    /// If --no-attach / -d (or --detached) is present, the session will not be attached to the
+    /// client on creation and will run in the background. --attach attaches to it, which is the
+    /// default, and the last of them given wins, so `sesh start -d --attach` attaches.
+    /// This is the same for `sesh` with no command, which starts a session and attaches to it.
+    /// If --no-alt-screen is present, the session is drawn on the main screen (see `sesh attach`).
+    /// If --status is present, a status line is shown on the bottom row (see `sesh attach`).
+    /// No more than 100 sessions can run at once, or as many as $SESH_MAX_SESSIONS was set to
+    /// when the server started. If $SESH_MAX_PER_PROGRAM was set, no more than that many can run
+    /// the same program, going by its file name. Either is unlimited if set to 0.
+    /// The session gets the environment sesh was run with, except for $TMUX, $TMUX_PANE and $STY,
+    /// and the $SESH_SESSION, $SESH_NAME, $SESH_ID and $SESH_STATUS_FILE of the session it was
+    /// run from. If $SESH_ENV_ALLOW was set when the server started, only the variables it lists
+    /// are passed on, and those in $SESH_ENV_DENY never are. Both are lists of names separated
+    /// by commas, which can end in * to match a prefix, like LC_*. $TERM is always passed on.
+    /// If --login / -l is present, the program is started as a login shell, so it reads
+    /// /etc/profile and your profile files. Use it when the server was started from a minimal
+    /// environment.
+    /// --term sets $TERM in the session, instead of passing on this terminal's.
+    /// --force-size gives the session a fixed size instead of this terminal's (see `sesh attach`).
+    /// --replay-input types the input recorded in an asciicast v2 file (as written by
+    /// `asciinema rec --stdin`) into the session, with the recorded timing. The recording's
+    /// output is ignored. This is useful for reproducing bugs in full-screen programs.
+    /// --here starts the program in the directory another session is working in, which is
+    /// that of the program in its foreground (like an editor started from its shell). This is
+    /// only supported on Linux.
+    /// --note sets a note on the session (see `sesh note`).
+    /// --tag adds the session to a group, and can be given several times. Sessions can be
+    /// listed or killed by tag with `sesh list --tag` and `sesh kill --tag`.
+    /// --script runs a script file with your shell. With --script -, the script is read from
+    /// stdin (like a heredoc) into a temporary file, which is removed when the session ends.
+    /// The session then starts detached unless stdin is a terminal.
+    /// --expand replaces ${VAR} in --name, the program and its args with the value of $VAR in
+    /// the environment sesh was run with, which is also the session's, for when the shell
+    /// calling sesh didn't expand it. Only the ${VAR} form is replaced, $$ stands for a literal
+    /// $, and an unset variable is an error. This happens after your shell's own expansion, and
+    /// before the name is derived from the program, so a session started with no name is named
+    /// after the expanded program. The program from $SESH_DEFAULT_PROGRAM isn't expanded.
+    /// --notify-attach and --notify-detach send a signal (by name or number, as with
+    /// `sesh send-signal`) to the session's process group whenever a client attaches or
+    /// detaches, so a program can slow down while nobody is looking. --attach-status keeps
+    /// attached=1 or attached=0 in <name>.status next to the session's socket, for scripts to
+    /// poll. The session finds it at $SESH_STATUS_FILE, but like the socket, it's moved when the
+    /// session is renamed. Sessions started without these flags aren't told anything.
+    /// --keep leaves the session in the list after its program exits, marked with how it
+    /// exited, until `sesh kill` removes it (like tmux's remain-on-exit). A client attached at
+    /// the time stays, so the output can still be read. Attaching later shows what the program
+    /// printed while nobody was attached, and how it exited.
+    /// --window runs another command in the session, in a window of its own, and can be given
+    /// several times, as in `sesh start --window logs:"tail -f log" vim`. The command is run with
+    /// your shell. Attaching shows the program's window, and with $SESH_PREFIX set, the prefix
+    /// followed by n or p shows the next or previous window (see `sesh attach`). A window whose
+    /// command exits is closed, and the session ends along with its program, closing the rest.
+    /// A window's output isn't read while it's not shown, so as in a detached session, a program
+    /// that prints a lot waits until it is. Switching clears the screen and asks the window's
+    /// program to redraw. Sessions with windows can't be handed over by `sesh upgrade`.
+    /// --no-flow-control turns off XON/XOFF flow control on the session's terminal, so Ctrl-S
+    /// reaches the program instead of freezing the session until Ctrl-Q. --no-echo turns off
+    /// echoing input back, for programs that expect it off from the start. Both apply to the
+    /// session's windows too, and are kept when it's revived.
     #[command(alias = "s", verbatim_doc_comment)]
     Start {
         #[arg(short, long)]
         name: Option<String>,
         program: Option<String>,
         args: Vec<String>,
+        #[command(flatten)]
+        attach: AttachArgs,
+        /// Stay on the main screen instead of switching to the alternate screen
+        #[arg(long)]
+        no_alt_screen: bool,
+        /// Show a status line on the bottom row
+        #[arg(long)]
+        status: bool,
+        /// Start the program as a login shell
         #[arg(short, long)]
-        detached: bool,
+        login: bool,
+        /// Value for $TERM in the session
+        #[arg(long)]
+        term: Option<String>,
+        /// Size the session as if the terminal was this many columns and rows, like 120x40
+        #[arg(long, value_name = "WxH", value_parser = parse_size)]
+        force_size: Option<(u16, u16)>,
+        /// Asciicast recording whose input is typed into the session
+        #[arg(long, value_name = "FILE")]
+        replay_input: Option<PathBuf>,
+        /// Start in the directory of this session's foreground program
+        #[arg(long, value_name = "SESSION")]
+        here: Option<SessionSelector>,
+        /// Note to show alongside the session
+        #[arg(long)]
+        note: Option<String>,
+        /// Tag to group the session under, can be given several times
+        #[arg(long = "tag", value_name = "TAG", value_parser = parse_tag)]
+        tags: Vec<String>,
+        /// Title for the terminal while attached, instead of the program's name
+        #[arg(long)]
+        title: Option<String>,
+        /// Script to run with your shell, or - to read it from stdin
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["program", "args"])]
+        script: Option<PathBuf>,
+        /// Replace ${VAR} in the name, program and args with the variable's value
+        #[arg(long)]
+        expand: bool,
+        #[command(flatten)]
+        notify: Box<NotifyArgs>,
+        /// Keep the session after its program exits, until it's killed
+        #[arg(long)]
+        keep: bool,
+        #[command(flatten)]
+        windows: Box<WindowArgs>,
+        #[command(flatten)]
+        termios: Box<TermiosArgs>,
     },
     #[command(alias = "a", verbatim_doc_comment)]
     /// Attach to a session [alias: a]
     ///
-    /// Select a session by index or name.
-    /// If --create / -c is present, a new session will be created if one does not exist.
-    /// If the session was selected by name and the session was not present, the new session
-    /// created by --create will have the specified name.
+    /// Select a session by index or name, or by its process's pid with pid:<pid>.
+    /// If several sessions are given, the first one that can be attached to is used.
+    /// If --create / -c is present, a new session will be created if none of them exist.
+    /// If the first session was selected by name, the new session created by --create will have
+    /// that name.
+    /// The program to run in the new session and its args can be given after --, as in
+    /// `sesh attach logs -c -- journalctl -f`. Otherwise it runs the default program, as with
+    /// `sesh start`.
+    /// If --no-alt-screen is present, the session is drawn on the main screen, so its output
+    /// stays in your scrollback after detaching. This suits line-oriented programs, but
+    /// full-screen programs will leave a mess behind.
+    /// If --status is present, the bottom row shows a status line with the session's id and
+    /// name, the other sessions (marked with * if attached elsewhere) and the time. The session
+    /// is given the rest of the screen.
+    /// The session is resized to fit this terminal. If --keep-size is present, it is left at the
+    /// size the last client gave it, so its program isn't disturbed. It is drawn from the top
+    /// left corner of the terminal, and the status line shows its size.
+    /// If --force-size is given as <columns>x<rows>, the session is sized as if the terminal was
+    /// that big, and isn't resized along with the terminal. This suits recording a session at a
+    /// fixed size, or terminals that report the wrong size.
+    /// If the terminal goes away while attached, as when its window is closed or an ssh
+    /// connection drops, the client detaches and the session keeps running. If
+    /// $SESH_ON_DISCONNECT is set to kill, the session is killed instead.
+    /// If $SESH_LOCK_AFTER is set to a number of seconds, the screen is blanked and locked once
+    /// nothing has been typed for that long, so the session isn't left on display. Press Enter to
+    /// unlock it. The session keeps running and follows the terminal's size while locked, but
+    /// nothing typed at the lock screen is sent to it, and it's reset as with `sesh reset` when
+    /// it's unlocked.
+    ///
+    /// Press Alt-\ to detach from the session.
+    /// If $SESH_PREFIX is set to a key such as C-b, pressing it followed by another key runs a
+    /// command: d detaches, s switches to another session, , or $ renames the session, K kills
+    /// it once you confirm with y, S shows or hides the status line, and n and p show the next
+    /// and previous window of a session started with --window.
+    /// Switching keeps the terminal as it is and attaches in place. Cancelling the picker goes
+    /// back to the session that was left.
+    /// Press the prefix key twice to send it to the session.
     Attach {
-        /// Id or name of session
-        session: SessionSelector,
+        /// Ids, names or pid:<pid>s of sessions to try in order
+        #[arg(required = true)]
+        sessions: Vec<SessionSelector>,
         /// Create a new session if one does not exist
         #[arg(short, long)]
         create: bool,
+        /// Stay on the main screen instead of switching to the alternate screen
+        #[arg(long)]
+        no_alt_screen: bool,
+        /// Show a status line on the bottom row
+        #[arg(long)]
+        status: bool,
+        /// Leave the session at its current size instead of resizing it to this terminal
+        #[arg(long)]
+        keep_size: bool,
+        /// Size the session as if the terminal was this many columns and rows, like 120x40
+        #[arg(long, value_name = "WxH", value_parser = parse_size, conflicts_with = "keep_size")]
+        force_size: Option<(u16, u16)>,
+        /// Program and args to run if --create starts a new session
+        #[arg(last = true, requires = "create", value_name = "PROGRAM")]
+        command: Vec<String>,
+    },
+    /// Stream a session's output to this terminal
+    ///
+    /// The output is written as it comes, without switching to raw mode or the alternate screen,
+    /// so it can be piped or scrolled back through. Nothing you type is sent to the session, and
+    /// it is left at its size.
+    /// Following doesn't attach to the session, so it can be followed while a client is
+    /// attached, and attached to while it's followed.
+    /// Press Ctrl-C to stop following. The session keeps running.
+    #[command(verbatim_doc_comment)]
+    Follow {
+        /// Id, name or pid:<pid> of the session
+        session: SessionSelector,
     },
     /// Fuzzy select a session to attach to [alias: f]
     ///
     /// Opens a fuzzy selection window provided by the dialoguer crate.
     /// Type to fuzzy find files, or use the Up/Down arrows to navigate.
     /// Press Enter to confirm your selection, or Escape to cancel.
+    /// If --json is present, or stdin or stdout isn't a terminal, the sessions are printed as
+    /// JSON instead (as with `sesh list --json`), so another tool like fzf can do the selecting
+    /// and then run `sesh attach`.
     #[command(alias = "f", verbatim_doc_comment)]
-    Select,
+    Select {
+        /// Print the sessions as JSON instead of selecting one
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show a live table of sessions [alias: top]
+    ///
+    /// Takes over the terminal to show the sessions, kept up to date as they change.
+    /// Use the Up/Down arrows or j/k to highlight a session, then press Enter to attach to it,
+    /// x to kill it, or r to rename it. Press q or Escape to quit.
+    #[command(alias = "top", verbatim_doc_comment)]
+    Monitor,
     /// Detach from a session [alias: d]
     ///
-    /// If no session is specified, detaches from the current session (if it exists).
-    /// Otherwise, detaches the specified session from its owning client.
+    /// If no session is specified, detaches from the current session (if it exists). It's found
+    /// from the process, so this works in shells that don't pass $SESH_NAME on.
+    /// Otherwise, detaches each of the specified sessions from its owning client.
+    ///
+    /// If $SESH_ON_DETACH is set, it is run with sh in the background after each detach,
+    /// with $SESH_NAME set to the name of the detached session.
     #[command(alias = "d", verbatim_doc_comment)]
     Detach {
-        /// Id or name of session
-        session: Option<SessionSelector>,
+        /// Ids, names or pid:<pid>s of sessions
+        sessions: Vec<SessionSelector>,
     },
     #[command(alias = "k", verbatim_doc_comment)]
-    /// Kill a session [alias: k]
+    /// Kill sessions [alias: k]
     ///
-    /// Kills a session and the process it owns.
-    /// Select a session by name or index.
+    /// Kills each of the given sessions and the processes they own, reporting how each went.
+    /// Select a session by name or index, or by its process's pid with pid:<pid>.
+    /// The process is sent SIGTERM, and then SIGKILL if it has not exited after the grace period.
+    /// If --signal / -s is present, the signal is sent to the session's process group instead,
+    /// as with `sesh send-signal`, and the session is not removed.
+    /// When run from a terminal, sessions with a note (see `sesh note`) show it, and are only
+    /// killed once you confirm.
+    /// If --tag is present, every session with that tag is killed too.
     Kill {
-        /// Id or name of session
+        /// Ids, names or pid:<pid>s of sessions
+        #[arg(required_unless_present = "tag")]
+        sessions: Vec<SessionSelector>,
+        /// Kill the sessions with this tag
+        #[arg(long, value_parser = parse_tag)]
+        tag: Option<String>,
+        /// Seconds to wait for the process to exit before sending SIGKILL
+        #[arg(short, long, default_value_t = 5)]
+        grace: u64,
+        /// Send a signal instead of killing the session, by name (HUP, SIGUSR1) or number
+        #[arg(short, long)]
+        signal: Option<String>,
+    },
+    /// Send a signal to a session's process group
+    ///
+    /// Useful for asking a program to reload (SIGHUP) or trigger a custom action (SIGUSR1)
+    /// without killing the session.
+    /// Signals can be given by name (HUP, SIGHUP) or number (1).
+    #[command(name = "send-signal", verbatim_doc_comment)]
+    Signal {
+        /// Id, name or pid:<pid> of session
+        session: SessionSelector,
+        /// Signal to send
+        signal: String,
+    },
+    /// Write stdin into a session, as if it was typed
+    ///
+    /// Reads until the end of the input, so a file or the output of a program can be piped in:
+    /// `cat snippet.sql | sesh paste db`.
+    /// If --bracketed / -b is present, the input is wrapped in bracketed paste markers, so
+    /// programs that support them, like editors and shells, treat it as a paste rather than
+    /// typed keys.
+    #[command(verbatim_doc_comment)]
+    Paste {
+        /// Id, name or pid:<pid> of session
+        session: SessionSelector,
+        /// Wrap the input in bracketed paste markers
+        #[arg(short, long)]
+        bracketed: bool,
+    },
+    /// Reset the terminal of a session's client [alias: clear]
+    ///
+    /// For when a session's screen is garbled, like after a binary file was printed in it.
+    /// The attached client's terminal gets its modes, colors and character set reset and is
+    /// cleared, and the program in the session is asked to draw itself again.
+    /// If no session is specified, resets the current session.
+    #[command(alias = "clear", verbatim_doc_comment)]
+    Reset {
+        /// Id, name or pid:<pid> of session
+        session: Option<SessionSelector>,
+    },
+    /// Rename a session
+    ///
+    /// Select a session by name or index, or by its process's pid with pid:<pid>.
+    /// Programs running in the session still see the old name in $SESH_NAME.
+    /// The new name follows the same rules as one given to `sesh start --name`.
+    #[command(verbatim_doc_comment)]
+    Rename {
+        /// Id, name or pid:<pid> of session
         session: SessionSelector,
+        /// New name for the session
+        name: String,
+    },
+    /// Give the sessions ids counting up from 0
+    ///
+    /// Ids are left as they are when sessions end, so they can end up far apart, like 0, 2
+    /// and 5. This gives the sessions ids from 0 again, in the order they were started, so they
+    /// are quicker to type. Names and sockets stay the same.
+    /// Prints each session whose id changed, with its old and new id.
+    #[command(verbatim_doc_comment)]
+    Renumber,
+    /// Remove sessions whose process is gone, and sockets left behind
+    ///
+    /// The server checks that each session's process is still running, in case it missed
+    /// one exiting, and removes the sessions whose process is gone. Sessions started with
+    /// --keep stay until they're killed. Sockets in the runtime directory that no session
+    /// uses and nothing listens on, like those of clients that were killed, are deleted.
+    /// Live sessions and attached clients are left alone.
+    /// Prints each session and file removed.
+    /// If --dry-run is present, prints what would be removed without removing it.
+    #[command(verbatim_doc_comment)]
+    Prune {
+        /// Only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Set a note on a session
+    ///
+    /// Select a session by name or index, or by its process's pid with pid:<pid>.
+    /// The note is shown by `sesh list --info`, and `sesh kill` shows it and asks before
+    /// killing the session, so it's a good place for warnings.
+    /// Give an empty note to remove it.
+    #[command(verbatim_doc_comment)]
+    Note {
+        /// Id, name or pid:<pid> of session
+        session: SessionSelector,
+        /// Text of the note
+        note: String,
     },
     /// List sessions [alias: ls]
     ///
     /// Prints a compact list of session names and indexes.
     /// With the --info / -i option, prints a nicely formatted table with info about each session.
+    /// The table is fit to the terminal: long notes, names and programs are cut short, and the
+    /// Started and Attached columns are left out if there still isn't room. The Tags and Note
+    /// columns are only shown if a session has tags or a note.
+    /// The Directory column (cwd) is where the program in the session's foreground is working,
+    /// like the directory its shell has cd'd to. On platforms other than Linux, and for dead
+    /// sessions, it's the directory the session was started in.
+    /// A session whose pty or client connection stopped being relayed because of an error is
+    /// shown in red, with the error after its program, like (io-error: relay: ...). It's also
+    /// the health field of --format json, csv and tsv, which is ok for sessions without errors.
+    /// --columns picks exactly which columns to show, and implies --info.
+    /// --tag only lists the sessions with that tag, --name those whose name matches a glob
+    /// (where * matches anything and ? any one character, as in --name 'build-*'), --program
+    /// those whose program's path contains the given text, and --attached or --detached those
+    /// that a client is or isn't attached to. Sessions have to match all of them to be listed.
+    /// The table shows how long ago sessions were started and attached to, like 2h ago.
+    /// --absolute shows the full date and time instead, and implies --info.
+    /// --format / -f csv or tsv prints a header row and a row per session, for spreadsheets and
+    /// tools like `column`. Fields containing the separator, quotes or line breaks are quoted.
     #[command(alias = "ls", verbatim_doc_comment)]
     #[group(required = false, multiple = true)]
     List {
-        /// Print detailed info about sessions
+        /// How to print the sessions
+        #[arg(short, long, value_enum, conflicts_with_all = ["info", "json"])]
+        format: Option<Format>,
+        /// Print detailed info about sessions, same as --format table
         #[arg(short, long)]
         info: bool,
-        /// Print session info as JSON, to be processed by another tool
+        /// Columns to show in the table, separated by commas
+        #[arg(long, value_enum, value_delimiter = ',')]
+        columns: Vec<Column>,
+        /// Print session info as JSON, to be processed by another tool, same as --format json
+        #[arg(short, long)]
+        json: bool,
+        /// List sessions that were lost when the server last exited
+        #[arg(long)]
+        dead: bool,
+        /// Only list sessions with this tag
+        #[arg(long, value_parser = parse_tag)]
+        tag: Option<String>,
+        /// Only list sessions whose name matches this glob
+        #[arg(long, value_name = "GLOB")]
+        name: Option<String>,
+        /// Only list sessions whose program's path contains this
+        #[arg(long, value_name = "TEXT")]
+        program: Option<String>,
+        /// Only list sessions a client is attached to
+        #[arg(long, conflicts_with = "detached")]
+        attached: bool,
+        /// Only list sessions no client is attached to
+        #[arg(long)]
+        detached: bool,
+        /// Show full dates and times in the table, instead of how long ago they were
+        #[arg(long)]
+        absolute: bool,
+    },
+    /// Restart a session that was lost when the server exited
+    ///
+    /// Sessions that were running when the server crashed or the machine rebooted can be
+    /// listed with `sesh list --dead`. Reviving one starts its program again as a new session,
+    /// with the same name, arguments, working directory and environment.
+    /// If --detached / -d is present, the revived session will run in the background.
+    #[command(verbatim_doc_comment)]
+    Revive {
+        /// Name of the dead session
+        session: String,
+        #[arg(short, long)]
+        detached: bool,
+    },
+    /// Print the session this shell is running in
+    ///
+    /// The session is read from the environment without contacting the server, so this is
+    /// cheap enough to run from a shell prompt or status bar.
+    /// The --format / -f template can use {name}, {id}, {socket} and {attached}.
+    /// With --verify, the server is asked whether the session still exists and whether a client
+    /// is attached to it. {attached} is empty unless --verify is present.
+    /// Prints nothing and exits with status 2 when not inside a session, or 3 when --verify
+    /// finds that the session no longer exists.
+    #[command(verbatim_doc_comment)]
+    Current {
+        /// Template to print
+        #[arg(short, long, default_value = "{name}")]
+        format: String,
+        /// Print the session as JSON instead
+        #[arg(short, long)]
+        json: bool,
+        /// Check with the server that the session still exists
+        #[arg(long)]
+        verify: bool,
+    },
+    /// Print server events as they happen
+    ///
+    /// Prints a line for each session that is started, attached, detached, renamed, killed
+    /// or exits, until the server shuts down or the command is interrupted.
+    /// With the --json / -j option, each event is printed as a JSON object.
+    #[command(verbatim_doc_comment)]
+    Events {
+        /// Print events as JSON, to be processed by another tool
         #[arg(short, long)]
         json: bool,
     },
+    /// Wait for a channel to be signaled, or signal it
+    ///
+    /// Blocks until another client runs `sesh wait-for --signal` on the same channel.
+    /// With --signal / -S, wakes every client waiting on the channel. If nothing is waiting,
+    /// the next client to wait on the channel returns immediately instead.
+    /// Channels are created on first use, so any name can be used.
+    #[command(name = "wait-for", verbatim_doc_comment)]
+    WaitFor {
+        /// Name of the channel
+        channel: String,
+        /// Wake everything waiting on the channel
+        #[arg(short = 'S', long)]
+        signal: bool,
+    },
+    /// Run several commands in one go
+    ///
+    /// Reads commands from a file, or from stdin with -, and sends them to the server in one
+    /// request, which saves starting sesh for each of them in scripts.
+    /// Each line is a command with its arguments, as given to sesh and quoted as in the shell,
+    /// like `start -n logs -- tail -f log`. Empty lines and lines starting with # are skipped.
+    /// The input can also be a JSON array, of command lines or of arrays of arguments.
+    /// start, kill, send-signal, rename, note, detach and reset can be batched. Sessions are
+    /// always started detached, and --script - can't be used.
+    /// The commands run in order, and the rest still run when one fails, unless --fail-fast is
+    /// present. Exits with 1 if any command failed or was skipped.
+    #[command(verbatim_doc_comment)]
+    Batch {
+        /// File of commands, or - to read them from stdin
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+        /// Stop at the first command that fails
+        #[arg(long)]
+        fail_fast: bool,
+    },
+    /// Measure how fast output is relayed through the server
+    ///
+    /// Starts a session running cat, attaches to it without taking over the terminal, sends it
+    /// --bytes of input and reads them back. Prints the throughput and the CPU time seshd used.
+    /// The session is killed afterwards.
+    #[command(hide = true, verbatim_doc_comment)]
+    Bench {
+        /// How much to send through the session, like 100M. K, M and G count in 1024s
+        #[arg(long, default_value = "100M", value_parser = parse_bytes)]
+        bytes: u64,
+    },
     /// Shutdown the server (kill all sessions)
     Shutdown,
+    /// Replace the running server with a new seshd binary, keeping all sessions
+    Upgrade,
+    /// Attach to a session on another machine over SSH
+    ///
+    /// Runs `sesh attach <SESSION>` on the host through `ssh -t`, or `sesh select` to pick a
+    /// session there if none is given. The host is passed to ssh as is, so it can be user@host
+    /// or a name from ~/.ssh/config.
+    /// The host needs sesh and seshd installed. The server there is started on first use and
+    /// keeps running after you disconnect. Non-interactive ssh commands often don't read your
+    /// shell's profile, so if sesh isn't found, give its full path with --remote-sesh, and
+    /// set $SESHD_PATH on the host if seshd isn't in the default PATH either.
+    /// ssh keeps the session at the size of this terminal. Detaching leaves the session running
+    /// on the host, and exits with status 10 like `sesh attach`.
+    #[command(verbatim_doc_comment)]
+    Ssh {
+        /// Host to connect to
+        host: String,
+        /// Id or name of the session on the host
+        session: Option<SessionSelector>,
+        /// Create the session on the host if it does not exist
+        #[arg(short, long, requires = "session")]
+        create: bool,
+        /// Path to sesh on the host, expanded by the shell there (quote ~ to keep it for the host)
+        #[arg(long, default_value = "sesh")]
+        remote_sesh: String,
+    },
+    /// Check the environment for common problems
+    ///
+    /// Checks that the runtime directory is usable, whether the server socket is live or left
+    /// over from a crash, that the server answers and runs the same version as this client,
+    /// that no other seshd is running for the same socket, that no sockets are left over from
+    /// sessions that are gone, that seshd can be found to start a server, and that $SHELL and
+    /// $TERM look sane.
+    /// Never starts a server. Exits with status 1 if any check fails.
+    /// If --fix is present, files left over are removed, and stray or stuck seshd processes are
+    /// stopped once you confirm, which ends their sessions.
+    /// If --reset-terminal is present, the terminal is first put back in its normal mode, for
+    /// when a client that was killed left it in raw mode or on the alternate screen.
+    #[command(verbatim_doc_comment)]
+    Doctor {
+        /// Fix what can be fixed
+        #[arg(long)]
+        fix: bool,
+        /// Reset the terminal to its normal mode first
+        #[arg(long)]
+        reset_terminal: bool,
+        /// Output the checks as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the version of sesh
+    ///
+    /// If --verbose / -v is present, prints the version, git commit, rustc version and target
+    /// sesh was built for as `key: value` lines, for including in bug reports.
+    #[command(verbatim_doc_comment)]
+    Version {
+        /// Also print what sesh was built from
+        #[arg(short, long)]
+        verbose: bool,
+    },
+}
+
+/// How `sesh list` prints sessions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Compact list of names and ids
+    List,
+    /// Table with info about each session
+    Table,
+    Json,
+    /// Comma-separated values
+    Csv,
+    /// Tab-separated values
+    Tsv,
+}
+
+/// A column of the `sesh list --info` table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Column {
+    Id,
+    Name,
+    Started,
+    Attached,
+    Program,
+    Cwd,
+    Pid,
+    Tags,
+    Note,
 }
 
 #[derive(Debug, Clone)]
 pub enum SessionSelector {
     Id(usize),
     Name(String),
+    /// The session running the process with this pid, written as pid:<pid>
+    Pid(i32),
 }
 
 impl SessionSelector {
     pub fn name(self) -> Option<String> {
         match self {
-            SessionSelector::Id(_) => None,
+            SessionSelector::Id(_) | SessionSelector::Pid(_) => None,
             SessionSelector::Name(name) => Some(name),
         }
     }
@@ -132,6 +786,7 @@ impl Display for SessionSelector {
         match self {
             SessionSelector::Id(id) => write!(f, "{}", id),
             SessionSelector::Name(name) => write!(f, "{}", name),
+            SessionSelector::Pid(pid) => write!(f, "pid:{}", pid),
         }
     }
 }
@@ -140,10 +795,142 @@ impl FromStr for SessionSelector {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Ok(id) = s.parse::<usize>() {
+        if let Some(pid) = s.strip_prefix("pid:") {
+            pid.parse()
+                .map(SessionSelector::Pid)
+                .map_err(|_| anyhow::anyhow!("Invalid pid: {}", pid))
+        } else if let Ok(id) = s.parse::<usize>() {
             Ok(SessionSelector::Id(id))
         } else {
             Ok(SessionSelector::Name(s.to_owned()))
         }
     }
 }
+
+/// Parses a number of seconds, which can be fractional
+pub fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("{} is not a number of seconds", s))
+}
+
+/// Parses a session tag, which is listed along with others so can't contain commas or spaces
+pub fn parse_tag(s: &str) -> Result<String, String> {
+    if s.is_empty() || s.contains(|c: char| c == ',' || c.is_whitespace()) {
+        return Err(format!(
+            "{:?} is not a tag, tags can't be empty or contain commas or spaces",
+            s
+        ));
+    }
+    Ok(s.to_owned())
+}
+
+/// Parses a window given as <name>:<command>, like logs:"tail -f log"
+pub fn parse_window(s: &str) -> Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((name, command)) if !name.is_empty() && !command.trim().is_empty() => {
+            Ok((name.to_owned(), command.to_owned()))
+        }
+        _ => Err(format!("{:?} is not a window like name:command", s)),
+    }
+}
+
+/// Parses a terminal size given as <columns>x<rows>, like 120x40
+pub fn parse_size(s: &str) -> Result<(u16, u16), String> {
+    let invalid = || format!("{} is not a size like 120x40", s);
+    let (cols, rows) = s.split_once(['x', 'X']).ok_or_else(invalid)?;
+    let cols = cols.parse::<u16>().map_err(|_| invalid())?;
+    let rows = rows.parse::<u16>().map_err(|_| invalid())?;
+    if cols == 0 || rows == 0 {
+        return Err(format!("{} has no room for the session", s));
+    }
+    Ok((cols, rows))
+}
+
+/// Parses a number of bytes, with an optional K, M or G suffix counting in 1024s, like 100M
+pub fn parse_bytes(s: &str) -> Result<u64, String> {
+    let invalid = || format!("{} is not a number of bytes like 100M", s);
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => s.split_at(at),
+        None => (s, ""),
+    };
+    let scale: u64 = match unit {
+        "" | "B" => 1,
+        "K" | "k" => 1 << 10,
+        "M" | "m" => 1 << 20,
+        "G" | "g" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(scale))
+        .filter(|&bytes| bytes > 0)
+        .ok_or_else(invalid)
+}
+
+/// A line of `sesh batch`, which is one of the commands without `sesh` in front of it
+#[derive(Debug, clap::Parser)]
+#[command(no_binary_name = true)]
+pub struct BatchLine {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    /// The attach policy `sesh <args>` starts its session with
+    fn policy(args: &[&str]) -> AttachPolicy {
+        let cli = Cli::try_parse_from(std::iter::once("sesh").chain(args.iter().copied()))
+            .unwrap_or_else(|e| panic!("{:?} didn't parse: {}", args, e));
+        match cli.command {
+            Some(Command::Start { attach, .. }) => attach.policy(),
+            Some(command) => panic!("{:?} parsed as {:?}", args, command),
+            None => cli.args.attach.policy(),
+        }
+    }
+
+    #[test]
+    fn attach_flags() {
+        use AttachPolicy::*;
+        for (args, expected) in [
+            (&[][..], Attach),
+            (&["--attach"], Attach),
+            (&["-d"], Detach),
+            (&["--detached"], Detach),
+            (&["--no-attach"], Detach),
+            (&["-d", "--attach"], Attach),
+            (&["--attach", "--no-attach"], Detach),
+        ] {
+            // The same with no command as with `sesh start`
+            assert_eq!(policy(args), expected, "sesh {:?}", args);
+            let start = [&["start"], args].concat();
+            assert_eq!(policy(&start), expected, "sesh {:?}", start);
+        }
+    }
+
+    #[test]
+    fn attach_flags_before_program() {
+        assert_eq!(policy(&["-d", "vim"]), AttachPolicy::Detach);
+        assert_eq!(policy(&["start", "-d", "vim"]), AttachPolicy::Detach);
+        // Flags after -- are the program's own
+        assert_eq!(policy(&["start", "--", "vim", "-d"]), AttachPolicy::Attach);
+    }
+
+    #[test]
+    fn byte_counts() {
+        assert_eq!(parse_bytes("512"), Ok(512));
+        assert_eq!(parse_bytes("4K"), Ok(4096));
+        assert_eq!(parse_bytes("100M"), Ok(100 << 20));
+        assert_eq!(parse_bytes("1g"), Ok(1 << 30));
+        assert!(parse_bytes("0").is_err());
+        assert!(parse_bytes("M").is_err());
+        assert!(parse_bytes("10MB").is_err());
+        assert!(parse_bytes("99999999999G").is_err());
+    }
+}
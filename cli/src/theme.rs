@@ -0,0 +1,203 @@
+//! Icons, colors and line characters that `sesh list` draws with.
+//!
+//! The default theme uses Unicode and Nerd Font glyphs, which show up as boxes in fonts that
+//! don't have them. The ASCII theme only uses plain characters, and is used when the locale
+//! isn't UTF-8 or `--ascii` is given. Either can be adjusted through $SESH_THEME.
+
+use clap::ValueEnum;
+
+use crate::Column;
+
+/// One of the 16 standard terminal colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    LightBlack,
+    LightRed,
+    LightGreen,
+    LightYellow,
+    LightBlue,
+    LightMagenta,
+    LightCyan,
+    LightWhite,
+}
+
+impl Color {
+    /// Index of the color in the terminal's palette
+    pub fn ansi(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Characters the table's borders are drawn with
+#[derive(Debug, Clone)]
+pub struct TableLines {
+    pub vertical: char,
+    /// Line, joint, and left and right ends of the line above the titles
+    pub top: [char; 4],
+    /// Same as `top`, for the line under the titles
+    pub middle: [char; 4],
+    /// Same as `top`, for the line under the last row
+    pub bottom: [char; 4],
+}
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// Marks sessions that have a client attached
+    pub active: char,
+    /// Starts each session in the compact list
+    pub bullet: char,
+    /// Goes between the fields of the compact list, and the date and time of absolute times
+    pub separator: char,
+    /// Whether the table's column titles start with an icon
+    pub icons: bool,
+    pub lines: TableLines,
+    /// Color of each column's title, in the order of `Column`'s variants
+    colors: [Color; 9],
+}
+
+impl Theme {
+    pub fn unicode() -> Self {
+        Self {
+            active: '⯌',
+            bullet: '❒',
+            separator: '∘',
+            icons: true,
+            lines: TableLines {
+                vertical: '│',
+                top: ['─', '┬', '╭', '╮'],
+                middle: ['─', '┼', '├', '┤'],
+                bottom: ['─', '┴', '╰', '╯'],
+            },
+            colors: [
+                Color::LightRed,
+                Color::LightBlue,
+                Color::LightYellow,
+                Color::LightGreen,
+                Color::LightCyan,
+                Color::Blue,
+                Color::LightMagenta,
+                Color::Yellow,
+                Color::LightWhite,
+            ],
+        }
+    }
+
+    pub fn ascii() -> Self {
+        Self {
+            active: '*',
+            bullet: '-',
+            separator: '|',
+            icons: false,
+            lines: TableLines {
+                vertical: '|',
+                top: ['-', '+', '+', '+'],
+                middle: ['-', '+', '+', '+'],
+                bottom: ['-', '+', '+', '+'],
+            },
+            ..Self::unicode()
+        }
+    }
+
+    /// The ASCII theme if `ascii` is set or the locale isn't UTF-8, or else the Unicode one
+    pub fn for_locale(ascii: bool) -> Self {
+        if ascii || !utf8_locale() {
+            Self::ascii()
+        } else {
+            Self::unicode()
+        }
+    }
+
+    /// Reads the theme from $SESH_THEME, a comma-separated list of settings:
+    /// `ascii` or `unicode` to start from that theme instead of the one for the locale,
+    /// `active=C`, `bullet=C` or `separator=C` to draw those with the character C, and
+    /// `COLUMN=COLOR` to color a column's title, like `name=light-green`.
+    /// If `ascii` is set, the ASCII theme is used whatever $SESH_THEME says.
+    pub fn from_env(ascii: bool) -> Result<Self, String> {
+        let spec = std::env::var("SESH_THEME").unwrap_or_default();
+        let settings = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>();
+
+        let mut theme = Self::for_locale(ascii);
+        for setting in &settings {
+            match *setting {
+                "ascii" => theme = Self::ascii(),
+                "unicode" if !ascii => theme = Self::unicode(),
+                "unicode" => {}
+                _ => {
+                    let (key, value) = setting
+                        .split_once('=')
+                        .ok_or_else(|| format!("Invalid setting: {}", setting))?;
+                    theme.set(key.trim(), value.trim())?;
+                }
+            }
+        }
+        Ok(theme)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let char_value = || {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(format!("{} must be one character", key)),
+            }
+        };
+        match key {
+            "active" => self.active = char_value()?,
+            "bullet" => self.bullet = char_value()?,
+            "separator" => self.separator = char_value()?,
+            _ => {
+                let column =
+                    Column::from_str(key, true).map_err(|_| format!("Unknown setting: {}", key))?;
+                self.colors[column as usize] = Color::from_str(value, true)
+                    .map_err(|_| format!("Unknown color: {}", value))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Icon before a column's title in the table, if the theme has icons
+    pub fn icon(&self, column: Column) -> Option<char> {
+        if !self.icons {
+            return None;
+        }
+        Some(match column {
+            Column::Id => '',
+            Column::Name => '',
+            Column::Started => '',
+            Column::Attached => '',
+            Column::Program => '',
+            Column::Cwd => '',
+            Column::Pid => '',
+            Column::Tags => '',
+            Column::Note => '',
+        })
+    }
+
+    pub fn color(&self, column: Column) -> Color {
+        self.colors[column as usize]
+    }
+}
+
+/// Whether the locale's character set is UTF-8, going by the first of $LC_ALL, $LC_CTYPE and
+/// $LANG that is set
+pub fn utf8_locale() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|locale| !locale.is_empty())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
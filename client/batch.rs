@@ -0,0 +1,373 @@
+//! `sesh batch`, which runs the commands read from a file in one request to the server.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde::Deserialize;
+use sesh_cli::{BatchLine, Command, SessionSelector};
+use sesh_proto::{
+    sesh_batch_command::Command as BatchCommand, sesh_batch_result::Result as BatchResult,
+    sesh_kill_request::Session, sesh_signal_request,
+};
+use sesh_shared::signal;
+
+use crate::session::{
+    describe, detached, killed, noted, start_request, was_reset, with_tagged, Ctx, Report,
+    StartOptions,
+};
+use crate::{error, success, Outcome};
+
+/// A command of a batch, split into its arguments
+#[derive(Debug, PartialEq, Eq)]
+pub struct Line {
+    /// The command as it was given, to name it in messages
+    pub text: String,
+    pub args: Vec<String>,
+}
+
+/// An entry of a batch given as JSON
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Text(String),
+    Args(Vec<String>),
+}
+
+/// Reads a batch: a JSON array if it starts with `[`, or else a command on each line, quoted as
+/// in the shell. Empty lines and comments are skipped.
+pub fn parse(input: &str) -> Result<Vec<Line>> {
+    if input.trim_start().starts_with('[') {
+        let entries = serde_json::from_str::<Vec<Entry>>(input).map_err(|e| {
+            anyhow::anyhow!(
+                "Commands given as JSON must be an array of strings or of string arrays: {}",
+                e
+            )
+        })?;
+        return entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| match entry {
+                Entry::Text(text) => {
+                    split(&text).map_err(|e| anyhow::anyhow!("Command {}: {}", i + 1, e))
+                }
+                Entry::Args(args) => Ok(Line {
+                    text: shell_words::join(&args),
+                    args,
+                }),
+            })
+            .collect();
+    }
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| split(line).map_err(|e| anyhow::anyhow!("Line {}: {}", i + 1, e)))
+        .collect()
+}
+
+fn split(text: &str) -> Result<Line> {
+    let args = shell_words::split(text)?;
+    if args.is_empty() {
+        anyhow::bail!("No command given");
+    }
+    Ok(Line {
+        text: text.trim().to_owned(),
+        args,
+    })
+}
+
+/// How a command of a batch is reported on, once the server has run it
+enum Batched {
+    Start,
+    Kill(Option<i32>),
+    Signal(SessionSelector, i32),
+    Rename(SessionSelector),
+    Note(SessionSelector, bool),
+    Detach,
+    Reset,
+}
+
+/// Runs the commands read from a file, or from stdin with -, in one request to the server
+pub async fn run(mut ctx: Ctx, input: PathBuf, fail_fast: bool) -> Result<Outcome> {
+    let text = if input.as_os_str() == "-" {
+        std::io::read_to_string(std::io::stdin()).context("Could not read stdin")?
+    } else {
+        std::fs::read_to_string(&input)
+            .map_err(|e| anyhow::anyhow!("Could not read {}: {}", input.display(), e))?
+    };
+    // Every command is checked before any of them is run
+    let mut sent = Vec::new();
+    let mut commands = Vec::new();
+    let mut grace_ms = 0;
+    for line in parse(&text)? {
+        let command = BatchLine::try_parse_from(&line.args)
+            .map_err(|e| {
+                // Only the first line, without the usage that follows
+                let e = e.to_string();
+                let e = e.lines().next().unwrap_or_default();
+                anyhow::anyhow!("`{}`: {}", line.text, e.trim_start_matches("error: "))
+            })?
+            .command;
+        let batched = batch_command(&mut ctx, command)
+            .await
+            .map_err(|e| anyhow::anyhow!("`{}`: {}", line.text, e))?;
+        for (batched, command) in batched {
+            if let BatchCommand::Kill(kill) = &command {
+                grace_ms += kill.grace_ms;
+            }
+            sent.push((line.text.clone(), batched));
+            commands.push(sesh_proto::SeshBatchCommand {
+                command: Some(command),
+            });
+        }
+    }
+    if commands.is_empty() {
+        return Ok(Outcome::Done);
+    }
+
+    let mut request = tonic::Request::new(sesh_proto::SeshBatchRequest {
+        commands,
+        fail_fast,
+    });
+    // The server waits out the grace period of each kill in turn
+    request.set_timeout(ctx.timeout + Duration::from_millis(grace_ms));
+    let results = ctx
+        .client
+        .batch(request)
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e.message()))?
+        .into_inner()
+        .results;
+    let mut report = Report::default();
+    let skipped = sent.len().saturating_sub(results.len());
+    for ((text, batched), result) in sent.iter().zip(results) {
+        match batch_result(batched, result.result) {
+            Ok(message) => report.done.push(message),
+            Err(e) => report
+                .failed
+                .push(error!("Could not run `{}`: {}", text, e)),
+        }
+    }
+    if skipped > 0 {
+        report.failed.push(error!(
+            "[skipped {} command{} after the failure]",
+            skipped,
+            if skipped == 1 { "" } else { "s" }
+        ));
+    }
+    Ok(report.outcome())
+}
+
+/// Turns a command of a batch into the requests to send for it. Sessions given by pid or tag
+/// are looked up now, before anything in the batch has run.
+async fn batch_command(ctx: &mut Ctx, command: Command) -> Result<Vec<(Batched, BatchCommand)>> {
+    let batched = match command {
+        Command::Start {
+            name,
+            program,
+            args,
+            login,
+            term,
+            force_size,
+            replay_input,
+            here,
+            note,
+            tags,
+            title,
+            script,
+            expand,
+            notify,
+            keep,
+            windows,
+            termios,
+            ..
+        } => {
+            if force_size.is_some() {
+                anyhow::bail!("--force-size can't be used in a batch");
+            }
+            if script
+                .as_ref()
+                .is_some_and(|script| script.as_os_str() == "-")
+            {
+                anyhow::bail!("--script - can't be used in a batch");
+            }
+            let options = StartOptions {
+                name,
+                program,
+                args,
+                login,
+                term,
+                replay_input,
+                here,
+                note,
+                tags,
+                title,
+                script,
+                expand,
+                notify: *notify,
+                keep,
+                windows: windows.windows,
+                termios: *termios,
+                ..Default::default()
+            };
+            let (req, _) = start_request(ctx, options).await?;
+            vec![(Batched::Start, BatchCommand::Start(req))]
+        }
+        Command::Kill {
+            sessions,
+            tag,
+            grace,
+            signal,
+        } => {
+            let signal = signal.as_deref().map(signal::parse).transpose()?;
+            let sessions = match tag {
+                Some(tag) => with_tagged(ctx, sessions, &tag).await?,
+                None => sessions,
+            };
+            let mut batched = Vec::with_capacity(sessions.len());
+            for session in sessions {
+                let selected = ctx.select(&session, Session::Id, Session::Name).await?;
+                let kill = sesh_proto::SeshKillRequest {
+                    session: Some(selected),
+                    grace_ms: grace.saturating_mul(1000),
+                    signal: signal.unwrap_or(0),
+                };
+                batched.push((Batched::Kill(signal), BatchCommand::Kill(kill)));
+            }
+            batched
+        }
+        Command::Signal { session, signal } => {
+            use sesh_signal_request::Session::*;
+            let signal = signal::parse(&signal)?;
+            let selected = ctx.select(&session, Id, Name).await?;
+            let request = sesh_proto::SeshSignalRequest {
+                session: Some(selected),
+                signal,
+            };
+            vec![(
+                Batched::Signal(session, signal),
+                BatchCommand::Signal(request),
+            )]
+        }
+        Command::Rename { session, name } => {
+            use sesh_proto::sesh_rename_request::Session::*;
+            let selected = ctx.select(&session, Id, Name).await?;
+            let request = sesh_proto::SeshRenameRequest {
+                session: Some(selected),
+                new_name: name,
+            };
+            vec![(Batched::Rename(session), BatchCommand::Rename(request))]
+        }
+        Command::Note { session, note } => {
+            use sesh_proto::sesh_note_request::Session::*;
+            let selected = ctx.select(&session, Id, Name).await?;
+            let removed = note.is_empty();
+            let request = sesh_proto::SeshNoteRequest {
+                session: Some(selected),
+                note,
+            };
+            vec![(Batched::Note(session, removed), BatchCommand::Note(request))]
+        }
+        Command::Detach { sessions } => {
+            use sesh_proto::sesh_detach_request::Session::*;
+            let selected = if sessions.is_empty() {
+                vec![ctx.select_current(Id, Name).await?]
+            } else {
+                let mut selected = Vec::with_capacity(sessions.len());
+                for session in &sessions {
+                    selected.push(ctx.select(session, Id, Name).await?);
+                }
+                selected
+            };
+            selected
+                .into_iter()
+                .map(|session| {
+                    let request = sesh_proto::SeshDetachRequest {
+                        session: Some(session),
+                    };
+                    (Batched::Detach, BatchCommand::Detach(request))
+                })
+                .collect()
+        }
+        Command::Reset { session } => {
+            use sesh_proto::sesh_reset_request::Session::*;
+            let selected = match session {
+                Some(session) => ctx.select(&session, Id, Name).await?,
+                None => ctx.select_current(Id, Name).await?,
+            };
+            let request = sesh_proto::SeshResetRequest {
+                session: Some(selected),
+            };
+            vec![(Batched::Reset, BatchCommand::Reset(request))]
+        }
+        _ => anyhow::bail!(
+            "Only start, kill, send-signal, rename, note, detach and reset can be batched"
+        ),
+    };
+    Ok(batched)
+}
+
+/// Handles the server's answer to a command of a batch, and returns the status message for it
+fn batch_result(batched: &Batched, result: Option<BatchResult>) -> Result<String> {
+    match (batched, result) {
+        (_, Some(BatchResult::Error(e))) => Err(anyhow::anyhow!(e)),
+        (Batched::Start, Some(BatchResult::Start(res))) => {
+            Ok(success!("[started {}]", describe(None, &res.name, res.pid)))
+        }
+        (Batched::Kill(signal), Some(BatchResult::Kill(res))) => killed(res, *signal),
+        (Batched::Signal(session, signal), Some(BatchResult::Signal(_))) => {
+            Ok(success!("[sent {} to {}]", signal::name(*signal), session))
+        }
+        (Batched::Rename(session), Some(BatchResult::Rename(res))) => {
+            Ok(success!("[renamed {} to {}]", session, res.name))
+        }
+        (Batched::Note(session, removed), Some(BatchResult::Note(_))) => {
+            Ok(noted(session, *removed))
+        }
+        (Batched::Detach, Some(BatchResult::Detach(res))) => detached(res),
+        (Batched::Reset, Some(BatchResult::Reset(res))) => was_reset(res),
+        _ => Err(anyhow::anyhow!("Unexpected response")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(lines: &[Line]) -> Vec<Vec<&str>> {
+        lines
+            .iter()
+            .map(|line| line.args.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn lines_are_split_like_the_shell() {
+        let input = "# bootstrap\nstart -n logs -- tail -f 'my log'\n\n  kill \"old one\"\n";
+        let lines = parse(input).unwrap();
+        assert_eq!(
+            args(&lines),
+            [
+                vec!["start", "-n", "logs", "--", "tail", "-f", "my log"],
+                vec!["kill", "old one"],
+            ]
+        );
+        assert_eq!(lines[1].text, "kill \"old one\"");
+        let error = parse("note a ok\nnote b 'unclosed").unwrap_err();
+        assert!(error.to_string().starts_with("Line 2: "), "{}", error);
+    }
+
+    #[test]
+    fn json_arrays() {
+        let lines = parse(r#"["start -n a", ["note", "a", "two words"]]"#).unwrap();
+        assert_eq!(
+            args(&lines),
+            [vec!["start", "-n", "a"], vec!["note", "a", "two words"]]
+        );
+        assert_eq!(lines[1].text, "note a 'two words'");
+        assert!(parse(r#"[1, 2]"#).is_err());
+        assert!(parse(r#"[""]"#).is_err());
+    }
+}
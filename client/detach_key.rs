@@ -0,0 +1,241 @@
+//! Recognizing the detach key (`Alt-\`) in terminal input.
+
+use std::time::Duration;
+
+/// How long an ESC is held back waiting for the `\` of the detach key. The two usually arrive
+/// together, so this only matters when they're split up on the way, like over a slow ssh
+/// connection. An ESC on its own, like the Escape key, is sent on after this.
+pub const TIMEOUT: Duration = Duration::from_millis(50);
+
+const PASTE_START: &[u8; 6] = b"\x1b[200~";
+const PASTE_END: &[u8; 6] = b"\x1b[201~";
+
+/// Finds the detach key anywhere in terminal input, including split across reads.
+///
+/// `Alt-\` arrives as `ESC \`, which is also the terminator of the OSC, DCS and APC strings
+/// the terminal replies to queries with, so it doesn't count at the end of one. Only a string
+/// that starts the way those replies do counts as one, since `Alt-]`, `Alt-P` and `Alt-_` start
+/// with the same bytes. Nor does the detach key count inside a bracketed paste, where it's
+/// pasted text.
+/// An ESC that may start the detach key is held back from the session until the next byte shows
+/// whether it does, or [`TIMEOUT`] passes and it's taken back with [`DetachKey::flush`].
+#[derive(Default)]
+pub struct DetachKey {
+    /// Whether the input so far ended inside a reply from the terminal
+    reply: Reply,
+    /// Whether the input so far ended inside a bracketed paste
+    in_paste: bool,
+    /// Whether the last byte read was ESC
+    escape: bool,
+    /// The last bytes read, to find the bracketed paste markers in
+    tail: [u8; 6],
+}
+
+/// Where the input so far is in a string the terminal may have replied with
+#[derive(Default)]
+enum Reply {
+    #[default]
+    Outside,
+    /// A string was started with `introducer`, but whether it's a reply isn't known yet from
+    /// how it `start`s
+    Starting {
+        introducer: u8,
+        start: Vec<u8>,
+    },
+    Inside,
+}
+
+/// Whether a string is one the terminal replies to queries with, judging by how it starts:
+/// `Some(true)` if it is, `Some(false)` if it isn't and `None` if more of it is needed to tell
+fn is_reply(introducer: u8, start: &[u8]) -> Option<bool> {
+    match introducer {
+        // OSC replies, like the colors, start with a number and `;`
+        b']' => {
+            let digits = start.iter().take_while(|b| b.is_ascii_digit()).count();
+            match start.get(digits) {
+                None => None,
+                Some(b';') => Some(digits > 0),
+                Some(_) => Some(false),
+            }
+        }
+        // DCS replies: DECRQSS (`1$r`), XTGETTCAP (`1+r`), XTVERSION (`>|`) and DA3 (`!|`)
+        b'P' => match start {
+            [] | [b'0' | b'1'] | [b'0' | b'1', b'$' | b'+'] | [b'>' | b'!'] => None,
+            [b'0' | b'1', b'$' | b'+', b'r', ..] | [b'>' | b'!', b'|', ..] => Some(true),
+            _ => Some(false),
+        },
+        // APC replies are kitty's graphics protocol
+        b'_' => match start {
+            [] => None,
+            [b'G', ..] => Some(true),
+            _ => Some(false),
+        },
+        _ => Some(false),
+    }
+}
+
+impl DetachKey {
+    /// Scans a read, and returns the bytes to send to the session and whether the detach key
+    /// was in it. The detach key itself isn't sent, and neither is anything read after it. The
+    /// client leaves right away, and bytes it already read can't be put back in the terminal's
+    /// input for the shell it leaves to, short of TIOCSTI, which newer kernels don't allow.
+    /// Sending them to the session instead would type into a session that was just left.
+    pub fn feed(&mut self, read: &[u8]) -> (Vec<u8>, bool) {
+        let mut forward = Vec::with_capacity(read.len() + 1);
+        for &byte in read {
+            if self.held() {
+                if byte == b'\\' {
+                    self.escape = false;
+                    return (forward, true);
+                }
+                forward.push(0x1b);
+            }
+            self.reply = match std::mem::take(&mut self.reply) {
+                Reply::Starting {
+                    introducer,
+                    mut start,
+                } => {
+                    start.push(byte);
+                    match is_reply(introducer, &start) {
+                        Some(true) => Reply::Inside,
+                        Some(false) => Reply::Outside,
+                        None => Reply::Starting { introducer, start },
+                    }
+                }
+                Reply::Inside if byte == 0x07 || (self.escape && byte == b'\\') => Reply::Outside,
+                Reply::Outside if self.escape && !self.in_paste && b"]P_".contains(&byte) => {
+                    Reply::Starting {
+                        introducer: byte,
+                        start: Vec::new(),
+                    }
+                }
+                reply => reply,
+            };
+            self.escape = byte == 0x1b;
+            self.tail.copy_within(1.., 0);
+            self.tail[5] = byte;
+            if &self.tail == PASTE_START {
+                self.in_paste = true;
+            } else if &self.tail == PASTE_END {
+                self.in_paste = false;
+            }
+            if !self.held() {
+                forward.push(byte);
+            }
+        }
+        (forward, false)
+    }
+
+    /// Whether an ESC is being held back, waiting to see if it starts the detach key
+    pub fn pending(&self) -> bool {
+        self.held()
+    }
+
+    /// Gives back the ESC being held back, once no `\` came in time
+    pub fn flush(&mut self) -> Vec<u8> {
+        if !self.held() {
+            return Vec::new();
+        }
+        self.escape = false;
+        vec![0x1b]
+    }
+
+    fn held(&self) -> bool {
+        self.escape && matches!(self.reply, Reply::Outside) && !self.in_paste
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds input in the given pieces, and returns what was sent on and whether it detached
+    fn feed(pieces: &[&[u8]]) -> (Vec<u8>, bool) {
+        let mut key = DetachKey::default();
+        let mut sent = Vec::new();
+        for piece in pieces {
+            let (forward, detach) = key.feed(piece);
+            sent.extend(forward);
+            if detach {
+                return (sent, true);
+            }
+        }
+        sent.extend(key.flush());
+        (sent, false)
+    }
+
+    /// Checks that `input` gives the same result however it's split into two or three reads
+    fn check_splits(input: &[u8], sent: &[u8], detach: bool) {
+        for i in 0..=input.len() {
+            for j in i..=input.len() {
+                let pieces = [&input[..i], &input[i..j], &input[j..]];
+                assert_eq!(
+                    feed(&pieces),
+                    (sent.to_vec(), detach),
+                    "{:?} split at {} and {}",
+                    String::from_utf8_lossy(input),
+                    i,
+                    j
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn detach_key_alone() {
+        check_splits(b"\x1b\\", b"", true);
+    }
+
+    #[test]
+    fn detach_key_after_other_input() {
+        check_splits(b"ls\r\x1b\\rest", b"ls\r", true);
+        check_splits(b"\x1b\x1b\\", b"\x1b", true);
+    }
+
+    #[test]
+    fn escape_alone_is_sent() {
+        check_splits(b"\x1b", b"\x1b", false);
+        check_splits(b"\x1b[A\x1bOP:q\r\x1b", b"\x1b[A\x1bOP:q\r\x1b", false);
+    }
+
+    #[test]
+    fn string_terminator_isnt_detach() {
+        let reply = b"\x1b]11;rgb:0000/0000/0000\x1b\\";
+        check_splits(reply, reply, false);
+        let dcs = b"\x1bP>|xterm\x1b\\";
+        check_splits(dcs, dcs, false);
+        // A string ended with BEL is over, so what follows counts
+        check_splits(b"\x1b]0;title\x07\x1b\\", b"\x1b]0;title\x07", true);
+        let graphics = b"\x1b_Gi=1;OK\x1b\\";
+        check_splits(graphics, graphics, false);
+    }
+
+    #[test]
+    fn alt_keys_arent_strings() {
+        // Alt-_, Alt-] and Alt-P send what a string starts with, but aren't followed by a reply
+        check_splits(b"\x1b_\x1b\\", b"\x1b_", true);
+        check_splits(b"\x1b]x\x1bP\x1b\\", b"\x1b]x\x1bP", true);
+        check_splits(b"\x1bX\x1b^\x1b\\", b"\x1bX\x1b^", true);
+    }
+
+    #[test]
+    fn paste_isnt_detach() {
+        let paste = b"\x1b[200~echo \x1b\\ \x1b]x\x1b[201~";
+        check_splits(paste, paste, false);
+        // Once the paste is over, the detach key works again
+        let mut input = paste.to_vec();
+        input.extend(b"\x1b\\");
+        check_splits(&input, paste, true);
+    }
+
+    #[test]
+    fn escape_held_until_flushed() {
+        let mut key = DetachKey::default();
+        assert_eq!(key.feed(b"a\x1b"), (b"a".to_vec(), false));
+        assert!(key.pending());
+        assert_eq!(key.flush(), b"\x1b");
+        assert!(!key.pending());
+        // A \ after the ESC was let go is just a \
+        assert_eq!(key.feed(b"\\"), (b"\\".to_vec(), false));
+    }
+}
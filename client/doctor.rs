@@ -0,0 +1,644 @@
+//! `sesh doctor`, and what it looks at outside the server: files in the runtime directory,
+//! seshd processes and the terminal.
+
+use std::io::IsTerminal;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use dialoguer::theme;
+use sesh_proto::SeshInfo;
+use sesh_shared::{runtime_dir, sys};
+use termion::color::{self, Fg};
+
+use crate::session::Ctx;
+use crate::{error, paint};
+
+/// How long a seshd that was sent SIGTERM gets to exit before it's sent SIGKILL
+const STOP_GRACE: Duration = Duration::from_secs(1);
+
+/// Leaves the alternate screen, shows the cursor, and turns off the scrolling region, mouse
+/// reporting, bracketed paste and text attributes, any of which a client that was killed may
+/// have left on
+const TERMINAL_RESET: &str =
+    "\x1b[?1049l\x1b[?25h\x1b[r\x1b[?1000l\x1b[?1002l\x1b[?1003l\x1b[?1006l\x1b[?2004l\x1b[0m";
+
+/// A seshd process of this user
+pub struct Seshd {
+    pub pid: i32,
+    /// The server socket it listens on, as given on its command line
+    pub socket: PathBuf,
+}
+
+/// The seshd processes this user is running, or None where there's no /proc to find them in.
+/// A seshd started without a socket is taken to listen on `default_sock`.
+pub fn seshd_processes(default_sock: &Path) -> Option<Vec<Seshd>> {
+    let uid = unsafe { libc::getuid() };
+    let processes = std::fs::read_dir("/proc").ok()?;
+    Some(
+        processes
+            .flatten()
+            .filter_map(|entry| {
+                let pid = entry.file_name().to_str()?.parse().ok()?;
+                if entry.metadata().ok()?.uid() != uid {
+                    return None;
+                }
+                let comm = std::fs::read_to_string(entry.path().join("comm")).ok()?;
+                // A zombie has no command line to go by, and is already gone as far as its
+                // socket is concerned
+                if comm.trim_end() != "seshd" || sys::zombie(pid) {
+                    return None;
+                }
+                let cmdline = std::fs::read(entry.path().join("cmdline")).ok()?;
+                let args = cmdline
+                    .split(|b| *b == 0)
+                    .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                    .collect::<Vec<_>>();
+                Some(Seshd {
+                    pid,
+                    socket: seshd_socket(&args, default_sock),
+                })
+            })
+            .collect(),
+    )
+}
+
+/// The socket a seshd started with `args` listens on
+fn seshd_socket(args: &[String], default_sock: &Path) -> PathBuf {
+    let flag = |name: &str| {
+        args.iter().enumerate().find_map(|(i, arg)| {
+            if arg == name {
+                args.get(i + 1).cloned()
+            } else {
+                arg.strip_prefix(name)?.strip_prefix('=').map(str::to_owned)
+            }
+        })
+    };
+    match (flag("--socket"), flag("--runtime-dir")) {
+        (Some(socket), _) => PathBuf::from(socket),
+        (None, Some(dir)) => Path::new(&dir).join("server.sock"),
+        (None, None) => default_sock.to_owned(),
+    }
+}
+
+/// The sockets in the runtime directory other than the server's: those of sessions, and
+/// `client-<pid>.sock`s of the clients attached to them
+pub fn session_sockets(dir: &Path, server_sock: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut sockets = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_socket()))
+        .map(|entry| entry.path())
+        .filter(|path| path != server_sock)
+        .collect::<Vec<_>>();
+    sockets.sort();
+    sockets
+}
+
+/// The sockets no session uses. A client's socket is in use while the session it's named after
+/// has a client attached.
+pub fn orphaned(sockets: Vec<PathBuf>, sessions: &[SeshInfo]) -> Vec<PathBuf> {
+    sockets
+        .into_iter()
+        .filter(|socket| {
+            !sessions.iter().any(|s| {
+                Path::new(&s.socket) == socket
+                    || (s.clients > 0
+                        && socket.file_name() == Some(format!("client-{}.sock", s.pid).as_ref()))
+            })
+        })
+        .collect()
+}
+
+/// Sends a process SIGTERM, and SIGKILL if it's still running after a moment.
+/// Returns whether it's gone.
+pub async fn stop(pid: i32) -> bool {
+    let alive = || unsafe { libc::kill(pid, 0) } == 0 && !sys::zombie(pid);
+    let exited = |grace| async move {
+        let start = std::time::Instant::now();
+        while alive() && start.elapsed() < grace {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        !alive()
+    };
+    unsafe {
+        libc::kill(pid, libc::SIGTERM);
+        // A stopped process only gets the signal once it's continued
+        libc::kill(pid, libc::SIGCONT);
+    }
+    if exited(STOP_GRACE).await {
+        return true;
+    }
+    unsafe { libc::kill(pid, libc::SIGKILL) };
+    exited(STOP_GRACE).await
+}
+
+/// Puts the controlling terminal back in its normal mode, as after `stty sane`, and resets what
+/// a client may have changed on it. It's written to /dev/tty, so output can still be piped.
+pub fn reset_terminal() -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::fd::AsRawFd;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    let fd = tty.as_raw_fd();
+    let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    termios.c_iflag |= libc::BRKINT | libc::ICRNL | libc::IXON;
+    termios.c_oflag |= libc::OPOST | libc::ONLCR;
+    termios.c_lflag |=
+        libc::ISIG | libc::ICANON | libc::IEXTEN | libc::ECHO | libc::ECHOE | libc::ECHOK;
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    tty.write_all(TERMINAL_RESET.as_bytes())?;
+    tty.flush()
+}
+
+/// Outcome of one of `sesh doctor`'s checks
+enum Check {
+    Pass(String),
+    Warn(String),
+    Fail(String),
+}
+
+impl std::fmt::Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Check::Pass(msg) => write!(
+                f,
+                "{}✓{} {}",
+                paint(Fg(color::Green)),
+                paint(Fg(color::Reset)),
+                msg
+            ),
+            Check::Warn(msg) => write!(
+                f,
+                "{}!{} {}",
+                paint(Fg(color::Yellow)),
+                paint(Fg(color::Reset)),
+                msg
+            ),
+            Check::Fail(msg) => write!(
+                f,
+                "{}✗{} {}",
+                paint(Fg(color::Red)),
+                paint(Fg(color::Reset)),
+                msg
+            ),
+        }
+    }
+}
+
+impl Check {
+    fn status(&self) -> &'static str {
+        match self {
+            Check::Pass(_) => "pass",
+            Check::Warn(_) => "warn",
+            Check::Fail(_) => "fail",
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            Check::Pass(msg) | Check::Warn(msg) | Check::Fail(msg) => msg,
+        }
+    }
+}
+
+/// What `sesh doctor --fix` can do about a failed check
+enum Fix {
+    /// Remove files nothing uses anymore
+    Remove(Vec<PathBuf>),
+    /// Stop seshd processes. Their sessions end with them, so it's only done once confirmed.
+    Stop(Vec<i32>),
+}
+
+/// One of `sesh doctor`'s checks, named for --json
+struct Diagnosis {
+    name: &'static str,
+    check: Check,
+    fix: Option<Fix>,
+    /// Whether --fix fixed what the check found
+    fixed: bool,
+}
+
+impl Diagnosis {
+    fn new(name: &'static str, check: Check) -> Self {
+        Self {
+            name,
+            check,
+            fix: None,
+            fixed: false,
+        }
+    }
+
+    fn fix(self, fix: Fix) -> Self {
+        Self {
+            fix: Some(fix),
+            ..self
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct DiagnosisSer<'a> {
+    name: &'a str,
+    status: &'a str,
+    message: &'a str,
+    fixed: bool,
+}
+
+/// Does what `--fix` can about a failed check, and says what was done
+async fn apply_fix(fix: &Fix) -> Result<String> {
+    match fix {
+        Fix::Remove(paths) => {
+            for path in paths {
+                std::fs::remove_file(path)
+                    .with_context(|| format!("Could not remove {}", path.display()))?;
+            }
+            Ok(format!("Removed {}", file_names(paths)))
+        }
+        Fix::Stop(pids) => {
+            let list = pids
+                .iter()
+                .map(i32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            if !std::io::stdin().is_terminal() {
+                anyhow::bail!("Did not stop seshd {}: confirm from a terminal", list);
+            }
+            let confirmed = dialoguer::Confirm::with_theme(&theme::ColorfulTheme::default())
+                .with_prompt(format!("Stop seshd {}? Its sessions end with it", list))
+                .default(false)
+                .interact_opt();
+            if !matches!(confirmed, Ok(Some(true))) {
+                anyhow::bail!("Did not stop seshd {}: not confirmed", list);
+            }
+            for pid in pids {
+                if !stop(*pid).await {
+                    anyhow::bail!("seshd {} did not stop", pid);
+                }
+            }
+            Ok(format!("Stopped seshd {}", list))
+        }
+    }
+}
+
+/// How long the server gets to answer `sesh doctor`
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Finds a program the way the shell would, in $PATH unless it's a path already
+fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return sys::accessible(&path, libc::X_OK).then_some(path);
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file() && sys::accessible(path, libc::X_OK))
+}
+
+fn check_runtime_dir(dir: &Path) -> Check {
+    match runtime_dir::check(dir) {
+        Err(e) => Check::Fail(e.to_string()),
+        Ok(()) if dir.is_dir() => {
+            Check::Pass(format!("Runtime directory {} is writable", dir.display()))
+        }
+        Ok(()) => Check::Pass(format!(
+            "Runtime directory {} will be created when the server starts",
+            dir.display()
+        )),
+    }
+}
+
+/// What `sesh doctor` found out about the server, which the checks after it go by
+enum ServerState {
+    /// Nothing is listening on the server socket
+    Down,
+    /// Something may be listening on the server socket, but it didn't answer
+    Silent,
+    /// The server answered, with its pid if it's new enough to tell
+    Up {
+        pid: Option<i32>,
+        sessions: Vec<SeshInfo>,
+    },
+}
+
+async fn check_server(server_sock: &Path) -> (Diagnosis, ServerState) {
+    let diagnosis = |check| Diagnosis::new("server", check);
+    if !server_sock.exists() {
+        return (
+            diagnosis(Check::Pass(
+                "Server is not running, it starts with the first session".to_owned(),
+            )),
+            ServerState::Down,
+        );
+    }
+    match std::os::unix::net::UnixStream::connect(server_sock) {
+        Ok(_) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            let check = Check::Fail(format!(
+                "Server socket {} is left over from a crashed server, the next sesh command removes it",
+                server_sock.display()
+            ));
+            return (
+                diagnosis(check).fix(Fix::Remove(vec![server_sock.to_owned()])),
+                ServerState::Down,
+            );
+        }
+        Err(e) => {
+            let check = Check::Fail(format!(
+                "Can't connect to server socket {}: {}",
+                server_sock.display(),
+                e
+            ));
+            return (diagnosis(check), ServerState::Silent);
+        }
+    }
+    let answer = tokio::time::timeout(TIMEOUT, async {
+        let mut ctx = Ctx::init(server_sock.to_owned(), TIMEOUT).await?;
+        let info = ctx
+            .client
+            .server_info(sesh_proto::ServerInfoRequest {})
+            .await;
+        let request = tonic::Request::new(sesh_proto::SeshListRequest {
+            dead: false,
+            filter: None,
+        });
+        let sessions = ctx
+            .client
+            .list_sessions(request)
+            .await?
+            .into_inner()
+            .sessions;
+        Result::<_, anyhow::Error>::Ok((info.map(tonic::Response::into_inner), sessions))
+    })
+    .await;
+    let version = env!("CARGO_PKG_VERSION");
+    let (check, state) = match answer {
+        Ok(Ok((Ok(info), sessions))) => {
+            let check = if info.version == version {
+                Check::Pass(format!(
+                    "Server {} (pid {}) is reachable",
+                    info.version, info.pid
+                ))
+            } else {
+                Check::Fail(format!(
+                    "Server is version {} but sesh is {}, run `sesh upgrade` to replace it",
+                    info.version, version
+                ))
+            };
+            let pid = Some(info.pid);
+            (check, ServerState::Up { pid, sessions })
+        }
+        Ok(Ok((Err(status), sessions))) if status.code() == tonic::Code::Unimplemented => (
+            Check::Fail(format!(
+                "Server is older than sesh {}, run `sesh upgrade` to replace it",
+                version
+            )),
+            ServerState::Up {
+                pid: None,
+                sessions,
+            },
+        ),
+        Ok(Ok((Err(status), _))) => (
+            Check::Fail(format!(
+                "Server isn't answering requests: {}",
+                status.message()
+            )),
+            ServerState::Silent,
+        ),
+        Ok(Err(e)) => (
+            Check::Fail(format!("Server isn't answering requests: {}", e)),
+            ServerState::Silent,
+        ),
+        Err(_) => (
+            Check::Fail("Server accepted a connection but didn't answer".to_owned()),
+            ServerState::Silent,
+        ),
+    };
+    (diagnosis(check), state)
+}
+
+/// Looks for seshd processes on the server socket other than the server answering on it, or
+/// one that holds it without answering. Only done where there's /proc to look in.
+fn check_daemons(
+    server_sock: &Path,
+    default_sock: &Path,
+    server: &ServerState,
+) -> Option<Diagnosis> {
+    let pids = seshd_processes(default_sock)?
+        .into_iter()
+        .filter(|seshd| seshd.socket == server_sock)
+        .map(|seshd| seshd.pid)
+        .filter(|pid| match server {
+            ServerState::Up {
+                pid: Some(server), ..
+            } => pid != server,
+            // Without its pid the server can't be told apart from the rest
+            ServerState::Up { pid: None, .. } => false,
+            ServerState::Down | ServerState::Silent => true,
+        })
+        .collect::<Vec<_>>();
+    let list = pids
+        .iter()
+        .map(i32::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let check = match server {
+        _ if pids.is_empty() => {
+            return Some(Diagnosis::new(
+                "daemons",
+                Check::Pass(format!(
+                    "No stray seshd is running for {}",
+                    server_sock.display()
+                )),
+            ))
+        }
+        ServerState::Silent => Check::Fail(format!(
+            "seshd {} holds {} but doesn't answer, stopping it ends its sessions",
+            list,
+            server_sock.display()
+        )),
+        _ => Check::Fail(format!(
+            "seshd {} runs for {} but isn't the server answering on it",
+            list,
+            server_sock.display()
+        )),
+    };
+    Some(Diagnosis::new("daemons", check).fix(Fix::Stop(pids)))
+}
+
+/// Looks for sockets in the runtime directory left behind by sessions and clients that are gone
+fn check_sockets(dir: &Path, server_sock: &Path, server: &ServerState) -> Diagnosis {
+    let sockets = session_sockets(dir, server_sock);
+    let orphaned = match server {
+        ServerState::Down => sockets,
+        ServerState::Up { sessions, .. } => orphaned(sockets, sessions),
+        ServerState::Silent => {
+            return Diagnosis::new(
+                "sockets",
+                Check::Warn(format!(
+                    "Can't tell which sockets in {} are in use while the server isn't answering",
+                    dir.display()
+                )),
+            )
+        }
+    };
+    if orphaned.is_empty() {
+        return Diagnosis::new(
+            "sockets",
+            Check::Pass(format!("No sockets are left over in {}", dir.display())),
+        );
+    }
+    let count = match orphaned.len() {
+        1 => "1 socket is".to_owned(),
+        n => format!("{} sockets are", n),
+    };
+    let check = Check::Fail(format!(
+        "{} left over in {} by sessions that are gone: {}",
+        count,
+        dir.display(),
+        file_names(&orphaned)
+    ));
+    Diagnosis::new("sockets", check).fix(Fix::Remove(orphaned))
+}
+
+/// File names of paths, for listing them in a message
+fn file_names(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| {
+            path.file_name()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn check_seshd() -> Check {
+    let seshd = std::env::var("SESHD_PATH").unwrap_or("seshd".to_owned());
+    match find_program(&seshd) {
+        Some(path) => Check::Pass(format!("Server binary found at {}", path.display())),
+        None => Check::Fail(format!(
+            "Server binary {} not found, install seshd or set $SESHD_PATH",
+            seshd
+        )),
+    }
+}
+
+fn check_shell() -> Check {
+    match std::env::var("SHELL") {
+        Ok(shell) if find_program(&shell).is_some() => Check::Pass(format!("$SHELL is {}", shell)),
+        Ok(shell) => Check::Fail(format!("$SHELL is {}, which isn't an executable", shell)),
+        Err(_) => Check::Warn(format!(
+            "$SHELL is not set, sessions will run {}",
+            sesh_shared::user::get_shell()
+        )),
+    }
+}
+
+fn check_term() -> Check {
+    match std::env::var("TERM").as_deref() {
+        Ok("") | Err(_) => {
+            Check::Warn("$TERM is not set, programs in sessions may not draw correctly".to_owned())
+        }
+        Ok("dumb") => {
+            Check::Warn("$TERM is dumb, full-screen programs in sessions won't work".to_owned())
+        }
+        Ok(term) => Check::Pass(format!("$TERM is {}", term)),
+    }
+}
+
+/// Checks the environment for common problems, and prints a line for each check.
+/// With `fix`, what can be fixed is, and with `reset_terminal` the terminal is reset first.
+pub async fn run(
+    server_sock: PathBuf,
+    runtime_dir: PathBuf,
+    default_sock: PathBuf,
+    fix: bool,
+    reset: bool,
+    json: bool,
+) -> ExitCode {
+    let mut checks = Vec::new();
+    if reset {
+        let check = match reset_terminal() {
+            Ok(()) => Check::Pass("Terminal reset to its normal mode".to_owned()),
+            Err(e) => Check::Warn(format!("Could not reset the terminal: {}", e)),
+        };
+        checks.push(Diagnosis::new("terminal", check));
+    }
+    if dirs::runtime_dir().is_none() {
+        checks.push(Diagnosis::new(
+            "xdg_runtime_dir",
+            Check::Warn("$XDG_RUNTIME_DIR is not set, falling back to /tmp".to_owned()),
+        ));
+    }
+    if let Some(dir) = server_sock.parent() {
+        checks.push(Diagnosis::new("runtime_dir", check_runtime_dir(dir)));
+    }
+    let (server, state) = check_server(&server_sock).await;
+    checks.push(server);
+    checks.extend(check_daemons(&server_sock, &default_sock, &state));
+    checks.push(check_sockets(&runtime_dir, &server_sock, &state));
+    checks.push(Diagnosis::new("seshd", check_seshd()));
+    checks.push(Diagnosis::new("shell", check_shell()));
+    checks.push(Diagnosis::new("term", check_term()));
+
+    if fix {
+        for diagnosis in &mut checks {
+            let Some(fix) = &diagnosis.fix else {
+                continue;
+            };
+            match apply_fix(fix).await {
+                Ok(done) => {
+                    diagnosis.check = Check::Pass(done);
+                    diagnosis.fixed = true;
+                }
+                Err(e) => eprintln!("{}", error!("{}", e)),
+            }
+        }
+    }
+
+    if json {
+        let checks = checks
+            .iter()
+            .map(|diagnosis| DiagnosisSer {
+                name: diagnosis.name,
+                status: diagnosis.check.status(),
+                message: diagnosis.check.message(),
+                fixed: diagnosis.fixed,
+            })
+            .collect::<Vec<_>>();
+        match serde_json::to_string_pretty(&checks) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("{}", error!("{}", e));
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        for diagnosis in &checks {
+            println!("{}", diagnosis.check);
+        }
+    }
+    if checks
+        .iter()
+        .any(|diagnosis| matches!(diagnosis.check, Check::Fail(_)))
+    {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,70 @@
+//! Reading the terminal while attached, without leaving a read behind when the client stops.
+
+use std::fs::File;
+use std::io::{IsTerminal, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{unix::AsyncFd, AsyncRead, ReadBuf};
+
+/// Input of an attached client.
+///
+/// tokio's stdin reads on a blocking thread, which keeps waiting after the read is dropped and
+/// takes the next key pressed, even once the client has moved on to a picker or exited back to
+/// the shell. The terminal is opened again instead, without blocking, so a read that's dropped
+/// leaves the input in the terminal for whatever reads it next. Its own open file is used, so
+/// the shell's stdin is left blocking.
+pub enum Input {
+    Tty(AsyncFd<File>),
+    /// stdin isn't a terminal, so it's read as it is
+    Stdin(tokio::io::Stdin),
+}
+
+impl Input {
+    pub fn open() -> std::io::Result<Self> {
+        if !std::io::stdin().is_terminal() {
+            return Ok(Input::Stdin(tokio::io::stdin()));
+        }
+        let tty = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY)
+            .open(tty_path()?)?;
+        Ok(Input::Tty(AsyncFd::new(tty)?))
+    }
+}
+
+/// Path of the terminal on stdin, which may not be the controlling terminal
+fn tty_path() -> std::io::Result<String> {
+    let path = unsafe { libc::ttyname(libc::STDIN_FILENO) };
+    if path.is_null() {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { std::ffi::CStr::from_ptr(path) }
+        .to_string_lossy()
+        .into_owned())
+}
+
+impl AsyncRead for Input {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Input::Tty(tty) => loop {
+                let mut ready = ready!(tty.poll_read_ready(cx))?;
+                let unfilled = buf.initialize_unfilled();
+                match ready.try_io(|tty| tty.get_ref().read(unfilled)) {
+                    Ok(read) => {
+                        buf.advance(read?);
+                        return Poll::Ready(Ok(()));
+                    }
+                    // Someone else read the input first
+                    Err(_would_block) => continue,
+                }
+            },
+            Input::Stdin(stdin) => Pin::new(stdin).poll_read(cx, buf),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Locking the screen of an attached session after a while without input, for terminals left
+//! alone on shared machines. Only the terminal is locked: the session runs on, and its output is
+//! read but not shown until it's unlocked.
+
+use std::time::Duration;
+
+use anyhow::Result;
+
+/// Reads how long the terminal may go without input before it's locked from $SESH_LOCK_AFTER,
+/// in seconds. It's never locked if that's unset.
+pub fn from_env() -> Result<Option<Duration>> {
+    let Ok(after) = std::env::var("SESH_LOCK_AFTER") else {
+        return Ok(None);
+    };
+    // Left unlocked by a typo, the screen would stay up for anyone to read
+    let after = sesh_cli::parse_seconds(&after)
+        .map_err(|e| anyhow::anyhow!("Invalid SESH_LOCK_AFTER: {}", e))?;
+    Ok(Some(after))
+}
+
+/// Escape sequences that blank the screen and show which session is locked in its middle.
+/// The cursor is hidden until the terminal is reset on unlocking.
+pub fn screen(name: &str, cols: u16, rows: u16) -> String {
+    let prompt = format!("[locked: {}, press Enter to unlock]", name);
+    let prompt = prompt.chars().take(cols as usize).collect::<String>();
+    let col = (cols as usize).saturating_sub(prompt.chars().count()) / 2 + 1;
+    let row = rows / 2 + 1;
+    format!(
+        "\x1b[0m\x1b[?25l\x1b[H\x1b[2J\x1b[{};{}H{}",
+        row, col, prompt
+    )
+}
+
+/// Whether input unlocks the screen, which Enter does. Anything else typed at the lock screen
+/// is dropped.
+pub fn unlocks(read: &[u8]) -> bool {
+    read.iter().any(|&byte| byte == b'\r' || byte == b'\n')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompt_is_centered() {
+        let prompt = "[locked: vim, press Enter to unlock]";
+        assert_eq!(
+            screen("vim", 80, 24),
+            format!("\x1b[0m\x1b[?25l\x1b[H\x1b[2J\x1b[13;23H{}", prompt)
+        );
+        // Cut short on a narrow terminal
+        assert!(screen("vim", 10, 5).ends_with("\x1b[3;1H[locked: v"));
+        assert!(unlocks(b"\r"));
+        assert!(!unlocks(b"hunter2\x1b\\"));
+    }
+}
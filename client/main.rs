@@ -165,28 +165,153 @@
 //!
 //! **Usage:** `sesh shutdown`
 
-use std::{path::PathBuf, process::ExitCode};
+use std::{
+    fmt::Display,
+    io::{ErrorKind, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    time::Duration,
+};
 
 use clap::Parser;
 use libc::exit;
-use sesh_cli::{Cli, Command};
-use sesh_shared::{pty::Pty, term::Size};
+use sesh_cli::{theme::Theme, AttachPolicy, Cli, Column, Command, Format};
+use sesh_shared::{pty::Pty, runtime_dir, sys::DirWatch, term::Size};
 use session::Ctx;
 use termion::{
-    color::{Color, Fg},
+    color::{AnsiValue, Fg},
     style::Bold,
 };
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, Notify};
 
 use sesh_proto::sesh_cli_server::SeshCli;
 
+mod batch;
+mod detach_key;
+mod doctor;
+mod input;
+mod lock;
+mod monitor;
 mod session;
+mod status;
+mod table;
 
 #[repr(u8)]
 #[derive(Debug, Clone)]
 enum ExitKind {
     Quit,
     Detach,
+    /// The session was ended by the server
+    Ended(Ending),
+    /// The session was killed from this client, with the prefix key
+    Killed,
+    /// Detached in order to pick another session to attach to, without leaving raw mode
+    Switch,
+}
+
+/// Why the server ended a session a client was attached to
+#[derive(Debug, Clone, Copy)]
+enum Ending {
+    /// Killed by another client
+    Killed,
+    /// Its program exited with a status
+    Code(i32),
+    /// Its program was killed by a signal
+    Signal(i32),
+    /// Its program exited, with no status known
+    Exited,
+}
+
+impl Ending {
+    /// The code to exit with, which follows the shell's so the client can stand in for the
+    /// program in scripts
+    fn code(self) -> u8 {
+        match self {
+            Ending::Killed => EXIT_KILLED,
+            Ending::Code(code) => code as u8,
+            Ending::Signal(sig) => 128u8.wrapping_add(sig as u8),
+            Ending::Exited => 0,
+        }
+    }
+}
+
+impl From<&sesh_proto::ClientSessionEndedRequest> for Ending {
+    fn from(ended: &sesh_proto::ClientSessionEndedRequest) -> Self {
+        use sesh_proto::client_session_ended_request::{ExitStatus, Reason};
+        match (ended.reason(), &ended.exit_status) {
+            (Reason::Killed, _) => Ending::Killed,
+            (Reason::Exited, Some(ExitStatus::Code(code))) => Ending::Code(*code),
+            (Reason::Exited, Some(ExitStatus::Signal(sig))) => Ending::Signal(*sig),
+            (Reason::Exited, None) => Ending::Exited,
+        }
+    }
+}
+
+/// Exit status after detaching from a session
+const EXIT_DETACHED: u8 = 10;
+/// Exit status when the session was killed, by another client or with the prefix key
+const EXIT_KILLED: u8 = 11;
+/// Exit status when the server could not be reached
+const EXIT_UNREACHABLE: u8 = 3;
+/// Exit status for usage errors, the same as clap's
+const EXIT_USAGE: u8 = 2;
+
+/// What a command has left to print once it's done
+enum Outcome {
+    /// Output the command was run for, like a list of sessions. Printed even with --quiet
+    Output(String),
+    /// A status message like `[started]`, left out with --quiet
+    Status(String),
+    /// The client left an attached session, with a status message and the code to exit with
+    Left(String, u8),
+    /// The client stopped following a session, with a status message for stderr and the code to
+    /// exit with
+    Followed(String, u8),
+    /// A command given several sessions failed for some of them, with the status of the rest and
+    /// the errors
+    Partial(String, String),
+    /// Nothing left to print
+    Done,
+}
+
+/// Whether output is colored, decided once at startup
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+/// Decides whether to color output: not if --no-color was given, $NO_COLOR is set to anything,
+/// or stdout isn't a terminal
+fn init_color(no_color: bool) {
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let color = !no_color && !no_color_env && std::io::stdout().is_terminal();
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+/// Icons and colors for lists, decided once at startup
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Reads the theme from $SESH_THEME, or uses the one for the locale if it's invalid
+fn init_theme(ascii: bool) {
+    let theme = Theme::from_env(ascii).unwrap_or_else(|e| {
+        eprintln!("{}", error!("[ignoring SESH_THEME: {}]", e));
+        Theme::for_locale(ascii)
+    });
+    THEME.set(theme).ok();
+}
+
+fn theme() -> &'static Theme {
+    THEME.get_or_init(|| Theme::for_locale(false))
+}
+
+/// Renders a color or style escape sequence, or nothing if output isn't colored
+fn paint(escape: impl Display) -> String {
+    if COLOR.load(Ordering::Relaxed) {
+        escape.to_string()
+    } else {
+        String::new()
+    }
 }
 
 /// Formats the given input as green, then resets
@@ -195,9 +320,9 @@ macro_rules! success {
     ($($arg:tt)*) => {
         format!(
             "{}{}{}",
-            termion::color::Fg(termion::color::Green),
+            $crate::paint(termion::color::Fg(termion::color::Green)),
             format!($($arg)*),
-            termion::color::Fg(termion::color::Reset)
+            $crate::paint(termion::color::Fg(termion::color::Reset))
         )
     };
 }
@@ -208,9 +333,9 @@ macro_rules! error {
     ($($arg:expr),*) => {
         format!(
             "{}{}{}",
-            termion::color::Fg(termion::color::Red),
+            $crate::paint(termion::color::Fg(termion::color::Red)),
             format!($($arg),*),
-            termion::color::Fg(termion::color::Reset)
+            $crate::paint(termion::color::Fg(termion::color::Reset))
         )
     };
 }
@@ -219,6 +344,8 @@ macro_rules! error {
 /// Server -> Client connection service
 struct SeshCliService {
     exit_tx: broadcast::Sender<ExitKind>,
+    /// Woken when the server asks for the terminal to be reset
+    reset: Arc<Notify>,
 }
 
 #[tonic::async_trait]
@@ -233,75 +360,401 @@ impl SeshCli for SeshCliService {
             .map_err(|_| tonic::Status::internal("Failed to send exit signal to client"))?;
         Ok(tonic::Response::new(sesh_proto::ClientDetachResponse {}))
     }
+
+    /// Server -> Client notification that the attached session has ended
+    async fn session_ended(
+        &self,
+        request: tonic::Request<sesh_proto::ClientSessionEndedRequest>,
+    ) -> std::result::Result<tonic::Response<sesh_proto::ClientSessionEndedResponse>, tonic::Status>
+    {
+        let ending = Ending::from(&request.into_inner());
+        self.exit_tx
+            .send(ExitKind::Ended(ending))
+            .map_err(|_| tonic::Status::internal("Failed to send exit signal to client"))?;
+        Ok(tonic::Response::new(
+            sesh_proto::ClientSessionEndedResponse {},
+        ))
+    }
+
+    /// Server -> Client check that the client is still around
+    async fn ping(
+        &self,
+        _: tonic::Request<sesh_proto::ClientPingRequest>,
+    ) -> std::result::Result<tonic::Response<sesh_proto::ClientPingResponse>, tonic::Status> {
+        Ok(tonic::Response::new(sesh_proto::ClientPingResponse {}))
+    }
+
+    /// Server -> Client request to reset the terminal, once the session's output messed it up
+    async fn reset(
+        &self,
+        _: tonic::Request<sesh_proto::ClientResetRequest>,
+    ) -> std::result::Result<tonic::Response<sesh_proto::ClientResetResponse>, tonic::Status> {
+        self.reset.notify_one();
+        Ok(tonic::Response::new(sesh_proto::ClientResetResponse {}))
+    }
 }
 
+/// The program a session runs when none was given: $SESH_DEFAULT_PROGRAM, or else the user's shell
 fn get_program(program: Option<String>) -> String {
-    program.unwrap_or_else(|| std::env::var("SHELL").unwrap_or("bash".to_owned()))
+    program
+        .or_else(|| {
+            std::env::var("SESH_DEFAULT_PROGRAM")
+                .ok()
+                .filter(|program| !program.is_empty())
+        })
+        .unwrap_or_else(sesh_shared::user::get_shell)
 }
 
-/// Formats an icon and title pair, giving the icon its own color
-fn icon_title<T: Color>(icon: char, title: &str, icon_color: Fg<T>) -> String {
-    format!(
-        "{}{}{} {}{}{}",
-        icon_color,
-        icon,
-        Fg(termion::color::Reset),
-        Bold,
-        title,
-        termion::style::Reset
-    )
+/// Replaces each `${VAR}` in `s` with the variable's value in this environment, which is the one
+/// a session is started with. `$$` stands for a literal `$`, and an unset variable is an error.
+fn expand_vars(s: &str) -> anyhow::Result<String> {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        expanded.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let Some(end) = after.find('}') else {
+                anyhow::bail!("Unclosed ${{ in {}", s);
+            };
+            let var = &after[..end];
+            match std::env::var(var) {
+                Ok(value) => expanded.push_str(&value),
+                Err(_) => anyhow::bail!("${{{}}} in {} is not set", var, s),
+            }
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
 }
 
-enum ListMode {
-    List,
-    Table,
-    Json,
+/// Formats an icon and title pair, giving the icon its own color.
+/// Without an icon, the title is given the color instead.
+fn icon_title(icon: Option<char>, title: &str, color: sesh_cli::theme::Color) -> String {
+    let color = paint(Fg(AnsiValue(color.ansi())));
+    match icon {
+        Some(icon) => format!(
+            "{}{}{} {}{}{}",
+            color,
+            icon,
+            paint(Fg(termion::color::Reset)),
+            paint(Bold),
+            title,
+            paint(termion::style::Reset)
+        ),
+        None => format!(
+            "{}{}{}{}",
+            paint(Bold),
+            color,
+            title,
+            paint(termion::style::Reset)
+        ),
+    }
 }
 
-impl ListMode {
-    pub fn new(table: bool, json: bool) -> Self {
-        if json {
-            Self::Json
-        } else if table {
-            Self::Table
-        } else {
-            Self::List
+/// Picks the format for `sesh list`, from --format or the shorthands for it
+fn list_format(
+    format: Option<Format>,
+    info: bool,
+    json: bool,
+    absolute: bool,
+    columns: &[Column],
+) -> Format {
+    match format {
+        Some(format) => format,
+        None if json => Format::Json,
+        None if info || absolute || !columns.is_empty() => Format::Table,
+        None => Format::List,
+    }
+}
+
+/// How long to wait for a newly started server when neither --wait-timeout nor
+/// $SESH_START_TIMEOUT is given
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the server has to answer a request when neither --timeout nor $SESH_TIMEOUT is given
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest pause between checks for the server's socket, when it can't be watched for or isn't
+/// listening yet
+const MAX_START_POLL: Duration = Duration::from_millis(250);
+
+/// Reads a timeout from its flag or else its environment variable, or uses the default
+fn timeout_setting(flag: Option<Duration>, var: &str, default: Duration) -> Duration {
+    flag.or_else(|| {
+        let timeout = std::env::var(var).ok()?;
+        match sesh_cli::parse_seconds(&timeout) {
+            Ok(timeout) => Some(timeout),
+            Err(e) => {
+                eprintln!("{}", error!("[ignoring {}: {}]", var, e));
+                None
+            }
         }
+    })
+    .unwrap_or(default)
+}
+
+/// What tonic fails a request with when the server doesn't answer it in time
+const DEADLINE_EXPIRED: &str = "Timeout expired";
+
+/// Whether a command failed because the server didn't answer in time. Commands pass on either
+/// the request's status or its message, maybe after their own words, so it's found at the end.
+fn timed_out(e: &anyhow::Error) -> bool {
+    e.chain()
+        .any(|cause| match cause.downcast_ref::<tonic::Status>() {
+            Some(status) => status.message() == DEADLINE_EXPIRED,
+            None => cause.to_string().ends_with(DEADLINE_EXPIRED),
+        })
+}
+
+/// Reads the server socket from --socket or else $SESH_SERVER_SOCKET, or None for the default.
+/// It's made absolute, and exported so sessions started from here use the same server.
+fn server_socket(flag: Option<PathBuf>) -> std::io::Result<Option<PathBuf>> {
+    let Some(socket) = flag.or_else(|| {
+        std::env::var_os("SESH_SERVER_SOCKET")
+            .filter(|socket| !socket.is_empty())
+            .map(PathBuf::from)
+    }) else {
+        return Ok(None);
+    };
+    let socket = std::path::absolute(socket)?;
+    std::env::set_var("SESH_SERVER_SOCKET", &socket);
+    Ok(Some(socket))
+}
+
+/// Reads the runtime directory from --runtime-dir or else $SESH_RUNTIME_DIR, or None for the
+/// default. Like the server socket, it's exported so sessions use the same one.
+fn runtime_dir_setting(flag: Option<PathBuf>) -> std::io::Result<Option<PathBuf>> {
+    let Some(dir) = flag.or_else(|| {
+        std::env::var_os("SESH_RUNTIME_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    }) else {
+        return Ok(None);
+    };
+    let dir = std::path::absolute(dir)?;
+    std::env::set_var("SESH_RUNTIME_DIR", &dir);
+    Ok(Some(dir))
+}
+
+/// Waits for a newly started server to accept connections on its socket. The runtime directory is
+/// watched for the socket to appear, and where it can't be, it's checked for less often the
+/// longer it takes. The socket is made just before the server listens on it, so once it's there
+/// it's tried until it's let in.
+/// Returns how long it waited if the server wasn't up in time.
+async fn wait_for_server(server_sock: &Path, timeout: Duration) -> Result<(), Duration> {
+    let start = std::time::Instant::now();
+    let server_sock = server_sock.to_owned();
+    tokio::task::spawn_blocking(move || {
+        // Watching starts before the first check, so a socket made in between isn't missed
+        let watch = server_sock.parent().and_then(|dir| DirWatch::new(dir).ok());
+        let mut poll = Duration::from_millis(5);
+        while std::os::unix::net::UnixStream::connect(&server_sock).is_err() {
+            let waited = start.elapsed();
+            if waited >= timeout {
+                return Err(waited);
+            }
+            let left = timeout - waited;
+            match &watch {
+                Some(watch) if !server_sock.exists() => {
+                    // A failed wait leaves it to the next check
+                    watch.wait(left).ok();
+                }
+                _ => {
+                    std::thread::sleep(poll.min(left));
+                    poll = (poll * 2).min(MAX_START_POLL);
+                }
+            }
+        }
+        Ok(())
+    })
+    .await
+    .unwrap_or_else(|_| Err(start.elapsed()))
+}
+
+/// Explains why the server on `server_sock` couldn't be connected to
+fn unreachable_reason(server_sock: &Path) -> String {
+    if let Some(Err(e)) = server_sock.parent().map(runtime_dir::check) {
+        return e.to_string();
+    }
+    match std::os::unix::net::UnixStream::connect(server_sock) {
+        Err(e) if e.kind() == ErrorKind::NotFound => "server is not running".to_owned(),
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => format!(
+            "{} is left over from a server that exited, and couldn't be removed",
+            server_sock.display()
+        ),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+            format!("permission denied on {}", server_sock.display())
+        }
+        Err(e) => e.to_string(),
+        Ok(_) => "server is running but didn't answer, run `sesh doctor` to check on it".to_owned(),
     }
 }
 
 #[tokio::main]
 async fn main() -> ExitCode {
     let cli = Cli::parse();
+    let quiet = cli.quiet;
+    init_color(cli.no_color);
+    init_theme(cli.ascii);
 
-    let rt = dirs::runtime_dir()
-        .unwrap_or(PathBuf::from("/tmp/"))
-        .join("sesh/");
-    let server_sock = rt.join("server.sock");
+    let socket = match server_socket(cli.socket) {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("{}", error!("[invalid server socket: {}]", e));
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    let dir = match runtime_dir_setting(cli.runtime_dir) {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("{}", error!("[invalid runtime directory: {}]", e));
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    let default_sock = dir
+        .clone()
+        .unwrap_or_else(|| {
+            dirs::runtime_dir()
+                .unwrap_or(PathBuf::from("/tmp/"))
+                .join("sesh")
+        })
+        .join("server.sock");
+    let server_sock = socket.clone().unwrap_or(default_sock.clone());
+    // A server on another socket gets a directory of its own, so its sessions' sockets and
+    // saved state can't clash with another server's
+    let runtime_dir = dir.or_else(|| {
+        socket
+            .filter(|socket| *socket != default_sock)
+            .map(|socket| {
+                let mut dir = socket.into_os_string();
+                dir.push(".d");
+                PathBuf::from(dir)
+            })
+    });
 
+    if cli.version {
+        println!("{}", session::version(cli.verbose));
+        return ExitCode::SUCCESS;
+    }
     let cmd = match cli.command {
         Some(cmd) => cmd,
         None => Command::Start {
             name: cli.args.name,
             program: cli.args.program,
             args: cli.args.args,
-            detached: cli.args.detached,
+            attach: cli.args.attach,
+            no_alt_screen: cli.args.no_alt_screen,
+            status: cli.args.status,
+            login: cli.args.login,
+            term: cli.args.term,
+            force_size: cli.args.force_size,
+            replay_input: cli.args.replay_input,
+            here: cli.args.here,
+            note: cli.args.note,
+            tags: cli.args.tags,
+            title: cli.args.title,
+            script: cli.args.script,
+            expand: cli.args.expand,
+            notify: Box::new(cli.args.notify),
+            keep: cli.args.keep,
+            windows: Box::new(cli.args.windows),
+            termios: Box::new(cli.args.termios),
         },
     };
+    if let Command::Current {
+        format,
+        json,
+        verify,
+    } = cmd
+    {
+        // Handled before anything else, since it's run often and mustn't start a server
+        let timeout = timeout_setting(cli.timeout, "SESH_TIMEOUT", REQUEST_TIMEOUT);
+        return session::current(server_sock, timeout, format, json, verify).await;
+    }
+    if let Command::Ssh {
+        host,
+        session,
+        create,
+        remote_sesh,
+    } = cmd
+    {
+        // Everything happens on the host, so there's no need for a local server
+        return session::ssh(host, session, create, remote_sesh);
+    }
+    if let Command::Version { verbose } = cmd {
+        println!("{}", session::version(verbose));
+        return ExitCode::SUCCESS;
+    }
+    if let Command::Doctor {
+        fix,
+        reset_terminal,
+        json,
+    } = cmd
+    {
+        // Diagnoses the server as it is, so it mustn't clean up or start one
+        let runtime_dir = runtime_dir.unwrap_or_else(|| {
+            default_sock
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+        });
+        return doctor::run(
+            server_sock,
+            runtime_dir,
+            default_sock,
+            fix,
+            reset_terminal,
+            json,
+        )
+        .await;
+    }
+    // A server that crashed leaves its socket behind, and nothing is listening on it
+    if std::os::unix::net::UnixStream::connect(&server_sock)
+        .is_err_and(|e| e.kind() == std::io::ErrorKind::ConnectionRefused)
+    {
+        std::fs::remove_file(&server_sock).ok();
+    }
     if !server_sock.exists() {
-        if matches!(cmd, Command::Shutdown)
-            || matches!(cmd, Command::List { .. })
-            || matches!(cmd, Command::Kill { .. })
+        if matches!(cmd, Command::Shutdown | Command::Upgrade)
+            || matches!(cmd, Command::List { dead: false, .. })
+            || matches!(
+                cmd,
+                Command::Kill { .. }
+                    | Command::Rename { .. }
+                    | Command::Renumber
+                    | Command::Prune { .. }
+                    | Command::Note { .. }
+                    | Command::Events { .. }
+            )
         {
-            println!("{}", success!("[not running]"));
+            if !quiet {
+                println!("{}", success!("[not running]"));
+            }
             return ExitCode::SUCCESS;
         } else {
-            let size = Size::term_size().unwrap_or(Size { cols: 80, rows: 24 });
+            // A server that can't make its socket dies without a word, so find out first. The
+            // directory is made here so it can be watched for the socket
+            if let Some(Err(e)) = server_sock.parent().map(runtime_dir::prepare) {
+                eprintln!("{}", error!("[failed to start server: {}]", e));
+                return ExitCode::from(EXIT_UNREACHABLE);
+            }
+            let size = Size::detect();
             if unsafe { libc::fork() == 0 } {
-                let res = Pty::builder(std::env::var("SESHD_PATH").unwrap_or("seshd".to_owned()))
-                    .daemonize()
-                    .env("RUST_LOG", "INFO")
-                    .spawn(&size);
+                let mut seshd =
+                    Pty::builder(std::env::var("SESHD_PATH").unwrap_or("seshd".to_owned()));
+                if let Some(dir) = &runtime_dir {
+                    seshd = seshd
+                        .arg("--runtime-dir")
+                        .arg(dir)
+                        .arg("--socket")
+                        .arg(&server_sock);
+                }
+                let res = seshd.daemonize().env("RUST_LOG", "INFO").spawn(&size);
                 unsafe {
                     match res {
                         Ok(_) => exit(0),
@@ -309,46 +762,209 @@ async fn main() -> ExitCode {
                     }
                 }
             }
-            let now = std::time::Instant::now();
-            while !server_sock.exists() {
-                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
-                if now.elapsed().as_secs() > 5 {
-                    eprintln!("{}", error!("[failed to connect to server]"));
-                    return ExitCode::FAILURE;
-                }
+            let timeout = timeout_setting(cli.wait_timeout, "SESH_START_TIMEOUT", START_TIMEOUT);
+            if let Err(waited) = wait_for_server(&server_sock, timeout).await {
+                let reason = match server_sock.exists() {
+                    true => "not listening",
+                    false => "no socket",
+                };
+                eprintln!(
+                    "{}",
+                    error!(
+                        "[failed to connect to server: {} after {:.1}s]",
+                        reason,
+                        waited.as_secs_f64()
+                    )
+                );
+                return ExitCode::from(EXIT_UNREACHABLE);
             }
         }
     }
 
-    let Ok(ctx) = Ctx::init(server_sock).await else {
-        eprintln!("{}", error!("[failed to connect to server]"));
-        return ExitCode::FAILURE;
+    let request_timeout = timeout_setting(cli.timeout, "SESH_TIMEOUT", REQUEST_TIMEOUT);
+    let Ok(ctx) = Ctx::init(server_sock.clone(), request_timeout).await else {
+        eprintln!(
+            "{}",
+            error!(
+                "[failed to connect to server: {}]",
+                unreachable_reason(&server_sock)
+            )
+        );
+        return ExitCode::from(EXIT_UNREACHABLE);
     };
 
-    let message = match cmd {
+    let outcome = match cmd {
         Command::Start {
             name,
             program,
             args,
-            detached,
-        } => session::start(ctx, name, program, args, !detached).await,
+            attach,
+            no_alt_screen,
+            status,
+            login,
+            term,
+            force_size,
+            replay_input,
+            here,
+            note,
+            tags,
+            title,
+            script,
+            expand,
+            notify,
+            keep,
+            windows,
+            termios,
+        } => {
+            if let Some((cols, rows)) = force_size {
+                session::force_size(Size { cols, rows });
+            }
+            let options = session::StartOptions {
+                name,
+                program,
+                args,
+                attach: attach.policy() == AttachPolicy::Attach,
+                no_alt_screen,
+                status,
+                login,
+                term,
+                replay_input,
+                here,
+                note,
+                tags,
+                title,
+                script,
+                expand,
+                notify: *notify,
+                keep,
+                windows: windows.windows,
+                termios: *termios,
+            };
+            session::start(ctx, options).await
+        }
         Command::Resume { create } => session::resume(ctx, create).await,
-        Command::Attach { session, create } => session::attach(ctx, session, create).await,
-        Command::Kill { session } => session::kill(ctx, session).await,
-        Command::Detach { session } => session::detach(ctx, session).await,
-        Command::Select => session::select(ctx).await,
-        Command::List { info, json } => session::list(ctx, info, json).await,
+        Command::Attach {
+            sessions,
+            create,
+            no_alt_screen,
+            status,
+            keep_size,
+            force_size,
+            command,
+        } => {
+            if let Some((cols, rows)) = force_size {
+                session::force_size(Size { cols, rows });
+            }
+            let options = session::AttachOptions {
+                create,
+                command,
+                no_alt_screen,
+                status,
+                keep_size,
+            };
+            session::attach(ctx, sessions, options).await
+        }
+        Command::Kill {
+            sessions,
+            tag,
+            grace,
+            signal,
+        } => session::kill(ctx, sessions, tag, grace, signal).await,
+        Command::Detach { sessions } => session::detach_sessions(ctx, sessions).await,
+        Command::Select { json } => session::select(ctx, json).await,
+        Command::Monitor => monitor::run(ctx).await,
+        Command::Signal { session, signal } => session::send_signal(ctx, session, signal).await,
+        Command::Paste { session, bracketed } => session::paste(ctx, session, bracketed).await,
+        Command::Reset { session } => session::reset(ctx, session).await,
+        Command::Rename { session, name } => session::rename(ctx, session, name).await,
+        Command::Renumber => session::renumber(ctx).await,
+        Command::Prune { dry_run } => session::prune(ctx, dry_run).await,
+        Command::Note { session, note } => session::note(ctx, session, note).await,
+        Command::List {
+            format,
+            info,
+            columns,
+            json,
+            dead,
+            tag,
+            name,
+            program,
+            attached,
+            detached,
+            absolute,
+        } => {
+            let format = list_format(format, info, json, absolute, &columns);
+            let filter = sesh_proto::SeshListFilter {
+                name: name.unwrap_or_default(),
+                program: program.unwrap_or_default(),
+                connected: (attached || detached).then_some(attached),
+                tags: tag.into_iter().collect(),
+            };
+            session::list(ctx, format, columns, dead, filter, absolute).await
+        }
+        Command::Revive { session, detached } => session::revive(ctx, session, !detached).await,
+        Command::Events { json } => session::events(ctx, json).await,
+        Command::WaitFor { channel, signal } => session::wait_for(ctx, channel, signal).await,
+        Command::Batch { input, fail_fast } => batch::run(ctx, input, fail_fast).await,
+        Command::Current { .. }
+        | Command::Doctor { .. }
+        | Command::Version { .. }
+        | Command::Ssh { .. } => {
+            unreachable!("handled before connecting")
+        }
+        Command::Follow { session } => session::follow(ctx, session).await,
+        Command::Bench { bytes } => session::bench(ctx, bytes).await,
         Command::Shutdown => session::shutdown(ctx).await,
+        Command::Upgrade => session::upgrade(ctx).await,
     };
 
-    match message {
-        Ok(Some(message)) => println!("{}", message),
-        Ok(None) => (),
+    let code = match outcome {
+        Ok(Outcome::Output(output)) => {
+            println!("{}", output);
+            0
+        }
+        Ok(Outcome::Status(message)) => {
+            if !quiet {
+                println!("{}", message);
+            }
+            0
+        }
+        Ok(Outcome::Left(message, code)) => {
+            if !quiet {
+                // The terminal may be gone if the client left because it was closed
+                writeln!(std::io::stdout(), "{}", message).ok();
+            }
+            code
+        }
+        Ok(Outcome::Followed(message, code)) => {
+            if !quiet {
+                eprintln!("{}", message);
+            }
+            code
+        }
+        Ok(Outcome::Partial(message, errors)) => {
+            if !quiet && !message.is_empty() {
+                println!("{}", message);
+            }
+            eprintln!("{}", errors);
+            return ExitCode::FAILURE;
+        }
+        Ok(Outcome::Done) => 0,
+        Err(e) if timed_out(&e) => {
+            eprintln!(
+                "{}",
+                error!(
+                    "[server unresponsive: it is running but didn't answer within {:.1}s, run `sesh doctor` to check on it]",
+                    request_timeout.as_secs_f64()
+                )
+            );
+            return ExitCode::from(EXIT_UNREACHABLE);
+        }
         Err(e) => {
-            println!("{}", error!("{}", e));
+            eprintln!("{}", error!("{}", e));
             return ExitCode::FAILURE;
         }
-    }
+    };
 
-    unsafe { exit(0) };
+    unsafe { exit(code as i32) };
 }
@@ -0,0 +1,357 @@
+//! `sesh monitor`, the live table of sessions, with its drawing and keys.
+
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use sesh_cli::SessionSelector;
+use termion::event::{Event, Key};
+use termion::{raw::IntoRawMode, screen::IntoAlternateScreen};
+use unicode_width::UnicodeWidthStr;
+
+use crate::session::{
+    attach, column_cell, column_title, default_columns, kill_one, without_deadline, AttachOptions,
+    Ctx,
+};
+use crate::{table, theme, Outcome};
+
+/// What a key does while no prompt is open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Up,
+    Down,
+    First,
+    Last,
+    Attach,
+    Kill,
+    Rename,
+    Quit,
+}
+
+/// The action bound to a key, if any
+pub fn action(key: Key) -> Option<Action> {
+    match key {
+        Key::Up | Key::Char('k') => Some(Action::Up),
+        Key::Down | Key::Char('j') => Some(Action::Down),
+        Key::Home | Key::Char('g') => Some(Action::First),
+        Key::End | Key::Char('G') => Some(Action::Last),
+        Key::Char('\n') | Key::Char('a') => Some(Action::Attach),
+        Key::Char('x') => Some(Action::Kill),
+        Key::Char('r') => Some(Action::Rename),
+        Key::Char('q') | Key::Esc | Key::Ctrl('c') => Some(Action::Quit),
+        _ => None,
+    }
+}
+
+/// Waits up to `timeout` for input on the terminal, and returns the keys that were read.
+/// Nothing is read unless input is waiting, so no read is left behind to take the keys meant
+/// for whatever uses the terminal after the monitor.
+pub async fn read_keys(timeout: Duration) -> Result<Vec<Key>> {
+    tokio::task::spawn_blocking(move || {
+        let mut stdin = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+        if unsafe { libc::poll(&mut stdin, 1, millis) } <= 0 {
+            return Ok(Vec::new());
+        }
+        let mut read = [0u8; 64];
+        let n = unsafe { libc::read(libc::STDIN_FILENO, read.as_mut_ptr().cast(), read.len()) };
+        if n == 0 {
+            anyhow::bail!("The terminal was closed");
+        }
+        let mut bytes = read[..n.max(0) as usize].iter().map(|b| Ok(*b));
+        let mut keys = Vec::new();
+        while let Some(Ok(byte)) = bytes.next() {
+            if let Ok(Event::Key(key)) = termion::event::parse_event(byte, &mut bytes) {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    })
+    .await?
+}
+
+/// The session table, laid out to fit the terminal
+pub struct Table {
+    pub titles: Vec<String>,
+    /// Plain text of each session's cells
+    pub rows: Vec<Vec<String>>,
+    pub widths: Vec<usize>,
+}
+
+/// Escape sequences that draw the whole screen: `header` on the top row, the table under it with
+/// the `selected` row in reverse video, and `footer` on the bottom row.
+/// When there are more sessions than rows, the table scrolls to keep the selected one in view.
+pub fn draw(
+    header: &str,
+    table: &Table,
+    selected: Option<usize>,
+    footer: &str,
+    cols: u16,
+    rows: u16,
+) -> String {
+    let cols = cols as usize;
+    let line = |cells: &[String]| {
+        let cells = cells
+            .iter()
+            .zip(&table.widths)
+            .map(|(cell, width)| {
+                let cell = table::truncate(cell, *width);
+                let pad = width.saturating_sub(cell.width());
+                format!("{}{}", cell, " ".repeat(pad))
+            })
+            .collect::<Vec<_>>();
+        fit(&format!(" {}", cells.join("   ")), cols)
+    };
+
+    // The header, the titles and the footer take a row each
+    let visible = (rows as usize).saturating_sub(3);
+    let offset = match selected {
+        Some(selected) if selected >= visible => selected + 1 - visible,
+        _ => 0,
+    };
+    let mut screen = format!("\x1b[H\x1b[0;7m{}\x1b[0m\r\n", fit(header, cols));
+    screen += &format!("\x1b[1m{}\x1b[0m\r\n", line(&table.titles));
+    for (i, row) in table.rows.iter().enumerate().skip(offset).take(visible) {
+        if Some(i) == selected {
+            screen += &format!("\x1b[7m{}\x1b[0m\r\n", line(row));
+        } else {
+            screen += &format!("{}\r\n", line(row));
+        }
+    }
+    // Rows left over from a longer table are cleared
+    screen += &format!("\x1b[J\x1b[{};1H\x1b[7m{}\x1b[0m", rows, fit(footer, cols));
+    screen
+}
+
+/// Cuts or pads a line to exactly `cols` columns, so reverse video spans the whole row
+fn fit(line: &str, cols: usize) -> String {
+    let line = table::truncate(line, cols);
+    let pad = cols.saturating_sub(line.width());
+    format!("{}{}", line, " ".repeat(pad))
+}
+
+/// How often the monitor lists the sessions, when no event has come in to say they changed
+const INTERVAL: Duration = Duration::from_secs(1);
+/// How long the monitor waits for a key before checking whether to redraw
+const POLL: Duration = Duration::from_millis(100);
+/// Seconds a session killed from the monitor has to exit before it's sent SIGKILL
+const GRACE: u64 = 5;
+
+/// What the bottom row of the monitor is asking for
+enum Prompt {
+    None,
+    /// Confirmation to kill the named session
+    Kill(String),
+    /// New name for the named session, as typed so far
+    Rename(String, String),
+}
+
+/// Shows a live table of sessions, and attaches to the one picked from it
+pub async fn run(mut ctx: Ctx) -> Result<Outcome> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        anyhow::bail!("The monitor needs a terminal");
+    }
+    // Any event may change the table, so the sessions are listed again once one comes in
+    let stale = Arc::new(AtomicBool::new(true));
+    let request = without_deadline(sesh_proto::SubscribeEventsRequest {});
+    let events = match ctx.client.subscribe_events(request).await {
+        Ok(stream) => {
+            let stale = Arc::clone(&stale);
+            let mut stream = stream.into_inner();
+            Some(tokio::task::spawn(async move {
+                while let Ok(Some(_)) = stream.message().await {
+                    stale.store(true, Ordering::Release);
+                }
+            }))
+        }
+        Err(_) => None,
+    };
+    let picked = run_monitor(&mut ctx, &stale).await;
+    // Leaving the alternate screen and showing the cursor are only buffered when dropped
+    std::io::Write::flush(&mut std::io::stdout()).ok();
+    if let Some(events) = events {
+        events.abort();
+    }
+
+    match picked? {
+        Some(name) => {
+            attach(
+                ctx,
+                vec![SessionSelector::Name(name)],
+                AttachOptions::default(),
+            )
+            .await
+        }
+        None => Ok(Outcome::Done),
+    }
+}
+
+/// Draws the monitor and handles its keys until it's quit, or a session is picked to attach to
+async fn run_monitor(ctx: &mut Ctx, stale: &AtomicBool) -> Result<Option<String>> {
+    use std::io::Write;
+
+    let raw = std::io::stdout()
+        .into_raw_mode()
+        .context("Failed to set raw mode")?;
+    let screen = raw
+        .into_alternate_screen()
+        .context("Failed to enter alternate screen")?;
+    let mut screen = termion::cursor::HideCursor::from(screen);
+
+    let mut sessions = Vec::new();
+    let mut listed = None::<std::time::Instant>;
+    // The highlighted session is kept by name, so it stays highlighted as others come and go
+    let mut selected = None::<String>;
+    let mut prompt = Prompt::None;
+    let mut message = String::new();
+    let mut drawn = String::new();
+    loop {
+        if stale.swap(false, Ordering::AcqRel)
+            || listed.is_none_or(|listed| listed.elapsed() >= INTERVAL)
+        {
+            let request = tonic::Request::new(sesh_proto::SeshListRequest {
+                dead: false,
+                filter: None,
+            });
+            sessions = ctx
+                .client
+                .list_sessions(request)
+                .await?
+                .into_inner()
+                .sessions;
+            // The server lists them in no particular order, so rows would jump around
+            sessions.sort_by_key(|s| s.id);
+            listed = Some(std::time::Instant::now());
+        }
+        let index = selected
+            .as_ref()
+            .and_then(|name| sessions.iter().position(|s| &s.name == name))
+            .or((!sessions.is_empty()).then_some(0));
+        selected = index.map(|i| sessions[i].name.clone());
+
+        let (cols, rows) = termion::terminal_size().unwrap_or((80, 24));
+        let now = chrono::Utc::now().timestamp_millis();
+        let columns = default_columns(&sessions);
+        let titles = columns
+            .iter()
+            .map(|c| column_title(*c).to_owned())
+            .collect::<Vec<_>>();
+        let cells = sessions
+            .iter()
+            .map(|s| {
+                columns
+                    .iter()
+                    .map(|c| column_cell(*c, s, now, false))
+                    .collect()
+            })
+            .collect::<Vec<Vec<_>>>();
+        let layout = table::layout(columns.clone(), &titles, &cells, Some(cols as usize), false);
+        // Columns the layout left out are dropped from the titles and cells too
+        let shown = layout
+            .iter()
+            .map(|(c, _)| columns.iter().position(|col| col == c).unwrap_or(0))
+            .collect::<Vec<_>>();
+        let table = Table {
+            titles: shown.iter().map(|i| titles[*i].clone()).collect(),
+            rows: cells
+                .iter()
+                .map(|row| shown.iter().map(|i| row[*i].clone()).collect())
+                .collect(),
+            widths: layout.iter().map(|(_, width)| *width).collect(),
+        };
+        let header = format!(
+            " sesh monitor {} {} sessions {} {}",
+            theme().separator,
+            sessions.len(),
+            theme().separator,
+            Local::now().format("%H:%M:%S")
+        );
+        let footer = match &prompt {
+            Prompt::Kill(name) => format!(" Kill {}? (y/n)", name),
+            Prompt::Rename(name, input) => format!(" Rename {} to: {}_", name, input),
+            Prompt::None if !message.is_empty() => format!(" {}", message),
+            Prompt::None => " j/k: move  enter: attach  x: kill  r: rename  q: quit".to_owned(),
+        };
+        let frame = draw(&header, &table, index, &footer, cols, rows);
+        if frame != drawn {
+            write!(screen, "{}", frame)?;
+            screen.flush()?;
+            drawn = frame;
+        }
+
+        for key in read_keys(POLL).await? {
+            message.clear();
+            prompt = match prompt {
+                Prompt::Kill(name) => {
+                    if matches!(key, Key::Char('y' | 'Y')) {
+                        let session = SessionSelector::Name(name.clone());
+                        message = match kill_one(ctx, &session, GRACE, None).await {
+                            Ok(_) => format!("Killed {}", name),
+                            Err(e) => format!("Could not kill {}: {}", name, e),
+                        };
+                        stale.store(true, Ordering::Release);
+                    }
+                    Prompt::None
+                }
+                Prompt::Rename(name, mut input) => match key {
+                    Key::Char('\n') if !input.is_empty() => {
+                        let request = tonic::Request::new(sesh_proto::SeshRenameRequest {
+                            session: Some(sesh_proto::sesh_rename_request::Session::Name(
+                                name.clone(),
+                            )),
+                            new_name: input,
+                        });
+                        message = match ctx.client.rename_session(request).await {
+                            Ok(res) => {
+                                let new_name = res.into_inner().name;
+                                selected = Some(new_name.clone());
+                                format!("Renamed {} to {}", name, new_name)
+                            }
+                            Err(e) => format!("Could not rename {}: {}", name, e.message()),
+                        };
+                        stale.store(true, Ordering::Release);
+                        Prompt::None
+                    }
+                    Key::Esc | Key::Ctrl('c') => Prompt::None,
+                    Key::Backspace => {
+                        input.pop();
+                        Prompt::Rename(name, input)
+                    }
+                    Key::Char(c) if !c.is_control() => {
+                        input.push(c);
+                        Prompt::Rename(name, input)
+                    }
+                    _ => Prompt::Rename(name, input),
+                },
+                Prompt::None => match action(key) {
+                    Some(Action::Attach) if selected.is_some() => return Ok(selected),
+                    Some(Action::Quit) => return Ok(None),
+                    Some(Action::Kill) => selected.clone().map_or(Prompt::None, Prompt::Kill),
+                    Some(Action::Rename) => selected
+                        .clone()
+                        .map_or(Prompt::None, |name| Prompt::Rename(name, String::new())),
+                    action => {
+                        let index = index.unwrap_or(0);
+                        let last = sessions.len().saturating_sub(1);
+                        let to = match action {
+                            Some(Action::Up) => index.saturating_sub(1),
+                            Some(Action::Down) => (index + 1).min(last),
+                            Some(Action::First) => 0,
+                            Some(Action::Last) => last,
+                            _ => index,
+                        };
+                        selected = sessions.get(to).map(|s| s.name.clone()).or(selected);
+                        Prompt::None
+                    }
+                },
+            };
+        }
+    }
+}
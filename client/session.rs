@@ -1,47 +1,97 @@
-use std::io::Cursor;
-use std::path::PathBuf;
+use std::io::{Cursor, IsTerminal};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{ExitCode, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use chrono::{Local, TimeZone};
 use dialoguer::theme;
 use prettytable::format::{FormatBuilder, LinePosition, LineSeparator};
-use prettytable::{row, Table};
-use sesh_cli::SessionSelector;
+use prettytable::Table;
+use sesh_cli::{Column, Format, NotifyArgs, SessionSelector, TermiosArgs};
 use sesh_proto::seshd_client::SeshdClient;
 use sesh_proto::SeshInfo;
 use sesh_proto::{
-    sesh_cli_server::SeshCliServer, sesh_kill_request::Session, sesh_resize_request,
-    SeshResizeRequest, SeshStartRequest, WinSize,
+    sesh_cli_server::SeshCliServer, sesh_cwd_request, sesh_kill_request::Session,
+    sesh_resize_request, sesh_signal_request, SeshAttachResponse, SeshCwdRequest, SeshPasteRequest,
+    SeshResizeRequest, SeshReviveRequest, SeshStartRequest, SeshStartResponse, WinSize,
 };
+use sesh_shared::{error::SeshError, signal, term::Size};
 use termion::color::{self, Fg};
 use termion::{raw::IntoRawMode, screen::IntoAlternateScreen};
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, watch, Notify};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::UnixStream,
     signal::unix::{signal, SignalKind},
+    task::JoinError,
 };
-use tokio_stream::wrappers::UnixListenerStream;
+use tokio_stream::wrappers::{ReceiverStream, UnixListenerStream};
+use tonic::service::{interceptor::InterceptedService, Interceptor};
 use tonic::transport::{Channel, Endpoint, Server as RPCServer, Uri};
 use tower::service_fn;
 
-use crate::{error, get_program, icon_title, success, ExitKind, ListMode, SeshCliService};
+use crate::detach_key::{self, DetachKey};
+use crate::input::Input;
+use crate::lock;
+use crate::status::{self, OutputState, Status};
+use crate::table;
+use crate::{
+    error, expand_vars, get_program, icon_title, paint, success, theme, Ending, ExitKind, Outcome,
+    SeshCliService, EXIT_DETACHED,
+};
+
+/// How many times connecting to the server is tried, as a new one may not be listening yet
+const CONNECT_ATTEMPTS: u32 = 5;
+/// Pause before trying to connect again, doubled after each attempt
+const CONNECT_BACKOFF: Duration = Duration::from_millis(20);
+
+/// Marks a request that is meant to wait, like `sesh wait-for`, so [`Deadline`] doesn't give it
+/// the default timeout
+#[derive(Clone, Copy)]
+struct NoDeadline;
+
+/// Gives each request a timeout, so a server that is stuck can't hang the client forever.
+/// Requests that set their own timeout, or are marked with [`NoDeadline`], keep it.
+#[derive(Clone)]
+pub(crate) struct Deadline(Duration);
 
-// TODO: Make these configurable
-/// Active session icon
-static ACTIVE_ICON: char = '⯌';
-/// Bullet icon
-static BULLET_ICON: char = '❒';
+impl Interceptor for Deadline {
+    fn call(
+        &mut self,
+        mut request: tonic::Request<()>,
+    ) -> Result<tonic::Request<()>, tonic::Status> {
+        if request.extensions().get::<NoDeadline>().is_none()
+            && !request.metadata().contains_key("grpc-timeout")
+        {
+            request.set_timeout(self.0);
+        }
+        Ok(request)
+    }
+}
+
+/// A request that may take as long as it needs
+pub(crate) fn without_deadline<T>(message: T) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    request.extensions_mut().insert(NoDeadline);
+    request
+}
 
-/// Initializes the Tonic client with a UnixStream from the provided socket path
-/// Sets up exit broadcast / mpmc channel
 pub struct Ctx {
-    client: SeshdClient<Channel>,
+    pub(crate) client: SeshdClient<InterceptedService<Channel, Deadline>>,
     exit: (broadcast::Sender<ExitKind>, broadcast::Receiver<ExitKind>),
+    /// How long the server has to answer a request
+    pub(crate) timeout: Duration,
 }
 
 impl Ctx {
-    pub async fn init(socket: PathBuf) -> Result<Self> {
+    /// Initializes the Tonic client with a UnixStream from the provided socket path
+    /// Sets up exit broadcast / mpmc channel
+    pub async fn init(socket: PathBuf, timeout: Duration) -> Result<Self> {
         if !socket.exists() {
             return Err(anyhow::anyhow!(
                 "Server socket not found at {}",
@@ -50,22 +100,109 @@ impl Ctx {
         }
 
         // Create a channel to the server socket
-        let channel = Endpoint::try_from("http://[::]:50051")?
-            .connect_with_connector(service_fn(move |_: Uri| {
+        let endpoint = Endpoint::try_from("http://[::]:50051")?;
+        let mut backoff = CONNECT_BACKOFF;
+        let mut attempt = 1;
+        let channel = loop {
+            let socket = socket.clone();
+            let connect = endpoint.connect_with_connector(service_fn(move |_: Uri| {
                 // Connect to a Uds socket
                 UnixStream::connect(socket.clone())
-            }))
-            .await?;
+            }));
+            let err = match tokio::time::timeout(timeout, connect).await {
+                Ok(Ok(channel)) => break channel,
+                Ok(Err(e)) => anyhow::Error::from(e),
+                Err(_) => anyhow::anyhow!("Timed out connecting to server"),
+            };
+            if attempt == CONNECT_ATTEMPTS {
+                return Err(err);
+            }
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        };
 
-        let client = SeshdClient::new(channel);
+        let client = SeshdClient::with_interceptor(channel, Deadline(timeout));
 
         let (tx, rx) = broadcast::channel(1);
 
         Ok(Ctx {
             client,
             exit: (tx, rx),
+            timeout,
         })
     }
+
+    /// Creates a context for attaching to another session over the same connection, without the
+    /// exit signal left over from the last one
+    fn renew(&self) -> Self {
+        let (tx, rx) = broadcast::channel(1);
+        Ctx {
+            client: self.client.clone(),
+            exit: (tx, rx),
+            timeout: self.timeout,
+        }
+    }
+
+    /// Turns a selector into a request's session field, given the field's id and name variants.
+    /// A session selected by pid is looked up and sent by id.
+    pub async fn select<T>(
+        &mut self,
+        session: &SessionSelector,
+        id: impl FnOnce(u64) -> T,
+        name: impl FnOnce(String) -> T,
+    ) -> Result<T> {
+        match session {
+            SessionSelector::Id(i) => Ok(id(*i as u64)),
+            SessionSelector::Name(n) => Ok(name(n.clone())),
+            SessionSelector::Pid(pid) => {
+                let request = tonic::Request::new(sesh_proto::SeshListRequest {
+                    dead: false,
+                    filter: None,
+                });
+                self.client
+                    .list_sessions(request)
+                    .await?
+                    .into_inner()
+                    .sessions
+                    .into_iter()
+                    .find(|s| s.pid == *pid)
+                    .map(|s| id(s.id))
+                    .ok_or_else(|| {
+                        anyhow::Error::from(SeshError::SessionNotFound)
+                            .context(format!("No session has pid {}", pid))
+                    })
+            }
+        }
+    }
+
+    /// Like [`Ctx::select`], for the session this is running in
+    pub async fn select_current<T>(
+        &mut self,
+        id: impl FnOnce(u64) -> T,
+        name: impl FnOnce(String) -> T,
+    ) -> Result<T> {
+        // The session's process leads the unix session we're in, which identifies it even if the
+        // environment wasn't passed down, or the session was renamed since
+        let sid = unsafe { libc::getsid(0) };
+        let request = tonic::Request::new(sesh_proto::SeshListRequest {
+            dead: false,
+            filter: None,
+        });
+        let sessions = self
+            .client
+            .list_sessions(request)
+            .await?
+            .into_inner()
+            .sessions;
+        match sessions.into_iter().find(|s| s.pid == sid) {
+            Some(session) => Ok(id(session.id)),
+            None => match std::env::var("SESH_NAME") {
+                Ok(current) => Ok(name(current)),
+                Err(_) => anyhow::bail!("Not in a session"),
+            },
+        }
+    }
 }
 
 impl Clone for Ctx {
@@ -73,34 +210,922 @@ impl Clone for Ctx {
         Ctx {
             client: self.client.clone(),
             exit: (self.exit.0.clone(), self.exit.0.subscribe()),
+            timeout: self.timeout,
         }
     }
 }
 
+/// A command entered after the prefix key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PrefixCommand {
+    /// `d`: detach from the session
+    Detach,
+    /// `s`: detach, then pick another session to attach to
+    Switch,
+    /// `,` or `$`: rename the session
+    Rename,
+    /// `S`: show or hide the status line
+    ToggleStatus,
+    /// `K`: kill the session, once the user confirms it
+    Kill,
+    /// `n`: show the next window
+    NextWindow,
+    /// `p`: show the previous window
+    PrevWindow,
+}
+
+/// Recognizes commands entered after the prefix key.
+///
+/// The prefix key is set with `SESH_PREFIX` (e.g. `C-b`), and there is none unless it is set.
+/// Pressing the prefix key twice sends it to the session.
+struct PrefixKey {
+    key: u8,
+    /// Whether the last byte read was the prefix key
+    pending: bool,
+}
+
+impl PrefixKey {
+    /// Reads the prefix key from the environment, if one has been set
+    fn from_env() -> Result<Option<Self>> {
+        let Ok(key) = std::env::var("SESH_PREFIX") else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            key: parse_key(&key)?,
+            pending: false,
+        }))
+    }
+
+    /// Splits input into the bytes meant for the session, the command entered, if any, and
+    /// what was read after the command. The rest is left to be split once the command has run,
+    /// so that e.g. what's typed after `n` goes to the next window.
+    fn split<'a>(&mut self, read: &'a [u8]) -> (Vec<u8>, Option<PrefixCommand>, &'a [u8]) {
+        let mut forward = Vec::with_capacity(read.len());
+        for (i, &byte) in read.iter().enumerate() {
+            if !self.pending {
+                if byte == self.key {
+                    self.pending = true;
+                } else {
+                    forward.push(byte);
+                }
+                continue;
+            }
+            self.pending = false;
+            let command = match byte {
+                b'd' => PrefixCommand::Detach,
+                b's' => PrefixCommand::Switch,
+                b',' | b'$' => PrefixCommand::Rename,
+                b'K' => PrefixCommand::Kill,
+                b'S' => PrefixCommand::ToggleStatus,
+                b'n' => PrefixCommand::NextWindow,
+                b'p' => PrefixCommand::PrevWindow,
+                _ if byte == self.key => {
+                    forward.push(byte);
+                    continue;
+                }
+                // Unbound keys are ignored
+                _ => continue,
+            };
+            return (forward, Some(command), &read[i + 1..]);
+        }
+        (forward, None, &[])
+    }
+}
+
+/// Parses a key like `C-b`, `^b` or `a` into the byte the terminal sends for it
+fn parse_key(key: &str) -> Result<u8> {
+    let ctrl = key.strip_prefix("C-").or_else(|| key.strip_prefix('^'));
+    match (ctrl.map(str::as_bytes), key.as_bytes()) {
+        (Some(&[byte]), _) if (b'@'..=b'_').contains(&byte.to_ascii_uppercase()) => {
+            Ok(byte.to_ascii_uppercase() & 0x1f)
+        }
+        (None, &[byte]) if byte.is_ascii() => Ok(byte),
+        _ => Err(anyhow::anyhow!(
+            "Invalid prefix key {:?}, expected something like C-b",
+            key
+        )),
+    }
+}
+
+/// Draws text in reverse video over the bottom row of the terminal, or clears the row if the
+/// text is empty. The cursor is left where it was.
+async fn bottom_line(text: &str) -> Result<()> {
+    let rows = termion::terminal_size().map(|s| s.1).unwrap_or(24);
+    let mut output = tokio::io::stdout();
+    let text = if text.is_empty() {
+        String::new()
+    } else {
+        format!("\x1b[7m{}\x1b[0m", text)
+    };
+    output
+        .write_all(format!("\x1b7\x1b[{};1H\x1b[2K{}\x1b8", rows, text).as_bytes())
+        .await?;
+    output.flush().await?;
+    Ok(())
+}
+
+/// Reads a line of input on the bottom row of the terminal.
+/// Returns None if it is cancelled with Escape or Ctrl-C.
+async fn prompt(input: &mut Input, label: &str, initial: &str) -> Result<Option<String>> {
+    let mut line = initial.to_owned();
+    let result = 'read: loop {
+        bottom_line(&format!("{}: {}", label, line)).await?;
+        let mut packet = [0; 1024];
+        let nbytes = input.read(&mut packet).await?;
+        if nbytes == 0 {
+            break None;
+        }
+        for c in String::from_utf8_lossy(&packet[..nbytes]).chars() {
+            match c {
+                '\r' | '\n' => break 'read Some(line),
+                '\x1b' | '\x03' => break 'read None,
+                '\x7f' | '\x08' => {
+                    line.pop();
+                }
+                // Ctrl-U
+                '\x15' => line.clear(),
+                c if !c.is_control() => line.push(c),
+                _ => (),
+            }
+        }
+    };
+    bottom_line("").await?;
+    Ok(result)
+}
+
+/// Asks a yes or no question on the bottom row of the terminal, and returns whether the next
+/// key pressed was y. Anything else counts as no.
+async fn confirm(input: &mut Input, question: &str) -> Result<bool> {
+    bottom_line(&format!("{} (y/n)", question)).await?;
+    let mut packet = [0; 1024];
+    let nbytes = input.read(&mut packet).await?;
+    bottom_line("").await?;
+    Ok(matches!(packet[..nbytes], [b'y' | b'Y', ..]))
+}
+
+/// Asks the program in the foreground of the session to redraw, after something was drawn over
+/// its output
+async fn redraw(ctx: &Ctx, name: &str) {
+    ctx.client
+        .clone()
+        .redraw_session(sesh_proto::SeshRedrawRequest {
+            session: Some(sesh_proto::sesh_redraw_request::Session::Name(
+                name.to_owned(),
+            )),
+        })
+        .await
+        .ok();
+}
+
+/// Kills the session from inside the client once the user confirms it, and returns whether it
+/// was killed. The server then ends the session for this client as for any other.
+async fn kill_attached(ctx: &mut Ctx, input: &mut Input, name: &str) -> Result<bool> {
+    if !confirm(input, &format!("kill session {}?", name)).await? {
+        redraw(ctx, name).await;
+        return Ok(false);
+    }
+    let session = SessionSelector::Name(name.to_owned());
+    match kill_one(ctx, &session, KILL_GRACE, None).await {
+        Ok(_) => Ok(true),
+        Err(e) => {
+            bottom_line(&e.to_string()).await?;
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            bottom_line("").await?;
+            redraw(ctx, name).await;
+            Ok(false)
+        }
+    }
+}
+
+/// Renames the session from inside the client, returning the new name
+async fn rename_attached(ctx: &Ctx, input: &mut Input, name: &str) -> Result<String> {
+    let Some(new_name) = prompt(input, "rename session", name).await? else {
+        return Ok(name.to_owned());
+    };
+    let mut client = ctx.client.clone();
+    let res = client
+        .rename_session(sesh_proto::SeshRenameRequest {
+            session: Some(sesh_proto::sesh_rename_request::Session::Name(
+                name.to_owned(),
+            )),
+            new_name,
+        })
+        .await;
+    let name = match res {
+        Ok(res) => res.into_inner().name,
+        Err(e) => {
+            bottom_line(e.message()).await?;
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            bottom_line("").await?;
+            name.to_owned()
+        }
+    };
+    // The prompt was drawn over the program's output
+    redraw(ctx, &name).await;
+    // Programs in the session keep the old name in $SESH_NAME, but commands run from it, like
+    // `sesh detach`, find the session by its process instead
+    Ok(name)
+}
+
+/// Brings the session back once the lock screen is gone. It's reset and redrawn as with
+/// `sesh reset`, since its output while locked was dropped. If the server can't do that, the
+/// terminal is still reset here, and the session is left to draw over it.
+async fn unlock(ctx: &mut Ctx, name: String, reset: &Notify) {
+    let request = sesh_proto::SeshResetRequest {
+        session: Some(sesh_proto::sesh_reset_request::Session::Name(name)),
+    };
+    if ctx.client.reset_session(request).await.is_err() {
+        reset.notify_one();
+    }
+}
+
+/// Shows the window `step` windows away in the session, from inside the client
+async fn select_window(ctx: &Ctx, name: &str, step: i32) -> Result<()> {
+    let res = ctx
+        .client
+        .clone()
+        .select_window(sesh_proto::SeshSelectWindowRequest {
+            session: Some(sesh_proto::sesh_select_window_request::Session::Name(
+                name.to_owned(),
+            )),
+            step,
+        })
+        .await;
+    if let Err(e) = res {
+        bottom_line(e.message()).await?;
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        bottom_line("").await?;
+    }
+    Ok(())
+}
+
+/// Size given with --force-size, used instead of the terminal's
+static FORCED_SIZE: OnceLock<Size> = OnceLock::new();
+
+/// Sizes sessions as if the terminal was the given size, and stops resizing them along with it
+pub fn force_size(size: Size) {
+    FORCED_SIZE.set(size).ok();
+}
+
+/// Size of the terminal, less the row taken by the status line if it is shown
+fn client_size(status: bool) -> WinSize {
+    let size = FORCED_SIZE.get().copied().unwrap_or_else(Size::detect);
+    WinSize::from(&Size {
+        rows: size.rows.saturating_sub(status as u16),
+        ..size
+    })
+}
+
+/// How often the status line is brought up to date
+const STATUS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Puts the terminal back in order after output that messed it up, like a binary file: a soft
+/// reset (DECSTR) of modes and colors, the ASCII character set, and a clear screen. Unlike a full
+/// reset, it leaves the terminal on the alternate screen if the session runs there.
+const RESET: &[u8] = b"\x1b[!p\x1b(B\x0f\x1b[H\x1b[2J";
+
+/// How long to wait for the server to say why a session ended, once its output stops
+const ENDED_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// How an attached session is shown
+#[derive(Debug, Clone, Copy)]
+struct View {
+    alt_screen: bool,
+    /// Whether the status line is shown at first
+    status: bool,
+    /// Whether the session keeps its size instead of taking the terminal's
+    keep_size: bool,
+}
+
+/// The session a start or revive attaches to
+fn started(res: SeshStartResponse) -> SeshAttachResponse {
+    SeshAttachResponse {
+        pid: res.pid,
+        socket: res.socket,
+        name: res.name,
+        program: res.program,
+        size: None,
+        title: res.title,
+        start_time: res.start_time,
+    }
+}
+
 /// Responsible for executing a session, and managing its IO until it exits.
-async fn exec_session(
-    ctx: Ctx,
-    pid: i32,
-    socket: String,
-    name: String,
-    program: String,
-) -> Result<ExitKind> {
-    std::env::set_var("SESH_NAME", &name);
+/// Switching to another session also happens here, so the terminal stays in raw mode.
+async fn exec_session(ctx: Ctx, session: SeshAttachResponse, view: View) -> Result<Left> {
+    let View {
+        alt_screen,
+        status,
+        keep_size,
+    } = view;
     // NOTE: This is used to set raw mode and alternate screen while
     // still using tokio's async stdout.
-    let _raw = std::io::stdout()
+    let raw = std::io::stdout()
         .into_raw_mode()
-        .context("Failed to set raw mode")?
-        .into_alternate_screen()
+        .context("Failed to set raw mode")?;
+    let _screen = alt_screen
+        .then(|| std::io::stdout().into_alternate_screen())
+        .transpose()
         .context("Failed to enter alternate screen")?;
 
+    let mut ctx = ctx;
+    let mut session = session;
+    let status = Arc::new(AtomicBool::new(status));
+    loop {
+        // The session can be renamed while attached, so its name is read back afterwards
+        let name = Arc::new(Mutex::new(session.name.clone()));
+        let exit = relay_session(
+            ctx.clone(),
+            session.pid,
+            &session.socket,
+            Arc::clone(&name),
+            // Sessions without a title are titled after their program
+            if session.title.is_empty() {
+                &session.program
+            } else {
+                &session.title
+            },
+            Arc::clone(&status),
+            keep_size,
+        )
+        .await?;
+        session.name = name.lock().expect("name lock poisoned").clone();
+        if !matches!(exit, ExitKind::Switch) {
+            return Ok(Left {
+                exit,
+                name: session.name,
+                start_time: session.start_time,
+            });
+        }
+
+        ctx = ctx.renew();
+        // The picker draws its own way, so it needs the terminal in its normal mode
+        raw.suspend_raw_mode()?;
+        let status_shown = status.load(Ordering::Acquire);
+        let picked = pick_session(&mut ctx, &session.name, status_shown, keep_size).await;
+        raw.activate_raw_mode()?;
+        session = match picked? {
+            Some(picked) => picked,
+            // Go back to the session that was left
+            None => match attach_session(&mut ctx, &session.name, status_shown, keep_size).await {
+                Ok(session) => session,
+                Err(_) => {
+                    return Ok(Left {
+                        exit: ExitKind::Detach,
+                        name: session.name,
+                        start_time: session.start_time,
+                    })
+                }
+            },
+        };
+    }
+}
+
+/// Sends an attach request for the named session, sized for this client's terminal
+async fn attach_session(
+    ctx: &mut Ctx,
+    name: &str,
+    status: bool,
+    keep_size: bool,
+) -> Result<SeshAttachResponse> {
+    let request = tonic::Request::new(sesh_proto::SeshAttachRequest {
+        session: Some(sesh_proto::sesh_attach_request::Session::Name(
+            name.to_owned(),
+        )),
+        size: Some(client_size(status)),
+        keep_size,
+    });
+    Ok(ctx.client.attach_session(request).await?.into_inner())
+}
+
+/// Lets the user pick another session and attaches to it.
+/// Returns None if the user cancelled.
+async fn pick_session(
+    ctx: &mut Ctx,
+    current: &str,
+    status: bool,
+    keep_size: bool,
+) -> Result<Option<SeshAttachResponse>> {
+    let mut output = tokio::io::stdout();
+    loop {
+        // Don't draw the picker over what's left of the last session
+        output.write_all(b"\x1b[2J\x1b[H").await?;
+        output.flush().await?;
+
+        let request = tonic::Request::new(sesh_proto::SeshListRequest {
+            dead: false,
+            filter: None,
+        });
+        let response = ctx.client.list_sessions(request).await?.into_inner();
+        // Sessions attached to another client can't be attached to
+        let sessions = response
+            .sessions
+            .into_iter()
+            .filter(|s| s.name != current && !s.connected)
+            .map(|s| s.name)
+            .collect::<Vec<_>>();
+        if sessions.is_empty() {
+            return Ok(None);
+        }
+
+        let Ok(Some(select)) = dialoguer::FuzzySelect::with_theme(&theme::ColorfulTheme::default())
+            .items(sessions.as_slice())
+            .default(0)
+            .with_prompt("Session")
+            .interact_opt()
+        else {
+            return Ok(None);
+        };
+        match attach_session(ctx, &sessions[select], status, keep_size).await {
+            Ok(session) => return Ok(Some(session)),
+            // It may have exited or been taken while the picker was open, so pick again
+            Err(_) => continue,
+        }
+    }
+}
+
+/// Relays IO between the terminal and a session until the client leaves it.
+async fn relay_session(
+    mut ctx: Ctx,
+    pid: i32,
+    socket: &str,
+    name: Arc<Mutex<String>>,
+    title: &str,
+    status: Arc<AtomicBool>,
+    keep_size: bool,
+) -> Result<ExitKind> {
+    std::env::set_var("SESH_NAME", &*name.lock().expect("name lock poisoned"));
     let mut output = tokio::io::stdout();
 
     // Set terminal title
     output
-        .write_all(format!("\x1B]0;{}\x07", program).as_bytes())
+        .write_all(format!("\x1B]0;{}\x07", title).as_bytes())
         .await?;
 
+    let (client_server_sock, uds_stream) = bind_client_socket(socket, pid).await?;
+
+    let (mut r_stream, mut w_stream) = UnixStream::connect(socket)
+        .await
+        .context("Could not connect to socket stream")?
+        .into_split();
+
+    // Set when the user asks to switch sessions, which detaches from this one first
+    let switch = Arc::new(AtomicBool::new(false));
+    // Set when the user kills the session from here, so it isn't reported as killed by another
+    // client
+    let killed = Arc::new(AtomicBool::new(false));
+    // Notifications for when the terminal's layout changes or the status line needs to be
+    // redrawn
+    let layout = Arc::new(Notify::new());
+    let refresh = Arc::new(Notify::new());
+    let reset = Arc::new(Notify::new());
+    let (status_tx, mut status_rx) = watch::channel(Status {
+        line: None,
+        cols: 0,
+        rows: 0,
+    });
+    // Set while the screen is locked for want of input, and notified when it's locked or
+    // unlocked
+    let locked = Arc::new(AtomicBool::new(false));
+    let lock_changed = Arc::new(Notify::new());
+
+    // Reads process output from the server and writes it to the terminal
+    let mut r_handle = tokio::task::spawn({
+        let exit = ctx.exit.0.subscribe();
+        let reset = Arc::clone(&reset);
+        let name = Arc::clone(&name);
+        let locked = Arc::clone(&locked);
+        let lock_changed = Arc::clone(&lock_changed);
+        async move {
+            let mut packet = [0; 4096];
+            let mut output_state = OutputState::default();
+            // The status line as it was last drawn, and whether it needs to be drawn again
+            let mut drawn = Status {
+                line: None,
+                cols: 0,
+                rows: 0,
+            };
+            let mut redraw = false;
+            let mut reserve = false;
+            let mut resetting = false;
+            // Whether the lock screen is shown, so the session's output is kept off the terminal
+            let mut hidden = false;
+            while exit.is_empty() {
+                tokio::select! {
+                    bytes = r_stream.read(&mut packet) => {
+                        let bytes = bytes?;
+                        if bytes == 0 {
+                            break;
+                        }
+                        let read = &packet[..bytes];
+                        if !hidden {
+                            output
+                                .write_all(read)
+                                .await
+                                .context("Could not write tty_output")?;
+                            output_state.feed(read);
+                            if drawn.line.is_some() {
+                                reserve |= status::resets_region(read);
+                                redraw |= status::erases_status(read);
+                            }
+                        }
+                    }
+                    changed = status_rx.changed() => {
+                        if changed.is_err() {
+                            // The status task has stopped, so there's nothing more to wait for
+                            status_rx = watch::channel(drawn.clone()).1;
+                            continue;
+                        }
+                        redraw = true;
+                    }
+                    _ = reset.notified() => resetting = true,
+                    _ = lock_changed.notified() => (),
+                }
+                if locked.load(Ordering::Acquire) {
+                    // Drawn again when the terminal is resized, once whatever escape sequence
+                    // the session was in the middle of has been written out
+                    if (!hidden || redraw) && output_state.is_ground() {
+                        let current = status_rx.borrow_and_update().clone();
+                        let name = name.lock().expect("name lock poisoned").clone();
+                        output
+                            .write_all(lock::screen(&name, current.cols, current.rows).as_bytes())
+                            .await?;
+                        drawn = current;
+                        hidden = true;
+                        redraw = false;
+                    }
+                    output.flush().await.context("Could not flush tty_output")?;
+                    continue;
+                }
+                // The session is drawn again by the reset asked for on unlocking
+                hidden = false;
+                if resetting {
+                    // The output may have been left in what looked like an unfinished escape
+                    // sequence, which the reset ends
+                    output.write_all(RESET).await?;
+                    output_state = OutputState::default();
+                    resetting = false;
+                    reserve = true;
+                    redraw = true;
+                }
+                // Drawing in the middle of an escape sequence would break it up
+                if (redraw || reserve) && output_state.is_ground() {
+                    let current = status_rx.borrow_and_update().clone();
+                    let layout_changed = current.rows != drawn.rows
+                        || current.line.is_some() != drawn.line.is_some();
+                    let mut seq = String::new();
+                    match &current.line {
+                        Some(line) => {
+                            if reserve || layout_changed {
+                                seq += &status::reserve(current.rows);
+                            }
+                            seq += &status::draw(line, current.rows);
+                        }
+                        None if drawn.line.is_some() => seq += &status::clear(current.rows),
+                        None => (),
+                    }
+                    output.write_all(seq.as_bytes()).await?;
+                    drawn = current;
+                    redraw = false;
+                    reserve = false;
+                }
+                output.flush().await.context("Could not flush tty_output")?;
+            }
+            Result::<_, anyhow::Error>::Ok(())
+        }
+    });
+
+    // Keeps the status line up to date
+    tokio::task::spawn({
+        let mut ctx = ctx.clone();
+        let name = Arc::clone(&name);
+        let status = Arc::clone(&status);
+        let refresh = Arc::clone(&refresh);
+        async move {
+            let mut interval = tokio::time::interval(STATUS_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = ctx.exit.1.recv() => break,
+                    _ = interval.tick() => (),
+                    _ = refresh.notified() => (),
+                }
+                let (cols, rows) = termion::terminal_size().unwrap_or((80, 24));
+                let line = if status.load(Ordering::Acquire) {
+                    let request = tonic::Request::new(sesh_proto::SeshListRequest {
+                        dead: false,
+                        filter: None,
+                    });
+                    let sessions = ctx
+                        .client
+                        .list_sessions(request)
+                        .await?
+                        .into_inner()
+                        .sessions;
+                    let name = name.lock().expect("name lock poisoned").clone();
+                    Some(status::render(&name, &sessions, cols, keep_size))
+                } else {
+                    None
+                };
+                // Only wakes the output task if something changed
+                status_tx.send_if_modified(|current| {
+                    let new = Status { line, cols, rows };
+                    let modified = *current != new;
+                    *current = new;
+                    modified
+                });
+            }
+            Result::<_, anyhow::Error>::Ok(())
+        }
+    });
+
+    // Reads terminal input and sends it to the server to be handled by the process.
+    let mut w_handle = tokio::task::spawn({
+        let mut ctx = ctx.clone();
+        let name = Arc::clone(&name);
+        let switch = Arc::clone(&switch);
+        let killed = Arc::clone(&killed);
+        let status = Arc::clone(&status);
+        let layout = Arc::clone(&layout);
+        let refresh = Arc::clone(&refresh);
+        let reset = Arc::clone(&reset);
+        let locked = Arc::clone(&locked);
+        let lock_changed = Arc::clone(&lock_changed);
+        let mut prefix_key = PrefixKey::from_env()?;
+        let lock_after = lock::from_env()?;
+        let mut input = Input::open().context("Failed to open tty_input")?;
+        async move {
+            let mut detach_key = DetachKey::default();
+            'input: while ctx.exit.1.is_empty() {
+                let mut packet = [0; 4096];
+
+                let read = input.read(&mut packet);
+                let idle = lock_after.filter(|_| !locked.load(Ordering::Acquire));
+                let nbytes = if detach_key.pending() {
+                    match tokio::time::timeout(detach_key::TIMEOUT, read).await {
+                        Ok(nbytes) => nbytes,
+                        // The ESC held back was the Escape key on its own
+                        Err(_) => {
+                            w_stream
+                                .write_all(&detach_key.flush())
+                                .await
+                                .context("Failed to write to w_stream")?;
+                            w_stream.flush().await.context("Failed to flush w_stream")?;
+                            continue;
+                        }
+                    }
+                } else if let Some(idle) = idle {
+                    match tokio::time::timeout(idle, read).await {
+                        Ok(nbytes) => nbytes,
+                        Err(_) => {
+                            locked.store(true, Ordering::Release);
+                            lock_changed.notify_one();
+                            continue;
+                        }
+                    }
+                } else {
+                    read.await
+                }
+                .context("Failed to read tty_input")?;
+                // The terminal was closed
+                if nbytes == 0 {
+                    return Ok(true);
+                }
+                if locked.load(Ordering::Acquire) {
+                    if lock::unlocks(&packet[..nbytes]) {
+                        locked.store(false, Ordering::Release);
+                        lock_changed.notify_one();
+                        let current = name.lock().expect("name lock poisoned").clone();
+                        unlock(&mut ctx, current, &reset).await;
+                    }
+                    continue;
+                }
+
+                // TODO: Make this configurable
+                let (read, detached) = detach_key.feed(&packet[..nbytes]);
+                // Commands run in the order they were typed, between the input around them
+                let mut rest = &read[..];
+                loop {
+                    let (forward, command, after) = match prefix_key.as_mut() {
+                        Some(prefix_key) if !detached => prefix_key.split(rest),
+                        _ => (rest.to_vec(), None, &[][..]),
+                    };
+                    rest = after;
+
+                    if !forward.is_empty() {
+                        w_stream
+                            .write_all(&forward)
+                            .await
+                            .context("Failed to write to w_stream")?;
+                        w_stream.flush().await.context("Failed to flush w_stream")?;
+                    }
+
+                    let current = name.lock().expect("name lock poisoned").clone();
+                    if detached {
+                        detach(ctx, Some(SessionSelector::Name(current))).await?;
+                        break 'input;
+                    }
+                    match command {
+                        Some(PrefixCommand::Detach) => {
+                            detach(ctx, Some(SessionSelector::Name(current))).await?;
+                            break 'input;
+                        }
+                        Some(PrefixCommand::Switch) => {
+                            switch.store(true, Ordering::Release);
+                            detach(ctx, Some(SessionSelector::Name(current))).await?;
+                            break 'input;
+                        }
+                        Some(PrefixCommand::Rename) => {
+                            let renamed = rename_attached(&ctx, &mut input, &current).await?;
+                            *name.lock().expect("name lock poisoned") = renamed;
+                            // The prompt was drawn over the status line
+                            refresh.notify_one();
+                        }
+                        Some(PrefixCommand::ToggleStatus) => {
+                            status.fetch_xor(true, Ordering::AcqRel);
+                            layout.notify_one();
+                        }
+                        Some(PrefixCommand::Kill) => {
+                            // Set first, since the session ends before the kill is answered
+                            killed.store(true, Ordering::Release);
+                            if kill_attached(&mut ctx, &mut input, &current).await? {
+                                // The client leaves once the session's end reaches it
+                                std::future::pending::<()>().await;
+                            }
+                            killed.store(false, Ordering::Release);
+                            // The question was drawn over the status line
+                            refresh.notify_one();
+                        }
+                        Some(PrefixCommand::NextWindow) => select_window(&ctx, &current, 1).await?,
+                        Some(PrefixCommand::PrevWindow) => {
+                            select_window(&ctx, &current, -1).await?
+                        }
+                        None => break,
+                    }
+                }
+            }
+            Result::<_, anyhow::Error>::Ok(false)
+        }
+    });
+    let w_abort_handle = w_handle.abort_handle();
+
+    tokio::task::spawn({
+        let (exit_tx, mut exit_rx) = (ctx.exit.0.clone(), ctx.exit.0.subscribe());
+        async move {
+            RPCServer::builder()
+                .add_service(SeshCliServer::new(SeshCliService { exit_tx, reset }))
+                .serve_with_incoming_shutdown(uds_stream, async move {
+                    exit_rx.recv().await.ok();
+                })
+                .await?;
+            w_abort_handle.abort();
+            Result::<_, anyhow::Error>::Ok(())
+        }
+    });
+
+    // Resizes the session when the terminal is resized or the status line is shown or hidden
+    tokio::task::spawn({
+        let name = Arc::clone(&name);
+        let status = Arc::clone(&status);
+        let mut ctx = ctx.clone();
+        async move {
+            let mut signal = signal(SignalKind::window_change())?;
+            loop {
+                let resized = tokio::select! {
+                    _ = ctx.exit.1.recv() => break,
+                    _ = signal.recv() => true,
+                    _ = layout.notified() => false,
+                };
+                // A session attached with --keep-size stays at its size, only the status line moves.
+                // One given a size with --force-size keeps it, unless the status line is toggled
+                if !keep_size && (!resized || FORCED_SIZE.get().is_none()) {
+                    let name = name.lock().expect("name lock poisoned").clone();
+                    ctx.client
+                        .resize_session(SeshResizeRequest {
+                            size: Some(client_size(status.load(Ordering::Acquire))),
+                            session: Some(sesh_resize_request::Session::Name(name)),
+                        })
+                        .await
+                        .context("Failed to resize")?;
+                }
+                refresh.notify_one();
+            }
+            Result::<_, anyhow::Error>::Ok(())
+        }
+    });
+
+    let mut exit_rx = std::mem::replace(&mut ctx.exit.1, ctx.exit.0.subscribe());
+    let mut quit = signal(SignalKind::quit())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut alarm = signal(SignalKind::alarm())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    let terminal_closed = tokio::select! {
+        kind = exit_rx.recv() => Err(kind.unwrap_or(ExitKind::Quit)),
+        _ = quit.recv() => Err(ExitKind::Quit),
+        _ = interrupt.recv() => Err(ExitKind::Quit),
+        _ = terminate.recv() => Err(ExitKind::Quit),
+        _ = alarm.recv() => Err(ExitKind::Quit),
+        _ = hangup.recv() => Ok(()),
+        res = &mut r_handle => match stopped_exit(Stopped::Output(&res)) {
+            ExitKind::Detach => Ok(()),
+            // The server sends the reason right after the session's output stops, so give it a
+            // moment
+            _ => Err(tokio::time::timeout(ENDED_TIMEOUT, exit_rx.recv())
+                .await
+                .ok()
+                .and_then(Result::ok)
+                .unwrap_or(ExitKind::Quit)),
+        },
+        res = &mut w_handle => match stopped_exit(Stopped::Input(&res)) {
+            ExitKind::Detach => Ok(()),
+            exit => Err(exit),
+        },
+    };
+    let exit = match terminal_closed {
+        Ok(()) => {
+            let name = name.lock().expect("name lock poisoned").clone();
+            let exit = disconnected(&mut ctx, &name).await;
+            // Stops the tasks still serving the session
+            ctx.exit.0.send(exit.clone()).ok();
+            exit
+        }
+        Err(exit) => exit,
+    };
+    let exit = match exit {
+        ExitKind::Ended(Ending::Killed) | ExitKind::Quit if killed.load(Ordering::Acquire) => {
+            ExitKind::Killed
+        }
+        exit => exit,
+    };
+
+    tokio::fs::remove_file(&client_server_sock).await.ok();
+    // Reads of the terminal are left where they are, so nothing typed after this is lost
+    w_handle.abort();
+    r_handle.abort();
+    if status.load(Ordering::Acquire) {
+        // Give the scrolling region back, or it would outlive the client on the main screen
+        let rows = termion::terminal_size().map(|s| s.1).unwrap_or(24);
+        let mut output = tokio::io::stdout();
+        output.write_all(status::clear(rows).as_bytes()).await.ok();
+        output.flush().await.ok();
+    }
+    if switch.load(Ordering::Acquire) {
+        return Ok(ExitKind::Switch);
+    }
+    Ok(exit)
+}
+
+/// One of the tasks relaying between the terminal and a session, once it has stopped
+enum Stopped<'a> {
+    /// Writing the session's output to the terminal
+    Output(&'a Result<Result<()>, JoinError>),
+    /// Reading input from the terminal, true if the terminal was closed
+    Input(&'a Result<Result<bool>, JoinError>),
+}
+
+/// How the client leaves once a relay task has stopped. The terminal going away detaches, so
+/// the session keeps running. Anything else quits, though output stopping for the session's end
+/// is told apart by the reason the server sends.
+fn stopped_exit(stopped: Stopped) -> ExitKind {
+    match stopped {
+        Stopped::Input(Ok(Ok(true))) => ExitKind::Detach,
+        Stopped::Output(Ok(Err(e))) | Stopped::Input(Ok(Err(e))) if terminal_gone(e) => {
+            ExitKind::Detach
+        }
+        _ => ExitKind::Quit,
+    }
+}
+
+/// Whether an error came from the terminal going away, which fails reads and writes on it
+/// with EIO
+fn terminal_gone(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.raw_os_error() == Some(libc::EIO))
+    })
+}
+
+/// Seconds a session killed from the client, or for its terminal going away, is given to exit,
+/// as with `sesh kill`
+const KILL_GRACE: u64 = 5;
+
+/// Leaves a session whose terminal went away, like when its window was closed or an ssh
+/// connection dropped. The session is the server's, so it keeps running detached, unless
+/// $SESH_ON_DISCONNECT is `kill`.
+async fn disconnected(ctx: &mut Ctx, name: &str) -> ExitKind {
+    if std::env::var("SESH_ON_DISCONNECT").is_ok_and(|action| action == "kill") {
+        let session = SessionSelector::Name(name.to_owned());
+        if kill_one(ctx, &session, KILL_GRACE, None).await.is_ok() {
+            return ExitKind::Quit;
+        }
+    }
+    ExitKind::Detach
+}
+
+/// Binds the socket the server sends (server -> client) requests to while a client is connected
+/// to the session on `socket`, replacing one left behind by an earlier client
+async fn bind_client_socket(socket: &str, pid: i32) -> Result<(PathBuf, UnixListenerStream)> {
     let sock = PathBuf::from(&socket);
     let sock_dir = sock
         .parent()
@@ -118,253 +1143,1078 @@ async fn exec_session(
         "Failed to bind listener to {}",
         &client_server_sock.display()
     ))?;
-    let uds_stream = UnixListenerStream::new(uds);
+    Ok((client_server_sock, UnixListenerStream::new(uds)))
+}
+
+/// Streams a session's output to stdout as it is, without taking over the terminal, until
+/// interrupted or the session ends. Nothing is read from stdin, and the session is left at its
+/// size. The session isn't attached to, so clients can still attach while it's followed.
+pub async fn follow(mut ctx: Ctx, session: SessionSelector) -> Result<Outcome> {
+    use sesh_proto::sesh_follow_request::Session::*;
+    use sesh_proto::sesh_follow_response::Event;
+    let session = ctx.select(&session, Id, Name).await?;
+    let request = without_deadline(sesh_proto::SeshFollowRequest {
+        session: Some(session),
+    });
+    let mut stream = ctx
+        .client
+        .follow_session(request)
+        .await
+        .map_err(|e| match SeshError::from_status(&e) {
+            Some(error) => anyhow::Error::from(error),
+            None => anyhow::anyhow!("{}", e.message()),
+        })?
+        .into_inner();
+    let Some(Event::Started(started)) = stream.message().await?.and_then(|m| m.event) else {
+        return Err(anyhow::anyhow!(
+            "Server did not start following the session"
+        ));
+    };
+
+    let mut output = tokio::io::stdout();
+    let relay = async {
+        while let Some(message) = stream.message().await? {
+            match message.event {
+                Some(Event::Output(bytes)) => {
+                    output
+                        .write_all(&bytes)
+                        .await
+                        .context("Could not write output")?;
+                    output.flush().await.context("Could not flush output")?;
+                }
+                Some(Event::Ended(ended)) => return Ok(ExitKind::Ended(Ending::from(&ended))),
+                _ => (),
+            }
+        }
+        Result::<_, anyhow::Error>::Ok(ExitKind::Quit)
+    };
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    let exit = tokio::select! {
+        _ = interrupt.recv() => ExitKind::Quit,
+        _ = terminate.recv() => ExitKind::Quit,
+        _ = hangup.recv() => ExitKind::Quit,
+        // The stream also ends when the server shuts down
+        res = relay => res.unwrap_or(ExitKind::Quit),
+    };
+
+    // Kept off stdout, where the session's output may be piped
+    let (message, code) = match exit {
+        ExitKind::Ended(ending) => (
+            ended(ending, &started.name, started.start_time),
+            ending.code(),
+        ),
+        _ => (success!("[stopped following: {}]", started.name), 0),
+    };
+    Ok(Outcome::Followed(message, code))
+}
+
+/// What the session `sesh bench` sends its input through runs: cat, on a terminal that passes
+/// everything through as it is. It says when it's ready, since input sent before `stty` has run
+/// would be echoed.
+const BENCH_SCRIPT: &str = "stty raw -echo && printf '[bench ready]' && exec cat";
 
-    let (mut r_stream, mut w_stream) = UnixStream::connect(&socket)
+/// Starts a session running cat, sends `bytes` of input through it and reads them back, then
+/// shows how fast that was and how much CPU time seshd used
+pub async fn bench(mut ctx: Ctx, bytes: u64) -> Result<Outcome> {
+    let server_pid = ctx
+        .client
+        .server_info(sesh_proto::ServerInfoRequest {})
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not get server info: {}", e.message()))?
+        .into_inner()
+        .pid;
+    let started = ctx
+        .client
+        .start_session(SeshStartRequest {
+            name: "bench".to_owned(),
+            program: "/bin/sh".to_owned(),
+            args: vec!["-c".to_owned(), BENCH_SCRIPT.to_owned()],
+            pwd: "/".to_owned(),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not start session: {}", e.message()))?
+        .into_inner();
+    let res = pump(&mut ctx, &started.name, bytes, server_pid).await;
+    ctx.client
+        .kill_session(sesh_proto::SeshKillRequest {
+            session: Some(Session::Name(started.name)),
+            grace_ms: 0,
+            signal: 0,
+        })
+        .await
+        .ok();
+    res
+}
+
+/// Attaches to the bench session like `sesh follow` does, and times `bytes` going through it
+async fn pump(ctx: &mut Ctx, name: &str, bytes: u64, server_pid: i32) -> Result<Outcome> {
+    let res = request_attach(ctx, &SessionSelector::Name(name.to_owned()), false, true).await?;
+    let (client_server_sock, uds_stream) = bind_client_socket(&res.socket, res.pid).await?;
+    // Answers the server's keepalive pings, which a long run would otherwise be disconnected by
+    let (exit_tx, mut exit_rx) = (ctx.exit.0.clone(), ctx.exit.0.subscribe());
+    let serving = tokio::task::spawn(async move {
+        RPCServer::builder()
+            .add_service(SeshCliServer::new(SeshCliService {
+                exit_tx,
+                reset: Arc::new(Notify::new()),
+            }))
+            .serve_with_incoming_shutdown(uds_stream, async move {
+                exit_rx.recv().await.ok();
+            })
+            .await
+    });
+    let (mut r_stream, mut w_stream) = UnixStream::connect(&res.socket)
         .await
         .context("Could not connect to socket stream")?
         .into_split();
 
-    // Reads process output from the server and writes it to the terminal
-    let mut r_handle = tokio::task::spawn({
-        let exit = ctx.exit.0.subscribe();
-        async move {
-            let mut packet = [0; 4096];
-            while exit.is_empty() {
-                let bytes = r_stream.read(&mut packet).await?;
-                if bytes == 0 {
-                    break;
-                }
-                output
-                    .write_all(&packet[..bytes])
-                    .await
-                    .context("Could not write tty_output")?;
-                output.flush().await.context("Could not flush tty_output")?;
+    let mut packet = vec![0; 1 << 16];
+    let mut output = Vec::new();
+    let ready = async {
+        while !output.ends_with(b"[bench ready]") {
+            let n = r_stream.read(&mut packet).await?;
+            if n == 0 {
+                anyhow::bail!("The session ended before it was ready");
             }
-            Result::<_, anyhow::Error>::Ok(())
+            output.extend_from_slice(&packet[..n]);
+        }
+        Ok(())
+    };
+    tokio::time::timeout(Duration::from_secs(5), ready)
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out waiting for the session to be ready"))??;
+
+    let cpu_before = sesh_shared::sys::process_cpu_time(server_pid);
+    let start = std::time::Instant::now();
+    // Written alongside the reads, since the pty only holds a few KiB that cat hasn't read yet.
+    // The write half is kept until everything is read back, as closing it would detach.
+    let writer = tokio::task::spawn(async move {
+        let chunk = [b'x'; 1 << 16];
+        let mut left = bytes;
+        while left > 0 {
+            let n = left.min(chunk.len() as u64) as usize;
+            w_stream.write_all(&chunk[..n]).await?;
+            left -= n as u64;
         }
+        Result::<_, std::io::Error>::Ok(w_stream)
     });
+    let mut received = 0;
+    while received < bytes {
+        let n = r_stream.read(&mut packet).await?;
+        if n == 0 {
+            anyhow::bail!("The session closed the connection after {} bytes", received);
+        }
+        received += n as u64;
+    }
+    let elapsed = start.elapsed();
+    let cpu = match (cpu_before, sesh_shared::sys::process_cpu_time(server_pid)) {
+        (Ok(before), Ok(after)) => {
+            format!("seshd used {:.2}s of CPU", (after - before).as_secs_f64())
+        }
+        (Err(e), _) | (_, Err(e)) => format!("seshd's CPU time is unknown: {}", e),
+    };
+    writer.await??;
 
-    // Reads terminal input and sends it to the server to be handled by the process.
-    let mut w_handle = tokio::task::spawn({
-        let ctx = ctx.clone();
-        let name = name.clone();
-        async move {
-            let mut input = tokio::io::stdin();
-            while ctx.exit.1.is_empty() {
-                let mut packet = [0; 4096];
+    ctx.exit.0.send(ExitKind::Quit).ok();
+    serving.abort();
+    tokio::fs::remove_file(&client_server_sock).await.ok();
+    let mib = bytes as f64 / (1 << 20) as f64;
+    Ok(Outcome::Status(success!(
+        "[bench: {:.1} MiB in {:.2}s, {:.1} MiB/s, {}]",
+        mib,
+        elapsed.as_secs_f64(),
+        mib / elapsed.as_secs_f64(),
+        cpu
+    )))
+}
 
-                let nbytes = input
-                    .read(&mut packet)
-                    .await
-                    .context("Failed to read tty_input")?;
-                if nbytes == 0 {
-                    break;
-                }
-                let read = &packet[..nbytes];
+/// How the client left an attached session, and the session it was attached to by then
+struct Left {
+    exit: ExitKind,
+    name: String,
+    /// When the session started, in unix milliseconds, as the server has it
+    start_time: i64,
+}
+
+/// Turns the way the client left a session into the message to print and the code to exit with
+fn exited(left: Left) -> Result<Outcome> {
+    match left.exit {
+        ExitKind::Quit => Ok(Outcome::Left(success!("[exited: {}]", left.name), 0)),
+        // Switching is handled while attached, so it only ends the client if it was given up
+        ExitKind::Detach | ExitKind::Switch => Ok(Outcome::Left(
+            success!("[detached: {}]", left.name),
+            EXIT_DETACHED,
+        )),
+        ExitKind::Ended(ending) => Ok(Outcome::Left(
+            ended(ending, &left.name, left.start_time),
+            ending.code(),
+        )),
+        ExitKind::Killed => {
+            let ran = table::duration(left.start_time, chrono::Utc::now().timestamp_millis());
+            Ok(Outcome::Left(
+                success!("[killed: {}, ran {}]", left.name, ran),
+                Ending::Killed.code(),
+            ))
+        }
+    }
+}
+
+/// The message for a session the server ended, like `[exited: build, ran 3h 12m, status 0]`.
+/// How long it ran is counted from the server's start time, not from when the client attached.
+fn ended(ending: Ending, name: &str, start_time: i64) -> String {
+    let ran = table::duration(start_time, chrono::Utc::now().timestamp_millis());
+    match ending {
+        Ending::Killed => success!("[killed: {}, ran {}, by another client]", name, ran),
+        Ending::Code(code) => success!("[exited: {}, ran {}, status {}]", name, ran, code),
+        Ending::Signal(sig) => success!(
+            "[exited: {}, ran {}, killed by {}]",
+            name,
+            ran,
+            signal::name(sig)
+        ),
+        Ending::Exited => success!("[exited: {}, ran {}]", name, ran),
+    }
+}
+
+/// Asks the server to attach this client to a session
+async fn request_attach(
+    ctx: &mut Ctx,
+    session: &SessionSelector,
+    status: bool,
+    keep_size: bool,
+) -> Result<SeshAttachResponse> {
+    use sesh_proto::sesh_attach_request::Session::*;
+    let session_resolved = ctx.select(session, Id, Name).await?;
+    let req = tonic::Request::new(sesh_proto::SeshAttachRequest {
+        session: Some(session_resolved),
+        size: Some(client_size(status)),
+        keep_size,
+    });
+    let res =
+        ctx.client
+            .attach_session(req)
+            .await
+            .map_err(|e| match SeshError::from_status(&e) {
+                Some(error) => anyhow::Error::from(error),
+                None => anyhow::anyhow!("{}", e.message()),
+            })?;
+    Ok(res.into_inner())
+}
+
+/// How to attach to a session, as given to `sesh attach`
+#[derive(Default)]
+pub struct AttachOptions {
+    /// Whether to start a session when none of the given ones exist
+    pub create: bool,
+    /// Program and args to start a created session with, instead of the shell
+    pub command: Vec<String>,
+    pub no_alt_screen: bool,
+    pub status: bool,
+    pub keep_size: bool,
+}
+
+/// Attaches to the first of the given sessions that the server can attach to.
+/// If none can be and `create` is set, a session named after the first one is started instead,
+/// running `command` if it isn't empty.
+pub async fn attach(
+    mut ctx: Ctx,
+    sessions: Vec<SessionSelector>,
+    options: AttachOptions,
+) -> Result<Outcome> {
+    let AttachOptions {
+        create,
+        command,
+        no_alt_screen,
+        status,
+        keep_size,
+    } = options;
+    let mut errors = Vec::new();
+    let mut attached = None;
+    // Whether every one of the sessions is missing, rather than failing to attach for another
+    // reason, like being attached elsewhere
+    let mut missing = true;
+    for session in &sessions {
+        match request_attach(&mut ctx, session, status, keep_size).await {
+            Ok(res) => {
+                attached = Some(res);
+                break;
+            }
+            Err(e) => {
+                missing &= e.downcast_ref() == Some(&SeshError::SessionNotFound);
+                errors.push(format!("Could not attach to {}: {}", session, e));
+            }
+        }
+    }
+    let Some(res) = attached else {
+        if create && missing {
+            let mut command = command.into_iter();
+            let options = StartOptions {
+                name: sessions.into_iter().next().and_then(SessionSelector::name),
+                program: command.next(),
+                args: command.collect(),
+                attach: true,
+                no_alt_screen,
+                status,
+                ..Default::default()
+            };
+            return start(ctx, options).await;
+        }
+        return Err(anyhow::anyhow!("{}", errors.join("\n")));
+    };
+
+    let view = View {
+        alt_screen: !no_alt_screen,
+        status,
+        keep_size,
+    };
+    let exit = exec_session(ctx, res, view).await?;
+    exited(exit)
+}
+
+/// What happened to each session a command was given
+#[derive(Default)]
+pub(crate) struct Report {
+    /// Status messages for the sessions it worked for
+    pub(crate) done: Vec<String>,
+    /// Errors for the sessions it failed for
+    pub(crate) failed: Vec<String>,
+}
+
+impl Report {
+    pub(crate) fn outcome(self) -> Outcome {
+        let done = self.done.join("\n");
+        if self.failed.is_empty() {
+            Outcome::Status(done)
+        } else {
+            Outcome::Partial(done, self.failed.join("\n"))
+        }
+    }
+}
+
+/// How a session the server resolved a request to is named in status messages, as in
+/// `3: cargo-watch (pid 4242)`
+pub(crate) fn describe(id: Option<u64>, name: &str, pid: i32) -> String {
+    let id = id.map(|id| format!("{}: ", id)).unwrap_or_default();
+    if pid > 0 {
+        format!("{}{} (pid {})", id, name, pid)
+    } else {
+        format!("{}{}", id, name)
+    }
+}
+
+/// Sends a detach session request to the server, and returns the status message for it
+pub async fn detach(mut ctx: Ctx, session: Option<SessionSelector>) -> Result<String> {
+    use sesh_proto::sesh_detach_request::Session::*;
+    let session = match session {
+        Some(session) => ctx.select(&session, Id, Name).await?,
+        None => ctx.select_current(Id, Name).await?,
+    };
+    let request = tonic::Request::new(sesh_proto::SeshDetachRequest {
+        session: Some(session),
+    });
+    let response = ctx
+        .client
+        .detach_session(request)
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e.message()))?
+        .into_inner();
+    let message = detached(response)?;
+    ctx.exit.0.send(ExitKind::Detach)?;
+
+    Ok(message)
+}
+
+/// Handles the server's answer to a detach, and returns the status message for it
+pub(crate) fn detached(response: sesh_proto::SeshDetachResponse) -> Result<String> {
+    use sesh_proto::sesh_detach_response::Error;
+    match (response.success, response.error()) {
+        (true, _) => (),
+        (false, Error::NotAttached) => anyhow::bail!("No client is attached to it"),
+        (false, Error::ClientUnreachable) => anyhow::bail!("Its client didn't answer"),
+        (false, Error::NotFound | Error::None) => anyhow::bail!("Session not found"),
+    }
+    run_on_detach(&response.name);
+    Ok(success!(
+        "[detached {}]",
+        describe(Some(response.id), &response.name, response.pid)
+    ))
+}
+
+/// Has the client attached to a session reset its terminal
+pub async fn reset(mut ctx: Ctx, session: Option<SessionSelector>) -> Result<Outcome> {
+    use sesh_proto::sesh_reset_request::Session::*;
+    let session = match session {
+        Some(session) => ctx.select(&session, Id, Name).await?,
+        None => ctx.select_current(Id, Name).await?,
+    };
+    let request = tonic::Request::new(sesh_proto::SeshResetRequest {
+        session: Some(session),
+    });
+    let response = ctx
+        .client
+        .reset_session(request)
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e.message()))?
+        .into_inner();
+    Ok(Outcome::Status(was_reset(response)?))
+}
+
+/// Handles the server's answer to a reset, and returns the status message for it
+pub(crate) fn was_reset(response: sesh_proto::SeshResetResponse) -> Result<String> {
+    if !response.attached {
+        anyhow::bail!("No client is attached to {}", response.name);
+    }
+    Ok(success!("[reset {}]", response.name))
+}
+
+/// Runs $SESH_ON_DETACH with `sh -c` after a session was detached, with $SESH_NAME set to the
+/// session's name. It runs in the background, in a process group of its own, so the client
+/// doesn't wait for it and it isn't stopped along with the client.
+fn run_on_detach(name: &str) {
+    let Some(command) = std::env::var_os("SESH_ON_DETACH").filter(|c| !c.is_empty()) else {
+        return;
+    };
+    let spawned = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("SESH_NAME", name)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn();
+    if let Err(e) = spawned {
+        eprintln!("{}", error!("Could not run SESH_ON_DETACH: {}", e));
+    }
+}
+
+/// Detaches each of the given sessions from its client, or the current session if none are given
+pub async fn detach_sessions(ctx: Ctx, sessions: Vec<SessionSelector>) -> Result<Outcome> {
+    if sessions.is_empty() {
+        detach(ctx, None).await?;
+        return Ok(Outcome::Done);
+    }
+    let mut report = Report::default();
+    for session in sessions {
+        match detach(ctx.renew(), Some(session.clone())).await {
+            Ok(message) => report.done.push(message),
+            Err(e) => report
+                .failed
+                .push(error!("Could not detach {}: {}", session, e)),
+        }
+    }
+    Ok(report.outcome())
+}
+
+/// Sends a kill session request to the server, and returns the status message for it
+pub(crate) async fn kill_one(
+    ctx: &mut Ctx,
+    session: &SessionSelector,
+    grace: u64,
+    signal: Option<i32>,
+) -> Result<String> {
+    let selected = ctx.select(session, Session::Id, Session::Name).await?;
+    let mut request = tonic::Request::new(sesh_proto::SeshKillRequest {
+        session: Some(selected),
+        grace_ms: grace.saturating_mul(1000),
+        signal: signal.unwrap_or(0),
+    });
+    // The server waits out the grace period before answering
+    request.set_timeout(ctx.timeout + Duration::from_secs(grace));
+    let response = ctx
+        .client
+        .kill_session(request)
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e.message()))?
+        .into_inner();
+    killed(response, signal)
+}
+
+/// Handles the server's answer to a kill, and returns the status message for it
+pub(crate) fn killed(
+    response: sesh_proto::SeshKillResponse,
+    signal: Option<i32>,
+) -> Result<String> {
+    use sesh_proto::sesh_kill_response::Error;
+    let session = if response.dead {
+        format!(
+            "dead session {}",
+            describe(None, &response.name, response.pid)
+        )
+    } else {
+        describe(Some(response.id), &response.name, response.pid)
+    };
+    match (response.success, response.error(), signal) {
+        (true, _, Some(signal)) => Ok(success!("[sent {} to {}]", signal::name(signal), session)),
+        (true, _, None) => Ok(success!("[killed {}]", session)),
+        (false, Error::NotFound, _) => Err(anyhow::anyhow!("Session not found")),
+        (false, Error::SignalFailed, _) => {
+            Err(anyhow::anyhow!("Could not signal process {}", response.pid))
+        }
+        (false, Error::None, Some(_)) => Err(anyhow::anyhow!("Could not signal process")),
+        (false, Error::None, None) => Err(anyhow::anyhow!("Could not kill process")),
+    }
+}
+
+/// Adds the sessions with a tag to the ones given by selector
+pub(crate) async fn with_tagged(
+    ctx: &mut Ctx,
+    mut sessions: Vec<SessionSelector>,
+    tag: &str,
+) -> Result<Vec<SessionSelector>> {
+    let request = tonic::Request::new(sesh_proto::SeshListRequest {
+        dead: false,
+        filter: None,
+    });
+    let tagged = ctx
+        .client
+        .list_sessions(request)
+        .await?
+        .into_inner()
+        .sessions
+        .into_iter()
+        .filter(|s| s.tags.iter().any(|t| t == tag))
+        .map(|s| SessionSelector::Name(s.name))
+        .collect::<Vec<_>>();
+    if tagged.is_empty() && sessions.is_empty() {
+        return Err(anyhow::anyhow!("No sessions are tagged {}", tag));
+    }
+    sessions.extend(tagged);
+    Ok(sessions)
+}
+
+/// Whether a selector picks out the given session
+fn selects(selector: &SessionSelector, session: &SeshInfo) -> bool {
+    match selector {
+        SessionSelector::Id(id) => session.id == *id as u64,
+        SessionSelector::Name(name) => session.name == *name,
+        SessionSelector::Pid(pid) => session.pid == *pid,
+    }
+}
+
+/// Shows the note of each session that has one and asks before killing it, since notes are
+/// often warnings. Returns the sessions to kill, and reports the rest as not killed.
+async fn confirm_noted(
+    ctx: &mut Ctx,
+    sessions: Vec<SessionSelector>,
+    report: &mut Report,
+) -> Result<Vec<SessionSelector>> {
+    let request = tonic::Request::new(sesh_proto::SeshListRequest {
+        dead: false,
+        filter: None,
+    });
+    let live = ctx
+        .client
+        .list_sessions(request)
+        .await?
+        .into_inner()
+        .sessions;
+    let mut confirmed = Vec::new();
+    for session in sessions {
+        let Some(note) = live
+            .iter()
+            .find(|s| selects(&session, s))
+            .map(|s| &s.note)
+            .filter(|note| !note.is_empty())
+        else {
+            confirmed.push(session);
+            continue;
+        };
+        let kill = dialoguer::Confirm::with_theme(&theme::ColorfulTheme::default())
+            .with_prompt(format!("{} has a note: {}\nKill it anyway?", session, note))
+            .default(false)
+            .interact_opt();
+        if let Ok(Some(true)) = kill {
+            confirmed.push(session);
+        } else {
+            report
+                .failed
+                .push(error!("Did not kill {}: not confirmed", session));
+        }
+    }
+    Ok(confirmed)
+}
+
+/// Kills each of the given sessions, and reports how each went.
+/// They're killed at the same time, so one that takes its whole grace period doesn't hold up
+/// the rest.
+pub async fn kill(
+    mut ctx: Ctx,
+    mut sessions: Vec<SessionSelector>,
+    tag: Option<String>,
+    grace: u64,
+    signal: Option<String>,
+) -> Result<Outcome> {
+    let signal = signal.as_deref().map(signal::parse).transpose()?;
+    if let Some(tag) = tag {
+        sessions = with_tagged(&mut ctx, sessions, &tag).await?;
+    }
+    let mut report = Report::default();
+    // Only someone at a terminal can answer, and a signal leaves the session running
+    let sessions = if signal.is_none() && std::io::stdin().is_terminal() {
+        confirm_noted(&mut ctx, sessions, &mut report).await?
+    } else {
+        sessions
+    };
+    let kills = sessions
+        .into_iter()
+        .map(|session| {
+            let mut ctx = ctx.renew();
+            tokio::task::spawn(async move {
+                let res = kill_one(&mut ctx, &session, grace, signal).await;
+                (session, res)
+            })
+        })
+        .collect::<Vec<_>>();
+    for kill in kills {
+        match kill.await? {
+            (_, Ok(message)) => report.done.push(message),
+            (session, Err(e)) => report
+                .failed
+                .push(error!("Could not kill {}: {}", session, e)),
+        }
+    }
+    Ok(report.outcome())
+}
+
+/// Sends a signal to a session's process group
+pub async fn send_signal(
+    mut ctx: Ctx,
+    session: SessionSelector,
+    signal: String,
+) -> Result<Outcome> {
+    let signal = signal::parse(&signal)?;
+    use sesh_signal_request::Session::*;
+    let selected = ctx.select(&session, Id, Name).await?;
+    let request = tonic::Request::new(sesh_proto::SeshSignalRequest {
+        session: Some(selected),
+        signal,
+    });
+    ctx.client
+        .signal_session(request)
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not signal session: {}", e.message()))?;
+    Ok(Outcome::Status(success!(
+        "[sent {} to {}]",
+        signal::name(signal),
+        session
+    )))
+}
 
-                // Alt-\
-                // TODO: Make this configurable
-                if nbytes >= 2 && read[0] == 27 && read[1] == 92 {
-                    detach(ctx, Some(SessionSelector::Name(name))).await?;
-                    break;
-                }
+/// Size of the chunks stdin is sent to the server in
+const PASTE_CHUNK: usize = 32 * 1024;
+/// Markers around a bracketed paste
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
 
-                w_stream
-                    .write_all(read)
-                    .await
-                    .context("Failed to write to w_stream")?;
-                w_stream.flush().await.context("Failed to flush w_stream")?;
+/// Streams stdin into a session until it ends
+pub async fn paste(mut ctx: Ctx, session: SessionSelector, bracketed: bool) -> Result<Outcome> {
+    use sesh_proto::sesh_paste_request::Session::*;
+    let selector = ctx.select(&session, Id, Name).await?;
+    // Only a few chunks are read ahead, so a session that's slow to take them slows down reading
+    // stdin instead of it all being buffered
+    let (tx, rx) = tokio::sync::mpsc::channel(2);
+    let reader = tokio::task::spawn(async move {
+        let chunk = |data| SeshPasteRequest {
+            session: None,
+            data,
+        };
+        let first = SeshPasteRequest {
+            session: Some(selector),
+            data: if bracketed {
+                PASTE_START.to_vec()
+            } else {
+                Vec::new()
+            },
+        };
+        let mut read = 0;
+        if tx.send(first).await.is_err() {
+            // The server gave up on the paste, and the response says why
+            return Ok(read);
+        }
+        let mut stdin = tokio::io::stdin();
+        loop {
+            let mut data = vec![0; PASTE_CHUNK];
+            let n = stdin.read(&mut data).await?;
+            if n == 0 {
+                break;
+            }
+            read += n;
+            data.truncate(n);
+            if tx.send(chunk(data)).await.is_err() {
+                return Ok(read);
             }
-            Result::<_, anyhow::Error>::Ok(())
         }
-    });
-    let w_abort_handle = w_handle.abort_handle();
-
-    tokio::task::spawn({
-        let (exit_tx, mut exit_rx) = (ctx.exit.0.clone(), ctx.exit.0.subscribe());
-        async move {
-            RPCServer::builder()
-                .add_service(SeshCliServer::new(SeshCliService { exit_tx }))
-                .serve_with_incoming_shutdown(uds_stream, async move {
-                    exit_rx.recv().await.ok();
-                })
-                .await?;
-            w_abort_handle.abort();
-            Result::<_, anyhow::Error>::Ok(())
+        if bracketed {
+            tx.send(chunk(PASTE_END.to_vec())).await.ok();
         }
+        std::io::Result::Ok(read)
     });
+    ctx.client
+        .paste_session(without_deadline(ReceiverStream::new(rx)))
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not paste into session: {}", e.message()))?;
+    let read = reader
+        .await?
+        .context("Failed to read stdin, only part of it was pasted")?;
+    Ok(Outcome::Status(success!(
+        "[pasted {} bytes into {}]",
+        read,
+        session
+    )))
+}
 
-    tokio::task::spawn({
-        let name = name.clone();
-        let mut ctx = ctx.clone();
-        async move {
-            let mut signal = signal(SignalKind::window_change())?;
-            loop {
-                tokio::select! {
-                    _ = ctx.exit.1.recv() => break,
-                    _ = signal.recv() => {
-                        let size = {
-                            let s = termion::terminal_size().unwrap_or((80, 24));
-                            WinSize {
-                                rows: s.1 as u32,
-                                cols: s.0 as u32,
-                            }
-                        };
-                        ctx.client.resize_session(SeshResizeRequest {
-                            size: Some(size),
-                            session: Some(sesh_resize_request::Session::Name(name.clone())),
-                        }).await.context("Failed to resize")?;
-                    }
-                }
-            }
-            Result::<_, anyhow::Error>::Ok(())
-        }
+/// Sends a rename session request to the server, and handles the response
+pub async fn rename(mut ctx: Ctx, session: SessionSelector, new_name: String) -> Result<Outcome> {
+    use sesh_proto::sesh_rename_request::Session::*;
+    let selected = ctx.select(&session, Id, Name).await?;
+    let request = tonic::Request::new(sesh_proto::SeshRenameRequest {
+        session: Some(selected),
+        new_name,
     });
+    let response = ctx
+        .client
+        .rename_session(request)
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not rename session: {}", e.message()))?;
+    Ok(Outcome::Status(success!(
+        "[renamed {} to {}]",
+        session,
+        response.into_inner().name
+    )))
+}
 
-    let mut exit_rx = ctx.exit.1;
-    let mut quit = signal(SignalKind::quit())?;
-    let mut interrupt = signal(SignalKind::interrupt())?;
-    let mut terminate = signal(SignalKind::terminate())?;
-    let mut alarm = signal(SignalKind::alarm())?;
-    let exit = tokio::select! {
-        kind = exit_rx.recv() => kind.unwrap_or(ExitKind::Quit),
-        _ = quit.recv() => ExitKind::Quit,
-        _ = interrupt.recv() => ExitKind::Quit,
-        _ = terminate.recv() => ExitKind::Quit,
-        _ = alarm.recv() => ExitKind::Quit,
-        _ = &mut r_handle => ExitKind::Quit,
-        _ = &mut w_handle => ExitKind::Quit,
-    };
-
-    tokio::fs::remove_file(&client_server_sock).await.ok();
-    // the write handle will block if it's not aborted
-    w_handle.abort();
-    r_handle.abort();
-    Ok(exit)
+/// Gives the sessions ids counting up from 0, and shows those whose id changed
+pub async fn renumber(mut ctx: Ctx) -> Result<Outcome> {
+    let renumbered = ctx
+        .client
+        .renumber_sessions(sesh_proto::SeshRenumberRequest {})
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not renumber sessions: {}", e.message()))?
+        .into_inner()
+        .renumbered;
+    if renumbered.is_empty() {
+        return Ok(Outcome::Status(success!("[ids already in order]")));
+    }
+    let lines = renumbered
+        .iter()
+        .map(|s| success!("[renumbered {} from {} to {}]", s.name, s.old_id, s.new_id))
+        .collect::<Vec<_>>();
+    Ok(Outcome::Status(lines.join("\n")))
 }
 
-/// Sends an attach session request to the server, and handles the response
-pub async fn attach(
-    mut ctx: Ctx,
-    session: SessionSelector,
-    create: bool,
-) -> Result<Option<String>> {
-    use sesh_proto::sesh_attach_request::Session::*;
-    let session_resolved = match &session {
-        SessionSelector::Id(id) => Id(*id as u64),
-        SessionSelector::Name(name) => Name(name.clone()),
-    };
-    let size = {
-        let s = termion::terminal_size().unwrap_or((80, 24));
-        WinSize {
-            rows: s.1 as u32,
-            cols: s.0 as u32,
-        }
+/// Removes sessions whose process is gone and sockets left behind, and shows what was removed,
+/// or would be with `dry_run`
+pub async fn prune(mut ctx: Ctx, dry_run: bool) -> Result<Outcome> {
+    let pruned = ctx
+        .client
+        .prune_sessions(sesh_proto::SeshPruneRequest { dry_run })
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not prune sessions: {}", e.message()))?
+        .into_inner();
+    if pruned.sessions.is_empty() && pruned.files.is_empty() {
+        return Ok(Outcome::Status(success!("[nothing to prune]")));
+    }
+    let (pruned_verb, removed_verb) = if dry_run {
+        ("would prune", "would remove")
+    } else {
+        ("pruned", "removed")
     };
-    let req = tonic::Request::new(sesh_proto::SeshAttachRequest {
-        session: Some(session_resolved),
-        size: Some(size),
+    let lines = pruned
+        .sessions
+        .iter()
+        .map(|s| success!("[{} {} (id {}, pid {})]", pruned_verb, s.name, s.id, s.pid))
+        .chain(
+            pruned
+                .files
+                .iter()
+                .map(|file| success!("[{} {}]", removed_verb, file)),
+        )
+        .collect::<Vec<_>>();
+    Ok(Outcome::Status(lines.join("\n")))
+}
+
+/// Sets or removes a session's note
+pub async fn note(mut ctx: Ctx, session: SessionSelector, note: String) -> Result<Outcome> {
+    use sesh_proto::sesh_note_request::Session::*;
+    let selected = ctx.select(&session, Id, Name).await?;
+    let removed = note.is_empty();
+    let request = tonic::Request::new(sesh_proto::SeshNoteRequest {
+        session: Some(selected),
+        note,
     });
-    let res = match ctx.client.attach_session(req).await {
-        Ok(res) => res.into_inner(),
-        Err(_) if create => return start(ctx, session.name(), None, vec![], true).await,
-        Err(e) => return Err(anyhow::anyhow!("Session not found: {e}")),
-    };
+    ctx.client
+        .note_session(request)
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not set note: {}", e.message()))?;
+    Ok(Outcome::Status(noted(&session, removed)))
+}
 
-    match exec_session(ctx, res.pid, res.socket, res.name, res.program).await? {
-        ExitKind::Quit => Ok(Some(success!("[exited]"))),
-        ExitKind::Detach => Ok(Some(success!("[detached]"))),
+/// Status message for a note that was set or removed
+pub(crate) fn noted(session: &SessionSelector, removed: bool) -> String {
+    if removed {
+        success!("[removed note from {}]", session)
+    } else {
+        success!("[set note on {}]", session)
     }
 }
 
-/// Sends a detach session request to the server, and handles the response
-pub async fn detach(mut ctx: Ctx, session: Option<SessionSelector>) -> Result<Option<String>> {
-    use sesh_proto::sesh_detach_request::Session::*;
-    let session = match session {
-        Some(SessionSelector::Id(id)) => Id(id as u64),
-        Some(SessionSelector::Name(name)) => Name(name),
-        None => {
-            let Ok(current) = std::env::var("SESH_NAME") else {
-                return Err(anyhow::anyhow!("No session name found in environment"));
-            };
-            Name(current)
-        }
-    };
-    let request = tonic::Request::new(sesh_proto::SeshDetachRequest {
-        session: Some(session),
-    });
-    let _response = ctx.client.detach_session(request).await?;
-    ctx.exit.0.send(ExitKind::Detach)?;
-
-    Ok(None)
+/// What to start a session with, as given to `sesh start`
+#[derive(Default)]
+pub struct StartOptions {
+    pub name: Option<String>,
+    pub program: Option<String>,
+    pub args: Vec<String>,
+    /// Whether to attach to the session once it's started
+    pub attach: bool,
+    pub no_alt_screen: bool,
+    pub status: bool,
+    pub login: bool,
+    pub term: Option<String>,
+    pub replay_input: Option<PathBuf>,
+    /// Session to start in the working directory of
+    pub here: Option<SessionSelector>,
+    pub note: Option<String>,
+    pub tags: Vec<String>,
+    pub title: Option<String>,
+    pub script: Option<PathBuf>,
+    pub expand: bool,
+    pub notify: NotifyArgs,
+    pub keep: bool,
+    /// Names and commands of windows to start alongside the program
+    pub windows: Vec<(String, String)>,
+    pub termios: TermiosArgs,
 }
 
-/// Sends a list sessions request to the server, and handles the response
-pub async fn kill(mut ctx: Ctx, session: SessionSelector) -> Result<Option<String>> {
-    let request = tonic::Request::new(sesh_proto::SeshKillRequest {
-        session: Some(match &session {
-            SessionSelector::Id(id) => Session::Id(*id as u64),
-            SessionSelector::Name(name) => Session::Name(name.clone()),
-        }),
-    });
-    let response = ctx.client.kill_session(request).await?;
-    if response.into_inner().success {
-        Ok(Some(success!("[killed {}]", session)))
+/// Sends a start session request to the server, and handles the response
+pub async fn start(mut ctx: Ctx, options: StartOptions) -> anyhow::Result<Outcome> {
+    let alt_screen = !options.no_alt_screen;
+    let status = options.status;
+    let (req, script) = start_request(&mut ctx, options).await?;
+    let attach = req.attach;
+
+    let res = match ctx.client.start_session(req).await {
+        Ok(res) => res.into_inner(),
+        Err(e) => {
+            // The server only takes the script over once the session has started
+            if let Some(script) = &script {
+                std::fs::remove_file(script).ok();
+            }
+            anyhow::bail!("Could not start session: {}", e.message());
+        }
+    };
+    if attach {
+        let view = View {
+            alt_screen,
+            status,
+            keep_size: false,
+        };
+        let exit = exec_session(ctx, started(res), view).await?;
+        exited(exit)
     } else {
-        Err(anyhow::anyhow!("{}", error!("Could not kill process")))
+        Ok(Outcome::Status(success!("[started]")))
     }
 }
 
-/// Sends a start session request to the server, and handles the response
-pub async fn start(
-    mut ctx: Ctx,
-    name: Option<String>,
-    program: Option<String>,
-    args: Vec<String>,
-    attach: bool,
-) -> anyhow::Result<Option<String>> {
-    let program = get_program(program);
-    let size = {
-        let s = termion::terminal_size().unwrap_or((80, 24));
-        WinSize {
-            rows: s.1 as u32,
-            cols: s.0 as u32,
-        }
-    };
-    let req = tonic::Request::new(SeshStartRequest {
-        name: name.unwrap_or_else(|| program.clone()),
+/// Builds the request to start a session with, along with the temporary file of a script read
+/// from stdin, which is removed if the session doesn't start
+pub(crate) async fn start_request(
+    ctx: &mut Ctx,
+    options: StartOptions,
+) -> Result<(SeshStartRequest, Option<PathBuf>)> {
+    let StartOptions {
+        name,
+        program,
+        args,
+        attach,
+        status,
+        login,
+        term,
+        replay_input,
+        here,
+        note,
+        tags,
+        title,
+        script,
+        expand,
+        notify,
+        keep,
+        windows,
+        termios,
+        no_alt_screen: _,
+    } = options;
+    // Checked before anything is started
+    let notify_signal = |signal: Option<String>| signal.as_deref().map(signal::parse).transpose();
+    let notify_attach = notify_signal(notify.notify_attach)?;
+    let notify_detach = notify_signal(notify.notify_detach)?;
+    let (name, program, args) = if expand {
+        (
+            name.as_deref().map(expand_vars).transpose()?,
+            program.as_deref().map(expand_vars).transpose()?,
+            args.iter()
+                .map(|arg| expand_vars(arg))
+                .collect::<Result<_>>()?,
+        )
+    } else {
+        (name, program, args)
+    };
+    let (program, args, script, attach) = match script {
+        // Read into a file the session can run, which the server removes once it ends.
+        // Attaching needs stdin for the terminal, and a heredoc has taken its place
+        Some(script) if script.as_os_str() == "-" => {
+            let file = read_script()?;
+            let args = vec![file.to_string_lossy().to_string()];
+            let attach = attach && std::io::stdin().is_terminal();
+            (sesh_shared::user::get_shell(), args, Some(file), attach)
+        }
+        // The session may start in another directory with --here
+        Some(script) => {
+            let args = vec![std::path::absolute(script)?.to_string_lossy().to_string()];
+            (sesh_shared::user::get_shell(), args, None, attach)
+        }
+        None => (get_program(program), args, None, attach),
+    };
+    // The server reads the recording, and its working directory isn't this one
+    let replay_input = replay_input
+        .map(std::path::absolute)
+        .transpose()?
+        .map(|path| path.to_string_lossy().to_string());
+    let pwd = match here {
+        Some(session) => session_cwd(ctx, &session).await?,
+        None => std::env::current_dir()?,
+    };
+    let size = client_size(attach && status);
+    let req = SeshStartRequest {
+        name: name.unwrap_or_else(|| sesh_shared::name::from_program(&program)),
         program,
         args,
         size: Some(size),
-        pwd: std::env::current_dir()?.to_string_lossy().to_string(),
+        pwd: pwd.to_string_lossy().to_string(),
         env: std::env::vars()
             .map(|v| sesh_proto::Var {
                 key: v.0,
                 value: v.1,
             })
             .collect(),
+        login,
+        term: term.unwrap_or_default(),
+        replay_input: replay_input.unwrap_or_default(),
+        note: note.unwrap_or_default(),
+        tags,
+        title: title.unwrap_or_default(),
+        script: script
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        attach,
+        notify_attach: notify_attach.unwrap_or_default(),
+        notify_detach: notify_detach.unwrap_or_default(),
+        attach_status: notify.attach_status,
+        keep,
+        windows: windows
+            .into_iter()
+            .map(|(name, command)| sesh_proto::Window {
+                name,
+                program: sesh_shared::user::get_shell(),
+                args: vec!["-c".to_owned(), command],
+            })
+            .collect(),
+        termios: Some(sesh_proto::Termios {
+            no_flow_control: termios.no_flow_control,
+            no_echo: termios.no_echo,
+        }),
+    };
+    Ok((req, script))
+}
+
+/// Reads a script from stdin into a new temporary file, and returns its path
+fn read_script() -> Result<PathBuf> {
+    let path = std::env::temp_dir().join(format!(
+        "sesh-script-{}-{}",
+        std::process::id(),
+        chrono::Utc::now().timestamp_millis()
+    ));
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .with_context(|| format!("Could not create {}", path.display()))?;
+    if let Err(e) = std::io::copy(&mut std::io::stdin(), &mut file) {
+        std::fs::remove_file(&path).ok();
+        anyhow::bail!("Could not read the script from stdin: {}", e);
+    }
+    Ok(path)
+}
+
+/// Directory the program in the foreground of a session is working in
+async fn session_cwd(ctx: &mut Ctx, session: &SessionSelector) -> Result<PathBuf> {
+    use sesh_cwd_request::Session::*;
+    let selected = ctx.select(session, Id, Name).await?;
+    let request = tonic::Request::new(SeshCwdRequest {
+        session: Some(selected),
+    });
+    let response = ctx.client.session_cwd(request).await.map_err(|e| {
+        anyhow::anyhow!(
+            "Could not find the directory of {}: {}",
+            session,
+            e.message()
+        )
+    })?;
+    Ok(PathBuf::from(response.into_inner().cwd))
+}
+
+/// Sends a revive session request to the server, and handles the response
+pub async fn revive(mut ctx: Ctx, name: String, attach: bool) -> Result<Outcome> {
+    let size = client_size(false);
+    let req = tonic::Request::new(SeshReviveRequest {
+        name,
+        size: Some(size),
+        attach,
     });
 
     let res = ctx
         .client
-        .start_session(req)
+        .revive_session(req)
         .await
-        .map_err(|e| anyhow::anyhow!("Could not start session: {}", e))?
+        .map_err(|e| anyhow::anyhow!("Could not revive session: {}", e.message()))?
         .into_inner();
     if attach {
-        match exec_session(ctx, res.pid, res.socket, res.name, res.program).await? {
-            ExitKind::Quit => Ok(Some(success!("[exited]"))),
-            ExitKind::Detach => Ok(Some(success!("[detached]"))),
-        }
+        let view = View {
+            alt_screen: true,
+            status: false,
+            keep_size: false,
+        };
+        let exit = exec_session(ctx, started(res), view).await?;
+        exited(exit)
     } else {
-        Ok(Some(success!("[started]")))
+        Ok(Outcome::Status(success!("[revived {}]", res.name)))
     }
 }
 
 /// Wraps the `list_sessions` and `attach_session` requests to allow fuzzy searching over sessions
-pub async fn select(mut ctx: Ctx) -> Result<Option<String>> {
-    let request = tonic::Request::new(sesh_proto::SeshListRequest {});
+pub async fn select(mut ctx: Ctx, json: bool) -> Result<Outcome> {
+    // The picker needs a terminal, so without one the sessions are left for something else to
+    // pick from
+    if json || !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return list(
+            ctx,
+            Format::Json,
+            Vec::new(),
+            false,
+            Default::default(),
+            false,
+        )
+        .await;
+    }
+    let request = tonic::Request::new(sesh_proto::SeshListRequest {
+        dead: false,
+        filter: None,
+    });
     let response = ctx.client.list_sessions(request).await?.into_inner();
     let sessions = response
         .sessions
@@ -379,18 +2229,26 @@ pub async fn select(mut ctx: Ctx) -> Result<Option<String>> {
         .with_prompt("Session")
         .interact_opt()
     else {
-        return Ok(Some(success!("[cancelled]")));
+        return Ok(Outcome::Status(success!("[cancelled]")));
     };
 
     let Some(name) = sessions.get(select) else {
         return Err(anyhow::anyhow!("Invalid selection"));
     };
 
-    attach(ctx, SessionSelector::Name(name.clone()), false).await
+    attach(
+        ctx,
+        vec![SessionSelector::Name(name.clone())],
+        AttachOptions::default(),
+    )
+    .await
 }
 
-pub async fn resume(mut ctx: Ctx, create: bool) -> Result<Option<String>> {
-    let request = tonic::Request::new(sesh_proto::SeshListRequest {});
+pub async fn resume(mut ctx: Ctx, create: bool) -> Result<Outcome> {
+    let request = tonic::Request::new(sesh_proto::SeshListRequest {
+        dead: false,
+        filter: None,
+    });
     let mut sessions = ctx
         .client
         .list_sessions(request)
@@ -398,13 +2256,218 @@ pub async fn resume(mut ctx: Ctx, create: bool) -> Result<Option<String>> {
         .into_inner()
         .sessions;
     sessions.retain(|s| !s.connected);
-    sessions.sort_by(|a, b| a.attach_time.cmp(&b.attach_time));
+    sessions.sort_by_key(|s| s.attach_time);
     let session = sessions.into_iter().last();
     match session {
-        Some(session) => attach(ctx, SessionSelector::Name(session.name), false).await,
-        None if create => start(ctx, None, None, vec![], true).await,
-        None => Ok(Some(error!("[no sessions to resume]"))),
+        Some(session) => {
+            let session = vec![SessionSelector::Name(session.name)];
+            attach(ctx, session, AttachOptions::default()).await
+        }
+        None if create => {
+            let options = StartOptions {
+                attach: true,
+                ..Default::default()
+            };
+            start(ctx, options).await
+        }
+        None => Ok(Outcome::Status(error!("[no sessions to resume]"))),
+    }
+}
+
+/// Columns of the session table when none are asked for
+pub(crate) fn default_columns(sessions: &[SeshInfo]) -> Vec<Column> {
+    // Most sessions don't have tags or a note, so those columns are only shown when one does
+    let tagged = sessions.iter().any(|s| !s.tags.is_empty());
+    let noted = sessions.iter().any(|s| !s.note.is_empty());
+    table::ALL
+        .into_iter()
+        .filter(|c| match c {
+            Column::Tags => tagged,
+            Column::Note => noted,
+            _ => true,
+        })
+        .collect()
+}
+
+/// Title of a column in the `sesh list --info` table
+pub(crate) fn column_title(column: Column) -> &'static str {
+    match column {
+        Column::Id => "Id",
+        Column::Name => "Name",
+        Column::Started => "Started",
+        Column::Attached => "Attached",
+        Column::Program => "Program",
+        Column::Cwd => "Directory",
+        Column::Pid => "PID",
+        Column::Tags => "Tags",
+        Column::Note => "Note",
+    }
+}
+
+/// Plain text of a session's cell in the `sesh list --info` table.
+/// Times are shown as how long ago they were, or in full if `absolute` is set.
+pub(crate) fn column_cell(column: Column, s: &SeshInfo, now: i64, absolute: bool) -> String {
+    let time = |millis| {
+        if !absolute {
+            return table::ago(millis, now);
+        }
+        match Local.timestamp_millis_opt(millis) {
+            chrono::LocalResult::None => "Unknown".to_owned(),
+            chrono::LocalResult::Single(time) | chrono::LocalResult::Ambiguous(time, _) => {
+                let format = format!("%m/%d/%g {} %I:%M%P", theme().separator);
+                time.format(&format).to_string()
+            }
+        }
+    };
+    match column {
+        Column::Id => s.id.to_string(),
+        Column::Name if s.connected => format!("{} {}", s.name, attached_marker(s)),
+        Column::Name => s.name.clone(),
+        Column::Started => time(s.start_time),
+        Column::Attached if s.attach_time > 0 => time(s.attach_time),
+        Column::Attached => "Never".to_owned(),
+        Column::Program => match (exit_description(s), io_error(s)) {
+            (_, Some(e)) => format!("{} (io-error: {})", s.program, e),
+            (Some(exited), None) => format!("{} ({})", s.program, exited),
+            (None, None) => s.program.clone(),
+        },
+        Column::Cwd => home_relative(&s.cwd),
+        Column::Pid => s.pid.to_string(),
+        Column::Tags => s.tags.join(", "),
+        // A note with line breaks would break the table's rows
+        Column::Note => s.note.lines().collect::<Vec<_>>().join(" "),
+    }
+}
+
+/// A path with the home directory shortened to ~, as shells show it
+fn home_relative(path: &str) -> String {
+    let home = dirs::home_dir();
+    let rest = home
+        .as_deref()
+        .and_then(|home| Path::new(path).strip_prefix(home).ok());
+    match rest {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_owned(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.to_owned(),
+    }
+}
+
+/// How the program of a session started with --keep exited, if it has
+fn exit_description(s: &SeshInfo) -> Option<String> {
+    use sesh_proto::sesh_info::ExitStatus;
+    if !s.exited {
+        return None;
+    }
+    Some(match s.exit_status {
+        Some(ExitStatus::Code(code)) => format!("exited with status {}", code),
+        Some(ExitStatus::Signal(sig)) => format!("killed by {}", signal::name(sig)),
+        None => "exited".to_owned(),
+    })
+}
+
+/// The last error relaying a session, if it's unhealthy
+fn io_error(s: &SeshInfo) -> Option<&str> {
+    s.health.strip_prefix("io-error: ")
+}
+
+/// Marks an attached session's name, with the number of clients if there's more than one
+fn attached_marker(s: &SeshInfo) -> String {
+    match s.clients {
+        0 | 1 => theme().active.to_string(),
+        n => format!("{} {} clients", theme().active, n),
+    }
+}
+
+/// Exit status of `sesh current` outside of a session
+const NOT_IN_SESSION: u8 = 2;
+/// Exit status of `sesh current --verify` when the session no longer exists
+const SESSION_GONE: u8 = 3;
+
+#[derive(serde::Serialize)]
+struct CurrentSer {
+    name: String,
+    id: Option<u64>,
+    socket: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attached: Option<bool>,
+}
+
+/// Prints the session the calling process is running in, as told by the environment
+pub async fn current(
+    server_sock: PathBuf,
+    timeout: Duration,
+    format: String,
+    json: bool,
+    verify: bool,
+) -> ExitCode {
+    let Ok(name) = std::env::var("SESH_NAME") else {
+        return ExitCode::from(NOT_IN_SESSION);
+    };
+    let mut current = CurrentSer {
+        name,
+        id: std::env::var("SESH_ID").ok().and_then(|id| id.parse().ok()),
+        socket: std::env::var("SESH_SESSION").unwrap_or_default(),
+        attached: None,
+    };
+
+    if verify {
+        let Ok(mut ctx) = Ctx::init(server_sock, timeout).await else {
+            return ExitCode::from(SESSION_GONE);
+        };
+        let request = tonic::Request::new(sesh_proto::SeshListRequest {
+            dead: false,
+            filter: None,
+        });
+        let sessions = match ctx.client.list_sessions(request).await {
+            Ok(res) => res.into_inner().sessions,
+            Err(e) => {
+                eprintln!("{}", error!("{}", e.message()));
+                return ExitCode::FAILURE;
+            }
+        };
+        // The session's process leads the unix session we're in, which still identifies it
+        // if it has been renamed since we started
+        let sid = unsafe { libc::getsid(0) };
+        let Some(session) = sessions
+            .iter()
+            .find(|s| s.pid == sid)
+            .or_else(|| sessions.iter().find(|s| s.name == current.name))
+        else {
+            return ExitCode::from(SESSION_GONE);
+        };
+        current = CurrentSer {
+            name: session.name.clone(),
+            id: Some(session.id),
+            socket: session.socket.clone(),
+            attached: Some(session.connected),
+        };
     }
+
+    if json {
+        match serde_json::to_string(&current) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("{}", error!("{}", e));
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        println!(
+            "{}",
+            format
+                .replace("{name}", &current.name)
+                .replace(
+                    "{id}",
+                    &current.id.map(|id| id.to_string()).unwrap_or_default()
+                )
+                .replace("{socket}", &current.socket)
+                .replace(
+                    "{attached}",
+                    &current.attached.map(|a| a.to_string()).unwrap_or_default()
+                )
+        );
+    }
+    ExitCode::SUCCESS
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -414,97 +2477,195 @@ struct SeshInfoSer {
     program: String,
     socket: String,
     connected: bool,
+    #[serde(default)]
+    clients: u32,
     start_time: i64,
     attach_time: i64,
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    title: String,
+    /// Whether the program of a session started with --keep has exited
+    #[serde(default)]
+    exited: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exit_signal: Option<String>,
+    /// Names of the session's windows, the first one running its program
+    #[serde(default)]
+    windows: Vec<String>,
+    /// Working directory of the program in the session's foreground
+    #[serde(default)]
+    cwd: String,
+    /// `ok`, or `io-error: ` and the last error relaying the session
+    #[serde(default)]
+    health: String,
 }
 
 /// Sends a list sessions request to the server, and handles the response
-pub async fn list(mut ctx: Ctx, table: bool, json: bool) -> Result<Option<String>> {
-    let request = tonic::Request::new(sesh_proto::SeshListRequest {});
-    let response = ctx.client.list_sessions(request).await?.into_inner();
-    let sessions = &response.sessions;
+pub async fn list(
+    mut ctx: Ctx,
+    format: Format,
+    columns: Vec<Column>,
+    dead: bool,
+    filter: sesh_proto::SeshListFilter,
+    absolute: bool,
+) -> Result<Outcome> {
+    let request = tonic::Request::new(sesh_proto::SeshListRequest {
+        dead,
+        filter: Some(filter.clone()),
+    });
+    let mut sessions = ctx
+        .client
+        .list_sessions(request)
+        .await?
+        .into_inner()
+        .sessions;
+    // An older server ignores the filter and sends every session
+    sessions.retain(|s| {
+        sesh_shared::filter::matches(&filter, &s.name, &s.program, s.connected, &s.tags)
+    });
+    let sessions = &sessions;
+    let theme = theme();
 
-    match ListMode::new(table, json) {
-        ListMode::List => {
+    match format {
+        Format::List => {
             let mut res = String::new();
             for (i, session) in sessions.iter().enumerate() {
                 if i > 0 {
                     res += "\n";
                 }
                 let bullet = if session.connected {
-                    success!("{}{}", termion::style::Bold, BULLET_ICON)
+                    success!("{}{}", paint(termion::style::Bold), theme.bullet)
+                } else {
+                    format!("{}{}", paint(termion::style::Bold), theme.bullet)
+                };
+                let orphaned = if dead && session.pid != 0 {
+                    format!(" (orphaned, pid {})", session.pid)
+                } else if let Some(e) = io_error(session) {
+                    error!(" (io-error: {})", e)
+                } else if let Some(exited) = exit_description(session) {
+                    format!(" ({})", exited)
                 } else {
-                    format!("{}{}", termion::style::Bold, BULLET_ICON)
+                    String::new()
                 };
                 res += &format!(
-                    "{bullet} {col}{id}{reset} \u{2218} {name} \u{2218} {program}{reset_attr}",
+                    "{bullet} {col}{id}{reset} {sep} {name} {sep} {program}{orphaned}{reset_attr}",
                     id = session.id,
+                    sep = theme.separator,
                     name = session.name,
-                    program = session.program.split('/').last().unwrap_or(""),
-                    col = Fg(color::LightBlue),
-                    reset = Fg(color::Reset),
-                    reset_attr = termion::style::Reset
+                    program = session.program.split('/').next_back().unwrap_or(""),
+                    col = paint(Fg(color::LightBlue)),
+                    reset = paint(Fg(color::Reset)),
+                    reset_attr = paint(termion::style::Reset)
                 );
             }
-            Ok(Some(res))
+            Ok(Outcome::Output(res))
         }
-        ListMode::Table => {
+        Format::Table => {
             let mut table = Table::new();
+            let lines = &theme.lines;
+            let line = |[line, joint, left, right]: [char; 4]| {
+                LineSeparator::new(line, joint, left, right)
+            };
             table.set_format(
                 FormatBuilder::new()
-                    .column_separator('│')
-                    .borders('│')
-                    .separator(LinePosition::Top, LineSeparator::new('─', '┬', '╭', '╮'))
-                    .separator(LinePosition::Intern, LineSeparator::new('─', '┼', '├', '┤'))
-                    .separator(LinePosition::Bottom, LineSeparator::new('─', '┴', '╰', '╯'))
+                    .column_separator(lines.vertical)
+                    .borders(lines.vertical)
+                    .separator(LinePosition::Top, line(lines.top))
+                    .separator(LinePosition::Intern, line(lines.middle))
+                    .separator(LinePosition::Bottom, line(lines.bottom))
                     .padding(1, 1)
                     .build(),
             );
-            table.set_titles(row![
-                icon_title('', "Id", Fg(color::LightRed)),
-                icon_title('', "Name", Fg(color::LightBlue)),
-                icon_title('', "Started", Fg(color::LightYellow)),
-                icon_title('', "Attached", Fg(color::LightGreen)),
-                icon_title('', "Program", Fg(color::LightCyan)),
-                icon_title('', "PID", Fg(color::LightMagenta))
-            ]);
-            sessions.iter().for_each(|s: &SeshInfo| {
-                let connected = if s.connected {
-                    success!(" {}{}", Fg(color::LightGreen), ACTIVE_ICON)
-                } else {
-                    "".to_owned()
-                };
-                let s_time = Local.timestamp_millis_opt(s.start_time).unwrap();
-                table.add_row(row![
-                    format!(
-                        "{col}{}{reset}",
-                        s.id,
-                        col = Fg(color::LightBlue),
-                        reset = Fg(color::Reset)
-                    ),
-                    format!("{}{}{reset}", s.name, connected, reset = Fg(color::Reset)),
-                    s_time.format("%m/%d/%g \u{2218} %I:%M%P"),
-                    if s.attach_time > 0 {
-                        match Local.timestamp_millis_opt(s.attach_time) {
-                            chrono::LocalResult::None => "Unknown".to_owned(),
-                            chrono::LocalResult::Single(a_time)
-                            | chrono::LocalResult::Ambiguous(a_time, _) => {
-                                a_time.format("%m/%d/%g \u{2218} %I:%M%P").to_string()
-                            }
-                        }
-                    } else {
-                        "Never".to_owned()
-                    },
-                    s.program,
-                    s.pid
-                ]);
-            });
+            let now = chrono::Utc::now().timestamp_millis();
+            let exact = !columns.is_empty();
+            let columns = if exact {
+                columns
+            } else {
+                default_columns(sessions)
+            };
+            let titles = columns
+                .iter()
+                .map(|c| match theme.icon(*c) {
+                    Some(icon) => format!("{} {}", icon, column_title(*c)),
+                    None => column_title(*c).to_owned(),
+                })
+                .collect::<Vec<_>>();
+            let cells = sessions
+                .iter()
+                .map(|s| {
+                    columns
+                        .iter()
+                        .map(|c| column_cell(*c, s, now, absolute))
+                        .collect()
+                })
+                .collect::<Vec<Vec<_>>>();
+            // Only a terminal has a width to fit, output to a pipe is left whole
+            let max_width = std::io::stdout()
+                .is_terminal()
+                .then(termion::terminal_size)
+                .and_then(Result::ok)
+                .map(|(cols, _)| cols as usize);
+            let layout = table::layout(columns.clone(), &titles, &cells, max_width, exact);
+
+            table.set_titles(
+                layout
+                    .iter()
+                    .map(|(c, width)| {
+                        let icon = theme.icon(*c);
+                        // The icon and the space after it take two columns
+                        let room = width.saturating_sub(if icon.is_some() { 2 } else { 0 });
+                        let title = table::truncate(column_title(*c), room);
+                        prettytable::Cell::new(&icon_title(icon, &title, theme.color(*c)))
+                    })
+                    .collect(),
+            );
+            for (s, row) in sessions.iter().zip(cells) {
+                table.add_row(
+                    layout
+                        .iter()
+                        .map(|(c, width)| {
+                            let i = columns.iter().position(|col| col == c).unwrap_or(0);
+                            let text = match c {
+                                Column::Id => format!(
+                                    "{}{}{}",
+                                    paint(Fg(color::LightBlue)),
+                                    row[i],
+                                    paint(Fg(color::Reset))
+                                ),
+                                // The marker for an attached session is kept when the name is cut
+                                Column::Name if s.connected => {
+                                    let marker = attached_marker(s);
+                                    success!(
+                                        "{} {}{}",
+                                        table::truncate(
+                                            &s.name,
+                                            width.saturating_sub(marker.chars().count() + 1)
+                                        ),
+                                        paint(Fg(color::LightGreen)),
+                                        marker
+                                    )
+                                }
+                                Column::Program if io_error(s).is_some() => {
+                                    error!("{}", table::truncate(&row[i], *width))
+                                }
+                                _ => table::truncate(&row[i], *width),
+                            };
+                            prettytable::Cell::new(&text)
+                        })
+                        .collect(),
+                );
+            }
             let mut rendered = Cursor::new(Vec::new());
             table.print(&mut rendered)?;
             let s = String::from_utf8(rendered.into_inner())?;
-            Ok(Some(s))
+            Ok(Outcome::Output(s))
         }
-        ListMode::Json => {
+        Format::Json => {
             let sessions = sessions
                 .iter()
                 .map(|s| SeshInfoSer {
@@ -513,23 +2674,354 @@ pub async fn list(mut ctx: Ctx, table: bool, json: bool) -> Result<Option<String
                     program: s.program.clone(),
                     socket: s.socket.clone(),
                     connected: s.connected,
+                    clients: s.clients,
                     start_time: s.start_time,
                     attach_time: s.attach_time,
+                    note: s.note.clone(),
+                    tags: s.tags.clone(),
+                    title: s.title.clone(),
+                    exited: s.exited,
+                    exit_code: match s.exit_status {
+                        Some(sesh_proto::sesh_info::ExitStatus::Code(code)) => Some(code),
+                        _ => None,
+                    },
+                    exit_signal: match s.exit_status {
+                        Some(sesh_proto::sesh_info::ExitStatus::Signal(sig)) => {
+                            Some(signal::name(sig))
+                        }
+                        _ => None,
+                    },
+                    windows: s.windows.clone(),
+                    cwd: s.cwd.clone(),
+                    health: s.health.clone(),
                 })
                 .collect::<Vec<_>>();
             let json = serde_json::to_string_pretty(&sessions)?;
-            Ok(Some(json))
+            Ok(Outcome::Output(json))
+        }
+        Format::Csv => Ok(Outcome::Output(separated(sessions, ','))),
+        Format::Tsv => Ok(Outcome::Output(separated(sessions, '\t'))),
+    }
+}
+
+/// Formats sessions as a header row and a row per session, with fields split by `sep`
+fn separated(sessions: &[SeshInfo], sep: char) -> String {
+    let header = [
+        "id",
+        "name",
+        "program",
+        "socket",
+        "connected",
+        "start_time",
+        "attach_time",
+        "pid",
+        "note",
+        "tags",
+        "title",
+        "clients",
+        "exited",
+        "cwd",
+        "health",
+    ];
+    let rows = sessions.iter().map(|s| {
+        [
+            s.id.to_string(),
+            s.name.clone(),
+            s.program.clone(),
+            s.socket.clone(),
+            s.connected.to_string(),
+            s.start_time.to_string(),
+            s.attach_time.to_string(),
+            s.pid.to_string(),
+            s.note.clone(),
+            s.tags.join(" "),
+            s.title.clone(),
+            s.clients.to_string(),
+            s.exited.to_string(),
+            s.cwd.clone(),
+            s.health.clone(),
+        ]
+    });
+    std::iter::once(header.map(str::to_owned))
+        .chain(rows)
+        .map(|row| {
+            row.iter()
+                .map(|field| quote(field, sep))
+                .collect::<Vec<_>>()
+                .join(&sep.to_string())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Quotes a field if it contains the separator, a quote or a line break, doubling its quotes
+fn quote(field: &str, sep: char) -> String {
+    if field.contains([sep, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SeshEventSer {
+    kind: String,
+    time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new_name: Option<String>,
+}
+
+/// Prints events from the server until it shuts down
+pub async fn events(mut ctx: Ctx, json: bool) -> Result<Outcome> {
+    use sesh_proto::sesh_event::{ExitStatus, Kind};
+    let request = without_deadline(sesh_proto::SubscribeEventsRequest {});
+    let mut stream = ctx.client.subscribe_events(request).await?.into_inner();
+    while let Some(event) = stream.message().await? {
+        let kind = event.kind();
+        let server_event = kind == Kind::Shutdown;
+        let (exit_code, signal) = match event.exit_status {
+            Some(ExitStatus::Code(code)) => (Some(code), None),
+            Some(ExitStatus::Signal(sig)) => (None, Some(signal::name(sig))),
+            None => (None, None),
+        };
+        let new_name = (kind == Kind::Renamed).then_some(event.new_name);
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string(&SeshEventSer {
+                    kind: kind.as_str_name().to_lowercase(),
+                    time: event.time,
+                    session: (!server_event).then(|| event.session.clone()),
+                    id: (!server_event).then_some(event.id),
+                    exit_code,
+                    signal,
+                    new_name,
+                })?
+            );
+            continue;
+        }
+        let time = match Local.timestamp_millis_opt(event.time) {
+            chrono::LocalResult::Single(time) | chrono::LocalResult::Ambiguous(time, _) => {
+                time.format("%H:%M:%S").to_string()
+            }
+            chrono::LocalResult::None => "--:--:--".to_owned(),
+        };
+        let mut line = format!(
+            "{} {}{}{}",
+            time,
+            paint(Fg(color::LightBlue)),
+            kind.as_str_name().to_lowercase(),
+            paint(Fg(color::Reset))
+        );
+        if !server_event {
+            line.push_str(&format!(" {} ({})", event.session, event.id));
+        }
+        if let Some(code) = exit_code {
+            line.push_str(&format!(" with status {}", code));
+        }
+        if let Some(signal) = signal {
+            line.push_str(&format!(" by {}", signal));
         }
+        if let Some(new_name) = new_name {
+            line.push_str(&format!(" to {}", new_name));
+        }
+        println!("{}", line);
     }
+    Ok(Outcome::Done)
+}
+
+/// Waits until the channel is signaled, or signals it. Prints nothing, so it can be used in
+/// scripts.
+pub async fn wait_for(mut ctx: Ctx, channel: String, signal: bool) -> Result<Outcome> {
+    let request = without_deadline(sesh_proto::SeshWaitForRequest { channel, signal });
+    ctx.client
+        .wait_for(request)
+        .await
+        .map_err(|e| anyhow::anyhow!("Wait cancelled: {}", e.message()))?;
+    Ok(Outcome::Done)
 }
 
 /// Sends a shutdown request to the server
-pub async fn shutdown(mut ctx: Ctx) -> Result<Option<String>> {
+pub async fn shutdown(mut ctx: Ctx) -> Result<Outcome> {
     let request = tonic::Request::new(sesh_proto::ShutdownServerRequest {});
     let response = ctx.client.shutdown_server(request).await?;
-    Ok(Some(if response.into_inner().success {
+    Ok(Outcome::Status(if response.into_inner().success {
         success!("[shutdown]")
     } else {
         return Err(anyhow::anyhow!("Failed to shutdown server"));
     }))
 }
+
+/// Quotes a string so a POSIX shell reads it as a single word
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Attaches to a session on another machine, by running sesh there over ssh
+pub fn ssh(
+    host: String,
+    session: Option<SessionSelector>,
+    create: bool,
+    remote_sesh: String,
+) -> ExitCode {
+    // ssh runs the command through the remote user's shell. The session name is quoted, but the
+    // path to sesh isn't, so it can use ~ or variables from the host
+    let mut command = vec![remote_sesh];
+    match session {
+        Some(session) => {
+            command.push("attach".to_owned());
+            if create {
+                command.push("--create".to_owned());
+            }
+            command.push(shell_quote(&session.to_string()));
+        }
+        None => command.push("select".to_owned()),
+    }
+
+    // -t gives the remote client a terminal, which ssh keeps at the size of this one
+    let status = std::process::Command::new("ssh")
+        .arg("-t")
+        .arg("--")
+        .arg(&host)
+        .arg(command.join(" "))
+        .status();
+    match status {
+        // ssh exits with the remote client's status, so detaching still exits with 10
+        Ok(status) => match (status.code(), status.signal()) {
+            (Some(code), _) => ExitCode::from(code as u8),
+            (None, Some(sig)) => ExitCode::from(128u8.wrapping_add(sig as u8)),
+            (None, None) => ExitCode::FAILURE,
+        },
+        Err(e) => {
+            eprintln!("{}", error!("Could not run ssh: {}", e));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Version of sesh, or with `verbose` a `key: value` line for each detail of the build
+pub fn version(verbose: bool) -> String {
+    let version = env!("CARGO_PKG_VERSION");
+    if !verbose {
+        return format!("sesh {}", version);
+    }
+    format!(
+        "version: {}\ncommit: {}\nrustc: {}\ntarget: {}",
+        version,
+        env!("GIT_HASH"),
+        env!("RUSTC_VERSION"),
+        env!("BUILD_TARGET")
+    )
+}
+
+/// Asks the server to hand its sessions over to a fresh seshd binary and exit
+pub async fn upgrade(mut ctx: Ctx) -> Result<Outcome> {
+    // The server bounds how long the handover takes on its own
+    let request = without_deadline(sesh_proto::UpgradeServerRequest {
+        seshd_path: std::env::var("SESHD_PATH").unwrap_or("seshd".to_owned()),
+    });
+    let response = ctx
+        .client
+        .upgrade_server(request)
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not upgrade server: {}", e.message()))?;
+    Ok(Outcome::Status(success!(
+        "[upgraded, pid {}]",
+        response.into_inner().pid
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terminal_gone_detaches() {
+        let eio = || {
+            anyhow::Error::new(std::io::Error::from_raw_os_error(libc::EIO))
+                .context("Could not write tty_output")
+        };
+        assert!(matches!(
+            stopped_exit(Stopped::Output(&Ok(Err(eio())))),
+            ExitKind::Detach
+        ));
+        assert!(matches!(
+            stopped_exit(Stopped::Input(&Ok(Err(eio())))),
+            ExitKind::Detach
+        ));
+        assert!(matches!(
+            stopped_exit(Stopped::Input(&Ok(Ok(true)))),
+            ExitKind::Detach
+        ));
+        // Other failures, and the session's output ending, aren't the terminal's doing
+        let refused = anyhow::Error::new(std::io::Error::from_raw_os_error(libc::ECONNREFUSED))
+            .context("Failed to write to w_stream");
+        assert!(matches!(
+            stopped_exit(Stopped::Input(&Ok(Err(refused)))),
+            ExitKind::Quit
+        ));
+        assert!(matches!(
+            stopped_exit(Stopped::Output(&Ok(Ok(())))),
+            ExitKind::Quit
+        ));
+    }
+
+    #[test]
+    fn input_after_a_command_is_kept() {
+        let mut prefix_key = PrefixKey {
+            key: 0x02,
+            pending: false,
+        };
+        assert_eq!(
+            prefix_key.split(b"\x02nls\r"),
+            (vec![], Some(PrefixCommand::NextWindow), &b"ls\r"[..])
+        );
+        assert_eq!(
+            prefix_key.split(b"ls\r"),
+            (b"ls\r".to_vec(), None, &b""[..])
+        );
+        // Across reads, and with the prefix key sent on to the session
+        assert_eq!(prefix_key.split(b"a\x02"), (b"a".to_vec(), None, &b""[..]));
+        assert_eq!(
+            prefix_key.split(b"\x02\x02Sb"),
+            (
+                b"\x02".to_vec(),
+                Some(PrefixCommand::ToggleStatus),
+                &b"b"[..]
+            )
+        );
+    }
+
+    #[test]
+    fn list_times() {
+        let now = 1_700_000_000_000;
+        // Started a little ahead of the client's clock, and never attached to
+        let session = SeshInfo {
+            start_time: now + 5000,
+            attach_time: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            column_cell(Column::Started, &session, now, false),
+            "just now"
+        );
+        assert_eq!(column_cell(Column::Attached, &session, now, false), "Never");
+        assert_eq!(column_cell(Column::Attached, &session, now, true), "Never");
+        let session = SeshInfo {
+            attach_time: now - 3 * 60 * 60 * 1000,
+            ..session
+        };
+        assert_eq!(
+            column_cell(Column::Attached, &session, now, false),
+            "3h ago"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use sesh_proto::SeshInfo;
+
+/// The status line drawn on the bottom row while attached, as it should currently be drawn.
+///
+/// The session is told the terminal is one row shorter than it is, and the scrolling region is
+/// limited to the rows above the status line, so the session's output never scrolls it away.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    /// The rendered line, or None if the status line is turned off
+    pub line: Option<String>,
+    /// Size of the terminal the line was rendered for
+    pub cols: u16,
+    pub rows: u16,
+}
+
+/// Renders the status line for the session with the given name.
+/// If `show_size` is set, the session's size is shown too, since it may not match the terminal.
+pub fn render(name: &str, sessions: &[SeshInfo], cols: u16, show_size: bool) -> String {
+    let current = sessions.iter().find(|s| s.name == name);
+    let id = current.map(|s| format!("{}:", s.id)).unwrap_or_default();
+    let size = current
+        .and_then(|s| s.size.as_ref())
+        .filter(|_| show_size)
+        .map(|size| format!(" {}x{}", size.cols, size.rows))
+        .unwrap_or_default();
+    let left = format!(" [{}{}{}]", id, name, size);
+    // Sessions attached to another client are marked with a *
+    let others = sessions
+        .iter()
+        .filter(|s| s.name != name)
+        .map(|s| format!("{}:{}{}", s.id, s.name, if s.connected { "*" } else { "" }))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let right = format!("{} ", chrono::Local::now().format("%H:%M"));
+
+    let cols = cols as usize;
+    let mut line = format!("{} {}", left, others);
+    let used = line.chars().count() + right.chars().count();
+    if used < cols {
+        line.extend(std::iter::repeat_n(' ', cols - used));
+        line.push_str(&right);
+    }
+    line.chars().take(cols).collect()
+}
+
+/// Escape sequences that draw the status line on the bottom row, leaving the cursor where it was
+pub fn draw(line: &str, rows: u16) -> String {
+    format!("\x1b7\x1b[{};1H\x1b[0;7m{}\x1b[0m\x1b8", rows, line)
+}
+
+/// Escape sequences that clear the status line and give the whole screen back to the session
+pub fn clear(rows: u16) -> String {
+    format!("\x1b7\x1b[r\x1b[{};1H\x1b[2K\x1b8", rows)
+}
+
+/// Escape sequences that keep the session's output from scrolling over the status line
+pub fn reserve(rows: u16) -> String {
+    // Setting the region moves the cursor, so it has to be saved and restored around it
+    format!("\x1b7\x1b[1;{}r\x1b8", rows.saturating_sub(1).max(1))
+}
+
+/// Whether the output may have reset the scrolling region, so the status line's row has to be
+/// reserved again
+pub fn resets_region(output: &[u8]) -> bool {
+    contains(output, b"\x1b[r") || contains(output, b"\x1bc") || contains(output, b"\x1b[?1049")
+}
+
+/// Whether the output may have erased the status line
+pub fn erases_status(output: &[u8]) -> bool {
+    resets_region(output)
+        || [&b"\x1b[J"[..], b"\x1b[0J", b"\x1b[2J", b"\x1b[3J"]
+            .iter()
+            .any(|seq| contains(output, seq))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    #[default]
+    Ground,
+    /// Inside a UTF-8 character, with this many bytes left
+    Utf8(u8),
+    Escape,
+    Csi,
+    /// Inside an OSC, DCS, SOS, PM or APC string
+    String,
+    StringEscape,
+}
+
+/// Tracks whether the session's output stopped in the middle of an escape sequence or
+/// character, where the status line can't be drawn without corrupting it.
+#[derive(Default)]
+pub struct OutputState {
+    state: State,
+}
+
+impl OutputState {
+    pub fn feed(&mut self, output: &[u8]) {
+        for &byte in output {
+            self.state = match (self.state, byte) {
+                (State::Utf8(n), 0x80..=0xbf) if n > 1 => State::Utf8(n - 1),
+                (State::Utf8(_), 0x80..=0xbf) => State::Ground,
+                (State::Ground | State::Utf8(_), 0x1b) => State::Escape,
+                (State::Ground | State::Utf8(_), 0xc0..=0xdf) => State::Utf8(1),
+                (State::Ground | State::Utf8(_), 0xe0..=0xef) => State::Utf8(2),
+                (State::Ground | State::Utf8(_), 0xf0..=0xf7) => State::Utf8(3),
+                (State::Ground | State::Utf8(_), _) => State::Ground,
+                (State::Escape, b'[') => State::Csi,
+                (State::Escape, b']' | b'P' | b'X' | b'^' | b'_') => State::String,
+                // Intermediate bytes, as in ESC ( B
+                (State::Escape, 0x20..=0x2f) => State::Escape,
+                (State::Escape, _) => State::Ground,
+                (State::Csi, 0x40..=0x7e) => State::Ground,
+                (State::Csi, _) => State::Csi,
+                (State::String | State::StringEscape, 0x07) => State::Ground,
+                (State::String | State::StringEscape, 0x1b) => State::StringEscape,
+                (State::StringEscape, b'\\') => State::Ground,
+                (State::String | State::StringEscape, _) => State::String,
+            };
+        }
+    }
+
+    /// Whether something else can be written to the terminal without breaking up the output
+    pub fn is_ground(&self) -> bool {
+        self.state == State::Ground
+    }
+}
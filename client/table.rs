@@ -0,0 +1,238 @@
+use sesh_cli::Column;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Every column, in the order they're shown by default
+pub const ALL: [Column; 9] = [
+    Column::Id,
+    Column::Name,
+    Column::Started,
+    Column::Attached,
+    Column::Program,
+    Column::Cwd,
+    Column::Pid,
+    Column::Tags,
+    Column::Note,
+];
+/// Columns left out when the table doesn't fit, least important first
+const DROPPABLE: [Column; 2] = [Column::Started, Column::Attached];
+/// Columns cut short when the table still doesn't fit, in the order they give up space
+const SHRINKABLE: [Column; 5] = [
+    Column::Note,
+    Column::Tags,
+    Column::Cwd,
+    Column::Program,
+    Column::Name,
+];
+/// Narrowest a column is cut down to
+const MIN_WIDTH: usize = 6;
+
+/// Width a table with columns of the given widths takes, with borders, separators and padding
+fn table_width(widths: &[usize]) -> usize {
+    widths.iter().sum::<usize>() + widths.len() * 3 + 1
+}
+
+/// Picks the columns to show and how wide each can be.
+///
+/// `cells` holds the plain text of each row, with a cell for every column in `columns`.
+/// Unless `exact` is set, less important columns are left out until the table fits in
+/// `max_width`. If it still doesn't fit, the name and program columns are narrowed.
+pub fn layout(
+    mut columns: Vec<Column>,
+    titles: &[String],
+    cells: &[Vec<String>],
+    max_width: Option<usize>,
+    exact: bool,
+) -> Vec<(Column, usize)> {
+    let mut widths = columns
+        .iter()
+        .enumerate()
+        .map(|(i, _)| {
+            cells
+                .iter()
+                .map(|row| row[i].width())
+                .chain([titles[i].width()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    let Some(max_width) = max_width else {
+        return columns.into_iter().zip(widths).collect();
+    };
+
+    if !exact {
+        for drop in DROPPABLE {
+            if table_width(&widths) <= max_width {
+                break;
+            }
+            if let Some(i) = columns.iter().position(|c| *c == drop) {
+                columns.remove(i);
+                widths.remove(i);
+            }
+        }
+    }
+    for shrink in SHRINKABLE {
+        let over = table_width(&widths).saturating_sub(max_width);
+        if over == 0 {
+            break;
+        }
+        if let Some(i) = columns.iter().position(|c| *c == shrink) {
+            widths[i] -= over.min(widths[i].saturating_sub(MIN_WIDTH));
+        }
+    }
+    columns.into_iter().zip(widths).collect()
+}
+
+/// How long before `now` the time `millis` was, both in unix milliseconds, like "2h ago".
+/// Times in the future are taken to be clock skew between the server and client, and shown as
+/// just now.
+pub fn ago(millis: i64, now: i64) -> String {
+    let secs = now.saturating_sub(millis).max(0) / 1000;
+    let (mins, hours, days) = (secs / 60, secs / 3600, secs / 86400);
+    match days {
+        _ if mins == 0 => "just now".to_owned(),
+        _ if hours == 0 => format!("{}m ago", mins),
+        0 => format!("{}h ago", hours),
+        1..=6 => format!("{}d ago", days),
+        7..=364 => format!("{}w ago", days / 7),
+        _ => format!("{}y ago", days / 365),
+    }
+}
+
+/// How long from the time `millis` until `now`, both in unix milliseconds, in its two largest
+/// units, like "3h 12m". As with `ago`, times in the future count as no time at all.
+pub fn duration(millis: i64, now: i64) -> String {
+    let secs = now.saturating_sub(millis).max(0) / 1000;
+    let (mins, hours, days) = (secs / 60, secs / 3600, secs / 86400);
+    match () {
+        _ if mins == 0 => format!("{}s", secs),
+        _ if hours == 0 => format!("{}m {}s", mins, secs % 60),
+        _ if days == 0 => format!("{}h {}m", hours, mins % 60),
+        _ => format!("{}d {}h", days, hours % 24),
+    }
+}
+
+/// Cuts a string down to the given number of terminal cells, ending it with an ellipsis if it
+/// was too long
+pub fn truncate(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_owned();
+    }
+    let mut used = 0;
+    let mut truncated = s
+        .chars()
+        .take_while(|c| {
+            used += c.width().unwrap_or(0);
+            used < width
+        })
+        .collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEC: i64 = 1000;
+    const MIN: i64 = 60 * SEC;
+    const HOUR: i64 = 60 * MIN;
+    const DAY: i64 = 24 * HOUR;
+
+    #[test]
+    fn relative_times() {
+        let now = 1_700_000_000_000;
+        assert_eq!(ago(now, now), "just now");
+        assert_eq!(ago(now - 59 * SEC, now), "just now");
+        assert_eq!(ago(now - MIN, now), "1m ago");
+        assert_eq!(ago(now - 59 * MIN, now), "59m ago");
+        assert_eq!(ago(now - HOUR, now), "1h ago");
+        assert_eq!(ago(now - 23 * HOUR, now), "23h ago");
+        assert_eq!(ago(now - DAY, now), "1d ago");
+        assert_eq!(ago(now - 6 * DAY, now), "6d ago");
+        assert_eq!(ago(now - 7 * DAY, now), "1w ago");
+        assert_eq!(ago(now - 364 * DAY, now), "52w ago");
+        assert_eq!(ago(now - 365 * DAY, now), "1y ago");
+        // Clock skew puts the time ahead of now
+        assert_eq!(ago(now + HOUR, now), "just now");
+        assert_eq!(ago(i64::MAX, i64::MIN), "just now");
+    }
+
+    #[test]
+    fn durations() {
+        let now = 1_700_000_000_000;
+        assert_eq!(duration(now, now), "0s");
+        assert_eq!(duration(now - 59 * SEC, now), "59s");
+        assert_eq!(duration(now - MIN - SEC, now), "1m 1s");
+        assert_eq!(duration(now - HOUR - 12 * MIN - 5 * SEC, now), "1h 12m");
+        assert_eq!(duration(now - 2 * DAY - 3 * HOUR - MIN, now), "2d 3h");
+        assert_eq!(duration(now + DAY, now), "0s");
+    }
+
+    #[test]
+    fn truncated_to_cells() {
+        assert_eq!(truncate("vim", 3), "vim");
+        assert_eq!(truncate("cargo-watch", 6), "cargo…");
+        // Multibyte characters are cut whole, and wide ones count for two cells
+        assert_eq!(truncate("héllo wörld", 6), "héllo…");
+        assert_eq!(truncate("日本語のノート", 7), "日本語…");
+        assert_eq!(truncate("日本語", 4), "日…");
+    }
+
+    /// Titles and one row for the columns, with each cell as wide as its width in `widths`
+    fn table(columns: &[Column], widths: &[usize]) -> (Vec<String>, Vec<Vec<String>>) {
+        let titles = columns.iter().map(|_| "x".to_owned()).collect();
+        let row = widths.iter().map(|&w| "y".repeat(w)).collect();
+        (titles, vec![row])
+    }
+
+    #[test]
+    fn layout_fits_the_width() {
+        let columns = [
+            Column::Id,
+            Column::Name,
+            Column::Started,
+            Column::Attached,
+            Column::Program,
+        ];
+        let (titles, cells) = table(&columns, &[1, 10, 8, 8, 20]);
+        // Everything fits: 47 cells of text, 3 for each column, and the last border
+        let all = layout(columns.to_vec(), &titles, &cells, Some(63), false);
+        assert_eq!(all.len(), 5);
+        assert_eq!(layout(columns.to_vec(), &titles, &cells, None, false), all);
+        // Started goes first, then Attached
+        let dropped = layout(columns.to_vec(), &titles, &cells, Some(62), false);
+        assert_eq!(
+            dropped,
+            [
+                (Column::Id, 1),
+                (Column::Name, 10),
+                (Column::Attached, 8),
+                (Column::Program, 20)
+            ]
+        );
+        let dropped = layout(columns.to_vec(), &titles, &cells, Some(43), false);
+        assert_eq!(
+            dropped,
+            [(Column::Id, 1), (Column::Name, 10), (Column::Program, 20)]
+        );
+        // Then the program is narrowed, down to the narrowest a column gets
+        let shrunk = layout(columns.to_vec(), &titles, &cells, Some(35), false);
+        assert_eq!(
+            shrunk,
+            [(Column::Id, 1), (Column::Name, 10), (Column::Program, 14)]
+        );
+        let shrunk = layout(columns.to_vec(), &titles, &cells, Some(10), false);
+        assert_eq!(
+            shrunk,
+            [
+                (Column::Id, 1),
+                (Column::Name, MIN_WIDTH),
+                (Column::Program, MIN_WIDTH)
+            ]
+        );
+        // Columns asked for exactly are narrowed but never dropped
+        let exact = layout(columns.to_vec(), &titles, &cells, Some(43), true);
+        assert_eq!(exact.len(), 5);
+        assert_eq!(exact[4], (Column::Program, MIN_WIDTH));
+    }
+}
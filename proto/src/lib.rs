@@ -1,6 +1,11 @@
 use std::fmt::Display;
 
-tonic::include_proto!("sesh");
+// A batch command holds any of the requests, and start requests are much bigger than the rest
+#[allow(clippy::large_enum_variant)]
+mod generated {
+    tonic::include_proto!("sesh");
+}
+pub use generated::*;
 
 impl Display for sesh_attach_request::Session {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -0,0 +1,826 @@
+//! Setting up a server and serving on a socket, apart from `main`, so tests can run one on a
+//! temporary directory inside the test process.
+
+use std::{
+    os::fd::AsRawFd,
+    path::PathBuf,
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use log::info;
+use sesh_proto::{sesh_event::Kind, seshd_server::SeshdServer};
+use sesh_shared::runtime_dir;
+use tokio::{
+    net::{UnixListener, UnixStream},
+    signal::unix::{signal, SignalKind},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        watch,
+    },
+    task::JoinSet,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Server as RPCServer;
+
+use crate::{commands, events, state::State, upgrade::Handover, Seshd, SHUTDOWN_GRACE};
+
+/// Sets up a server: where it keeps its files, and whether it exits on its own
+pub struct SeshdBuilder {
+    runtime_dir: PathBuf,
+    socket_path: Option<PathBuf>,
+    state_path: Option<PathBuf>,
+    exit_on_empty: bool,
+    signals: bool,
+}
+
+impl SeshdBuilder {
+    /// A server keeping its sockets in `runtime_dir`, which exits once it has no sessions left
+    /// and on SIGINT or SIGQUIT
+    pub fn new(runtime_dir: PathBuf) -> Self {
+        Self {
+            runtime_dir,
+            socket_path: None,
+            state_path: None,
+            exit_on_empty: true,
+            signals: true,
+        }
+    }
+
+    /// The socket clients connect to, which is removed once the server exits. Defaults to
+    /// server.sock in the runtime directory
+    pub fn socket_path(mut self, path: PathBuf) -> Self {
+        self.socket_path = Some(path);
+        self
+    }
+
+    /// Where the list of dead sessions is kept. Defaults to sessions.json in the runtime
+    /// directory
+    pub fn state_path(mut self, path: PathBuf) -> Self {
+        self.state_path = Some(path);
+        self
+    }
+
+    /// Whether the server exits once its last session is gone
+    // Only a server embedded in tests sets this and the signals
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn exit_on_empty(mut self, exit_on_empty: bool) -> Self {
+        self.exit_on_empty = exit_on_empty;
+        self
+    }
+
+    /// Whether the server exits on SIGINT and SIGQUIT. A server embedded in another process
+    /// leaves its signals alone.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn signals(mut self, signals: bool) -> Self {
+        self.signals = signals;
+        self
+    }
+
+    /// Prepares the runtime directory and sets up the server, without serving yet
+    pub fn build(self) -> Result<SeshdHandle> {
+        runtime_dir::prepare(&self.runtime_dir)?;
+        let socket_path = self
+            .socket_path
+            .unwrap_or_else(|| self.runtime_dir.join("server.sock"));
+        let state_path = self
+            .state_path
+            .unwrap_or_else(|| self.runtime_dir.join("sessions.json"));
+        let state = Arc::new(State::load(state_path));
+
+        let (exit_tx, exit_rx) = mpsc::channel::<()>(1);
+        if self.signals {
+            exit_on_signals(exit_tx.clone())?;
+        }
+
+        let seshd = Seshd::new(
+            exit_tx,
+            self.runtime_dir,
+            socket_path,
+            state,
+            self.exit_on_empty,
+        )?;
+        Ok(SeshdHandle {
+            seshd: Arc::new(seshd),
+            exit_rx: Arc::new(Mutex::new(Some(exit_rx))),
+        })
+    }
+}
+
+/// Sends an exit on the first SIGINT or SIGQUIT
+fn exit_on_signals(exit_tx: Sender<()>) -> Result<()> {
+    let mut sigint = signal(SignalKind::interrupt())?;
+    let mut sigquit = signal(SignalKind::quit())?;
+    tokio::task::spawn(async move {
+        tokio::select! {
+            _ = sigint.recv() => info!(target: "exit", "Received SIGINT"),
+            _ = sigquit.recv() => info!(target: "exit", "Received SIGQUIT"),
+        }
+        exit_tx.send(()).await.ok();
+    });
+    Ok(())
+}
+
+/// Accepts clients on `listener` while `accepting` is set. While it isn't, they wait in the
+/// socket's backlog for whichever server accepts next, which after a handover is the new one.
+fn accept(
+    listener: UnixListener,
+    mut accepting: watch::Receiver<bool>,
+) -> Receiver<std::io::Result<UnixStream>> {
+    let (tx, rx) = mpsc::channel(1);
+    tokio::task::spawn(async move {
+        loop {
+            let accept = *accepting.borrow_and_update();
+            tokio::select! {
+                accepted = listener.accept(), if accept => {
+                    if tx.send(accepted.map(|(stream, _)| stream)).await.is_err() {
+                        break;
+                    }
+                }
+                changed = accepting.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                }
+                // The server stopped serving
+                _ = tx.closed() => break,
+            }
+        }
+    });
+    rx
+}
+
+/// A server that's been set up, which serves until it's shut down
+#[derive(Clone)]
+pub struct SeshdHandle {
+    seshd: Arc<Seshd>,
+    /// Taken by the one call to `serve`
+    exit_rx: Arc<Mutex<Option<Receiver<()>>>>,
+}
+
+impl SeshdHandle {
+    /// Takes over the sessions of the server that's upgrading to this one
+    pub fn adopt(&self, handover: Handover) -> Result<()> {
+        self.seshd.adopt(handover)
+    }
+
+    /// Serves clients on `listener` until the server is shut down, then stops its sessions and
+    /// removes its socket
+    pub async fn serve(self, listener: UnixListener) -> Result<()> {
+        let mut exit_rx = self
+            .exit_rx
+            .lock()
+            .expect("exit lock poisoned")
+            .take()
+            .ok_or_else(|| anyhow!("The server is already serving"))?;
+        self.seshd
+            .listener_fd
+            .store(listener.as_raw_fd(), Ordering::Release);
+
+        info!(target: "init", "Setting up RPC server");
+        let incoming = accept(listener, self.seshd.accepting.subscribe());
+        let event_tx = self.seshd.events.clone();
+        let channels = Arc::clone(&self.seshd.channels);
+        RPCServer::builder()
+            .add_service(SeshdServer::from_arc(Arc::clone(&self.seshd)))
+            .serve_with_incoming_shutdown(ReceiverStream::new(incoming), async move {
+                exit_rx.recv().await;
+                // Event streams and waits end after this, so the server can finish shutting down
+                event_tx.send(events::server_event(Kind::Shutdown)).ok();
+                channels.close();
+            })
+            .await?;
+
+        info!(target: "exit", "Shutting down");
+        // Stop the sessions' processes and wait for them, instead of leaving it to each pty's drop
+        let mut stopping = JoinSet::new();
+        for mut session in self.seshd.sessions.remove_all() {
+            stopping.spawn(async move {
+                let mut exit = session.exit.clone();
+                let status = commands::terminate(session.pid(), &mut exit, SHUTDOWN_GRACE).await;
+                info!(target: &session.log_group(), "Subprocess exited with {}", status);
+                // The process has already been reaped, so don't signal its pid again on drop
+                session.pty.daemonize();
+            });
+        }
+        while stopping.join_next().await.is_some() {}
+        // sessions are killed on a clean exit, so only the dead ones need to be remembered
+        self.seshd.state.save(Vec::new())?;
+        // remove socket on exit
+        std::fs::remove_file(&self.seshd.socket_path)?;
+
+        Ok(())
+    }
+
+    /// Asks the server to stop serving, as `sesh shutdown` does
+    // The server only stops on its own or by request, unless it's embedded in tests
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn shutdown(&self) {
+        // The channel only holds one exit, so a full one already has an exit waiting
+        self.seshd.exit_signal.try_send(()).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use sesh_proto::{
+        client_session_ended_request::Reason, sesh_attach_request, sesh_follow_request,
+        sesh_follow_response, sesh_kill_request, sesh_kill_response, sesh_redraw_request,
+        seshd_client::SeshdClient, SeshAttachRequest, SeshFollowRequest, SeshFollowResponse,
+        SeshKillRequest, SeshListRequest, SeshRedrawRequest, SeshStartRequest,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::UnixStream,
+        task::JoinHandle,
+    };
+    use tonic::{
+        transport::{Channel, Endpoint, Uri},
+        Streaming,
+    };
+    use tower::service_fn;
+
+    use super::*;
+
+    /// A server embedded in the test, serving on a socket in a directory of its own
+    struct TestServer {
+        dir: PathBuf,
+        handle: SeshdHandle,
+        serving: JoinHandle<Result<()>>,
+        client: SeshdClient<Channel>,
+    }
+
+    impl TestServer {
+        async fn start(test: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("sesh-test-{}-{}", test, std::process::id()));
+            std::fs::remove_dir_all(&dir).ok();
+            let handle = SeshdBuilder::new(dir.clone())
+                .exit_on_empty(false)
+                .signals(false)
+                .build()
+                .expect("failed to build server");
+            let socket = dir.join("server.sock");
+            let listener = UnixListener::bind(&socket).expect("failed to bind server socket");
+            let serving = tokio::task::spawn(handle.clone().serve(listener));
+            let channel = Endpoint::try_from("http://[::]:50051")
+                .expect("invalid endpoint")
+                .connect_with_connector(service_fn(move |_: Uri| {
+                    UnixStream::connect(socket.clone())
+                }))
+                .await
+                .expect("failed to connect to server");
+            Self {
+                dir,
+                handle,
+                serving,
+                client: SeshdClient::new(channel),
+            }
+        }
+
+        async fn start_session(&mut self, name: &str, program: &str) -> String {
+            self.client
+                .start_session(SeshStartRequest {
+                    name: name.to_owned(),
+                    program: program.to_owned(),
+                    pwd: "/".to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .expect("failed to start session")
+                .into_inner()
+                .socket
+        }
+
+        async fn stop(self) {
+            self.handle.shutdown();
+            self.serving
+                .await
+                .expect("server panicked")
+                .expect("server failed");
+            assert!(!self.dir.join("server.sock").exists());
+            std::fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+
+    #[tokio::test]
+    async fn start_and_list() {
+        let mut server = TestServer::start("list").await;
+        server.start_session("one", "cat").await;
+        server.start_session("two", "cat").await;
+        let list = server
+            .client
+            .list_sessions(SeshListRequest::default())
+            .await
+            .expect("failed to list sessions")
+            .into_inner();
+        let mut names = list
+            .sessions
+            .iter()
+            .map(|s| (s.name.as_str(), s.program.as_str()))
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, [("one", "cat"), ("two", "cat")]);
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn attach_echoes_input() {
+        let mut server = TestServer::start("attach").await;
+        let socket = server.start_session("echo", "cat").await;
+        server
+            .client
+            .attach_session(SeshAttachRequest {
+                session: Some(sesh_attach_request::Session::Name("echo".to_owned())),
+                ..Default::default()
+            })
+            .await
+            .expect("failed to attach");
+        let mut stream = UnixStream::connect(&socket)
+            .await
+            .expect("failed to connect to session");
+        stream.write_all(b"round trip\n").await.unwrap();
+        let mut output = Vec::new();
+        let mut packet = [0; 4096];
+        // The line comes back twice, echoed by the pty and then printed by cat
+        let read = async {
+            while output.windows(10).filter(|w| w == b"round trip").count() < 2 {
+                let n = stream.read(&mut packet).await.expect("failed to read");
+                assert_ne!(n, 0, "session closed the connection");
+                output.extend_from_slice(&packet[..n]);
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), read)
+            .await
+            .expect("timed out waiting for output");
+        drop(stream);
+        server.stop().await;
+    }
+
+    /// Reads a session's socket until `text` has come back from cat, after the pty's echo
+    async fn echoed(stream: &mut UnixStream, text: &str) {
+        let mut output = Vec::new();
+        let mut packet = [0; 4096];
+        let read = async {
+            while String::from_utf8_lossy(&output).matches(text).count() < 2 {
+                let n = stream.read(&mut packet).await.expect("failed to read");
+                assert_ne!(n, 0, "session closed the connection");
+                output.extend_from_slice(&packet[..n]);
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), read)
+            .await
+            .unwrap_or_else(|_| panic!("{:?} never came back in {:?}", text, output));
+    }
+
+    #[tokio::test]
+    async fn attach_survives_a_handover() {
+        let mut old = TestServer::start("handover-old").await;
+        let socket = old.start_session("kept", "cat").await;
+        old.client
+            .attach_session(SeshAttachRequest {
+                session: Some(sesh_attach_request::Session::Name("kept".to_owned())),
+                ..Default::default()
+            })
+            .await
+            .expect("failed to attach");
+        let mut stream = UnixStream::connect(&socket)
+            .await
+            .expect("failed to connect to session");
+        stream.write_all(b"before\n").await.unwrap();
+        echoed(&mut stream, "before").await;
+
+        let new = TestServer::start("handover-new").await;
+        let path = old.dir.join("upgrade.sock");
+        let listener = UnixListener::bind(&path).expect("failed to bind handover socket");
+        let receiving = tokio::task::spawn_blocking(move || Handover::receive(&path));
+        let adopting = async {
+            let handover = receiving
+                .await
+                .expect("receiving panicked")
+                .expect("failed to receive sessions");
+            // The new server here already has its own socket
+            drop(handover.listener().expect("missing server listener"));
+            new.handle.adopt(handover)
+        };
+        let (sent, adopted) = tokio::join!(old.handle.seshd.hand_over_on(&listener), adopting);
+        sent.expect("failed to hand over");
+        adopted.expect("failed to adopt");
+
+        // Only the new server relays to the client now, which didn't have to attach again
+        stream.write_all(b"after\n").await.unwrap();
+        echoed(&mut stream, "after").await;
+        let clients = |server: &TestServer| {
+            let session = server.handle.seshd.sessions.get("kept");
+            session.map(|session| session.info.clients().load(Ordering::Acquire))
+        };
+        assert_eq!(clients(&new), Some(1));
+        assert_eq!(clients(&old), Some(0));
+
+        drop(stream);
+        new.stop().await;
+        old.stop().await;
+    }
+
+    /// Reads a follower's stream until its output has `text` in it
+    async fn follow_until(stream: &mut Streaming<SeshFollowResponse>, text: &str) {
+        let mut output = Vec::new();
+        let read = async {
+            while !String::from_utf8_lossy(&output).contains(text) {
+                let message = stream
+                    .message()
+                    .await
+                    .expect("follower failed")
+                    .expect("follower stream ended");
+                if let Some(sesh_follow_response::Event::Output(bytes)) = message.event {
+                    output.extend_from_slice(&bytes);
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), read)
+            .await
+            .unwrap_or_else(|_| panic!("{:?} never came in {:?}", text, output));
+    }
+
+    #[tokio::test]
+    async fn follow_doesnt_take_the_session() {
+        let mut server = TestServer::start("follow").await;
+        let socket = server.start_session("watched", "cat").await;
+        let mut follower = server
+            .client
+            .follow_session(SeshFollowRequest {
+                session: Some(sesh_follow_request::Session::Name("watched".to_owned())),
+            })
+            .await
+            .expect("failed to follow")
+            .into_inner();
+        let started = follower
+            .message()
+            .await
+            .expect("follower failed")
+            .and_then(|m| m.event);
+        assert!(matches!(
+            started,
+            Some(sesh_follow_response::Event::Started(started)) if started.name == "watched"
+        ));
+
+        // The session can be attached to while it's followed, and the follower sees the same
+        server
+            .client
+            .attach_session(SeshAttachRequest {
+                session: Some(sesh_attach_request::Session::Name("watched".to_owned())),
+                ..Default::default()
+            })
+            .await
+            .expect("failed to attach to a followed session");
+        let mut stream = UnixStream::connect(&socket)
+            .await
+            .expect("failed to connect to session");
+        stream.write_all(b"shared\n").await.unwrap();
+        follow_until(&mut follower, "shared").await;
+
+        // A second follower doesn't get in the way of the first either
+        let mut second = server
+            .client
+            .follow_session(SeshFollowRequest {
+                session: Some(sesh_follow_request::Session::Name("watched".to_owned())),
+            })
+            .await
+            .expect("failed to follow an attached session")
+            .into_inner();
+        stream.write_all(b"again\n").await.unwrap();
+        follow_until(&mut second, "again").await;
+
+        server
+            .client
+            .kill_session(SeshKillRequest {
+                session: Some(sesh_kill_request::Session::Name("watched".to_owned())),
+                grace_ms: 1000,
+                signal: 0,
+            })
+            .await
+            .expect("failed to kill session");
+        let ended = async {
+            loop {
+                let message = follower.message().await.expect("follower failed");
+                match message.and_then(|m| m.event) {
+                    Some(sesh_follow_response::Event::Ended(ended)) => return Some(ended),
+                    Some(_) => continue,
+                    None => return None,
+                }
+            }
+        };
+        let ended = tokio::time::timeout(Duration::from_secs(5), ended)
+            .await
+            .expect("follower was never told the session ended")
+            .expect("follower stream ended without the reason");
+        assert_eq!(ended.reason(), Reason::Killed);
+        drop(stream);
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn follow_reads_a_session_nobody_is_attached_to() {
+        let mut server = TestServer::start("follow-detached").await;
+        server
+            .client
+            .start_session(SeshStartRequest {
+                name: "alone".to_owned(),
+                program: "/bin/sh".to_owned(),
+                args: vec![
+                    "-c".to_owned(),
+                    "while :; do echo unattached; /bin/sleep 0.05; done".to_owned(),
+                ],
+                pwd: "/".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .expect("failed to start session");
+        let mut follower = server
+            .client
+            .follow_session(SeshFollowRequest {
+                session: Some(sesh_follow_request::Session::Name("alone".to_owned())),
+            })
+            .await
+            .expect("failed to follow")
+            .into_inner();
+        follow_until(&mut follower, "unattached").await;
+        let list = server
+            .client
+            .list_sessions(SeshListRequest::default())
+            .await
+            .expect("failed to list sessions")
+            .into_inner();
+        assert!(!list.sessions[0].connected);
+        drop(follower);
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn kill_removes_socket() {
+        let mut server = TestServer::start("kill").await;
+        let socket = server.start_session("doomed", "cat").await;
+        assert!(Path::new(&socket).exists());
+        let killed = server
+            .client
+            .kill_session(SeshKillRequest {
+                session: Some(sesh_kill_request::Session::Name("doomed".to_owned())),
+                grace_ms: 1000,
+                signal: 0,
+            })
+            .await
+            .expect("failed to kill session")
+            .into_inner();
+        assert!(killed.success);
+        assert!(!Path::new(&socket).exists());
+        let list = server
+            .client
+            .list_sessions(SeshListRequest::default())
+            .await
+            .expect("failed to list sessions")
+            .into_inner();
+        assert!(list.sessions.is_empty());
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn kill_signal_reaches_the_process_group() {
+        let mut server = TestServer::start("signal").await;
+        let got = server.dir.join("got-usr1");
+        // The child runs in the session's process group, but isn't its leader
+        let child = format!(
+            "trap ': > {}; exit' USR1; while :; do /bin/sleep 0.05; done",
+            got.display()
+        );
+        server
+            .client
+            .start_session(SeshStartRequest {
+                name: "group".to_owned(),
+                program: "/bin/sh".to_owned(),
+                args: vec![
+                    "-c".to_owned(),
+                    format!("trap : USR1; /bin/sh -c \"{}\" & wait; wait", child),
+                ],
+                pwd: "/".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .expect("failed to start session");
+        // Give the child time to set its trap
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let killed = server
+            .client
+            .kill_session(SeshKillRequest {
+                session: Some(sesh_kill_request::Session::Name("group".to_owned())),
+                grace_ms: 0,
+                signal: libc::SIGUSR1,
+            })
+            .await
+            .expect("failed to signal session")
+            .into_inner();
+        assert!(killed.success);
+        let received = async {
+            while !got.exists() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), received)
+            .await
+            .expect("the child never got the signal");
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn kill_signal_failure_is_reported() {
+        let mut server = TestServer::start("signal-failed").await;
+        server.start_session("bad", "cat").await;
+        let killed = server
+            .client
+            .kill_session(SeshKillRequest {
+                session: Some(sesh_kill_request::Session::Name("bad".to_owned())),
+                grace_ms: 0,
+                // Not a signal, so kill(2) fails with EINVAL
+                signal: 1000,
+            })
+            .await
+            .expect("a failed signal should not fail the request")
+            .into_inner();
+        assert!(!killed.success);
+        assert_eq!(killed.error(), sesh_kill_response::Error::SignalFailed);
+        assert_eq!(killed.name, "bad");
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn redraw_reaches_the_foreground_job() {
+        let mut server = TestServer::start("redraw").await;
+        let got = server.dir.join("got-winch");
+        // With job control on, the job runs in the foreground of the pty in a group of its own,
+        // as an editor started from an interactive shell does
+        let job = format!(
+            "trap ': > {}; exit' WINCH; while :; do /bin/sleep 0.05; done",
+            got.display()
+        );
+        server
+            .client
+            .start_session(SeshStartRequest {
+                name: "job".to_owned(),
+                program: "/bin/sh".to_owned(),
+                args: vec!["-c".to_owned(), format!("set -m; /bin/sh -c \"{}\"", job)],
+                pwd: "/".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .expect("failed to start session");
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        server
+            .client
+            .redraw_session(SeshRedrawRequest {
+                session: Some(sesh_redraw_request::Session::Name("job".to_owned())),
+            })
+            .await
+            .expect("failed to redraw session");
+        let received = async {
+            while !got.exists() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), received)
+            .await
+            .expect("the foreground job never got SIGWINCH");
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn session_ends_with_its_program() {
+        let mut server = TestServer::start("exit").await;
+        let socket = server.start_session("brief", "true").await;
+        let gone = async {
+            while Path::new(&socket).exists() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), gone)
+            .await
+            .expect("session outlived its program");
+        server.stop().await;
+    }
+
+    /// Children of the test process that have exited but haven't been reaped
+    fn zombies() -> Vec<i32> {
+        let me = std::process::id().to_string();
+        let Ok(procs) = std::fs::read_dir("/proc") else {
+            return Vec::new();
+        };
+        procs
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<i32>().ok())
+            .filter(|pid| {
+                let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+                    return false;
+                };
+                // The command name is in parentheses and may contain spaces, so the fields
+                // after it are split from its closing one
+                let Some((_, fields)) = stat.rsplit_once(')') else {
+                    return false;
+                };
+                let mut fields = fields.split_whitespace();
+                fields.next() == Some("Z") && fields.next() == Some(me.as_str())
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn concurrent_start_and_kill_leaves_no_zombies() {
+        let server = TestServer::start("zombies").await;
+        let mut pairs = JoinSet::new();
+        for i in 0..50 {
+            let mut client = server.client.clone();
+            pairs.spawn(async move {
+                let name = format!("brief-{}", i);
+                // Some exit on their own while being killed, racing the kill against the reaper
+                client
+                    .start_session(SeshStartRequest {
+                        name: name.clone(),
+                        program: "sleep".to_owned(),
+                        args: vec![format!("0.0{}", i % 10)],
+                        pwd: "/".to_owned(),
+                        ..Default::default()
+                    })
+                    .await
+                    .expect("failed to start session");
+                let killed = client
+                    .kill_session(SeshKillRequest {
+                        session: Some(sesh_kill_request::Session::Name(name)),
+                        grace_ms: 1000,
+                        signal: 0,
+                    })
+                    .await
+                    .expect("failed to kill session")
+                    .into_inner();
+                assert!(killed.success || killed.error() == sesh_kill_response::Error::NotFound);
+            });
+        }
+        while let Some(pair) = pairs.join_next().await {
+            pair.expect("start and kill panicked");
+        }
+        // Sessions of other tests may have just exited, so give the reaper a moment
+        let reaped = async {
+            while !zombies().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), reaped)
+            .await
+            .unwrap_or_else(|_| panic!("zombies left behind: {:?}", zombies()));
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn concurrent_starts_get_their_own_names() {
+        let mut server = TestServer::start("same-name").await;
+        let mut starts = JoinSet::new();
+        for _ in 0..10 {
+            let mut client = server.client.clone();
+            starts.spawn(async move {
+                client
+                    .start_session(SeshStartRequest {
+                        name: "twin".to_owned(),
+                        program: "cat".to_owned(),
+                        pwd: "/".to_owned(),
+                        ..Default::default()
+                    })
+                    .await
+                    .expect("failed to start session")
+                    .into_inner()
+            });
+        }
+        let mut names = Vec::new();
+        while let Some(started) = starts.join_next().await {
+            let started = started.expect("start panicked");
+            // No start took the socket of another
+            assert!(Path::new(&started.socket).exists());
+            names.push(started.name);
+        }
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), 10);
+        let list = server
+            .client
+            .list_sessions(SeshListRequest::default())
+            .await
+            .expect("failed to list sessions")
+            .into_inner();
+        assert_eq!(list.sessions.len(), 10);
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn shutdown_stops_sessions() {
+        let mut server = TestServer::start("shutdown").await;
+        let socket = server.start_session("left", "cat").await;
+        server.stop().await;
+        assert!(!Path::new(&socket).exists());
+    }
+}
@@ -0,0 +1,76 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::oneshot;
+
+#[derive(Default)]
+struct Channel {
+    waiters: Vec<oneshot::Sender<()>>,
+    /// Signaled while nobody was waiting, so the next wait returns immediately
+    woken: bool,
+}
+
+#[derive(Default)]
+struct Inner {
+    channels: HashMap<String, Channel>,
+    closed: bool,
+}
+
+/// Named channels that clients can wait on until another client signals them, used by
+/// `sesh wait-for` to synchronize scripts.
+#[derive(Default)]
+pub struct Channels {
+    inner: Mutex<Inner>,
+}
+
+impl Channels {
+    /// Waits until the channel is signaled, or returns an error if the server shuts down first.
+    ///
+    /// Dropping the future (e.g. because the client disconnected) cancels the wait.
+    pub async fn wait(&self, name: &str) -> Result<()> {
+        let rx = {
+            let mut inner = self.inner.lock().expect("channels lock poisoned");
+            if inner.closed {
+                return Err(anyhow!("Server is shutting down"));
+            }
+            let channel = inner.channels.entry(name.to_owned()).or_default();
+            if channel.woken {
+                inner.channels.remove(name);
+                return Ok(());
+            }
+            // Forget waiters whose clients have gone away
+            channel.waiters.retain(|tx| !tx.is_closed());
+            let (tx, rx) = oneshot::channel();
+            channel.waiters.push(tx);
+            rx
+        };
+        // The sender is only dropped without sending when the channels are closed
+        rx.await.map_err(|_| anyhow!("Server is shutting down"))
+    }
+
+    /// Wakes everything waiting on the channel, or the next waiter if there are none
+    pub fn signal(&self, name: &str) {
+        let mut inner = self.inner.lock().expect("channels lock poisoned");
+        let channel = inner.channels.remove(name).unwrap_or_default();
+        let mut woke = false;
+        for tx in channel.waiters {
+            woke |= tx.send(()).is_ok();
+        }
+        if !woke {
+            inner.channels.insert(
+                name.to_owned(),
+                Channel {
+                    waiters: Vec::new(),
+                    woken: true,
+                },
+            );
+        }
+    }
+
+    /// Cancels every wait, so that the server can shut down without waiting for them
+    pub fn close(&self) {
+        let mut inner = self.inner.lock().expect("channels lock poisoned");
+        inner.closed = true;
+        inner.channels.clear();
+    }
+}
@@ -1,11 +1,11 @@
-use std::{os::fd::AsRawFd, sync::atomic::Ordering};
+use std::sync::atomic::Ordering;
 
 use anyhow::Result;
 use log::info;
 use sesh_proto::{sesh_attach_request, SeshAttachResponse, WinSize};
-use sesh_shared::term::Size;
+use sesh_shared::{error::SeshError, term::Size};
 
-use crate::{Seshd, Session};
+use crate::Seshd;
 
 use super::CommandResponse;
 
@@ -15,6 +15,7 @@ impl Seshd {
         &self,
         session: Option<sesh_attach_request::Session>,
         size: Option<WinSize>,
+        keep_size: bool,
     ) -> Result<CommandResponse> {
         if let Some(session) = session {
             let session = match &session {
@@ -23,44 +24,48 @@ impl Seshd {
                     self.sessions.get_by_id(*id as usize)
                 }
             }
-            .ok_or_else(|| anyhow::anyhow!("Session {} not found", session))?;
+            .ok_or(SeshError::SessionNotFound)?;
             if session.info.connected().load(Ordering::Relaxed) {
-                return Err(anyhow::anyhow!("Session already connected"));
+                return Err(SeshError::SessionAlreadyConnected.into());
             }
             info!(target: &session.log_group(), "Attaching");
-            let size = if let Some(size) = size {
-                Size {
-                    rows: size.rows as u16,
-                    cols: size.cols as u16,
-                }
+            // A session shared between clients can be left at its size, so the other client's
+            // program isn't disturbed
+            let size = if keep_size {
+                None
+            } else if let Some(size) = size {
+                Some(Size::from(&size))
             } else {
-                Size::term_size()?
+                Some(Size::detect())
             };
-            session.pty.resize(&Size {
-                cols: size.cols.checked_sub(2).unwrap_or(2),
-                rows: size.rows.checked_sub(2).unwrap_or(2),
-            })?;
-            tokio::task::spawn({
-                let sock_path = session.info.sock_path().clone();
-                let socket = session.listener.clone();
-                let file = session.pty.file().as_raw_fd();
-                let file = unsafe { libc::fcntl(file, libc::F_DUPFD, file) };
-                let connected = session.info.connected();
-                let attach_time = session.info.attach_time.clone();
-                async move {
-                    Session::start(sock_path, socket, file, connected, size, attach_time).await?;
-                    Result::<_, anyhow::Error>::Ok(())
-                }
-            });
+            if let Some(size) = &size {
+                session.resize(Size {
+                    cols: size.cols.checked_sub(2).unwrap_or(2),
+                    rows: size.rows.checked_sub(2).unwrap_or(2),
+                })?;
+            }
+            session.info.set_connect_size(size);
+            self.serve_clients(&session, None)?;
 
+            // The client connects right after this, so the session will be at this size
+            let size = match size {
+                Some(size) => Size {
+                    rows: size.rows,
+                    cols: size.cols.saturating_sub(1),
+                },
+                None => session.info.size(),
+            };
             Ok(CommandResponse::AttachSession(SeshAttachResponse {
                 socket: session.info.sock_path().to_string_lossy().to_string(),
                 pid: session.pid(),
                 name: session.name.clone(),
                 program: session.program.clone(),
+                size: Some(WinSize::from(&size)),
+                title: session.title.clone().unwrap_or_default(),
+                start_time: session.info.start_time,
             }))
         } else {
-            anyhow::bail!("No session specified");
+            Err(SeshError::NoSessionSpecified.into())
         }
     }
 }
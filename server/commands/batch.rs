@@ -0,0 +1,68 @@
+use crate::Seshd;
+
+use anyhow::Result;
+use log::info;
+use sesh_proto::{
+    sesh_batch_command::Command as BatchCommand, sesh_batch_result::Result as BatchResult,
+    SeshBatchCommand, SeshBatchResponse, SeshBatchResult,
+};
+
+use super::{Command, CommandResponse};
+
+impl Seshd {
+    /// RPC handler for running several commands in one request
+    pub async fn exec_batch(
+        &self,
+        commands: Vec<SeshBatchCommand>,
+        fail_fast: bool,
+    ) -> Result<CommandResponse> {
+        info!(target: "batch", "Running {} commands", commands.len());
+        let mut results = Vec::with_capacity(commands.len());
+        for command in commands.into_iter().filter_map(|c| c.command) {
+            let command = match command {
+                BatchCommand::Start(req) => Command::StartSession(req),
+                BatchCommand::Kill(req) => Command::KillSession(req),
+                BatchCommand::Signal(req) => Command::SignalSession(req),
+                BatchCommand::Rename(req) => Command::RenameSession(req),
+                BatchCommand::Note(req) => Command::NoteSession(req),
+                BatchCommand::Detach(req) => Command::DetachSession(req),
+                BatchCommand::Reset(req) => Command::ResetSession(req),
+            };
+            // Boxed, since exec is what called this
+            let result = match Box::pin(self.exec(command)).await {
+                Ok(CommandResponse::StartSession(res)) => BatchResult::Start(res),
+                Ok(CommandResponse::KillSession(res)) => BatchResult::Kill(res),
+                Ok(CommandResponse::SignalSession(res)) => BatchResult::Signal(res),
+                Ok(CommandResponse::RenameSession(res)) => BatchResult::Rename(res),
+                Ok(CommandResponse::NoteSession(res)) => BatchResult::Note(res),
+                Ok(CommandResponse::DetachSession(res)) => BatchResult::Detach(res),
+                Ok(CommandResponse::ResetSession(res)) => BatchResult::Reset(res),
+                Ok(_) => BatchResult::Error("Unexpected response".to_owned()),
+                Err(e) => BatchResult::Error(e.to_string()),
+            };
+            let failed = failed(&result);
+            results.push(SeshBatchResult {
+                result: Some(result),
+            });
+            if failed && fail_fast {
+                info!(target: "batch", "Stopping after command {} failed", results.len());
+                break;
+            }
+        }
+        Ok(CommandResponse::Batch(SeshBatchResponse { results }))
+    }
+}
+
+/// Whether a command failed, including the ones that answer with why they didn't do anything
+fn failed(result: &BatchResult) -> bool {
+    match result {
+        BatchResult::Error(_) => true,
+        BatchResult::Kill(res) => !res.success,
+        BatchResult::Detach(res) => !res.success,
+        BatchResult::Reset(res) => !res.attached,
+        BatchResult::Start(_)
+        | BatchResult::Signal(_)
+        | BatchResult::Rename(_)
+        | BatchResult::Note(_) => false,
+    }
+}
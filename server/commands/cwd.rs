@@ -0,0 +1,24 @@
+use crate::Seshd;
+
+use anyhow::Result;
+use sesh_proto::{sesh_cwd_request as req, SeshCwdResponse};
+use sesh_shared::error::SeshError;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for finding the directory a session is working in
+    pub async fn exec_cwd(&self, session: Option<req::Session>) -> Result<CommandResponse> {
+        let session = match session {
+            Some(req::Session::Name(name)) => self.sessions.get(&name),
+            Some(req::Session::Id(id)) => self.sessions.get_by_id(id as usize),
+            None => None,
+        }
+        .ok_or(SeshError::SessionNotFound)?;
+
+        let cwd = session.cwd()?;
+        Ok(CommandResponse::SessionCwd(SeshCwdResponse {
+            cwd: cwd.to_string_lossy().to_string(),
+        }))
+    }
+}
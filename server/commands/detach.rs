@@ -1,38 +1,60 @@
 use std::sync::atomic::Ordering;
 
 use anyhow::Result;
-use log::info;
-use sesh_proto::{sesh_detach_request as req, SeshDetachResponse};
+use log::{info, warn};
+use sesh_proto::{
+    sesh_detach_request as req, sesh_detach_response::Error, sesh_event::Kind, SeshDetachResponse,
+};
 
-use crate::Seshd;
+use crate::{events, session::now_millis, Seshd};
 
 use super::CommandResponse;
 
 impl Seshd {
     /// RPC handler for detaching a session
     pub async fn exec_detach(&self, session: Option<req::Session>) -> Result<CommandResponse> {
-        if let Some(session) = session {
-            let name = match session {
-                sesh_proto::sesh_detach_request::Session::Name(name) => Some(name),
-                sesh_proto::sesh_detach_request::Session::Id(id) => {
-                    self.sessions.get_by_id(id as usize).map(|s| s.name.clone())
-                }
-            };
-
-            if let Some(name) = name {
-                if let Some(session) = self.sessions.get(&name) {
-                    info!(target: &session.log_group(), "Detaching");
-                    session.detach().await?;
-                    session
-                        .info
-                        .attach_time
-                        .store(chrono::Utc::now().timestamp_millis(), Ordering::Relaxed);
-                    info!(target: &session.log_group(), "Detached");
-                }
+        let name = match session {
+            Some(req::Session::Name(name)) => Some(name),
+            Some(req::Session::Id(id)) => {
+                self.sessions.get_by_id(id as usize).map(|s| s.name.clone())
             }
+            None => None,
+        };
+        let Some(session) = name.as_deref().and_then(|name| self.sessions.get(name)) else {
+            return Ok(CommandResponse::DetachSession(SeshDetachResponse {
+                success: false,
+                error: Error::NotFound.into(),
+                ..Default::default()
+            }));
+        };
+        let mut response = SeshDetachResponse {
+            success: false,
+            name: session.name.clone(),
+            id: session.id as u64,
+            pid: session.pid(),
+            error: Error::None.into(),
+        };
+        if !session.info.connected().load(Ordering::Acquire) {
+            response.error = Error::NotAttached.into();
+            return Ok(CommandResponse::DetachSession(response));
+        }
+        info!(target: &session.log_group(), "Detaching");
+        if let Err(e) = session.detach().await {
+            warn!(target: &session.log_group(), "Could not tell the client to detach: {}", e);
+            response.error = Error::ClientUnreachable.into();
+            return Ok(CommandResponse::DetachSession(response));
         }
-        Ok(CommandResponse::DetachSession(SeshDetachResponse {
-            success: true,
-        }))
+        session
+            .info
+            .attach_time
+            .store(now_millis(), Ordering::Relaxed);
+        info!(target: &session.log_group(), "Detached");
+        self.emit(events::session_event(
+            Kind::Detached,
+            &session.name,
+            session.id,
+        ));
+        response.success = true;
+        Ok(CommandResponse::DetachSession(response))
     }
 }
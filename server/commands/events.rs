@@ -0,0 +1,12 @@
+use anyhow::Result;
+
+use crate::Seshd;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for subscribing to server events
+    pub async fn exec_subscribe_events(&self) -> Result<CommandResponse> {
+        Ok(CommandResponse::SubscribeEvents(self.events.subscribe()))
+    }
+}
@@ -0,0 +1,31 @@
+use anyhow::Result;
+use log::info;
+use sesh_proto::{sesh_follow_request as req, sesh_follow_response::Started};
+use sesh_shared::error::SeshError;
+
+use crate::Seshd;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for following a session's output without attaching to it
+    pub async fn exec_follow(&self, session: Option<req::Session>) -> Result<CommandResponse> {
+        let session = match session {
+            Some(req::Session::Name(name)) => self.sessions.get(&name),
+            Some(req::Session::Id(id)) => self.sessions.get_by_id(id as usize),
+            None => return Err(SeshError::NoSessionSpecified.into()),
+        }
+        .ok_or(SeshError::SessionNotFound)?;
+        info!(target: &session.log_group(), "Following");
+        let output = session.info.follow();
+        // A session started detached isn't relayed until something needs its output
+        self.serve_clients(&session, None)?;
+        Ok(CommandResponse::FollowSession(
+            Started {
+                name: session.name.clone(),
+                start_time: session.info.start_time,
+            },
+            output,
+        ))
+    }
+}
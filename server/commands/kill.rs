@@ -1,40 +1,126 @@
-use crate::Seshd;
+use std::time::Duration;
 
+use crate::{events, Seshd};
+
+use crate::reaper::{ExitStatus, ExitWatch};
 use anyhow::Result;
-use log::info;
-use sesh_proto::{sesh_kill_request as req, SeshKillResponse};
+use log::{info, warn};
+use sesh_proto::{
+    client_session_ended_request::Reason, sesh_event::Kind, sesh_kill_request as req,
+    sesh_kill_response::Error, sesh_signal_request::Session as SignalSession, SeshKillResponse,
+};
 
 use super::CommandResponse;
 
+/// Sends SIGTERM to the process, then SIGKILL if it hasn't exited after `grace`.
+/// Resolves once the process has exited and been reaped.
+pub async fn terminate(pid: i32, exit: &mut ExitWatch, grace: Duration) -> ExitStatus {
+    // Never signal a pid that has already been reaped, it may belong to another process now
+    if let Some(status) = exit.status() {
+        return status;
+    }
+    unsafe { libc::kill(pid, libc::SIGTERM) };
+    match tokio::time::timeout(grace, exit.wait()).await {
+        Ok(status) => status,
+        Err(_) => {
+            warn!(target: "exec", "Process {} did not exit after SIGTERM, sending SIGKILL", pid);
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+            exit.wait().await
+        }
+    }
+}
+
 impl Seshd {
-    pub async fn exec_kill(&self, session: Option<req::Session>) -> Result<CommandResponse> {
-        if let Some(session) = session {
-            let name = match session {
-                req::Session::Name(name) => Some(name),
-                req::Session::Id(id) => {
-                    self.sessions.get_by_id(id as usize).map(|s| s.name.clone())
-                }
-            };
+    pub async fn exec_kill(
+        &self,
+        session: Option<req::Session>,
+        grace_ms: u64,
+        signal: i32,
+    ) -> Result<CommandResponse> {
+        let not_found = SeshKillResponse {
+            success: false,
+            error: Error::NotFound.into(),
+            ..Default::default()
+        };
+        // TODO: Kill the *current* session and exit?
+        let Some(session) = session else {
+            return Ok(CommandResponse::KillSession(not_found));
+        };
+        let name = match session {
+            req::Session::Name(name) => Some(name),
+            req::Session::Id(id) => self.sessions.get_by_id(id as usize).map(|s| s.name.clone()),
+        };
+        let Some(name) = name else {
+            return Ok(CommandResponse::KillSession(not_found));
+        };
 
-            let success = if let Some(name) = name {
-                if let Some(session) = self.sessions.remove(&name) {
-                    info!(target: &session.log_group(), "Killing subprocess");
-                    true
+        if signal != 0 {
+            // Don't hold the entry across the await, exec_signal looks it up again
+            let Some((id, pid)) = self.sessions.get(&name).map(|s| (s.id as u64, s.pid())) else {
+                return Ok(CommandResponse::KillSession(not_found));
+            };
+            // Signalled like `sesh send-signal` does
+            let sent = self
+                .exec_signal(Some(SignalSession::Name(name.clone())), signal)
+                .await;
+            if let Err(e) = &sent {
+                warn!(target: "exec", "Could not signal session {}: {}", name, e);
+            }
+            return Ok(CommandResponse::KillSession(SeshKillResponse {
+                success: sent.is_ok(),
+                name,
+                id,
+                pid,
+                dead: false,
+                error: if sent.is_ok() {
+                    Error::None
                 } else {
-                    false
+                    Error::SignalFailed
                 }
-            } else {
-                false
-            };
-            if self.sessions.is_empty() && crate::EXIT_ON_EMPTY {
-                self.exit_signal.send(()).await?;
+                .into(),
+            }));
+        }
+
+        let response = if let Some(mut session) = self.sessions.remove(&name) {
+            info!(target: &session.log_group(), "Killing subprocess");
+            let mut exit = session.exit.clone();
+            let grace = Duration::from_millis(grace_ms);
+            let status = terminate(session.pid(), &mut exit, grace).await;
+            info!(target: &session.log_group(), "Subprocess exited with {}", status);
+            session.ended(Reason::Killed, status).await.ok();
+            self.emit(events::exit_event(Kind::Killed, &session, status));
+            // The process has already been reaped, so don't signal its pid again on drop
+            session.pty.daemonize();
+            SeshKillResponse {
+                success: true,
+                name,
+                id: session.id as u64,
+                pid: session.pid(),
+                dead: false,
+                error: Error::None.into(),
+            }
+        } else if let Some(record) = self.state.take_dead(&name) {
+            // Killing a dead session forgets about it, and stops its process if that outlived
+            // the old server
+            let pid = record.orphan_pid();
+            if let Some(pid) = pid {
+                info!(target: "exec", "Killing orphaned process {}", pid);
+                let mut exit = self.reaper.watch_foreign(pid);
+                terminate(pid, &mut exit, Duration::from_millis(grace_ms)).await;
+            }
+            SeshKillResponse {
+                success: true,
+                name,
+                id: 0,
+                pid: pid.unwrap_or(0),
+                dead: true,
+                error: Error::None.into(),
             }
-            Ok(CommandResponse::KillSession(SeshKillResponse { success }))
         } else {
-            // TODO: Kill the *current* session and exit?
-            Ok(CommandResponse::KillSession(SeshKillResponse {
-                success: false,
-            }))
-        }
+            not_found
+        };
+        self.persist();
+        self.starts.exit_if_empty();
+        Ok(CommandResponse::KillSession(response))
     }
 }
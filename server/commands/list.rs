@@ -1,30 +1,97 @@
 use std::sync::atomic::Ordering;
 
 use log::info;
-use sesh_proto::SeshListResponse;
+use sesh_proto::{
+    sesh_info::ExitStatus as ProtoExitStatus, SeshListFilter, SeshListResponse, WinSize,
+};
+use sesh_shared::{filter, name};
 
-use crate::Seshd;
+use crate::{reaper::ExitStatus, Seshd};
 
 use super::CommandResponse;
 use anyhow::Result;
 
 impl Seshd {
-    pub async fn exec_list(&self) -> Result<CommandResponse> {
+    pub async fn exec_list(&self, dead: bool, filter: SeshListFilter) -> Result<CommandResponse> {
+        if dead {
+            info!(target: "exec", "Listing dead sessions");
+            let sessions = self
+                .state
+                .dead()
+                .into_iter()
+                .enumerate()
+                // Ids are the position in the whole list, so they're kept when filtering
+                .filter(|(_, record)| {
+                    filter::matches(&filter, &record.name, &record.program, false, &record.tags)
+                })
+                .map(|(id, record)| sesh_proto::SeshInfo {
+                    windows: std::iter::once(name::from_program(&record.program))
+                        .chain(record.windows.iter().map(|window| window.name.clone()))
+                        .collect(),
+                    id: id as u64,
+                    // Dead sessions only have a pid if their process outlived the old server
+                    pid: record.orphan_pid().unwrap_or(0),
+                    name: record.name,
+                    program: record.program,
+                    connected: false,
+                    clients: 0,
+                    attach_time: 0,
+                    start_time: record.start_time,
+                    socket: String::new(),
+                    size: None,
+                    note: record.note.unwrap_or_default(),
+                    tags: record.tags,
+                    title: record.title.unwrap_or_default(),
+                    exited: false,
+                    exit_status: None,
+                    // Where it was started, since it isn't running
+                    cwd: record.pwd,
+                    health: String::new(),
+                })
+                .collect::<Vec<_>>();
+            return Ok(CommandResponse::ListSessions(SeshListResponse { sessions }));
+        }
         info!(target: "exec", "Listing sessions");
         let sessions = self
             .sessions
             .iter()
+            // Filtered before anything is looked up for the sessions, like their cwd
+            .filter(|entry| {
+                let (name, session) = entry.pair();
+                let connected = session.info.clients().load(Ordering::Acquire) > 0;
+                filter::matches(&filter, name, &session.program, connected, &session.tags)
+            })
             .map(|entry| {
                 let (name, session) = entry.pair();
+                let size = session.info.size();
+                let clients = session.info.clients().load(Ordering::Acquire) as u32;
+                let status = session.exit.status();
                 sesh_proto::SeshInfo {
                     id: session.id as u64,
                     name: name.clone(),
                     program: session.program.clone(),
-                    connected: session.info.connected().load(Ordering::Relaxed),
+                    connected: clients > 0,
+                    clients,
                     attach_time: session.info.attach_time.load(Ordering::Relaxed),
                     start_time: session.info.start_time,
                     socket: session.info.sock_path().to_string_lossy().to_string(),
                     pid: session.pid(),
+                    size: Some(WinSize::from(&size)),
+                    note: session.info.note().unwrap_or_default(),
+                    tags: session.tags.clone(),
+                    title: session.title.clone().unwrap_or_default(),
+                    exited: status.is_some(),
+                    exit_status: match status {
+                        Some(ExitStatus::Code(code)) => Some(ProtoExitStatus::Code(code)),
+                        Some(ExitStatus::Signal(sig)) => Some(ProtoExitStatus::Signal(sig)),
+                        Some(ExitStatus::Unknown) | None => None,
+                    },
+                    windows: session.window_names(),
+                    cwd: session
+                        .cwd()
+                        .map(|cwd| cwd.to_string_lossy().into_owned())
+                        .unwrap_or_else(|_| session.pwd.clone()),
+                    health: session.info.supervisor.health(),
                 }
             })
             .collect::<Vec<_>>();
@@ -1,30 +1,89 @@
 use sesh_proto::*;
+use tokio::sync::broadcast;
+use tonic::Streaming;
 
 mod attach;
+mod batch;
+mod cwd;
 mod detach;
+mod events;
+mod follow;
 mod kill;
 mod list;
+mod note;
+mod paste;
+mod prune;
+mod redraw;
+mod rename;
+mod renumber;
+mod reset;
 mod resize;
+mod revive;
+mod server_info;
 mod shutdown;
+mod signal;
 mod start;
+mod upgrade;
+mod wait_for;
+mod window;
+
+pub use kill::terminate;
+pub use start::StartOptions;
 
 #[derive(Debug)]
 pub enum Command {
     StartSession(SeshStartRequest),
     KillSession(SeshKillRequest),
-    ListSessions,
+    SignalSession(SeshSignalRequest),
+    ListSessions(SeshListRequest),
+    RenameSession(SeshRenameRequest),
+    RenumberSessions,
+    PruneSessions(SeshPruneRequest),
+    ReviveSession(SeshReviveRequest),
+    PasteSession(Streaming<SeshPasteRequest>),
+    SessionCwd(SeshCwdRequest),
+    NoteSession(SeshNoteRequest),
     ShutdownServer,
+    UpgradeServer(UpgradeServerRequest),
+    ServerInfo,
     AttachSession(SeshAttachRequest),
     DetachSession(SeshDetachRequest),
+    FollowSession(SeshFollowRequest),
     ResizeSession(SeshResizeRequest),
+    SelectWindow(SeshSelectWindowRequest),
+    ResetSession(SeshResetRequest),
+    RedrawSession(SeshRedrawRequest),
+    SubscribeEvents,
+    WaitFor(SeshWaitForRequest),
+    Batch(SeshBatchRequest),
 }
 
 pub enum CommandResponse {
     StartSession(SeshStartResponse),
     KillSession(SeshKillResponse),
+    SignalSession(SeshSignalResponse),
     ListSessions(SeshListResponse),
+    RenameSession(SeshRenameResponse),
+    RenumberSessions(SeshRenumberResponse),
+    PruneSessions(SeshPruneResponse),
+    ReviveSession(SeshStartResponse),
+    PasteSession(SeshPasteResponse),
+    SessionCwd(SeshCwdResponse),
+    NoteSession(SeshNoteResponse),
     ShutdownServer(ShutdownServerResponse),
+    UpgradeServer(UpgradeServerResponse),
+    ServerInfo(ServerInfoResponse),
     AttachSession(SeshAttachResponse),
     DetachSession(SeshDetachResponse),
+    FollowSession(
+        sesh_follow_response::Started,
+        broadcast::Receiver<SeshFollowResponse>,
+    ),
     ResizeSession(SeshResizeResponse),
+    SelectWindow(SeshSelectWindowResponse),
+    ResetSession(SeshResetResponse),
+    RedrawSession(SeshRedrawResponse),
+    SubscribeEvents(broadcast::Receiver<SeshEvent>),
+    WaitFor(SeshWaitForResponse),
+    Batch(SeshBatchResponse),
 }
@@ -0,0 +1,34 @@
+use crate::Seshd;
+
+use anyhow::Result;
+use log::info;
+use sesh_proto::{sesh_note_request as req, SeshNoteResponse};
+use sesh_shared::error::SeshError;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for setting or removing a session's note
+    pub async fn exec_note(
+        &self,
+        session: Option<req::Session>,
+        note: String,
+    ) -> Result<CommandResponse> {
+        {
+            let session = match session {
+                Some(req::Session::Name(name)) => self.sessions.get(&name),
+                Some(req::Session::Id(id)) => self.sessions.get_by_id(id as usize),
+                None => None,
+            }
+            .ok_or(SeshError::SessionNotFound)?;
+            info!(target: &session.log_group(), "Setting note to {:?}", note);
+            session.info.set_note(Some(note));
+        }
+        // Saving reads every session, so this one mustn't still be borrowed
+        self.persist();
+
+        Ok(CommandResponse::NoteSession(SeshNoteResponse {
+            success: true,
+        }))
+    }
+}
@@ -0,0 +1,49 @@
+use crate::Seshd;
+
+use anyhow::Result;
+use log::info;
+use sesh_proto::{sesh_paste_request as req, SeshPasteRequest, SeshPasteResponse};
+use sesh_shared::error::SeshError;
+use tokio::io::AsyncWriteExt;
+use tonic::Streaming;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for writing a client's input into a session's pty.
+    /// A chunk is only read from the stream once the last one was written, so a program that
+    /// isn't reading its input slows the client down instead of filling up the server.
+    pub async fn exec_paste(
+        &self,
+        mut chunks: Streaming<SeshPasteRequest>,
+    ) -> Result<CommandResponse> {
+        let Some(first) = chunks.message().await? else {
+            return Err(SeshError::NoSessionSpecified.into());
+        };
+        let (mut pty, log_group) = {
+            let session = match first.session {
+                Some(req::Session::Name(name)) => self.sessions.get(&name),
+                Some(req::Session::Id(id)) => self.sessions.get_by_id(id as usize),
+                None => None,
+            }
+            .ok_or(SeshError::SessionNotFound)?;
+            // The session is locked while it's borrowed, so it isn't held for the whole paste
+            (session.pty.file().try_clone().await?, session.log_group())
+        };
+
+        let mut bytes = 0;
+        let mut data = first.data;
+        loop {
+            pty.write_all(&data).await?;
+            pty.flush().await?;
+            bytes += data.len() as u64;
+            match chunks.message().await? {
+                Some(chunk) => data = chunk.data,
+                None => break,
+            }
+        }
+        info!(target: &log_group, "Pasted {} bytes", bytes);
+
+        Ok(CommandResponse::PasteSession(SeshPasteResponse { bytes }))
+    }
+}
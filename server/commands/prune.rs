@@ -0,0 +1,135 @@
+use std::{
+    io::ErrorKind,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::{info, warn};
+use sesh_proto::{
+    client_session_ended_request::Reason, sesh_event::Kind, SeshPruneResponse, SeshPruned,
+};
+use sesh_shared::sys;
+
+use crate::{events, reaper::ExitStatus, session::Session, Seshd};
+
+use super::CommandResponse;
+
+/// How long connecting to a socket may take before it's taken to have a listener that's just
+/// slow to accept
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+
+impl Seshd {
+    /// RPC handler for removing sessions whose process is gone without the server noticing,
+    /// and sockets in the runtime directory that nothing listens on. Live sessions and their
+    /// clients are left alone.
+    pub async fn exec_prune(&self, dry_run: bool) -> Result<CommandResponse> {
+        let gone = self
+            .sessions
+            .iter()
+            .filter(|session| gone(session))
+            .map(|session| {
+                let pruned = SeshPruned {
+                    name: session.name.clone(),
+                    id: session.id as u64,
+                    pid: session.pid(),
+                };
+                (pruned, session.exit.clone())
+            })
+            .collect::<Vec<_>>();
+        let files = self.stale_sockets().await;
+        if dry_run {
+            return Ok(CommandResponse::PruneSessions(SeshPruneResponse {
+                sessions: gone.into_iter().map(|(pruned, _)| pruned).collect(),
+                files: files.iter().map(|f| f.display().to_string()).collect(),
+            }));
+        }
+
+        // Collects the processes that exited unnoticed, so their exit status is known
+        self.reaper.reap();
+        let mut sessions = Vec::new();
+        for (pruned, exit) in gone {
+            // The session may have just been removed on its own, once its process was reaped
+            if let Some(mut session) = self.sessions.remove_exited(&exit) {
+                let status = exit.status().unwrap_or(ExitStatus::Unknown);
+                self.events
+                    .send(events::exit_event(Kind::Exited, &session, status))
+                    .ok();
+                info!(
+                    target: &session.log_group(),
+                    "Pruned session, its subprocess {} is gone", session.program
+                );
+                session.ended(Reason::Exited, status).await.ok();
+                // The pid has been reaped and may be reused, so don't signal it on drop
+                session.pty.daemonize();
+            }
+            sessions.push(pruned);
+        }
+        let mut removed = Vec::new();
+        for file in files {
+            match std::fs::remove_file(&file) {
+                Ok(()) => {
+                    info!(target: "session", "Removed stale socket {}", file.display());
+                    removed.push(file.display().to_string());
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => warn!(target: "session", "Could not remove {}: {}", file.display(), e),
+            }
+        }
+        if !sessions.is_empty() {
+            self.persist();
+            self.starts.exit_if_empty();
+        }
+        Ok(CommandResponse::PruneSessions(SeshPruneResponse {
+            sessions,
+            files: removed,
+        }))
+    }
+
+    /// The sockets in the runtime directory that no session uses and nothing listens on, left
+    /// behind by sessions and clients that were killed
+    async fn stale_sockets(&self) -> Vec<PathBuf> {
+        let Ok(entries) = std::fs::read_dir(&self.runtime_dir) else {
+            return Vec::new();
+        };
+        let mut sockets = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !entry.file_type().is_ok_and(|t| t.is_socket())
+                || path == self.socket_path
+                // Connecting to it would be taken for the new server during an upgrade, and
+                // one left behind is replaced by the next upgrade
+                || path == self.runtime_dir.join("upgrade.sock")
+                || self.sessions.iter().any(|s| *s.info.sock_path() == path)
+            {
+                continue;
+            }
+            if !listening(&path).await {
+                sockets.push(path);
+            }
+        }
+        sockets.sort();
+        sockets
+    }
+}
+
+/// Whether a session's process is gone, though the session is still listed. Sessions started
+/// with --keep are kept once their process exits, until they're killed.
+fn gone(session: &Session) -> bool {
+    if session.keep {
+        return false;
+    }
+    let pid = session.pid();
+    // The process is the server's child, so its pid isn't reused before it's reaped
+    let alive = unsafe { libc::kill(pid, 0) } == 0 && !sys::zombie(pid);
+    session.exit.status().is_some() || !alive
+}
+
+/// Whether anything listens on a socket. Only a refused connection counts as nothing.
+async fn listening(path: &Path) -> bool {
+    match tokio::time::timeout(CONNECT_TIMEOUT, tokio::net::UnixStream::connect(path)).await {
+        Ok(Err(e)) => e.kind() != ErrorKind::ConnectionRefused,
+        Ok(Ok(_)) | Err(_) => true,
+    }
+}
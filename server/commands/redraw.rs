@@ -0,0 +1,26 @@
+use crate::Seshd;
+
+use anyhow::Result;
+use sesh_proto::{sesh_redraw_request as req, SeshRedrawResponse};
+use sesh_shared::error::SeshError;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for making a session's program redraw, after the client drew over it
+    pub async fn exec_redraw(&self, session: Option<req::Session>) -> Result<CommandResponse> {
+        let session = match session {
+            Some(req::Session::Name(name)) => self.sessions.get(&name),
+            Some(req::Session::Id(id)) => self.sessions.get_by_id(id as usize),
+            None => None,
+        }
+        .ok_or(SeshError::SessionNotFound)?;
+
+        // The pid has been reaped, and the pty has no foreground job left to redraw
+        if session.exit.status().is_none() {
+            session.redraw();
+        }
+
+        Ok(CommandResponse::RedrawSession(SeshRedrawResponse {}))
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use log::info;
+use sesh_proto::{sesh_rename_request as req, SeshRenameResponse};
+use sesh_shared::error::SeshError;
+use sesh_shared::name;
+
+use crate::{events, Seshd};
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for renaming a session
+    pub async fn exec_rename(
+        &self,
+        session: Option<req::Session>,
+        new_name: String,
+    ) -> Result<CommandResponse> {
+        name::check(&new_name)?;
+        let name = match session {
+            Some(req::Session::Name(name)) => Some(name),
+            Some(req::Session::Id(id)) => self
+                .sessions
+                .get_by_id(id as usize)
+                .map(|session| session.name.clone()),
+            None => None,
+        }
+        .ok_or(SeshError::SessionNotFound)?;
+
+        let id = self.sessions.rename(&name, new_name.clone())?;
+        info!(target: "session", "Renamed {} to {}", name, new_name);
+        self.emit(events::rename_event(name, id, &new_name));
+        self.persist();
+
+        Ok(CommandResponse::RenameSession(SeshRenameResponse {
+            name: new_name,
+        }))
+    }
+}
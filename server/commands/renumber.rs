@@ -0,0 +1,30 @@
+use anyhow::Result;
+use log::info;
+use sesh_proto::{SeshRenumberResponse, SeshRenumbered};
+
+use crate::Seshd;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for giving the sessions ids counting up from 0. Names and sockets are left
+    /// as they are.
+    pub async fn exec_renumber(&self) -> Result<CommandResponse> {
+        let renumbered = self
+            .sessions
+            .renumber()
+            .into_iter()
+            .map(|(name, old_id, new_id)| {
+                info!(target: "session", "Renumbered {} from {} to {}", name, old_id, new_id);
+                SeshRenumbered {
+                    name,
+                    old_id: old_id as u64,
+                    new_id: new_id as u64,
+                }
+            })
+            .collect();
+        Ok(CommandResponse::RenumberSessions(SeshRenumberResponse {
+            renumbered,
+        }))
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::atomic::Ordering;
+
+use crate::Seshd;
+
+use anyhow::{Context, Result};
+use log::info;
+use sesh_proto::{sesh_reset_request as req, SeshResetResponse};
+use sesh_shared::error::SeshError;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for resetting the terminal of a session's attached client, such as after a
+    /// binary file was printed to it
+    pub async fn exec_reset(&self, session: Option<req::Session>) -> Result<CommandResponse> {
+        let session = match session {
+            Some(req::Session::Name(name)) => self.sessions.get(&name),
+            Some(req::Session::Id(id)) => self.sessions.get_by_id(id as usize),
+            None => None,
+        }
+        .ok_or(SeshError::SessionNotFound)?;
+
+        // A client that attaches later starts on a clean screen anyway
+        let attached = session.info.connected().load(Ordering::Acquire);
+        if attached {
+            info!(target: &session.log_group(), "Resetting the client's terminal");
+            session
+                .reset()
+                .await
+                .context("Could not reset the client's terminal")?;
+        }
+
+        Ok(CommandResponse::ResetSession(SeshResetResponse {
+            name: session.name.clone(),
+            attached,
+        }))
+    }
+}
@@ -1,6 +1,6 @@
 use log::info;
 use sesh_proto::{SeshResizeResponse, WinSize};
-use sesh_shared::term::Size;
+use sesh_shared::{error::SeshError, term::Size};
 
 use crate::Seshd;
 use sesh_proto::sesh_resize_request as req;
@@ -15,10 +15,10 @@ impl Seshd {
         size: Option<WinSize>,
     ) -> Result<CommandResponse> {
         let Some(size) = size else {
-            return Err(anyhow::anyhow!("Invalid size"));
+            return Err(SeshError::InvalidSize.into());
         };
         let Some(session) = session else {
-            return Err(anyhow::anyhow!("Session not found"));
+            return Err(SeshError::SessionNotFound.into());
         };
         let Some(name) = (match session {
             req::Session::Name(name) => Some(name),
@@ -31,18 +31,12 @@ impl Seshd {
                 }
             }),
         }) else {
-            return Err(anyhow::anyhow!("Session not found"));
+            return Err(SeshError::SessionNotFound.into());
         };
-        let session = self
-            .sessions
-            .get(&name)
-            .ok_or_else(|| anyhow::anyhow!("Session not found: {}", name))?;
+        let session = self.sessions.get(&name).ok_or(SeshError::SessionNotFound)?;
         info!(target: &session.log_group(), "Resizing");
 
-        session.pty.resize(&Size {
-            cols: size.cols as u16,
-            rows: size.rows as u16,
-        })?;
+        session.resize(Size::from(&size))?;
         Ok(CommandResponse::ResizeSession(SeshResizeResponse {}))
     }
 }
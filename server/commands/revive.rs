@@ -0,0 +1,63 @@
+use anyhow::Result;
+use log::info;
+use sesh_proto::WinSize;
+use sesh_shared::error::SeshError;
+
+use crate::Seshd;
+
+use super::{start::StartOptions, CommandResponse};
+
+impl Seshd {
+    /// RPC handler for restarting a session that was lost when the server last exited
+    pub async fn exec_revive(
+        &self,
+        name: String,
+        size: Option<WinSize>,
+        attach: bool,
+    ) -> Result<CommandResponse> {
+        let record = self
+            .state
+            .take_dead(&name)
+            .ok_or(SeshError::SessionNotFound)?;
+        if let Some(pid) = record.orphan_pid() {
+            let name = record.name.clone();
+            self.state.insert_dead(record);
+            anyhow::bail!("{} is still running as pid {}, kill it first", name, pid);
+        }
+        info!(target: "exec", "Reviving {}", record.name);
+
+        match self
+            .exec_start(StartOptions {
+                name: record.name.clone(),
+                program: record.program.clone(),
+                args: record.args.clone(),
+                size,
+                pwd: record.pwd.clone(),
+                env: record.env.clone(),
+                login: record.login,
+                term: record.term.clone(),
+                replay_input: None,
+                note: record.note.clone(),
+                tags: record.tags.clone(),
+                title: record.title.clone(),
+                script: record.script.clone(),
+                attach,
+                notify: record.notify.clone(),
+                keep: record.keep,
+                windows: record.windows.clone(),
+                termios: record.termios,
+            })
+            .await
+        {
+            Ok(CommandResponse::StartSession(response)) => {
+                Ok(CommandResponse::ReviveSession(response))
+            }
+            Ok(_) => anyhow::bail!("Unexpected response"),
+            Err(e) => {
+                // Keep the record around so the revive can be retried
+                self.state.insert_dead(record);
+                Err(e)
+            }
+        }
+    }
+}
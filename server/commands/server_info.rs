@@ -0,0 +1,16 @@
+use anyhow::Result;
+use sesh_proto::ServerInfoResponse;
+
+use crate::Seshd;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for describing the running server, so clients can check it matches them
+    pub async fn exec_server_info(&self) -> Result<CommandResponse> {
+        Ok(CommandResponse::ServerInfo(ServerInfoResponse {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            pid: std::process::id() as i32,
+        }))
+    }
+}
@@ -0,0 +1,43 @@
+use crate::Seshd;
+
+use anyhow::{anyhow, Result};
+use log::info;
+use sesh_proto::{sesh_signal_request as req, SeshSignalResponse};
+use sesh_shared::error::SeshError;
+use sesh_shared::signal;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for sending a signal to a session's process group
+    pub async fn exec_signal(
+        &self,
+        session: Option<req::Session>,
+        signal: i32,
+    ) -> Result<CommandResponse> {
+        let session = match session {
+            Some(req::Session::Name(name)) => self.sessions.get(&name),
+            Some(req::Session::Id(id)) => self.sessions.get_by_id(id as usize),
+            None => None,
+        }
+        .ok_or(SeshError::SessionNotFound)?;
+
+        // The pid has been reaped and may belong to another process now
+        if session.exit.status().is_some() {
+            return Err(anyhow!("The session's process has exited"));
+        }
+        info!(target: &session.log_group(), "Sending {}", signal::name(signal));
+        // Sessions are started with setsid, so the pid is also the process group id
+        if unsafe { libc::kill(-session.pid(), signal) } != 0 {
+            return Err(anyhow!(
+                "Could not send {}: {}",
+                signal::name(signal),
+                std::io::Error::last_os_error()
+            ));
+        }
+
+        Ok(CommandResponse::SignalSession(SeshSignalResponse {
+            success: true,
+        }))
+    }
+}
@@ -1,92 +1,374 @@
-use std::{os::fd::AsRawFd, path::PathBuf};
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
-use log::info;
-use sesh_proto::{SeshStartResponse, WinSize};
-use sesh_shared::{pty::Pty, term::Size};
+use anyhow::{Context, Result};
+use sesh_proto::{sesh_event::Kind, SeshStartRequest, SeshStartResponse, WinSize};
+use sesh_shared::{
+    name,
+    pty::{Pty, PtyBuilder},
+    term::{Size, Termios},
+};
 
-use crate::{Seshd, Session};
+use crate::{
+    env::EnvPolicy,
+    events, limits,
+    notify::{self, Notify},
+    replay,
+    window::{self, Window, WindowSpec},
+    Seshd, Session,
+};
 
 use super::CommandResponse;
 
-impl Seshd {
-    pub async fn exec_start(
-        &self,
-        name: String,
-        program: String,
-        args: Vec<String>,
-        size: Option<WinSize>,
-        pwd: String,
-        env: Vec<(String, String)>,
-    ) -> Result<CommandResponse> {
-        let name = PathBuf::from(&name)
-            .file_name()
-            .map(|s| s.to_string_lossy().to_string())
-            .unwrap_or(name.replace('/', "_"));
-
-        let mut session_name = name.clone();
-        let mut i = 0;
-        while self.sessions.contains(&session_name) {
-            session_name = format!("{}-{}", name, i);
-            i += 1;
+/// What a session is started with, from a start request or the record of a dead session
+pub struct StartOptions {
+    pub name: String,
+    pub program: String,
+    pub args: Vec<String>,
+    pub size: Option<WinSize>,
+    pub pwd: String,
+    pub env: Vec<(String, String)>,
+    pub login: bool,
+    pub term: Option<String>,
+    /// Recording typed into the session once it's started
+    pub replay_input: Option<PathBuf>,
+    pub note: Option<String>,
+    pub tags: Vec<String>,
+    pub title: Option<String>,
+    /// Script the program was started to run, kept so the session can be revived
+    pub script: Option<PathBuf>,
+    /// Whether a client attaches right after starting, so the socket is served right away
+    pub attach: bool,
+    pub notify: Notify,
+    pub keep: bool,
+    pub windows: Vec<WindowSpec>,
+    pub termios: Termios,
+}
+
+impl From<SeshStartRequest> for StartOptions {
+    fn from(req: SeshStartRequest) -> Self {
+        Self {
+            name: req.name,
+            program: req.program,
+            args: req.args,
+            size: req.size,
+            pwd: req.pwd,
+            env: req.env.into_iter().map(|v| (v.key, v.value)).collect(),
+            login: req.login,
+            term: (!req.term.is_empty()).then_some(req.term),
+            replay_input: (!req.replay_input.is_empty()).then(|| PathBuf::from(req.replay_input)),
+            note: Some(req.note),
+            tags: req.tags,
+            title: (!req.title.is_empty()).then_some(req.title),
+            script: (!req.script.is_empty()).then(|| PathBuf::from(req.script)),
+            attach: req.attach,
+            notify: Notify {
+                attach_signal: (req.notify_attach > 0).then_some(req.notify_attach),
+                detach_signal: (req.notify_detach > 0).then_some(req.notify_detach),
+                status_file: req.attach_status,
+            },
+            keep: req.keep,
+            windows: req.windows.into_iter().map(WindowSpec::from).collect(),
+            termios: req.termios.as_ref().map(Termios::from).unwrap_or_default(),
         }
+    }
+}
+
+impl Seshd {
+    pub async fn exec_start(&self, mut options: StartOptions) -> Result<CommandResponse> {
+        // The name makes up the socket's path, so it mustn't lead out of the runtime directory
+        name::check(&options.name)?;
+        window::check_names(&name::from_program(&options.program), &options.windows)?;
+        self.check_accepting()?;
+        // Held until the session is added, so the server doesn't exit for having none first, and
+        // other starts count this one against the limits
+        let mut start = self.starts.begin(&self.limits, &options.program)?;
+        limits::warn_fd_headroom();
+
+        // Read before anything is started, so a bad recording doesn't leave a session behind
+        let input = options
+            .replay_input
+            .take()
+            .map(|path| replay::load(&path))
+            .transpose()?;
 
+        let session_name = start.name(&options.name);
+        // The suffix can make a name that isn't allowed, like `client-0`
+        name::check(&session_name)?;
         let socket_path = self.runtime_dir.join(format!("{}.sock", session_name));
+        if socket_path.exists() {
+            // No live session has this name, and no other start can take it while it's reserved,
+            // so the socket was left behind by a dead server
+            std::fs::remove_file(&socket_path)?;
+        }
 
-        let pty = Pty::builder(&program)
-            .args(args)
-            .current_dir(pwd)
-            .envs(env)
-            .env("SESH_SESSION", socket_path.clone())
-            .env("SESH_NAME", session_name.clone())
-            .spawn(&Size::term_size()?)?;
+        // Written before the program starts, so it can read the file right away
+        let status_file = options
+            .notify
+            .status_file
+            .then(|| notify::status_path(&socket_path));
+        if let Some(path) = &status_file {
+            notify::write_status(path, false)?;
+        }
+
+        let id = self.sessions.next_id();
+        let windows = std::mem::take(&mut options.windows);
+        let launch = Launch {
+            pwd: &options.pwd,
+            env: &options.env,
+            policy: &self.env_policy,
+            login: options.login,
+            term: options.term.as_deref(),
+            socket_path: &socket_path,
+            status_file: status_file.as_deref(),
+            name: &session_name,
+            id,
+        };
+        let pty = session_command(&options.program, &options.args, &launch)
+            .termios(options.termios)
+            .spawn(&Size::detect())
+            .inspect_err(|_| {
+                if let Some(path) = &status_file {
+                    std::fs::remove_file(path).ok();
+                }
+            })?;
 
         let pid = pty.pid();
-        let size = if let Some(size) = size {
-            Size {
-                rows: size.rows as u16,
-                cols: size.cols as u16,
-            }
-        } else {
-            Size::term_size()?
+        let exit = self.reaper.watch(pid);
+        let size = match &options.size {
+            Some(size) => Size::from(size),
+            None => Size::detect(),
         };
         pty.resize(&size)?;
 
+        let mut started = Vec::with_capacity(windows.len());
+        for spec in windows {
+            let window = session_command(&spec.program, &spec.args, &launch)
+                .termios(options.termios)
+                .spawn(&size)
+                .with_context(|| format!("Could not start window {}", spec.name));
+            // The windows already started, and the program, are stopped as they're dropped
+            let pty = window.inspect_err(|_| {
+                if let Some(path) = &status_file {
+                    std::fs::remove_file(path).ok();
+                }
+            })?;
+            let exit = self.reaper.watch(pty.pid());
+            started.push(Window { spec, pty, exit });
+        }
+
+        let attach = options.attach;
         let session = Session::new(
-            self.sessions.count(),
+            id,
             session_name.clone(),
-            program.clone(),
+            options,
             pty,
+            started,
             PathBuf::from(&socket_path),
+            exit.clone(),
         )?;
-        self.sessions.insert(session.name.clone(), session);
+        session.info.set_connect_size(Some(size));
+        if let Some(input) = input {
+            let pty = session.pty.file().try_clone().await?;
+            let replay = replay::play(pty, input, exit.clone(), session.log_group());
+            session.info.supervisor.spawn("input replay", replay);
+        }
+        let program = session.program.clone();
+        let title = session.title.clone();
+        let start_time = session.info.start_time;
+        start.add(session)?;
+        self.watch_session(exit);
+        self.emit(events::session_event(Kind::Started, &session_name, id));
+        self.persist();
 
-        tokio::task::spawn({
+        // A session started detached may not be attached to for a long time, so nothing waits
+        // for a client until one attaches
+        if attach {
             let session = self
                 .sessions
                 .get(&session_name)
                 .expect("session should exist in sessions");
-            let sock_path = session.info.sock_path().clone();
-            let socket = session.listener.clone();
-            let file = session.pty.file().as_raw_fd();
-            // Duplicate FD
-            // I do not know why this makes the socket connection not die, but it does
-            let file = unsafe { libc::fcntl(file, libc::F_DUPFD, file) };
-            let connected = session.info.connected();
-            let attach_time = session.info.attach_time.clone();
-
-            info!(target: &session.log_group(), "Starting on {}", session.info.sock_path().display());
-            async move {
-                Session::start(sock_path, socket, file, connected, size, attach_time).await?;
-                Result::<_, anyhow::Error>::Ok(())
-            }
-        });
+            self.serve_clients(&session, None)?;
+        }
 
         Ok(CommandResponse::StartSession(SeshStartResponse {
             pid,
             program,
             name: session_name,
             socket: socket_path.to_string_lossy().to_string(),
+            title: title.unwrap_or_default(),
+            start_time,
         }))
     }
 }
+
+/// What every process in a session is started with, its program and windows alike
+struct Launch<'a> {
+    pwd: &'a str,
+    env: &'a [(String, String)],
+    /// Which of `env` is passed on
+    policy: &'a EnvPolicy,
+    login: bool,
+    term: Option<&'a str>,
+    socket_path: &'a Path,
+    status_file: Option<&'a Path>,
+    name: &'a str,
+    id: usize,
+}
+
+/// Builds the command that runs a session's program.
+///
+/// The server may have been started with a minimal environment, so the client's environment
+/// replaces the server's instead of being layered on top of it. Variables the client didn't
+/// send would otherwise leak in from the server. Only the variables `launch.policy` lets
+/// through are passed on, and the sesh variables are set last, so a client can't override
+/// them. The server's environment is only used if the client didn't send one.
+fn session_command(program: &str, args: &[String], launch: &Launch) -> PtyBuilder {
+    let mut command = Pty::builder(program).args(args).current_dir(launch.pwd);
+    if !launch.env.is_empty() {
+        command = command.env_clear();
+    }
+    if launch.login {
+        command = command.login();
+    }
+    command = command.envs(
+        launch
+            .env
+            .iter()
+            .filter(|(key, _)| launch.policy.forwards(key))
+            .cloned(),
+    );
+    if let Some(term) = launch.term {
+        command = command.term(term);
+    }
+    if let Some(status_file) = launch.status_file {
+        command = command.env("SESH_STATUS_FILE", status_file);
+    }
+    command
+        .env("SESH_SESSION", launch.socket_path)
+        .env("SESH_NAME", launch.name)
+        .env("SESH_ID", launch.id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    /// Runs a shell command in a session's environment and returns what it printed
+    async fn output(env: &[(String, String)], script: &str) -> String {
+        output_with(env, false, None, script).await
+    }
+
+    async fn output_with(
+        env: &[(String, String)],
+        login: bool,
+        term: Option<&str>,
+        script: &str,
+    ) -> String {
+        let launch = Launch {
+            pwd: "/",
+            env,
+            policy: &EnvPolicy::default(),
+            login,
+            term,
+            socket_path: Path::new("/tmp/test.sock"),
+            status_file: None,
+            name: "test",
+            id: 0,
+        };
+        let pty = session_command("/bin/sh", &["-c".to_owned(), script.to_owned()], &launch)
+            .spawn(&Size::default())
+            .expect("failed to spawn");
+        let mut file = pty.file().try_clone().await.expect("failed to clone pty");
+        let mut output = Vec::new();
+        let mut packet = [0; 4096];
+        // The pty fails to read once the child has exited and closed its side
+        while let Ok(n @ 1..) = file.read(&mut packet).await {
+            output.extend_from_slice(&packet[..n]);
+        }
+        String::from_utf8_lossy(&output).trim().to_owned()
+    }
+
+    #[tokio::test]
+    async fn client_path_wins() {
+        let env = vec![("PATH".to_owned(), "/client/bin".to_owned())];
+        assert_eq!(output(&env, "printf %s \"$PATH\"").await, "/client/bin");
+    }
+
+    #[tokio::test]
+    async fn server_env_does_not_leak() {
+        std::env::set_var("SESHD_ONLY", "1");
+        let env = vec![("PATH".to_owned(), "/client/bin".to_owned())];
+        assert_eq!(output(&env, "printf %s \"$SESHD_ONLY\"").await, "");
+    }
+
+    #[tokio::test]
+    async fn forwards_only_what_the_policy_allows() {
+        let env = [
+            ("PATH", "/client/bin"),
+            ("HOME", "/home/client"),
+            ("TERM", "xterm"),
+            ("TMUX", "/tmp/tmux-1000/default,1,0"),
+            ("SESH_NAME", "outer"),
+            ("SESH_STATUS_FILE", "/tmp/outer.status"),
+            ("SESH_SERVER_SOCKET", "/tmp/server.sock"),
+            ("SECRET_TOKEN", "hunter2"),
+        ]
+        .map(|(key, value)| (key.to_owned(), value.to_owned()));
+        let policy = EnvPolicy::new(None, vec!["SECRET_*".to_owned()]);
+        let launch = Launch {
+            pwd: "/",
+            env: &env,
+            policy: &policy,
+            login: false,
+            term: None,
+            socket_path: Path::new("/tmp/test.sock"),
+            status_file: None,
+            name: "test",
+            id: 0,
+        };
+        let pty = session_command("/usr/bin/env", &[], &launch)
+            .spawn(&Size::default())
+            .expect("failed to spawn");
+        let mut file = pty.file().try_clone().await.expect("failed to clone pty");
+        let mut output = Vec::new();
+        let mut packet = [0; 4096];
+        while let Ok(n @ 1..) = file.read(&mut packet).await {
+            output.extend_from_slice(&packet[..n]);
+        }
+        let mut vars = String::from_utf8_lossy(&output)
+            .lines()
+            .map(|line| line.trim().to_owned())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+        vars.sort();
+        assert_eq!(
+            vars,
+            [
+                "HOME=/home/client",
+                "PATH=/client/bin",
+                "SESH_ID=0",
+                "SESH_NAME=test",
+                "SESH_SERVER_SOCKET=/tmp/server.sock",
+                "SESH_SESSION=/tmp/test.sock",
+                "TERM=xterm",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn login_prefixes_argv0() {
+        assert_eq!(
+            output_with(&[], true, None, "printf %s \"$0\"").await,
+            "-sh"
+        );
+    }
+
+    #[tokio::test]
+    async fn term_overrides_client_term() {
+        let env = vec![("TERM".to_owned(), "xterm".to_owned())];
+        let term = output_with(&env, false, Some("vt100"), "printf %s \"$TERM\"").await;
+        assert_eq!(term, "vt100");
+    }
+}
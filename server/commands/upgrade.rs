@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use log::info;
+use sesh_proto::UpgradeServerResponse;
+
+use crate::Seshd;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for replacing this server with a new one, without killing any sessions
+    pub async fn exec_upgrade(&self, seshd_path: String) -> Result<CommandResponse> {
+        let pid = self.hand_over(&seshd_path).await?;
+
+        // Exit without dropping the sessions, since they belong to the new server now.
+        // The delay gives this response time to reach the client.
+        tokio::task::spawn(async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            info!(target: "exit", "Upgraded, exiting");
+            std::process::exit(0);
+        });
+
+        Ok(CommandResponse::UpgradeServer(UpgradeServerResponse {
+            pid,
+        }))
+    }
+}
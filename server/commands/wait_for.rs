@@ -0,0 +1,20 @@
+use anyhow::Result;
+use log::info;
+use sesh_proto::SeshWaitForResponse;
+
+use crate::Seshd;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for waiting on or signaling a named channel
+    pub async fn exec_wait_for(&self, channel: String, signal: bool) -> Result<CommandResponse> {
+        if signal {
+            info!(target: "channels", "Signaling {}", channel);
+            self.channels.signal(&channel);
+        } else {
+            self.channels.wait(&channel).await?;
+        }
+        Ok(CommandResponse::WaitFor(SeshWaitForResponse {}))
+    }
+}
@@ -0,0 +1,42 @@
+use crate::{window, Seshd};
+
+use anyhow::Result;
+use log::info;
+use sesh_proto::{sesh_select_window_request as req, SeshSelectWindowResponse};
+use sesh_shared::error::SeshError;
+
+use super::CommandResponse;
+
+impl Seshd {
+    /// RPC handler for showing the attached client another of a session's windows
+    pub async fn exec_select_window(
+        &self,
+        session: Option<req::Session>,
+        step: i32,
+    ) -> Result<CommandResponse> {
+        let session = match session {
+            Some(req::Session::Name(name)) => self.sessions.get(&name),
+            Some(req::Session::Id(id)) => self.sessions.get_by_id(id as usize),
+            None => None,
+        }
+        .ok_or(SeshError::SessionNotFound)?;
+
+        let running = std::iter::once(true)
+            .chain(
+                session
+                    .windows
+                    .iter()
+                    .map(|window| window.exit.status().is_none()),
+            )
+            .collect::<Vec<_>>();
+        let index = window::step(session.info.active(), step, &running);
+        let name = session.window_names().swap_remove(index);
+        info!(target: &session.log_group(), "Showing window {} ({})", index, name);
+        session.info.select_window(index);
+
+        Ok(CommandResponse::SelectWindow(SeshSelectWindowResponse {
+            index: index as u32,
+            name,
+        }))
+    }
+}
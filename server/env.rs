@@ -0,0 +1,96 @@
+//! Which of the client's environment variables are passed on to the sessions it starts.
+
+/// Variables of the multiplexer the client runs in, or of the session it was started from, which
+/// would make programs in the new session think they run there. The sesh ones are set again for
+/// the new session.
+const DROPPED: &[&str] = &[
+    "TMUX",
+    "TMUX_PANE",
+    "STY",
+    "SESH_SESSION",
+    "SESH_NAME",
+    "SESH_ID",
+    "SESH_STATUS_FILE",
+];
+
+/// Which variables are passed on, as set by $SESH_ENV_ALLOW and $SESH_ENV_DENY when the server
+/// started. Each is a comma-separated list of names, which can end in `*` to match a prefix.
+#[derive(Debug, Clone, Default)]
+pub struct EnvPolicy {
+    /// Only these are passed on, if set
+    allow: Option<Vec<String>>,
+    /// These are never passed on
+    deny: Vec<String>,
+}
+
+impl EnvPolicy {
+    pub fn new(allow: Option<Vec<String>>, deny: Vec<String>) -> Self {
+        Self { allow, deny }
+    }
+
+    pub fn from_env() -> Self {
+        let list = |var| {
+            std::env::var(var).ok().map(|list| {
+                list.split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>()
+            })
+        };
+        Self::new(
+            list("SESH_ENV_ALLOW"),
+            list("SESH_ENV_DENY").unwrap_or_default(),
+        )
+    }
+
+    /// Whether a variable of the client's is passed on. $TERM always is, so the session can
+    /// draw on the client's terminal, unless the session was given its own.
+    pub fn forwards(&self, name: &str) -> bool {
+        if name == "TERM" {
+            return true;
+        }
+        let matches = |patterns: &[String]| {
+            patterns
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => name.starts_with(prefix),
+                    None => name == pattern,
+                })
+        };
+        !DROPPED.contains(&name)
+            && self.allow.as_deref().is_none_or(matches)
+            && !matches(&self.deny)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: Option<&[&str]>, deny: &[&str]) -> EnvPolicy {
+        let list = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        EnvPolicy::new(allow.map(list), list(deny))
+    }
+
+    #[test]
+    fn drops_outer_session_and_multiplexer() {
+        let policy = EnvPolicy::default();
+        for name in ["TMUX", "STY", "SESH_NAME", "SESH_ID"] {
+            assert!(!policy.forwards(name), "{} was passed on", name);
+        }
+        // Settings are meant to be inherited
+        assert!(policy.forwards("SESH_SERVER_SOCKET"));
+        assert!(policy.forwards("PATH"));
+    }
+
+    #[test]
+    fn allow_and_deny_lists() {
+        let policy = policy(Some(&["PATH", "LC_*"]), &["LC_ALL"]);
+        assert!(policy.forwards("PATH"));
+        assert!(policy.forwards("LC_CTYPE"));
+        assert!(!policy.forwards("LC_ALL"));
+        assert!(!policy.forwards("HOME"));
+        assert!(policy.forwards("TERM"));
+    }
+}
@@ -0,0 +1,57 @@
+use sesh_proto::{
+    sesh_event::{ExitStatus as ProtoExitStatus, Kind},
+    SeshEvent,
+};
+
+use crate::{reaper::ExitStatus, session::now_millis, Seshd, Session};
+
+/// How many events a subscriber can fall behind before it starts missing them
+pub const EVENT_BUFFER: usize = 256;
+
+/// Creates an event about a session
+pub fn session_event(kind: Kind, name: impl Into<String>, id: usize) -> SeshEvent {
+    SeshEvent {
+        kind: kind.into(),
+        time: now_millis(),
+        session: name.into(),
+        id: id as u64,
+        ..Default::default()
+    }
+}
+
+/// Creates an event about a session's process exiting
+pub fn exit_event(kind: Kind, session: &Session, status: ExitStatus) -> SeshEvent {
+    SeshEvent {
+        exit_status: match status {
+            ExitStatus::Code(code) => Some(ProtoExitStatus::Code(code)),
+            ExitStatus::Signal(sig) => Some(ProtoExitStatus::Signal(sig)),
+            ExitStatus::Unknown => None,
+        },
+        ..session_event(kind, &session.name, session.id)
+    }
+}
+
+/// Creates an event about a session being renamed
+pub fn rename_event(name: impl Into<String>, id: usize, new_name: impl Into<String>) -> SeshEvent {
+    SeshEvent {
+        new_name: new_name.into(),
+        ..session_event(Kind::Renamed, name, id)
+    }
+}
+
+/// Creates an event about the server itself
+pub fn server_event(kind: Kind) -> SeshEvent {
+    SeshEvent {
+        kind: kind.into(),
+        time: now_millis(),
+        ..Default::default()
+    }
+}
+
+impl Seshd {
+    /// Publishes an event to everyone subscribed with `SubscribeEvents`
+    pub fn emit(&self, event: SeshEvent) {
+        // Sending only fails when nobody is subscribed
+        self.events.send(event).ok();
+    }
+}
@@ -0,0 +1,125 @@
+//! How many sessions may run at once, so a script starting them in a loop is stopped before
+//! the server runs out of fds and every session breaks at once.
+
+use log::{error, warn};
+use sesh_shared::{error::SeshError, sys};
+
+/// Most sessions that can run at once when $SESH_MAX_SESSIONS isn't set
+pub const DEFAULT_MAX_SESSIONS: usize = 100;
+/// How few more fds the server may open before starting a session is warned about. Each session
+/// takes a few, for its pty, its socket and its clients.
+const FD_HEADROOM: u64 = 20;
+
+/// The limits set by $SESH_MAX_SESSIONS and $SESH_MAX_PER_PROGRAM when the server started.
+/// Either is unlimited if set to 0.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Most sessions that can run at once, or None for no limit
+    sessions: Option<usize>,
+    /// Most sessions running the same program, by its file name, or None for no limit
+    per_program: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            sessions: Some(DEFAULT_MAX_SESSIONS),
+            per_program: None,
+        }
+    }
+}
+
+impl Limits {
+    pub fn new(sessions: Option<usize>, per_program: Option<usize>) -> Self {
+        Self {
+            sessions,
+            per_program,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self::new(
+            limit("SESH_MAX_SESSIONS", defaults.sessions),
+            limit("SESH_MAX_PER_PROGRAM", defaults.per_program),
+        )
+    }
+
+    /// Checks that another session running `program` can be started, given how many sessions
+    /// there are, and how many of them run the same program
+    pub fn check(
+        &self,
+        program: &str,
+        sessions: usize,
+        same_program: usize,
+    ) -> Result<(), SeshError> {
+        if let Some(max) = self.sessions.filter(|&max| sessions >= max) {
+            return Err(SeshError::SessionLimit(format!(
+                "Session limit ({}) reached, kill a session or raise SESH_MAX_SESSIONS",
+                max
+            )));
+        }
+        if let Some(max) = self.per_program.filter(|&max| same_program >= max) {
+            return Err(SeshError::SessionLimit(format!(
+                "Session limit for {} ({}) reached, kill one of its sessions or raise \
+                 SESH_MAX_PER_PROGRAM",
+                program, max
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Reads a limit from an environment variable, using `default` if it's unset or invalid
+fn limit(var: &str, default: Option<usize>) -> Option<usize> {
+    let Ok(limit) = std::env::var(var) else {
+        return default;
+    };
+    match limit.trim().parse() {
+        Ok(0) => None,
+        Ok(limit) => Some(limit),
+        Err(_) => {
+            error!(target: "init", "Ignoring invalid {}: {}", var, limit);
+            default
+        }
+    }
+}
+
+/// Warns when the server is close to the most fds it may have open, which starting sessions
+/// would soon run into
+pub fn warn_fd_headroom() {
+    match sys::fd_headroom() {
+        Ok(left) if left <= FD_HEADROOM => warn!(
+            target: "exec",
+            "Only {} more fds can be opened, raise the server's open file limit (ulimit -n) or \
+             kill some sessions",
+            left
+        ),
+        Ok(_) => (),
+        Err(e) => warn!(target: "exec", "Could not count open fds: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let limits = Limits::new(Some(3), Some(1));
+        assert!(limits.check("vim", 2, 0).is_ok());
+        assert_eq!(
+            limits.check("vim", 3, 0),
+            Err(SeshError::SessionLimit(
+                "Session limit (3) reached, kill a session or raise SESH_MAX_SESSIONS".to_owned()
+            ))
+        );
+        let error = limits.check("vim", 2, 1).unwrap_err();
+        assert!(error.to_string().starts_with("Session limit for vim (1)"));
+        assert_eq!(error.code(), tonic::Code::ResourceExhausted);
+        let unlimited = Limits::new(None, None);
+        assert!(unlimited.check("vim", 10_000, 10_000).is_ok());
+        assert!(Limits::default().check("vim", 99, 99).is_ok());
+        assert!(Limits::default().check("vim", 100, 0).is_err());
+    }
+}
@@ -1,32 +1,87 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use clap::Parser;
 use dashmap::DashMap;
-use log::info;
+use log::{error, info};
 
-use session::Session;
-use std::{path::PathBuf, sync::Arc};
+use sesh_shared::{error::SeshError, name};
+use session::{now_millis, RelayHandles, Session};
+use std::{
+    collections::HashSet,
+    os::fd::FromRawFd,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 use tokio::{
-    net::UnixListener,
-    signal::unix::{signal, SignalKind},
-    sync::mpsc::Sender,
+    fs::File,
+    net::{UnixListener, UnixStream},
+    sync::{broadcast, mpsc::Sender, watch},
 };
-use tokio_stream::wrappers::UnixListenerStream;
-use tonic::transport::Server as RPCServer;
 
 use sesh_proto::{
-    seshd_server::SeshdServer, SeshAttachRequest, SeshDetachRequest, SeshKillRequest,
-    SeshResizeRequest, SeshStartRequest,
+    client_session_ended_request::Reason, sesh_event::Kind, SeshAttachRequest, SeshBatchRequest,
+    SeshCwdRequest, SeshDetachRequest, SeshEvent, SeshFollowRequest, SeshKillRequest,
+    SeshListRequest, SeshNoteRequest, SeshPruneRequest, SeshRedrawRequest, SeshRenameRequest,
+    SeshResetRequest, SeshResizeRequest, SeshReviveRequest, SeshSelectWindowRequest,
+    SeshSignalRequest, SeshWaitForRequest, UpgradeServerRequest,
 };
 
+mod builder;
+mod channels;
 mod commands;
+mod env;
+mod events;
+mod limits;
+mod notify;
+mod reaper;
+mod replay;
 mod rpc;
 mod session;
+mod starts;
+mod state;
+mod supervisor;
+mod upgrade;
+mod window;
+use builder::SeshdBuilder;
+use channels::Channels;
 use commands::{Command, CommandResponse};
+use env::EnvPolicy;
+use limits::Limits;
+use reaper::{ExitWatch, Reaper};
+use starts::Starts;
+use state::{SessionRecord, State};
+use upgrade::Handover;
+
+/// How long sessions get to exit after SIGTERM when the server shuts down, before SIGKILL
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
 
-pub const EXIT_ON_EMPTY: bool = true;
+#[derive(Debug, Parser)]
+#[clap(name = "seshd", version)]
+/// The sesh server. This is started automatically by the sesh client.
+struct Args {
+    /// Take over the sessions of a running server, received over this socket
+    #[arg(long, hide = true)]
+    upgrade: Option<PathBuf>,
+    /// Directory for the server's and sessions' sockets, instead of sesh/ in $XDG_RUNTIME_DIR.
+    /// Also set by $SESH_RUNTIME_DIR. The list of dead sessions is kept there too, so it isn't
+    /// shared with the default server
+    #[arg(long)]
+    runtime_dir: Option<PathBuf>,
+    /// Socket to listen on, instead of server.sock in the runtime directory
+    #[arg(long)]
+    socket: Option<PathBuf>,
+}
 
 struct SessionList {
     sessions: DashMap<String, Session>,
     lookup: DashMap<usize, String>,
+    /// Held while sessions are added, removed, or moved to a new name or id, so the lookup
+    /// always matches the sessions. Holds the names reserved by starts in flight, so no two
+    /// sessions end up with the same name.
+    moving: Mutex<HashSet<String>>,
 }
 
 impl SessionList {
@@ -34,6 +89,7 @@ impl SessionList {
         Self {
             sessions: DashMap::new(),
             lookup: DashMap::new(),
+            moving: Mutex::default(),
         }
     }
 
@@ -42,131 +98,539 @@ impl SessionList {
         self.sessions.len()
     }
 
-    /// Checks if a session exists by name
-    pub fn contains(&self, name: impl AsRef<str>) -> bool {
-        self.sessions.contains_key(name.as_ref())
-    }
-
     /// Gets a session by name
-    pub fn get(&self, name: impl AsRef<str>) -> Option<dashmap::mapref::one::Ref<String, Session>> {
+    pub fn get(
+        &self,
+        name: impl AsRef<str>,
+    ) -> Option<dashmap::mapref::one::Ref<'_, String, Session>> {
         self.sessions.get(name.as_ref())
     }
 
     /// Gets a session by id
-    pub fn get_by_id(&self, id: usize) -> Option<dashmap::mapref::one::Ref<String, Session>> {
+    pub fn get_by_id(&self, id: usize) -> Option<dashmap::mapref::one::Ref<'_, String, Session>> {
         self.lookup
             .get(&id)
             .and_then(|name| self.sessions.get(name.as_str()))
     }
 
-    /// Inserts a session into the list
-    pub fn insert(&self, name: String, session: Session) {
+    /// The id for a new session, after the highest one in use
+    pub fn next_id(&self) -> usize {
+        self.lookup
+            .iter()
+            .map(|entry| *entry.key() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Picks a name for a new session from `name`, adding a suffix if a session already has it
+    /// or another start has reserved it. The name is reserved until the session is inserted or
+    /// the name is released.
+    pub fn reserve(&self, name: &str) -> String {
+        let mut reserved = self.moving.lock().expect("session list lock poisoned");
+        let mut session_name = name.to_owned();
+        let mut i = 0;
+        while self.sessions.contains_key(&session_name) || reserved.contains(&session_name) {
+            session_name = name::with_suffix(name, i);
+            i += 1;
+        }
+        reserved.insert(session_name.clone());
+        session_name
+    }
+
+    /// Gives back a name reserved for a start that failed
+    pub fn release(&self, name: &str) {
+        let mut reserved = self.moving.lock().expect("session list lock poisoned");
+        reserved.remove(name);
+    }
+
+    /// Inserts a session into the list, taking the place of the name reserved for it. Fails if
+    /// a session already has the name.
+    pub fn insert(&self, name: String, session: Session) -> Result<()> {
+        let mut reserved = self.moving.lock().expect("session list lock poisoned");
+        if self.sessions.contains_key(&name) {
+            return Err(SeshError::SessionExists(name).into());
+        }
+        reserved.remove(&name);
         self.lookup.insert(session.id, name.clone());
         self.sessions.insert(name, session);
+        Ok(())
     }
 
     /// Removes a session by name
     pub fn remove(&self, name: impl AsRef<str>) -> Option<Session> {
+        let _moving = self.moving.lock().expect("session list lock poisoned");
         self.sessions.remove(name.as_ref()).map(|(_, session)| {
             self.lookup.remove(&session.id);
             session
         })
     }
 
-    /// Removes sessions with exited processes
-    pub fn clean(&self) -> bool {
-        self.sessions.retain(|name, session| {
-            let pid = session.pid();
-            let res = unsafe { libc::waitpid(pid, &mut 0, libc::WNOHANG) };
-            if res > 0 {
-                info!(
-                    target: &format!("{}: {}", session.id, name),
-                    "Subprocess {} exited", session.program
-                );
+    /// Removes every session
+    pub fn remove_all(&self) -> Vec<Session> {
+        let names = self
+            .sessions
+            .iter()
+            .map(|s| s.key().clone())
+            .collect::<Vec<_>>();
+        names
+            .into_iter()
+            .filter_map(|name| self.remove(name))
+            .collect()
+    }
+
+    /// Name of the session running the process watched by `exit`, whatever it's been renamed
+    /// or renumbered to since
+    fn running(&self, exit: &ExitWatch) -> Option<String> {
+        self.sessions
+            .iter()
+            .find(|session| session.exit.same_process(exit))
+            .map(|session| session.key().clone())
+    }
+
+    /// Removes the session running the process watched by `exit`, unless it has already been
+    /// removed
+    pub fn remove_exited(&self, exit: &ExitWatch) -> Option<Session> {
+        let _moving = self.moving.lock().expect("session list lock poisoned");
+        let name = self.running(exit)?;
+        self.sessions.remove(&name).map(|(_, session)| {
+            self.lookup.remove(&session.id);
+            session
+        })
+    }
+
+    /// Takes note that the process watched by `exit` has exited, if it belongs to a session
+    /// started with --keep. Returns the name of the session if it's kept.
+    pub fn keep_exited(&self, exit: &ExitWatch) -> Option<String> {
+        let _moving = self.moving.lock().expect("session list lock poisoned");
+        let name = self.running(exit)?;
+        let mut session = self.sessions.get_mut(&name)?;
+        if !session.keep {
+            return None;
+        }
+        // The pid has been reaped and may be reused, so don't signal it on drop
+        session.pty.daemonize();
+        Some(name)
+    }
+
+    /// Gives the sessions ids counting up from 0, in the order they were started. Returns the
+    /// name, old id and new id of each session whose id changed.
+    pub fn renumber(&self) -> Vec<(String, usize, usize)> {
+        let _moving = self.moving.lock().expect("session list lock poisoned");
+        let mut order = self
+            .sessions
+            .iter()
+            .map(|session| (session.info.start_time, session.id, session.key().clone()))
+            .collect::<Vec<_>>();
+        order.sort();
+        let count = order.len();
+        let mut renumbered = Vec::new();
+        for (new_id, (_, old_id, name)) in order.into_iter().enumerate() {
+            if let Some(mut session) = self.sessions.get_mut(&name) {
+                session.id = new_id;
             }
-            return res <= 0;
-        });
-        self.lookup
-            .retain(|_, name| self.sessions.contains_key(name));
-        return self.sessions.is_empty();
+            // Replaced rather than cleared first, so a session looked up meanwhile is found
+            self.lookup.insert(new_id, name.clone());
+            if new_id != old_id {
+                renumbered.push((name, old_id, new_id));
+            }
+        }
+        self.lookup.retain(|&id, _| id < count);
+        renumbered
+    }
+
+    /// Renames a session, returning its id
+    pub fn rename(&self, name: impl AsRef<str>, new_name: String) -> Result<usize> {
+        let reserved = self.moving.lock().expect("session list lock poisoned");
+        if self.sessions.contains_key(&new_name) || reserved.contains(&new_name) {
+            return Err(SeshError::SessionExists(new_name).into());
+        }
+        let (old_name, mut session) = self
+            .sessions
+            .remove(name.as_ref())
+            .ok_or(SeshError::SessionNotFound)?;
+        if let Err(e) = session.rename(new_name.clone()) {
+            self.sessions.insert(old_name, session);
+            return Err(e);
+        }
+        let id = session.id;
+        self.lookup.insert(id, new_name.clone());
+        self.sessions.insert(new_name, session);
+        Ok(id)
     }
 
     pub fn is_empty(&self) -> bool {
         self.sessions.is_empty()
     }
 
+    /// Returns the records needed to restart each session
+    pub fn records(&self) -> Vec<SessionRecord> {
+        self.sessions.iter().map(|s| s.record()).collect()
+    }
+
     pub fn iter(
         &self,
-    ) -> impl Iterator<Item = dashmap::mapref::multiple::RefMulti<String, Session>> {
+    ) -> impl Iterator<Item = dashmap::mapref::multiple::RefMulti<'_, String, Session>> {
         self.sessions.iter()
     }
 }
 
 struct Seshd {
     sessions: Arc<SessionList>,
+    state: Arc<State>,
     exit_signal: Sender<()>,
     runtime_dir: PathBuf,
+    /// The socket clients connect to
+    socket_path: PathBuf,
+    reaper: Arc<Reaper>,
+    events: broadcast::Sender<SeshEvent>,
+    channels: Arc<Channels>,
+    /// The server's listening socket, passed on to a new server when upgrading. Set once the
+    /// server starts serving on it.
+    listener_fd: AtomicI32,
+    /// Whether the server takes new clients and starts serving sessions, which it stops doing
+    /// while handing them over to a new server
+    accepting: watch::Sender<bool>,
+    /// How many sessions can run at once
+    limits: Limits,
+    /// Which of a client's variables are passed on to the sessions it starts
+    env_policy: EnvPolicy,
+    /// Decides when the server exits for having no sessions
+    starts: Arc<Starts>,
+}
+
+/// Held by the task serving a session's clients. However the task ends, even by panicking,
+/// the session is left as not served and with no clients, so a later attach can start another
+struct Serving {
+    serving: Arc<AtomicBool>,
+    connected: Arc<AtomicBool>,
+    clients: Arc<AtomicUsize>,
+}
+
+impl Drop for Serving {
+    fn drop(&mut self) {
+        self.clients.store(0, Ordering::Release);
+        self.connected.store(false, Ordering::Release);
+        self.serving.store(false, Ordering::Release);
+    }
 }
 
 impl Seshd {
-    fn new(exit_signal: Sender<()>, runtime_dir: PathBuf) -> Result<Self> {
+    fn new(
+        exit_signal: Sender<()>,
+        runtime_dir: PathBuf,
+        socket_path: PathBuf,
+        state: Arc<State>,
+        exit_on_empty: bool,
+    ) -> Result<Self> {
         let sessions = Arc::new(SessionList::new());
-        // Handle process exits
-        tokio::task::spawn({
-            let sessions = Arc::clone(&sessions);
-            let exit = exit_signal.clone();
-            async move {
-                let mut signal = signal(SignalKind::child())?;
-                loop {
-                    signal.recv().await;
-                    if sessions.clean() && EXIT_ON_EMPTY {
-                        exit.send(()).await?;
-                        break;
-                    }
-                }
-                Result::<_, anyhow::Error>::Ok(())
-            }
-        });
+        let reaper = Reaper::spawn()?;
+        let (events, _) = broadcast::channel(events::EVENT_BUFFER);
         info!(target: "rpc", "Server started");
+        let starts = Arc::new(Starts::new(
+            Arc::clone(&sessions),
+            exit_signal.clone(),
+            exit_on_empty,
+        ));
         Ok(Self {
             sessions,
+            state,
             exit_signal,
             runtime_dir,
+            socket_path,
+            reaper,
+            events,
+            channels: Arc::default(),
+            listener_fd: AtomicI32::new(-1),
+            accepting: watch::channel(true).0,
+            limits: Limits::from_env(),
+            env_policy: EnvPolicy::from_env(),
+            starts,
         })
     }
 
+    /// Fails while the server is handing its sessions over, since a session served or started
+    /// then would be left behind
+    pub fn check_accepting(&self) -> Result<()> {
+        if !*self.accepting.borrow() {
+            anyhow::bail!("Server is upgrading");
+        }
+        Ok(())
+    }
+
+    /// Writes the current session list to the state file
+    pub fn persist(&self) {
+        if let Err(e) = self.state.save(self.sessions.records()) {
+            error!(target: "state", "Failed to save state: {}", e);
+        }
+    }
+
+    /// Serves clients on the session's socket until its process exits: waits for a client,
+    /// relays between it and the pty until it leaves, then waits for the next one. `client` is
+    /// served first if one is already connected. Does nothing if a task is already serving the
+    /// session, since that one takes the next client.
+    pub fn serve_clients(&self, session: &Session, client: Option<UnixStream>) -> Result<()> {
+        self.check_accepting()?;
+        let sock_path = session.info.sock_path().clone();
+        let socket = session.listener.clone();
+        let fds = session.window_fds();
+        let active = session.info.active_handle();
+        let mut exit = session.exit.clone();
+        let connected = session.info.connected();
+        let clients = session.info.clients();
+        let serving = session.info.serving();
+        let connect_size = session.info.connect_size_handle();
+        let session_size = session.info.size_handle();
+        let attach_time = session.info.attach_time.clone();
+        let client_fd = session.info.client_fd();
+        let notify = session.info.notify.clone();
+        let status_file = session.info.status_file_handle();
+        let pid = session.pid();
+        let keep = session.keep;
+        let mut removed = session.info.removed();
+        let supervisor = session.info.supervisor.clone();
+        let client_sock_path = session.client_sock_path()?;
+        let followers = session.info.followers_handle();
+        let followed = session.info.followed_handle();
+        let event_tx = self.events.clone();
+        let (name, id) = (session.name.clone(), session.id);
+        if serving.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+        // Duplicate FD
+        // I do not know why this makes the socket connection not die, but it does
+        let fds = fds
+            .into_iter()
+            .map(|fd| unsafe { libc::fcntl(fd, libc::F_DUPFD, fd) })
+            .collect::<Vec<_>>();
+
+        info!(target: &session.log_group(), "Waiting for clients on {}", sock_path.display());
+        let task_supervisor = supervisor.clone();
+        supervisor.spawn("serving clients", async move {
+            let supervisor = task_supervisor;
+            // The pty files are closed along with the task, so a later attach can start another
+            let _serving = Serving {
+                serving,
+                connected: connected.clone(),
+                clients: clients.clone(),
+            };
+            let res = async {
+                let mut pty_readers = fds
+                    .iter()
+                    .map(|&fd| unsafe { File::from_raw_fd(fd) })
+                    .collect::<Vec<_>>();
+                let mut pty_writers = Vec::with_capacity(pty_readers.len());
+                for pty_reader in &pty_readers {
+                    pty_writers.push(pty_reader.try_clone().await?);
+                }
+                let mut client = client;
+                let mut packet = [0; 4096];
+                // Whether the pty can still be read for followers, which it can't once it's
+                // closed
+                let mut readable = true;
+                loop {
+                    let stream = match client.take() {
+                        Some(stream) => stream,
+                        None => {
+                            let accept = Session::accept(
+                                &socket,
+                                &fds,
+                                &connected,
+                                &connect_size,
+                                &session_size,
+                                &attach_time,
+                            );
+                            let window = *active.borrow();
+                            let following = readable && followers.receiver_count() > 0;
+                            // A kept session can still be attached to after its process exits
+                            let stream = tokio::select! {
+                                stream = accept => stream?,
+                                _ = exit.wait(), if !keep => break,
+                                _ = removed.changed() => break,
+                                // Without a client the pty is only read while someone follows
+                                read = session::read_pty(&mut pty_readers[window], &mut packet),
+                                    if following =>
+                                {
+                                    match read {
+                                        // Followers go back to the program's window when
+                                        // another one exits, as a client would
+                                        Ok(0) if window > 0 => {
+                                            active.send_replace(0);
+                                        }
+                                        Ok(0) | Err(_) => readable = false,
+                                        Ok(n) => session::follow(&followers, &packet[..n]),
+                                    }
+                                    continue;
+                                }
+                                _ = followed.notified() => continue,
+                            };
+                            // Clients are shown the program's own window when they attach
+                            active.send_replace(0);
+                            event_tx
+                                .send(events::session_event(Kind::Attached, &name, id))
+                                .ok();
+                            // A client handed over by an upgrade was already attached
+                            notify.send(pid, &exit, &status_file, true);
+                            stream
+                        }
+                    };
+
+                    clients.fetch_add(1, Ordering::AcqRel);
+                    let relay = Session::relay(
+                        stream,
+                        &mut pty_readers,
+                        &mut pty_writers,
+                        RelayHandles {
+                            active: active.clone(),
+                            connected: connected.clone(),
+                            client_fd: client_fd.clone(),
+                            kept: keep.then(|| exit.clone()),
+                            followers: followers.clone(),
+                        },
+                    );
+                    tokio::pin!(relay);
+                    // The keepalive disconnects a client that stopped answering, which ends the
+                    // relay
+                    let timed_out = tokio::select! {
+                        res = &mut relay => {
+                            match res {
+                                Ok(()) => supervisor.recovered(),
+                                Err(e) => supervisor.fail("relay", &e),
+                            }
+                            false
+                        }
+                        true = Session::keepalive(
+                            client_sock_path.clone(),
+                            connected.clone(),
+                            client_fd.clone(),
+                        ) => {
+                            relay.await.ok();
+                            true
+                        }
+                    };
+                    clients.fetch_sub(1, Ordering::AcqRel);
+                    notify.send(pid, &exit, &status_file, false);
+                    if timed_out {
+                        attach_time.store(now_millis(), Ordering::Relaxed);
+                        event_tx
+                            .send(events::session_event(Kind::Detached, &name, id))
+                            .ok();
+                    }
+                }
+                Result::<_, anyhow::Error>::Ok(())
+            }
+            .await;
+            if let Err(e) = &res {
+                error!(target: "session", "Serving clients on {} failed: {:#}", sock_path.display(), e);
+            }
+            res
+        });
+        Ok(())
+    }
+
+    /// Removes the session once its process exits, unless it has already been replaced or killed
+    pub fn watch_session(&self, mut exit: ExitWatch) {
+        let sessions = Arc::clone(&self.sessions);
+        let state = Arc::clone(&self.state);
+        let starts = Arc::clone(&self.starts);
+        let event_tx = self.events.clone();
+        tokio::task::spawn(async move {
+            let status = exit.wait().await;
+            if let Some(name) = sessions.keep_exited(&exit) {
+                if let Some(session) = sessions.get(name) {
+                    event_tx
+                        .send(events::exit_event(Kind::Exited, &session, status))
+                        .ok();
+                    info!(
+                        target: &session.log_group(),
+                        "Subprocess {} exited with {}, keeping the session until it's killed",
+                        session.program,
+                        status
+                    );
+                }
+                return;
+            }
+            let Some(mut session) = sessions.remove_exited(&exit) else {
+                return;
+            };
+            event_tx
+                .send(events::exit_event(Kind::Exited, &session, status))
+                .ok();
+            info!(
+                target: &session.log_group(),
+                "Subprocess {} exited with {}", session.program, status
+            );
+            session.ended(Reason::Exited, status).await.ok();
+            // The pid has been reaped and may be reused, so don't signal it on drop
+            session.pty.daemonize();
+            drop(session);
+            if let Err(e) = state.save(sessions.records()) {
+                error!(target: "state", "Failed to save state: {}", e);
+            }
+            starts.exit_if_empty();
+        });
+    }
+
     pub async fn exec(&self, cmd: Command) -> Result<CommandResponse> {
         match cmd {
             Command::ResizeSession(SeshResizeRequest { session, size }) => {
                 self.exec_resize(session, size).await
             }
-            Command::ListSessions => self.exec_list().await,
-            Command::StartSession(SeshStartRequest {
-                name,
-                program,
-                args,
-                size,
-                pwd,
-                env,
-            }) => {
-                self.exec_start(
-                    name,
-                    program,
-                    args,
-                    size,
-                    pwd,
-                    env.into_iter().map(|v| (v.key, v.value)).collect(),
-                )
-                .await
-            }
-            Command::AttachSession(SeshAttachRequest { session, size }) => {
-                self.exec_attach(session, size).await
+            Command::SelectWindow(SeshSelectWindowRequest { session, step }) => {
+                self.exec_select_window(session, step).await
             }
+            Command::ResetSession(SeshResetRequest { session }) => self.exec_reset(session).await,
+            Command::RedrawSession(SeshRedrawRequest { session }) => {
+                self.exec_redraw(session).await
+            }
+            Command::ListSessions(SeshListRequest { dead, filter }) => {
+                self.exec_list(dead, filter.unwrap_or_default()).await
+            }
+            Command::StartSession(req) => self.exec_start(req.into()).await,
+            Command::AttachSession(SeshAttachRequest {
+                session,
+                size,
+                keep_size,
+            }) => self.exec_attach(session, size, keep_size).await,
             Command::DetachSession(SeshDetachRequest { session }) => {
                 self.exec_detach(session).await
             }
-            Command::KillSession(SeshKillRequest { session }) => self.exec_kill(session).await,
+            Command::KillSession(SeshKillRequest {
+                session,
+                grace_ms,
+                signal,
+            }) => self.exec_kill(session, grace_ms, signal).await,
+            Command::SignalSession(SeshSignalRequest { session, signal }) => {
+                self.exec_signal(session, signal).await
+            }
+            Command::RenameSession(SeshRenameRequest { session, new_name }) => {
+                self.exec_rename(session, new_name).await
+            }
+            Command::ReviveSession(SeshReviveRequest { name, size, attach }) => {
+                self.exec_revive(name, size, attach).await
+            }
+            Command::PasteSession(chunks) => self.exec_paste(chunks).await,
+            Command::SessionCwd(SeshCwdRequest { session }) => self.exec_cwd(session).await,
+            Command::NoteSession(SeshNoteRequest { session, note }) => {
+                self.exec_note(session, note).await
+            }
             Command::ShutdownServer => self.exec_shutdown().await,
+            Command::SubscribeEvents => self.exec_subscribe_events().await,
+            Command::FollowSession(SeshFollowRequest { session }) => {
+                self.exec_follow(session).await
+            }
+            Command::UpgradeServer(UpgradeServerRequest { seshd_path }) => {
+                self.exec_upgrade(seshd_path).await
+            }
+            Command::ServerInfo => self.exec_server_info().await,
+            Command::RenumberSessions => self.exec_renumber().await,
+            Command::PruneSessions(SeshPruneRequest { dry_run }) => self.exec_prune(dry_run).await,
+            Command::WaitFor(SeshWaitForRequest { channel, signal }) => {
+                self.exec_wait_for(channel, signal).await
+            }
+            Command::Batch(SeshBatchRequest {
+                commands,
+                fail_fast,
+            }) => self.exec_batch(commands, fail_fast).await,
         }
     }
 }
@@ -174,54 +638,54 @@ impl Seshd {
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
+    let args = Args::parse();
 
-    let runtime_dir = dirs::runtime_dir()
+    let default_runtime_dir = dirs::runtime_dir()
         .unwrap_or(PathBuf::from("/tmp/"))
         .join("sesh/");
+    let runtime_dir = args
+        .runtime_dir
+        .or_else(|| {
+            std::env::var_os("SESH_RUNTIME_DIR")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+        })
+        .unwrap_or_else(|| default_runtime_dir.clone());
 
     info!(target: "init", "Starting up");
-    if !runtime_dir.exists() {
-        info!(target: "init", "Creating runtime directory");
-        std::fs::create_dir_all(&runtime_dir)?;
-    }
-
-    // Create the server socket
-    info!(target: "init", "Creating server socket");
-    let socket_path = runtime_dir.join("server.sock");
-    let uds = UnixListener::bind(&socket_path)?;
-    let uds_stream = UnixListenerStream::new(uds);
-
-    let (exit_tx, mut exit_rx) = tokio::sync::mpsc::channel::<()>(1);
-
-    let sigint_tx = exit_tx.clone();
-    let mut sigint = signal(SignalKind::interrupt())?;
-    let sigquit_tx = exit_tx.clone();
-    let mut sigquit = signal(SignalKind::quit())?;
-    tokio::task::spawn(async move {
-        tokio::select! {
-            _ = sigint.recv() => {
-                info!(target: "exit", "Received SIGINT");
-                sigint_tx.send(()).await.ok();
-            },
-            _ = sigquit.recv() => {
-                info!(target: "exit", "Received SIGQUIT");
-                sigquit_tx.send(()).await.ok();
-            }
-        }
-    });
-
-    // Initialize the Tonic gRPC server
-    info!(target: "init", "Setting up RPC server");
-    RPCServer::builder()
-        .add_service(SeshdServer::new(Seshd::new(exit_tx, runtime_dir)?))
-        .serve_with_incoming_shutdown(uds_stream, async move {
-            exit_rx.recv().await;
-        })
-        .await?;
+    let state_path = if runtime_dir == default_runtime_dir {
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .unwrap_or(runtime_dir.clone())
+            .join("sesh/sessions.json")
+    } else {
+        runtime_dir.join("sessions.json")
+    };
+    let socket_path = args
+        .socket
+        .unwrap_or_else(|| runtime_dir.join("server.sock"));
+    let seshd = SeshdBuilder::new(runtime_dir)
+        .socket_path(socket_path.clone())
+        .state_path(state_path)
+        .build()?;
 
-    info!(target: "exit", "Shutting down");
-    // remove socket on exit
-    std::fs::remove_file(&socket_path)?;
+    let uds = match args.upgrade {
+        Some(path) => {
+            // Reuse the old server's socket, so clients never see it disappear
+            let handover = Handover::receive(&path)?;
+            let uds = handover.listener()?;
+            seshd.adopt(handover)?;
+            uds
+        }
+        None => {
+            // Create the server socket
+            info!(target: "init", "Creating server socket");
+            UnixListener::bind(&socket_path).with_context(|| {
+                format!("Could not create server socket {}", socket_path.display())
+            })?
+        }
+    };
+    seshd.serve(uds).await?;
 
     Ok(())
 }
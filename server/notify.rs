@@ -0,0 +1,102 @@
+//! Telling a session's program when clients attach and detach, for sessions started with
+//! `sesh start --notify-attach`, `--notify-detach` or `--attach-status`. Other sessions aren't
+//! told anything.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sesh_shared::signal;
+
+use crate::reaper::ExitWatch;
+
+/// How a session is told about clients coming and going
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notify {
+    /// Signal sent to the session's process group when a client attaches
+    #[serde(default)]
+    pub attach_signal: Option<i32>,
+    /// Signal sent to the session's process group when its client detaches
+    #[serde(default)]
+    pub detach_signal: Option<i32>,
+    /// Whether `attached=0` or `attached=1` is kept in the session's status file
+    #[serde(default)]
+    pub status_file: bool,
+}
+
+impl Notify {
+    /// Tells the session whose process is `pid` that a client attached or detached, unless the
+    /// process has already exited. `status_file` is where its status file is, if it has one.
+    pub fn send(
+        &self,
+        pid: i32,
+        exit: &ExitWatch,
+        status_file: &Mutex<Option<PathBuf>>,
+        attached: bool,
+    ) {
+        // A client leaves when the session ends, which is nothing to tell it about, and its
+        // status file may be gone already
+        if exit.status().is_some() {
+            return;
+        }
+        let signal = match attached {
+            true => self.attach_signal,
+            false => self.detach_signal,
+        };
+        // Sessions are started with setsid, so the pid is also the process group id
+        if let Some(signal) = signal {
+            if unsafe { libc::kill(-pid, signal) } != 0 {
+                warn!(
+                    target: "session",
+                    "Could not send {} to {}: {}",
+                    signal::name(signal),
+                    pid,
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+        let status_file = status_file.lock().expect("status file lock poisoned");
+        if let Some(path) = status_file.as_deref() {
+            if let Err(e) = write_status(path, attached) {
+                warn!(target: "session", "Could not write {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// The status file of the session with this socket, which sits next to it
+pub fn status_path(sock_path: &Path) -> PathBuf {
+    sock_path.with_extension("status")
+}
+
+/// Writes `attached=0` or `attached=1` to a status file. The file is replaced in one go, so
+/// a script polling it never reads it half written.
+pub fn write_status(path: &Path, attached: bool) -> std::io::Result<()> {
+    let tmp = path.with_extension("status.tmp");
+    std::fs::write(&tmp, format!("attached={}\n", attached as u8))?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_file_is_replaced() {
+        let dir = std::env::temp_dir().join(format!("sesh-notify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create dir");
+        let path = status_path(&dir.join("test.sock"));
+        assert_eq!(path, dir.join("test.status"));
+
+        write_status(&path, true).expect("failed to write status");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "attached=1\n");
+        write_status(&path, false).expect("failed to write status");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "attached=0\n");
+        // Nothing is left behind but the file itself
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
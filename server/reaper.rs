@@ -0,0 +1,169 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use anyhow::Result;
+use log::trace;
+use sesh_shared::{signal, term::process_exit};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+
+/// How a session's process exited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Exited normally with the given code
+    Code(i32),
+    /// Terminated by a signal
+    Signal(i32),
+    /// The process was not our child (e.g. it was adopted during an upgrade), so it could not be
+    /// waited on
+    Unknown,
+}
+
+impl ExitStatus {
+    fn from_raw(status: libc::c_int) -> Self {
+        if libc::WIFEXITED(status) {
+            Self::Code(libc::WEXITSTATUS(status))
+        } else if libc::WIFSIGNALED(status) {
+            Self::Signal(libc::WTERMSIG(status))
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+impl Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Code(code) => write!(f, "status {}", code),
+            Self::Signal(sig) => write!(f, "signal {}", signal::name(*sig)),
+            Self::Unknown => write!(f, "unknown status"),
+        }
+    }
+}
+
+/// Resolves with a process's exit status once the reaper has collected it
+#[derive(Clone)]
+pub struct ExitWatch(watch::Receiver<Option<ExitStatus>>);
+
+impl ExitWatch {
+    /// Returns the exit status if the process has already exited
+    pub fn status(&self) -> Option<ExitStatus> {
+        *self.0.borrow()
+    }
+
+    /// Whether both watch the same process. Unlike its pid, which is reused once it's reaped,
+    /// this tells it apart from any other.
+    pub fn same_process(&self, other: &ExitWatch) -> bool {
+        self.0.same_channel(&other.0)
+    }
+
+    /// Waits for the process to exit
+    pub async fn wait(&mut self) -> ExitStatus {
+        match self.0.wait_for(Option::is_some).await {
+            Ok(status) => status.unwrap_or(ExitStatus::Unknown),
+            Err(_) => ExitStatus::Unknown,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Watched {
+    pending: HashMap<i32, watch::Sender<Option<ExitStatus>>>,
+    /// Children that were reaped before anyone started watching them
+    unclaimed: HashMap<i32, ExitStatus>,
+}
+
+/// The processes being watched, shared by every reaper in the process. `waitpid(-1)` collects
+/// any child, so one reaper may collect a child another is watching, as when tests run several
+/// servers at once.
+static WATCHED: LazyLock<Mutex<Watched>> = LazyLock::new(Mutex::default);
+
+/// The only place the server waits on its children.
+///
+/// Every child is reaped here when SIGCHLD arrives, and its exit status is handed to whoever is
+/// watching that pid. Nothing else may call `waitpid`, since reaping a pid twice would either
+/// fail or, if the pid has been reused, collect an unrelated process.
+pub struct Reaper {
+    watched: &'static Mutex<Watched>,
+}
+
+impl Reaper {
+    /// Starts reaping children whenever SIGCHLD is received
+    pub fn spawn() -> Result<Arc<Self>> {
+        let reaper = Arc::new(Self { watched: &WATCHED });
+        let mut sigchld = signal(SignalKind::child())?;
+        tokio::task::spawn({
+            let reaper = Arc::clone(&reaper);
+            async move {
+                loop {
+                    // Several exits can be coalesced into one signal, so reap everything available
+                    reaper.reap();
+                    if sigchld.recv().await.is_none() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(reaper)
+    }
+
+    /// Collects every child that has exited. Also called when a child is known to have
+    /// exited, in case its SIGCHLD was missed.
+    pub fn reap(&self) {
+        loop {
+            let mut status = 0;
+            let pid = unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) };
+            if pid <= 0 {
+                break;
+            }
+            self.exited(pid, ExitStatus::from_raw(status));
+        }
+    }
+
+    fn exited(&self, pid: i32, status: ExitStatus) {
+        trace!(target: "reaper", "Process {} exited with {}", pid, status);
+        let mut watched = self.watched.lock().expect("reaper lock poisoned");
+        match watched.pending.remove(&pid) {
+            Some(tx) => {
+                tx.send_replace(Some(status));
+            }
+            None => {
+                watched.unclaimed.insert(pid, status);
+            }
+        }
+    }
+
+    /// Watches a child process for its exit
+    pub fn watch(&self, pid: i32) -> ExitWatch {
+        let mut watched = self.watched.lock().expect("reaper lock poisoned");
+        // The process may have exited between being spawned and being watched
+        if let Some(status) = watched.unclaimed.remove(&pid) {
+            return ExitWatch(watch::channel(Some(status)).1);
+        }
+        // Watched twice, as by a server embedded in the same process as the one it took over
+        // from. A pending pid hasn't been reaped, so it's still the same process.
+        if let Some(tx) = watched.pending.get(&pid) {
+            return ExitWatch(tx.subscribe());
+        }
+        let (tx, rx) = watch::channel(None);
+        watched.pending.insert(pid, tx);
+        ExitWatch(rx)
+    }
+
+    /// Watches a process that isn't our child, such as one adopted from a previous server.
+    /// It can't be waited on, so its exit is detected by polling instead.
+    pub fn watch_foreign(self: &Arc<Self>, pid: i32) -> ExitWatch {
+        let exit = self.watch(pid);
+        let reaper = Arc::clone(self);
+        tokio::task::spawn(async move {
+            process_exit(pid).await;
+            reaper.exited(pid, ExitStatus::Unknown);
+        });
+        exit
+    }
+}
@@ -0,0 +1,127 @@
+//! Playback of recorded keystrokes into a session, for `sesh start --replay-input`.
+//!
+//! Recordings are asciicast v2 files, as written by `asciinema rec --stdin`. Only the input
+//! ("i") events are played, so a recording that also has the output can be used as it is.
+
+use std::{path::Path, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use tokio::{io::AsyncWriteExt, time::Instant};
+
+use crate::ExitWatch;
+
+/// Input to write into the pty, and when to write it
+#[derive(Debug, PartialEq)]
+pub struct InputEvent {
+    /// Time since the start of the recording
+    pub at: Duration,
+    pub data: Vec<u8>,
+}
+
+/// Reads the input events from the recording at `path`.
+/// Errors are reported to the client as one line, so the cause is part of the message.
+pub fn load(path: &Path) -> Result<Vec<InputEvent>> {
+    let recording = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+    parse(&recording).map_err(|e| anyhow!("Invalid recording {}: {:#}", path.display(), e))
+}
+
+fn parse(recording: &str) -> Result<Vec<InputEvent>> {
+    let mut lines = recording
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or_else(|| anyhow!("Recording is empty"))?;
+    let header: serde_json::Value = serde_json::from_str(header).context("Invalid header")?;
+    if header.get("version").and_then(serde_json::Value::as_u64) != Some(2) {
+        anyhow::bail!("Only asciicast v2 recordings are supported");
+    }
+
+    let mut events = Vec::new();
+    for (i, line) in lines {
+        let (time, kind, data): (f64, String, String) = serde_json::from_str(line)
+            .with_context(|| format!("Invalid event on line {}", i + 1))?;
+        if kind != "i" {
+            continue;
+        }
+        let at = Duration::try_from_secs_f64(time)
+            .with_context(|| format!("Invalid time on line {}", i + 1))?;
+        events.push(InputEvent {
+            at,
+            data: data.into_bytes(),
+        });
+    }
+    Ok(events)
+}
+
+/// Writes each event into the pty when its time comes, counting from when this is called.
+/// Stops early if the session exits.
+pub async fn play(
+    mut pty: tokio::fs::File,
+    events: Vec<InputEvent>,
+    mut exit: ExitWatch,
+    log_group: String,
+) -> Result<()> {
+    let start = Instant::now();
+    let count = events.len();
+    let replay = async {
+        for event in events {
+            tokio::time::sleep_until(start + event.at).await;
+            pty.write_all(&event.data).await?;
+            pty.flush().await?;
+        }
+        Result::<_, std::io::Error>::Ok(())
+    };
+    tokio::select! {
+        res = replay => match res {
+            Ok(()) => info!(target: &log_group, "Replayed {} input events", count),
+            Err(e) => {
+                warn!(target: &log_group, "Could not replay input: {}", e);
+                return Err(e).context("Failed to write to the pty");
+            }
+        },
+        _ = exit.wait() => info!(target: &log_group, "Exited before the input was replayed"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"{"version": 2, "width": 80, "height": 24}"#;
+
+    #[test]
+    fn plays_only_input() {
+        let recording = format!(
+            "{}\n[0.5, \"o\", \"$ \"]\n[1.25, \"i\", \"ls\\r\"]\n\n[2, \"i\", \"\\u0003\"]\n",
+            HEADER
+        );
+        assert_eq!(
+            parse(&recording).unwrap(),
+            vec![
+                InputEvent {
+                    at: Duration::from_millis(1250),
+                    data: b"ls\r".to_vec(),
+                },
+                InputEvent {
+                    at: Duration::from_secs(2),
+                    data: vec![3],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let recording = r#"{"version": 1, "width": 80, "height": 24, "stdout": []}"#;
+        assert!(parse(recording).is_err());
+    }
+
+    #[test]
+    fn rejects_negative_times() {
+        let recording = format!("{}\n[-1, \"i\", \"x\"]\n", HEADER);
+        assert!(parse(&recording).is_err());
+    }
+}
@@ -1,18 +1,37 @@
-use log::error;
+use std::pin::Pin;
+
+use log::{error, warn};
 use sesh_proto::{
-    seshd_server::Seshd as RPCDefs, SeshKillRequest, SeshKillResponse, SeshResizeRequest,
-    SeshResizeResponse, SeshStartRequest, SeshStartResponse, ShutdownServerRequest,
-    ShutdownServerResponse,
+    sesh_event::Kind, sesh_follow_response::Event as FollowEvent, seshd_server::Seshd as RPCDefs,
+    ServerInfoRequest, ServerInfoResponse, SeshBatchRequest, SeshBatchResponse, SeshCwdRequest,
+    SeshCwdResponse, SeshEvent, SeshFollowRequest, SeshFollowResponse, SeshKillRequest,
+    SeshKillResponse, SeshNoteRequest, SeshNoteResponse, SeshPasteRequest, SeshPasteResponse,
+    SeshPruneRequest, SeshPruneResponse, SeshRedrawRequest, SeshRedrawResponse, SeshRenameRequest,
+    SeshRenameResponse, SeshRenumberRequest, SeshRenumberResponse, SeshResetRequest,
+    SeshResetResponse, SeshResizeRequest, SeshResizeResponse, SeshReviveRequest,
+    SeshSelectWindowRequest, SeshSelectWindowResponse, SeshSignalRequest, SeshSignalResponse,
+    SeshStartRequest, SeshStartResponse, SeshWaitForRequest, SeshWaitForResponse,
+    ShutdownServerRequest, ShutdownServerResponse, SubscribeEventsRequest, UpgradeServerRequest,
+    UpgradeServerResponse,
 };
-use tonic::{Request, Response, Status};
+use sesh_shared::error::SeshError;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tonic::{Request, Response, Status, Streaming};
 
 use crate::{
     commands::{Command, CommandResponse},
+    events::EVENT_BUFFER,
+    session::FOLLOW_BUFFER,
     Seshd,
 };
 
 #[tonic::async_trait]
 impl RPCDefs for Seshd {
+    type SubscribeEventsStream = Pin<Box<dyn Stream<Item = Result<SeshEvent, Status>> + Send>>;
+    type FollowSessionStream =
+        Pin<Box<dyn Stream<Item = Result<SeshFollowResponse, Status>> + Send>>;
+
     async fn start_session(
         &self,
         request: Request<SeshStartRequest>,
@@ -25,9 +44,8 @@ impl RPCDefs for Seshd {
             Ok(CommandResponse::StartSession(response)) => Ok(Response::new(response)),
             Ok(_) => Err(Status::internal("Unexpected response")),
             Err(e) => {
-                let err_s = format!("{}", e);
-                error!(target: "rpc", "{}", err_s);
-                Err(Status::internal(err_s))
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
             }
         }
     }
@@ -44,9 +62,8 @@ impl RPCDefs for Seshd {
             Ok(CommandResponse::AttachSession(response)) => Ok(Response::new(response)),
             Ok(_) => Err(Status::internal("Unexpected response")),
             Err(e) => {
-                let err_s = format!("{}", e);
-                error!(target: "rpc", "{}", err_s);
-                Err(Status::internal(err_s))
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
             }
         }
     }
@@ -63,9 +80,52 @@ impl RPCDefs for Seshd {
             Ok(CommandResponse::DetachSession(response)) => Ok(Response::new(response)),
             Ok(_) => Err(Status::internal("Unexpected response")),
             Err(e) => {
-                let err_s = format!("{}", e);
-                error!(target: "rpc", "{}", err_s);
-                Err(Status::internal(err_s))
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn follow_session(
+        &self,
+        request: Request<SeshFollowRequest>,
+    ) -> Result<Response<Self::FollowSessionStream>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::FollowSession(req)).await;
+
+        match res {
+            Ok(CommandResponse::FollowSession(started, mut output)) => {
+                let (tx, rx) = mpsc::channel(FOLLOW_BUFFER);
+                tokio::task::spawn(async move {
+                    let started = SeshFollowResponse {
+                        event: Some(FollowEvent::Started(started)),
+                    };
+                    if tx.send(Ok(started)).await.is_err() {
+                        return;
+                    }
+                    loop {
+                        let event = match output.recv().await {
+                            Ok(event) => event,
+                            Err(RecvError::Lagged(n)) => {
+                                // Slow followers miss output rather than holding up the session
+                                warn!(target: "rpc", "Follower lagged, skipped {} packets", n);
+                                continue;
+                            }
+                            Err(RecvError::Closed) => break,
+                        };
+                        let ended = matches!(event.event, Some(FollowEvent::Ended(_)));
+                        if tx.send(Ok(event)).await.is_err() || ended {
+                            break;
+                        }
+                    }
+                });
+                Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+            }
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
             }
         }
     }
@@ -82,26 +142,114 @@ impl RPCDefs for Seshd {
             Ok(CommandResponse::KillSession(response)) => Ok(Response::new(response)),
             Ok(_) => Err(Status::internal("Unexpected response")),
             Err(e) => {
-                let err_s = format!("{}", e);
-                error!(target: "rpc", "{}", err_s);
-                Err(Status::internal(err_s))
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn signal_session(
+        &self,
+        request: Request<SeshSignalRequest>,
+    ) -> Result<Response<SeshSignalResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::SignalSession(req)).await;
+
+        match res {
+            Ok(CommandResponse::SignalSession(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
             }
         }
     }
 
     async fn list_sessions(
         &self,
-        _: Request<sesh_proto::SeshListRequest>,
+        request: Request<sesh_proto::SeshListRequest>,
     ) -> Result<Response<sesh_proto::SeshListResponse>, Status> {
-        let res = self.exec(Command::ListSessions).await;
+        let req = request.into_inner();
+
+        let res = self.exec(Command::ListSessions(req)).await;
 
         match res {
             Ok(CommandResponse::ListSessions(response)) => Ok(Response::new(response)),
             Ok(_) => Err(Status::internal("Unexpected response")),
             Err(e) => {
-                let err_s = format!("{}", e);
-                error!(target: "rpc", "{}", err_s);
-                Err(Status::internal(err_s))
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn revive_session(
+        &self,
+        request: Request<SeshReviveRequest>,
+    ) -> Result<Response<SeshStartResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::ReviveSession(req)).await;
+
+        match res {
+            Ok(CommandResponse::ReviveSession(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn rename_session(
+        &self,
+        request: Request<SeshRenameRequest>,
+    ) -> Result<Response<SeshRenameResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::RenameSession(req)).await;
+
+        match res {
+            Ok(CommandResponse::RenameSession(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn renumber_sessions(
+        &self,
+        _: Request<SeshRenumberRequest>,
+    ) -> Result<Response<SeshRenumberResponse>, Status> {
+        let res = self.exec(Command::RenumberSessions).await;
+
+        match res {
+            Ok(CommandResponse::RenumberSessions(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn prune_sessions(
+        &self,
+        request: Request<SeshPruneRequest>,
+    ) -> Result<Response<SeshPruneResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::PruneSessions(req)).await;
+
+        match res {
+            Ok(CommandResponse::PruneSessions(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
             }
         }
     }
@@ -118,9 +266,62 @@ impl RPCDefs for Seshd {
             Ok(CommandResponse::ResizeSession(res)) => Ok(Response::new(res)),
             Ok(_) => Err(Status::internal("Unexpected response")),
             Err(e) => {
-                let err_s = format!("{}", e);
-                error!(target: "rpc", "{}", err_s);
-                Err(Status::internal(err_s))
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn reset_session(
+        &self,
+        request: Request<SeshResetRequest>,
+    ) -> Result<Response<SeshResetResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::ResetSession(req)).await;
+
+        match res {
+            Ok(CommandResponse::ResetSession(res)) => Ok(Response::new(res)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn redraw_session(
+        &self,
+        request: Request<SeshRedrawRequest>,
+    ) -> Result<Response<SeshRedrawResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::RedrawSession(req)).await;
+
+        match res {
+            Ok(CommandResponse::RedrawSession(res)) => Ok(Response::new(res)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn select_window(
+        &self,
+        request: Request<SeshSelectWindowRequest>,
+    ) -> Result<Response<SeshSelectWindowResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::SelectWindow(req)).await;
+
+        match res {
+            Ok(CommandResponse::SelectWindow(res)) => Ok(Response::new(res)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
             }
         }
     }
@@ -135,10 +336,281 @@ impl RPCDefs for Seshd {
             Ok(CommandResponse::ShutdownServer(response)) => Ok(Response::new(response)),
             Ok(_) => Err(Status::internal("Unexpected response")),
             Err(e) => {
-                let err_s = format!("{}", e);
-                error!(target: "rpc", "{}", err_s);
-                Err(Status::internal(err_s))
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn upgrade_server(
+        &self,
+        request: Request<UpgradeServerRequest>,
+    ) -> Result<Response<UpgradeServerResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::UpgradeServer(req)).await;
+
+        match res {
+            Ok(CommandResponse::UpgradeServer(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn server_info(
+        &self,
+        _: Request<ServerInfoRequest>,
+    ) -> Result<Response<ServerInfoResponse>, Status> {
+        let res = self.exec(Command::ServerInfo).await;
+
+        match res {
+            Ok(CommandResponse::ServerInfo(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
             }
         }
     }
+
+    async fn subscribe_events(
+        &self,
+        _: Request<SubscribeEventsRequest>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        let res = self.exec(Command::SubscribeEvents).await;
+
+        match res {
+            Ok(CommandResponse::SubscribeEvents(mut events)) => {
+                let (tx, rx) = mpsc::channel(EVENT_BUFFER);
+                tokio::task::spawn(async move {
+                    loop {
+                        let event = match events.recv().await {
+                            Ok(event) => event,
+                            Err(RecvError::Lagged(n)) => {
+                                // Slow subscribers miss events rather than holding up the server
+                                warn!(target: "rpc", "Event subscriber lagged, skipped {} events", n);
+                                continue;
+                            }
+                            Err(RecvError::Closed) => break,
+                        };
+                        let shutdown = event.kind() == Kind::Shutdown;
+                        if tx.send(Ok(event)).await.is_err() || shutdown {
+                            // Ending the stream lets the server finish shutting down
+                            break;
+                        }
+                    }
+                });
+                Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+            }
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn paste_session(
+        &self,
+        request: Request<Streaming<SeshPasteRequest>>,
+    ) -> Result<Response<SeshPasteResponse>, Status> {
+        let chunks = request.into_inner();
+
+        let res = self.exec(Command::PasteSession(chunks)).await;
+
+        match res {
+            Ok(CommandResponse::PasteSession(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn session_cwd(
+        &self,
+        request: Request<SeshCwdRequest>,
+    ) -> Result<Response<SeshCwdResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::SessionCwd(req)).await;
+
+        match res {
+            Ok(CommandResponse::SessionCwd(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn note_session(
+        &self,
+        request: Request<SeshNoteRequest>,
+    ) -> Result<Response<SeshNoteResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::NoteSession(req)).await;
+
+        match res {
+            Ok(CommandResponse::NoteSession(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn batch(
+        &self,
+        request: Request<SeshBatchRequest>,
+    ) -> Result<Response<SeshBatchResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::Batch(req)).await;
+
+        match res {
+            Ok(CommandResponse::Batch(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+
+    async fn wait_for(
+        &self,
+        request: Request<SeshWaitForRequest>,
+    ) -> Result<Response<SeshWaitForResponse>, Status> {
+        let req = request.into_inner();
+
+        let res = self.exec(Command::WaitFor(req)).await;
+
+        match res {
+            Ok(CommandResponse::WaitFor(response)) => Ok(Response::new(response)),
+            Ok(_) => Err(Status::internal("Unexpected response")),
+            Err(e) => {
+                error!(target: "rpc", "{}", e);
+                Err(status(e))
+            }
+        }
+    }
+}
+
+/// The status to answer a failed request with. Errors clients can tell apart get their own code,
+/// and anything else is an internal error.
+fn status(e: anyhow::Error) -> Status {
+    match e.downcast_ref::<SeshError>() {
+        Some(error) => error.clone().into(),
+        None => Status::internal(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use sesh_proto::{
+        sesh_note_request, sesh_rename_request, sesh_resize_request, SeshAttachRequest,
+    };
+    use tonic::Code;
+
+    use super::*;
+    use crate::state::State;
+
+    fn seshd() -> Seshd {
+        let dir = std::env::temp_dir().join(format!("sesh-rpc-{}", std::process::id()));
+        let (exit_tx, _) = mpsc::channel(1);
+        Seshd::new(
+            exit_tx,
+            dir.clone(),
+            dir.join("server.sock"),
+            Arc::new(State::load(dir.join("state.json"))),
+            true,
+        )
+        .expect("failed to create server")
+    }
+
+    fn code<T>(res: Result<T, Status>) -> Code {
+        res.err().expect("request succeeded").code()
+    }
+
+    #[tokio::test]
+    async fn errors_have_their_own_codes() {
+        let seshd = seshd();
+
+        let note = SeshNoteRequest {
+            session: Some(sesh_note_request::Session::Name("nope".to_owned())),
+            note: String::new(),
+        };
+        assert_eq!(
+            code(seshd.note_session(Request::new(note)).await),
+            Code::NotFound
+        );
+        let rename = SeshRenameRequest {
+            session: Some(sesh_rename_request::Session::Id(7)),
+            new_name: "other".to_owned(),
+        };
+        assert_eq!(
+            code(seshd.rename_session(Request::new(rename)).await),
+            Code::NotFound
+        );
+        let resize = SeshResizeRequest {
+            session: Some(sesh_resize_request::Session::Name("nope".to_owned())),
+            size: None,
+        };
+        assert_eq!(
+            code(seshd.resize_session(Request::new(resize)).await),
+            Code::InvalidArgument
+        );
+        let attach = SeshAttachRequest::default();
+        assert_eq!(
+            code(seshd.attach_session(Request::new(attach)).await),
+            Code::InvalidArgument
+        );
+        let rename = SeshRenameRequest {
+            session: Some(sesh_rename_request::Session::Id(7)),
+            new_name: "../escape".to_owned(),
+        };
+        assert_eq!(
+            code(seshd.rename_session(Request::new(rename)).await),
+            Code::InvalidArgument
+        );
+        let start = SeshStartRequest {
+            name: "server".to_owned(),
+            program: "cat".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            code(seshd.start_session(Request::new(start)).await),
+            Code::InvalidArgument
+        );
+        let revive = SeshReviveRequest {
+            name: "nope".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            code(seshd.revive_session(Request::new(revive)).await),
+            Code::NotFound
+        );
+    }
+
+    #[test]
+    fn other_errors_are_internal() {
+        let error = anyhow::anyhow!("Could not open pty");
+        assert_eq!(status(error).code(), Code::Internal);
+        // Context added on the way up doesn't hide what the error was
+        let error = anyhow::Error::from(SeshError::SessionNotFound).context("Could not attach");
+        assert_eq!(
+            SeshError::from_status(&status(error)),
+            Some(SeshError::SessionNotFound)
+        );
+    }
 }
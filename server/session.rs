@@ -1,44 +1,140 @@
 use anyhow::{Context, Result};
-use log::{info, trace};
-use sesh_shared::{error::CResult, pty::Pty, term::Size};
+use log::{error, info, trace};
+use sesh_shared::{
+    pty::Pty,
+    sys,
+    term::{Size, Termios},
+};
 use std::{
-    os::fd::{FromRawFd, RawFd},
-    path::PathBuf,
+    os::fd::{AsRawFd, RawFd},
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, AtomicI64, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicI32, AtomicI64, AtomicUsize, Ordering},
+        Arc, Mutex,
     },
+    time::Duration,
 };
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    fs::File,
+    io::{unix::AsyncFd, AsyncReadExt, AsyncWriteExt, Interest},
     net::{UnixListener, UnixStream},
+    sync::{broadcast, watch},
 };
-use tonic::transport::{Endpoint, Uri};
+use tonic::transport::{Channel, Endpoint, Uri};
 use tower::service_fn;
 
-use sesh_proto::{sesh_cli_client::SeshCliClient, ClientDetachRequest};
+use sesh_proto::{
+    client_session_ended_request::{ExitStatus as ProtoExitStatus, Reason},
+    sesh_cli_client::SeshCliClient,
+    sesh_follow_response::Event as FollowEvent,
+    ClientDetachRequest, ClientPingRequest, ClientResetRequest, ClientSessionEndedRequest,
+    SeshFollowResponse,
+};
+
+use crate::{
+    commands::StartOptions,
+    notify::{self, Notify},
+    reaper::{ExitStatus, ExitWatch},
+    state::SessionRecord,
+    supervisor::Supervisor,
+    window::Window,
+};
+
+/// How often an attached client is pinged
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+/// How long a client has to answer a ping before it's treated as gone
+const KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Written to the client when it's switched to another window, which is then told to redraw
+const CLEAR_SCREEN: &[u8] = b"\x1b[H\x1b[2J";
+/// How many packets of output a follower can fall behind before it starts missing them
+pub const FOLLOW_BUFFER: usize = 256;
+
+/// Current time as unix milliseconds, which is how the server stamps everything. They don't
+/// depend on the server's timezone, and clients show them in their own.
+pub fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
 pub struct Session {
     pub id: usize,
     pub name: String,
     pub program: String,
+    pub args: Vec<String>,
+    pub pwd: String,
+    pub env: Vec<(String, String)>,
+    pub login: bool,
+    pub term: Option<String>,
+    /// Tags the user grouped the session under
+    pub tags: Vec<String>,
+    /// Title for the terminal, if the user gave one instead of using the program
+    pub title: Option<String>,
+    /// Temporary script file the program runs, removed along with the session
+    pub script: Option<PathBuf>,
+    /// Whether the session stays after its program exits, until it's killed
+    pub keep: bool,
+    /// Changes made to the terminal settings before the program started
+    pub termios: Termios,
     pub pty: Pty,
+    /// Windows started along with the program, which runs in the first one
+    pub windows: Vec<Window>,
     pub listener: Arc<UnixListener>,
     pub info: SessionInfo,
+    /// Resolves when the session's process exits
+    pub exit: ExitWatch,
 }
 
 pub struct SessionInfo {
     pub start_time: i64,
     pub attach_time: Arc<AtomicI64>,
     connected: Arc<AtomicBool>,
+    /// Number of clients attached
+    clients: Arc<AtomicUsize>,
+    /// Whether a task is serving clients on the session's socket
+    serving: Arc<AtomicBool>,
+    /// Socket fd of the attached client, if any
+    client_fd: Arc<AtomicI32>,
+    /// Size of the pty, as last set by the server
+    size: Arc<Mutex<Size>>,
+    /// Size to give the pty when the next client connects, set by the last attach or start
+    connect_size: Arc<Mutex<Option<Size>>>,
+    /// Note set by the user, shown when listing the session and before killing it
+    note: Mutex<Option<String>>,
+    /// How the session is told about clients attaching and detaching
+    pub notify: Notify,
+    /// Path of the status file, if the session keeps one. It moves along with the socket
+    status_file: Arc<Mutex<Option<PathBuf>>>,
+    /// Closed when the session is dropped, which stops the task serving its clients
+    alive: watch::Sender<()>,
+    /// Index of the window the attached client is shown, 0 being the program's own
+    active: Arc<watch::Sender<usize>>,
+    /// Sends the session's output to followers, which read it without attaching
+    followers: broadcast::Sender<SeshFollowResponse>,
+    /// Woken when a follower starts, so the pty is read even if no client is attached
+    followed: Arc<tokio::sync::Notify>,
+    /// Runs the tasks relaying the session, and keeps their last error
+    pub supervisor: Supervisor,
     sock_path: PathBuf,
 }
 
 impl SessionInfo {
-    pub fn new(sock_path: PathBuf) -> Self {
+    pub fn new(sock_path: PathBuf, size: Size) -> Self {
         Self {
-            start_time: chrono::Local::now().timestamp_millis(),
+            start_time: now_millis(),
             attach_time: Arc::new(AtomicI64::new(0)),
             connected: Arc::new(AtomicBool::new(false)),
+            clients: Arc::new(AtomicUsize::new(0)),
+            serving: Arc::new(AtomicBool::new(false)),
+            client_fd: Arc::new(AtomicI32::new(-1)),
+            size: Arc::new(Mutex::new(size)),
+            connect_size: Arc::new(Mutex::new(None)),
+            note: Mutex::new(None),
+            notify: Notify::default(),
+            status_file: Arc::new(Mutex::new(None)),
+            alive: watch::channel(()).0,
+            active: Arc::new(watch::channel(0).0),
+            followers: broadcast::channel(FOLLOW_BUFFER).0,
+            followed: Arc::new(tokio::sync::Notify::new()),
+            supervisor: Supervisor::default(),
             sock_path,
         }
     }
@@ -47,29 +143,221 @@ impl SessionInfo {
         self.connected.clone()
     }
 
+    /// Resolves with an error once the session has been dropped
+    pub fn removed(&self) -> watch::Receiver<()> {
+        self.alive.subscribe()
+    }
+
+    pub fn clients(&self) -> Arc<AtomicUsize> {
+        self.clients.clone()
+    }
+
+    pub fn serving(&self) -> Arc<AtomicBool> {
+        self.serving.clone()
+    }
+
+    pub fn client_fd(&self) -> Arc<AtomicI32> {
+        self.client_fd.clone()
+    }
+
     pub fn sock_path(&self) -> &PathBuf {
         &self.sock_path
     }
+
+    pub fn size(&self) -> Size {
+        *self.size.lock().expect("size lock poisoned")
+    }
+
+    /// Shared handle to the size, for tasks that resize the pty later on
+    pub fn size_handle(&self) -> Arc<Mutex<Size>> {
+        self.size.clone()
+    }
+
+    /// Sets the size the pty is given when the next client connects, or leaves it as it is
+    /// if `None`.
+    ///
+    /// A session started detached still has a task waiting for its first client, and that task
+    /// may be the one to accept the next attach. So the size is kept here for whichever task
+    /// accepts, instead of being handed to the task that was spawned for the attach.
+    pub fn set_connect_size(&self, size: Option<Size>) {
+        *self.connect_size.lock().expect("size lock poisoned") = size;
+    }
+
+    pub fn connect_size_handle(&self) -> Arc<Mutex<Option<Size>>> {
+        self.connect_size.clone()
+    }
+
+    pub fn note(&self) -> Option<String> {
+        self.note.lock().expect("note lock poisoned").clone()
+    }
+
+    /// Sets the note, or removes it if `None` or empty
+    pub fn set_note(&self, note: Option<String>) {
+        *self.note.lock().expect("note lock poisoned") = note.filter(|note| !note.is_empty());
+    }
+
+    /// Sets how the session is told about clients. Its status file, if it keeps one, is next to
+    /// its socket.
+    pub fn set_notify(&mut self, notify: Notify) {
+        *self.status_file.lock().expect("status file lock poisoned") = notify
+            .status_file
+            .then(|| notify::status_path(&self.sock_path));
+        self.notify = notify;
+    }
+
+    /// Shared handle to the status file's path, for the task that writes it
+    pub fn status_file_handle(&self) -> Arc<Mutex<Option<PathBuf>>> {
+        self.status_file.clone()
+    }
+
+    pub fn active(&self) -> usize {
+        *self.active.borrow()
+    }
+
+    /// Shows the attached client the window at `index`, unless it's already shown
+    pub fn select_window(&self, index: usize) {
+        self.active
+            .send_if_modified(|active| std::mem::replace(active, index) != index);
+    }
+
+    /// Shared handle to the window shown, for the task relaying it to the client
+    pub fn active_handle(&self) -> Arc<watch::Sender<usize>> {
+        self.active.clone()
+    }
+
+    /// Starts following the session's output. Unlike attaching, this doesn't count as a client
+    /// being connected.
+    pub fn follow(&self) -> broadcast::Receiver<SeshFollowResponse> {
+        let follower = self.followers.subscribe();
+        self.followed.notify_one();
+        follower
+    }
+
+    /// Handle to send output to followers, for the task relaying the session
+    pub fn followers_handle(&self) -> broadcast::Sender<SeshFollowResponse> {
+        self.followers.clone()
+    }
+
+    /// Woken when a follower starts, for the task relaying the session
+    pub fn followed_handle(&self) -> Arc<tokio::sync::Notify> {
+        self.followed.clone()
+    }
+}
+
+/// The session's state a relay shares with the rest of the server
+pub struct RelayHandles {
+    /// Which window the client is shown
+    pub active: Arc<watch::Sender<usize>>,
+    pub connected: Arc<AtomicBool>,
+    pub client_fd: Arc<AtomicI32>,
+    /// The process's exit, if the session is kept after it exits
+    pub kept: Option<ExitWatch>,
+    /// Sent the same output as the client
+    pub followers: broadcast::Sender<SeshFollowResponse>,
 }
 
 impl Session {
+    /// Creates a session for a program just started with `options`, and the windows started
+    /// alongside it
     pub fn new(
         id: usize,
         name: String,
-        program: String,
+        options: StartOptions,
         pty: Pty,
+        windows: Vec<Window>,
         sock_path: PathBuf,
+        exit: ExitWatch,
     ) -> Result<Self> {
+        let size = pty.size()?;
+        let listener = UnixListener::bind(&sock_path)?;
+        let mut info = SessionInfo::new(sock_path, size);
+        info.set_note(options.note);
+        info.set_notify(options.notify);
         Ok(Self {
             id,
             name,
-            program,
+            program: options.program,
+            args: options.args,
+            pwd: options.pwd,
+            env: options.env,
+            login: options.login,
+            term: options.term,
+            tags: options.tags,
+            title: options.title,
+            script: options.script,
+            keep: options.keep,
+            termios: options.termios,
             pty,
-            listener: Arc::new(UnixListener::bind(&sock_path)?),
-            info: SessionInfo::new(sock_path),
+            windows,
+            listener: Arc::new(listener),
+            info,
+            exit,
         })
     }
 
+    /// Recreates a session from a pty and listener inherited from another server process
+    pub fn adopt(
+        id: usize,
+        record: SessionRecord,
+        pty: Pty,
+        listener: UnixListener,
+        sock_path: PathBuf,
+        exit: ExitWatch,
+    ) -> Self {
+        // The old server doesn't hand over the size, but the pty still has it
+        let size = pty.size().unwrap_or_default();
+        let mut info = SessionInfo::new(sock_path, size);
+        info.start_time = record.start_time;
+        info.set_note(record.note);
+        info.set_notify(record.notify);
+        Self {
+            id,
+            name: record.name,
+            program: record.program,
+            args: record.args,
+            pwd: record.pwd,
+            env: record.env,
+            login: record.login,
+            term: record.term,
+            tags: record.tags,
+            title: record.title,
+            script: record.script,
+            keep: record.keep,
+            termios: record.termios,
+            pty,
+            // Sessions with windows aren't handed over
+            windows: Vec::new(),
+            listener: Arc::new(listener),
+            info,
+            exit,
+        }
+    }
+
+    /// Renames the session, moving its socket to match the new name
+    pub fn rename(&mut self, name: String) -> Result<()> {
+        let sock_path = self.info.sock_path.with_file_name(format!("{}.sock", name));
+        // Clients that are already attached keep their connection, since the socket is only moved
+        std::fs::rename(&self.info.sock_path, &sock_path)
+            .context("Failed to move session socket")?;
+        let mut status_file = self
+            .info
+            .status_file
+            .lock()
+            .expect("status file lock poisoned");
+        if let Some(path) = status_file.as_mut() {
+            let moved = notify::status_path(&sock_path);
+            // The next attach or detach writes it at the new path anyway
+            if let Err(e) = std::fs::rename(&*path, &moved) {
+                log::warn!(target: "session", "Failed to move {}: {}", path.display(), e);
+            }
+            *path = moved;
+        }
+        drop(status_file);
+        self.info.sock_path = sock_path;
+        self.name = name;
+        Ok(())
+    }
+
     pub fn log_group(&self) -> String {
         format!("{}: {}", self.id, self.name)
     }
@@ -78,114 +366,966 @@ impl Session {
         self.pty.pid()
     }
 
-    pub async fn start(
-        sock_path: PathBuf,
-        socket: Arc<UnixListener>,
-        fd: RawFd,
-        connected: Arc<AtomicBool>,
-        size: Size,
-        attach_time: Arc<AtomicI64>,
-    ) -> Result<()> {
-        info!(target: "session", "Listening on {:?}", sock_path);
+    /// Names of the session's windows, the first one named after its program
+    pub fn window_names(&self) -> Vec<String> {
+        std::iter::once(sesh_shared::name::from_program(&self.program))
+            .chain(self.windows.iter().map(|window| window.spec.name.clone()))
+            .collect()
+    }
+
+    /// Fds of the windows' ptys, starting with the program's own
+    pub fn window_fds(&self) -> Vec<RawFd> {
+        std::iter::once(self.pty.fd())
+            .chain(self.windows.iter().map(|window| window.pty.fd()))
+            .collect()
+    }
+
+    /// Working directory of the program in the foreground of the session, such as an editor
+    /// started from its shell. Falls back to the session's own process
+    pub fn cwd(&self) -> Result<PathBuf> {
+        // The pid has been reaped and may belong to another process now
+        if self.exit.status().is_some() {
+            anyhow::bail!("The session's process has exited");
+        }
+        let pgrp = unsafe { libc::tcgetpgrp(self.pty.file().as_raw_fd()) };
+        // The group's leader may have exited while the rest of the group runs on
+        if pgrp > 0 {
+            if let Ok(cwd) = sys::process_cwd(pgrp) {
+                return Ok(cwd);
+            }
+        }
+        sys::process_cwd(self.pid())
+    }
+
+    /// Resizes the ptys of all windows and remembers the new size
+    pub fn resize(&self, size: Size) -> Result<()> {
+        self.pty.resize(&size)?;
+        for window in &self.windows {
+            window.pty.resize(&size)?;
+        }
+        *self.info.size.lock().expect("size lock poisoned") = size;
+        Ok(())
+    }
+
+    /// Returns the parameters needed to start this session again
+    pub fn record(&self) -> SessionRecord {
+        SessionRecord {
+            name: self.name.clone(),
+            program: self.program.clone(),
+            args: self.args.clone(),
+            pwd: self.pwd.clone(),
+            env: self.env.clone(),
+            login: self.login,
+            term: self.term.clone(),
+            start_time: self.info.start_time,
+            pid: self.pid(),
+            note: self.info.note(),
+            tags: self.tags.clone(),
+            title: self.title.clone(),
+            script: self.script.clone(),
+            notify: self.info.notify.clone(),
+            keep: self.keep,
+            termios: self.termios,
+            windows: self
+                .windows
+                .iter()
+                .map(|window| window.spec.clone())
+                .collect(),
+        }
+    }
+
+    /// Waits for a client to connect to the session's socket, given the ptys of its windows.
+    /// If a connect size was set, they're resized to it once the client is connected.
+    pub async fn accept(
+        socket: &UnixListener,
+        fds: &[RawFd],
+        connected: &AtomicBool,
+        connect_size: &Mutex<Option<Size>>,
+        session_size: &Mutex<Size>,
+        attach_time: &AtomicI64,
+    ) -> Result<UnixStream> {
         let (stream, _addr) = socket.accept().await?;
-        attach_time.store(chrono::Utc::now().timestamp_millis(), Ordering::Relaxed);
+        attach_time.store(now_millis(), Ordering::Relaxed);
         info!(target: "session", "Accepted connection from {:?}", _addr);
         connected.store(true, Ordering::Release);
 
-        let (mut r_socket, mut w_socket) = stream.into_split();
-
-        let pty = unsafe { tokio::fs::File::from_raw_fd(fd) };
-        unsafe {
-            libc::ioctl(
-                fd,
-                libc::TIOCSWINSZ,
-                &Into::<libc::winsize>::into(&sesh_shared::term::Size {
-                    rows: size.rows,
-                    cols: size.cols - 1,
-                }),
-            )
-            .to_result()
-            .map(|_| ())
-            .context("Failed to resize")?;
+        let size = connect_size.lock().expect("size lock poisoned").take();
+        if let Some(size) = size {
+            let size = Size {
+                rows: size.rows,
+                cols: size.cols - 1,
+            };
+            for &fd in fds {
+                sys::set_winsize(fd, &size).context("Failed to resize")?;
+            }
+            *session_size.lock().expect("size lock poisoned") = size;
+        } else {
+            // Resizing is what usually makes the program redraw for the new client, so ask the
+            // foreground job to redraw at the size it already has
+            redraw(fds[0]);
         }
+        Ok(stream)
+    }
 
-        let w_handle = tokio::task::spawn({
-            let connected = connected.clone();
-            let mut pty = pty.try_clone().await?;
-            async move {
-                info!(target: "session", "Starting pty write loop");
-                while connected.load(Ordering::Relaxed) {
-                    let mut i_packet = [0; 4096];
+    /// Forwards data between a connected client and the pty of the `active` window until
+    /// either side closes. `pty_readers` and `pty_writers` have a file for each window's pty.
+    /// The pty files outlive the connection: a read of the pty can't be cancelled, so one
+    /// left waiting when the client leaves, or switches windows, is picked up by the next read
+    /// of that pty instead of keeping its own fd open and taking the output meant for the next
+    /// client.
+    /// When a window other than the first exits, the client is switched back to the first.
+    /// If the session is kept after its process exits, the client is told how it exited once
+    /// its output has been read, and stays until it leaves.
+    pub async fn relay(
+        mut stream: UnixStream,
+        pty_readers: &mut [File],
+        pty_writers: &mut [File],
+        handles: RelayHandles,
+    ) -> Result<()> {
+        let RelayHandles {
+            active,
+            connected,
+            client_fd,
+            kept,
+            followers,
+        } = handles;
+        client_fd.store(stream.as_raw_fd(), Ordering::Release);
+        // Borrowed halves, since an owned write half shuts the connection down when it's
+        // dropped, which would end it for a new server the client was handed over to
+        let (mut r_socket, mut w_socket) = stream.split();
+        let exited = kept.clone();
+        let mut kept = kept;
+        let mut switched = active.subscribe();
+        let shown = active.subscribe();
+        let fds = pty_readers.iter().map(File::as_raw_fd).collect::<Vec<_>>();
 
-                    let i_count = pty.read(&mut i_packet).await?;
-                    if i_count == 0 {
-                        connected.store(false, Ordering::Relaxed);
+        let to_client = async {
+            info!(target: "session", "Starting pty read loop");
+            loop {
+                let mut i_packet = [0; 4096];
+
+                let window = *switched.borrow_and_update();
+                let pty_reader = &mut pty_readers[window];
+                let i_count = tokio::select! {
+                    // Checked first, so nothing more is sent from a window that was left
+                    biased;
+                    _ = switched.changed() => {
+                        let window = *switched.borrow();
+                        info!(target: "session", "Switching to window {}", window);
+                        w_socket.write_all(CLEAR_SCREEN).await?;
+                        w_socket.flush().await?;
+                        redraw(fds[window]);
+                        continue;
+                    }
+                    i_count = read_pty(pty_reader, &mut i_packet) => {
+                        i_count.context("Failed to read from the pty")?
+                    }
+                };
+                if i_count == 0 && window > 0 {
+                    info!(target: "session", "Window {} exited", window);
+                    active.send_replace(0);
+                    continue;
+                }
+                if i_count == 0 {
+                    info!(target: "session", "Session's process exited");
+                    if let Some(exit) = kept.as_mut() {
+                        let status = exit.wait().await;
+                        let notice = format!(
+                            "\r\n[process exited with {}, the session is kept until it's killed]\r\n",
+                            status
+                        );
+                        w_socket.write_all(notice.as_bytes()).await?;
                         w_socket.flush().await?;
-                        pty.flush().await?;
-                        break;
+                        std::future::pending::<()>().await;
                     }
-                    trace!(target: "session", "Read {} bytes from pty", i_count);
-                    let read = &i_packet[..i_count];
-                    w_socket.write_all(read).await?;
                     w_socket.flush().await?;
+                    break;
                 }
-                info!(target: "session","Exiting pty read loop");
-                Result::<_, anyhow::Error>::Ok(())
+                trace!(target: "session", "Read {} bytes from pty", i_count);
+                let read = &i_packet[..i_count];
+                follow(&followers, read);
+                w_socket.write_all(read).await?;
+                w_socket.flush().await?;
             }
-        });
-        tokio::task::spawn({
-            let connected = connected.clone();
-            let mut pty = pty.try_clone().await?;
-            async move {
-                info!(target: "session","Starting socket read loop");
-                while connected.load(Ordering::Relaxed) {
-                    let mut o_packet = [0; 4096];
-
-                    let o_count = r_socket.read(&mut o_packet).await?;
-                    if o_count == 0 {
-                        connected.store(false, Ordering::Relaxed);
-                        w_handle.abort();
-                        break;
+            Result::<_, anyhow::Error>::Ok(())
+        };
+        let to_pty = async {
+            info!(target: "session", "Starting socket read loop");
+            loop {
+                let mut o_packet = [0; 4096];
+
+                let o_count = r_socket.read(&mut o_packet).await?;
+                if o_count == 0 {
+                    break;
+                }
+                trace!(target: "session", "Read {} bytes from socket", o_count);
+                // Nothing reads the pty once its process has exited
+                if exited.as_ref().is_some_and(|exit| exit.status().is_some()) {
+                    continue;
+                }
+                let read = &o_packet[..o_count];
+                let window = *shown.borrow();
+                let pty_writer = &mut pty_writers[window];
+                let written = async {
+                    pty_writer.write_all(read).await?;
+                    pty_writer.flush().await
+                }
+                .await;
+                match written {
+                    // A window other than the first may have exited and not been left yet
+                    Err(e) if window > 0 => {
+                        trace!(target: "session", "Could not write to window {}: {}", window, e)
                     }
-                    trace!(target: "session", "Read {} bytes from socket", o_count);
-                    let read = &o_packet[..o_count];
-                    pty.write_all(read).await?;
-                    pty.flush().await?;
+                    res => res?,
                 }
-                info!(target: "session","Exiting socket and pty read loops");
+            }
+            Result::<_, anyhow::Error>::Ok(())
+        };
+        // Whichever side finishes first ends the connection
+        let res = tokio::select! {
+            res = to_client => res,
+            res = to_pty => res,
+        };
+        client_fd.store(-1, Ordering::Release);
+        connected.store(false, Ordering::Release);
+        if let Err(e) = &res {
+            // The pty failed, or the client went away without closing the connection
+            error!(target: "session", "Relaying to client failed: {:#}", e);
+        }
+        info!(target: "session", "Exiting socket and pty read loops");
+        res
+    }
 
-                Result::<_, anyhow::Error>::Ok(())
+    /// Pings the attached client until it leaves. If it stops answering, it is disconnected,
+    /// and true is returned.
+    pub async fn keepalive(
+        client_sock_path: PathBuf,
+        connected: Arc<AtomicBool>,
+        client_fd: Arc<AtomicI32>,
+    ) -> bool {
+        let mut interval = tokio::time::interval(KEEPALIVE_INTERVAL);
+        // The first tick is immediate, and the client was only just connected
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if !connected.load(Ordering::Acquire) {
+                return false;
             }
-        });
-        info!(target: "session", "Started {}", sock_path.display());
-        Ok(())
+            let ping = tokio::time::timeout(KEEPALIVE_TIMEOUT, ping(&client_sock_path)).await;
+            if matches!(ping, Ok(Ok(()))) {
+                continue;
+            }
+            // The client may have left on its own while it was being pinged
+            if !connected.swap(false, Ordering::AcqRel) {
+                return false;
+            }
+            info!(target: "session", "Client stopped answering, disconnecting it");
+            // Ends the relay, which would otherwise wait for output to notice the client is gone
+            let fd = client_fd.load(Ordering::Acquire);
+            if fd >= 0 {
+                unsafe { libc::shutdown(fd, libc::SHUT_RDWR) };
+            }
+            return true;
+        }
     }
 
-    pub async fn detach(&self) -> Result<()> {
-        self.info.connected.store(false, Ordering::Relaxed);
+    /// Path of the socket the attached client listens on for server -> client requests
+    pub fn client_sock_path(&self) -> Result<PathBuf> {
         let parent = self
             .info
             .sock_path
             .parent()
             .ok_or(anyhow::anyhow!("No parent"))?;
-        let client_sock_path = parent.join(format!("client-{}.sock", self.pid()));
+        Ok(parent.join(format!("client-{}.sock", self.pid())))
+    }
+
+    async fn cli_client(&self) -> Result<SeshCliClient<Channel>> {
+        cli_client(&self.client_sock_path()?).await
+    }
 
-        let channel = Endpoint::try_from("http://[::]:50051")?
-            .connect_with_connector(service_fn(move |_: Uri| {
-                UnixStream::connect(client_sock_path.clone())
-            }))
-            .await?;
-        let mut client = SeshCliClient::new(channel);
+    pub async fn detach(&self) -> Result<()> {
+        self.info.connected.store(false, Ordering::Relaxed);
+        let mut client = self.cli_client().await?;
 
         client.detach(ClientDetachRequest {}).await?;
 
         Ok(())
     }
+
+    /// Has the attached client reset its terminal, then asks the window it shows to draw itself
+    /// on the clean screen
+    pub async fn reset(&self) -> Result<()> {
+        let mut client = self.cli_client().await?;
+        client.reset(ClientResetRequest {}).await?;
+        self.redraw();
+        Ok(())
+    }
+
+    /// Asks the foreground job of the window being shown to redraw, such as an editor started
+    /// from the session's shell, which is in a process group of its own
+    pub fn redraw(&self) {
+        if let Some(&fd) = self.window_fds().get(self.info.active()) {
+            redraw(fd);
+        }
+    }
+
+    /// Tells the attached client, if there is one, and the followers that the session is gone
+    /// and why
+    pub async fn ended(&self, reason: Reason, status: ExitStatus) -> Result<()> {
+        let ended = ClientSessionEndedRequest {
+            reason: reason.into(),
+            exit_status: match status {
+                ExitStatus::Code(code) => Some(ProtoExitStatus::Code(code)),
+                ExitStatus::Signal(sig) => Some(ProtoExitStatus::Signal(sig)),
+                ExitStatus::Unknown => None,
+            },
+        };
+        self.info
+            .followers
+            .send(SeshFollowResponse {
+                event: Some(FollowEvent::Ended(ended.clone())),
+            })
+            .ok();
+        if !self.client_sock_path()?.exists() {
+            return Ok(());
+        }
+        let mut client = self.cli_client().await?;
+
+        client.session_ended(ended).await?;
+
+        Ok(())
+    }
+}
+
+/// Sends output to the session's followers, if it has any
+pub fn follow(followers: &broadcast::Sender<SeshFollowResponse>, output: &[u8]) {
+    if followers.receiver_count() > 0 {
+        followers
+            .send(SeshFollowResponse {
+                event: Some(FollowEvent::Output(output.to_vec())),
+            })
+            .ok();
+    }
+}
+
+/// Asks the foreground job of the pty to redraw, as it does when it's resized
+fn redraw(fd: RawFd) {
+    unsafe {
+        let pgrp = libc::tcgetpgrp(fd);
+        if pgrp > 0 {
+            libc::kill(-pgrp, libc::SIGWINCH);
+        }
+    }
+}
+
+/// Reads output from a pty master, returning 0 once the child has exited.
+/// Reading the master fails with EIO once the slave side is closed, which is the normal way for
+/// a session to end, so it's treated the same as the end of the file.
+/// The pty is only read once it has output, instead of in a blocking read that would go on after
+/// this is dropped and take output meant for whoever reads next, like the server a session was
+/// handed over to. The fd can be non-blocking once it has been shared, so a read with nothing to
+/// return, or one cut short by a signal, is tried again instead of ending the session.
+pub async fn read_pty(pty: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let fd = pty.as_raw_fd();
+    let pty = AsyncFd::with_interest(fd, Interest::READABLE)?;
+    loop {
+        let mut ready = pty.readable().await?;
+        let read = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+        if read >= 0 {
+            return Ok(read as usize);
+        }
+        let e = std::io::Error::last_os_error();
+        if e.raw_os_error() == Some(libc::EIO) {
+            return Ok(0);
+        }
+        match e.kind() {
+            // Whatever there was has already been read through another fd
+            std::io::ErrorKind::WouldBlock => ready.clear_ready(),
+            std::io::ErrorKind::Interrupted => (),
+            _ => return Err(e),
+        }
+        trace!(target: "session", "Retrying pty read: {}", e);
+    }
+}
+
+async fn cli_client(client_sock_path: &Path) -> Result<SeshCliClient<Channel>> {
+    let client_sock_path = client_sock_path.to_owned();
+    let channel = Endpoint::try_from("http://[::]:50051")?
+        .connect_with_connector(service_fn(move |_: Uri| {
+            UnixStream::connect(client_sock_path.clone())
+        }))
+        .await?;
+    Ok(SeshCliClient::new(channel))
+}
+
+/// Checks that the client listening on the given socket is still there
+async fn ping(client_sock_path: &Path) -> Result<()> {
+    let mut client = cli_client(client_sock_path).await?;
+    match client.ping(ClientPingRequest {}).await {
+        // Clients from before pings were added still answer, just not with a pong
+        Err(status) if status.code() != tonic::Code::Unimplemented => Err(status.into()),
+        _ => Ok(()),
+    }
 }
 
 impl Drop for Session {
     fn drop(&mut self) {
+        self.info.supervisor.abort();
         // get rid of the socket
         std::fs::remove_file(&self.info.sock_path).ok();
+        if let Some(status_file) = self
+            .info
+            .status_file
+            .lock()
+            .expect("status file lock poisoned")
+            .as_ref()
+        {
+            std::fs::remove_file(status_file).ok();
+        }
+        if let Some(script) = &self.script {
+            std::fs::remove_file(script).ok();
+        }
+        for window in &mut self.windows {
+            // The pid has been reaped and may be reused, so don't signal it on drop
+            if window.exit.status().is_some() {
+                window.pty.daemonize();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::fd::FromRawFd;
+
+    use sesh_shared::error::CResult;
+
+    use super::*;
+
+    /// Window shown by relays of sessions with only the program's own
+    fn first_window() -> Arc<watch::Sender<usize>> {
+        Arc::new(watch::channel(0).0)
+    }
+
+    /// Followers of a session nobody follows
+    fn no_followers() -> broadcast::Sender<SeshFollowResponse> {
+        broadcast::channel(1).0
+    }
+
+    /// Handles for relaying a session with one window, which isn't kept and has no followers
+    fn handles() -> RelayHandles {
+        RelayHandles {
+            active: first_window(),
+            connected: Arc::new(AtomicBool::new(true)),
+            client_fd: Arc::new(AtomicI32::new(-1)),
+            kept: None,
+            followers: no_followers(),
+        }
+    }
+
+    #[tokio::test]
+    async fn pty_read_ends_when_child_exits() {
+        let pty = Pty::builder("/bin/sh")
+            .args(["-c", "echo hi"])
+            .spawn(&Size::default())
+            .expect("failed to spawn");
+        let mut file = pty.file().try_clone().await.expect("failed to clone pty");
+        let mut output = Vec::new();
+        let mut packet = [0; 4096];
+        loop {
+            match read_pty(&mut file, &mut packet).await.expect("read failed") {
+                0 => break,
+                n => output.extend_from_slice(&packet[..n]),
+            }
+        }
+        assert_eq!(String::from_utf8_lossy(&output).trim(), "hi");
+    }
+
+    #[tokio::test]
+    async fn pty_read_retries_when_nothing_to_read() {
+        let mut fds = [0; 2];
+        unsafe {
+            libc::pipe(fds.as_mut_ptr())
+                .to_result()
+                .expect("failed to create pipe");
+            libc::fcntl(fds[0], libc::F_SETFL, libc::O_NONBLOCK);
+        }
+        let mut reader = unsafe { tokio::fs::File::from_raw_fd(fds[0]) };
+        let mut writer = unsafe { std::fs::File::from_raw_fd(fds[1]) };
+
+        // The pipe is empty, so the first reads fail with EAGAIN until this is written
+        let write = tokio::task::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            std::io::Write::write_all(&mut writer, b"hi").expect("failed to write");
+        });
+        let mut packet = [0; 16];
+        let n = tokio::time::timeout(Duration::from_secs(5), read_pty(&mut reader, &mut packet))
+            .await
+            .expect("read didn't retry")
+            .expect("read failed");
+        assert_eq!(&packet[..n], b"hi");
+        write.await.expect("writer panicked");
+    }
+
+    #[tokio::test]
+    async fn relay_ends_cleanly_when_child_exits() {
+        let pty = Pty::builder("/bin/sh")
+            .args(["-c", "echo hi"])
+            .spawn(&Size::default())
+            .expect("failed to spawn");
+        let mut reader = unsafe { File::from_raw_fd(libc::dup(pty.fd())) };
+        let mut writer = reader.try_clone().await.expect("failed to clone pty");
+        let (server, mut client) = UnixStream::pair().expect("failed to create socket pair");
+        let connected = Arc::new(AtomicBool::new(true));
+
+        let relay = tokio::task::spawn({
+            let connected = connected.clone();
+            async move {
+                Session::relay(
+                    server,
+                    std::slice::from_mut(&mut reader),
+                    std::slice::from_mut(&mut writer),
+                    RelayHandles {
+                        connected,
+                        ..handles()
+                    },
+                )
+                .await
+            }
+        });
+
+        // The server closes its end once the output is sent, instead of failing on EIO
+        let mut output = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut output))
+            .await
+            .expect("relay didn't close the connection")
+            .expect("failed to read from relay");
+        assert_eq!(String::from_utf8_lossy(&output).trim(), "hi");
+        relay.await.expect("relay panicked").expect("relay failed");
+        assert!(!connected.load(Ordering::Acquire));
+    }
+
+    #[tokio::test]
+    async fn kept_relay_tells_client_how_process_exited() {
+        let reaper = crate::reaper::Reaper::spawn().expect("failed to start reaper");
+        let pty = Pty::builder("/bin/sh")
+            .args(["-c", "echo hi; exit 3"])
+            .spawn(&Size::default())
+            .expect("failed to spawn");
+        let exit = reaper.watch(pty.pid());
+        let mut reader = unsafe { File::from_raw_fd(libc::dup(pty.fd())) };
+        let mut writer = reader.try_clone().await.expect("failed to clone pty");
+        let (server, mut client) = UnixStream::pair().expect("failed to create socket pair");
+        let connected = Arc::new(AtomicBool::new(true));
+
+        let relay = tokio::task::spawn({
+            let connected = connected.clone();
+            async move {
+                Session::relay(
+                    server,
+                    std::slice::from_mut(&mut reader),
+                    std::slice::from_mut(&mut writer),
+                    RelayHandles {
+                        connected,
+                        kept: Some(exit),
+                        ..handles()
+                    },
+                )
+                .await
+            }
+        });
+
+        let mut output = String::new();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            let mut packet = [0; 256];
+            while !output.contains("kept until") {
+                let n = client.read(&mut packet).await.expect("failed to read");
+                assert!(n > 0, "relay closed the connection");
+                output += &String::from_utf8_lossy(&packet[..n]);
+            }
+        })
+        .await
+        .expect("client wasn't told the process exited");
+        assert!(output.contains("hi"));
+        assert!(output.contains("exited with status 3"));
+
+        // Input is dropped, and the client stays until it leaves
+        client
+            .write_all(b"ignored\n")
+            .await
+            .expect("failed to write");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!relay.is_finished());
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(5), relay)
+            .await
+            .expect("relay didn't end when the client left")
+            .expect("relay panicked")
+            .expect("relay failed");
+        assert!(!connected.load(Ordering::Acquire));
+    }
+
+    #[tokio::test]
+    async fn next_client_gets_output_after_one_leaves() {
+        let pty = Pty::builder("/bin/sh")
+            .args(["-c", "read line; echo got $line"])
+            .spawn(&Size::default())
+            .expect("failed to spawn");
+        let mut reader = unsafe { File::from_raw_fd(libc::dup(pty.fd())) };
+        let mut writer = reader.try_clone().await.expect("failed to clone pty");
+        let connected = Arc::new(AtomicBool::new(true));
+        let client_fd = Arc::new(AtomicI32::new(-1));
+
+        // The first client leaves while the relay is waiting on the pty
+        let (server, client) = UnixStream::pair().expect("failed to create socket pair");
+        let (res, _) = tokio::join!(
+            Session::relay(
+                server,
+                std::slice::from_mut(&mut reader),
+                std::slice::from_mut(&mut writer),
+                RelayHandles {
+                    connected: connected.clone(),
+                    client_fd: client_fd.clone(),
+                    ..handles()
+                },
+            ),
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                drop(client);
+            }
+        );
+        res.expect("first relay failed");
+        assert!(!connected.load(Ordering::Acquire));
+
+        // The wait on the pty carries over, so its output goes to the next client
+        connected.store(true, Ordering::Release);
+        let (server, mut client) = UnixStream::pair().expect("failed to create socket pair");
+        let (res, output) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(
+                Session::relay(
+                    server,
+                    std::slice::from_mut(&mut reader),
+                    std::slice::from_mut(&mut writer),
+                    RelayHandles {
+                        connected,
+                        client_fd,
+                        ..handles()
+                    },
+                ),
+                async move {
+                    client.write_all(b"hi\n").await.expect("failed to write");
+                    let mut output = Vec::new();
+                    client
+                        .read_to_end(&mut output)
+                        .await
+                        .expect("failed to read from relay");
+                    output
+                }
+            )
+        })
+        .await
+        .expect("second relay didn't finish");
+        res.expect("second relay failed");
+        assert!(String::from_utf8_lossy(&output).contains("got hi"));
+    }
+
+    /// Reads from the relay into `output` until `text` is in it
+    async fn read_until(client: &mut UnixStream, output: &mut String, text: &str) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            let mut packet = [0; 256];
+            while !output.contains(text) {
+                let n = client.read(&mut packet).await.expect("failed to read");
+                assert!(n > 0, "relay closed the connection");
+                *output += &String::from_utf8_lossy(&packet[..n]);
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("{:?} never came in {:?}", text, output));
+    }
+
+    #[tokio::test]
+    async fn relay_switches_windows() {
+        let spawn = |script: &str| {
+            Pty::builder("/bin/sh")
+                .args(["-c", script])
+                .spawn(&Size::default())
+                .expect("failed to spawn")
+        };
+        let main = spawn("echo main; sleep 5");
+        let window = spawn("read line; echo window $line");
+        let mut readers = Vec::new();
+        let mut writers = Vec::new();
+        for pty in [&main, &window] {
+            let reader = unsafe { File::from_raw_fd(libc::dup(pty.fd())) };
+            writers.push(reader.try_clone().await.expect("failed to clone pty"));
+            readers.push(reader);
+        }
+        let active = first_window();
+        let (server, mut client) = UnixStream::pair().expect("failed to create socket pair");
+        let relay = tokio::task::spawn({
+            let active = active.clone();
+            async move {
+                Session::relay(
+                    server,
+                    &mut readers,
+                    &mut writers,
+                    RelayHandles {
+                        active,
+                        ..handles()
+                    },
+                )
+                .await
+            }
+        });
+
+        let mut output = String::new();
+        read_until(&mut client, &mut output, "main").await;
+
+        // Input goes to the window shown, and its output comes back after the screen is cleared
+        active.send_replace(1);
+        read_until(&mut client, &mut output, "\x1b[2J").await;
+        client.write_all(b"hi\n").await.expect("failed to write");
+        read_until(&mut client, &mut output, "window hi").await;
+
+        // Once the window exits, the client is back on the program's window
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while *active.borrow() != 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("client wasn't switched back");
+        drop(client);
+        relay.await.expect("relay panicked").expect("relay failed");
+    }
+
+    #[tokio::test]
+    async fn attach_time_follows_start_time() {
+        let pty = Pty::builder("/bin/sh")
+            .args(["-c", "sleep 5"])
+            .spawn(&Size::default())
+            .expect("failed to spawn");
+        let sock_path = std::env::temp_dir().join(format!("sesh-test-{}.sock", std::process::id()));
+        std::fs::remove_file(&sock_path).ok();
+        let listener = UnixListener::bind(&sock_path).expect("failed to bind");
+        let info = SessionInfo::new(sock_path.clone(), Size::default());
+        let fds = [pty.fd()];
+
+        let (accepted, _client) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(
+                Session::accept(
+                    &listener,
+                    &fds,
+                    &info.connected,
+                    &info.connect_size,
+                    &info.size,
+                    &info.attach_time,
+                ),
+                UnixStream::connect(&sock_path),
+            )
+        })
+        .await
+        .expect("client was never accepted");
+        accepted.expect("failed to accept");
+        std::fs::remove_file(&sock_path).ok();
+
+        assert!(info.connected().load(Ordering::Acquire));
+        assert!(info.attach_time.load(Ordering::Relaxed) >= info.start_time);
+    }
+
+    #[tokio::test]
+    async fn session_outlives_its_client() {
+        let pty = Pty::builder("/bin/sh")
+            .args(["-c", "sleep 5"])
+            .spawn(&Size::default())
+            .expect("failed to spawn");
+        // The program leads a session of its own, so a hangup of the client's terminal
+        // doesn't reach it
+        assert_eq!(unsafe { libc::getsid(pty.pid()) }, pty.pid());
+
+        let sock_path =
+            std::env::temp_dir().join(format!("sesh-test-hup-{}.sock", std::process::id()));
+        std::fs::remove_file(&sock_path).ok();
+        let listener = UnixListener::bind(&sock_path).expect("failed to bind");
+        let info = SessionInfo::new(sock_path.clone(), Size::default());
+        let mut reader = unsafe { File::from_raw_fd(libc::dup(pty.fd())) };
+        let mut writer = reader.try_clone().await.expect("failed to clone pty");
+        let fds = [pty.fd()];
+
+        let (server, client) = tokio::join!(
+            Session::accept(
+                &listener,
+                &fds,
+                &info.connected,
+                &info.connect_size,
+                &info.size,
+                &info.attach_time,
+            ),
+            UnixStream::connect(&sock_path),
+        );
+        let (server, client) = (
+            server.expect("failed to accept"),
+            client.expect("failed to connect"),
+        );
+        tokio::task::spawn({
+            let (connected, client_fd) = (info.connected(), info.client_fd());
+            async move {
+                Session::relay(
+                    server,
+                    std::slice::from_mut(&mut reader),
+                    std::slice::from_mut(&mut writer),
+                    RelayHandles {
+                        connected,
+                        client_fd,
+                        ..handles()
+                    },
+                )
+                .await
+            }
+        });
+        let wait_for = |connected: bool| {
+            let flag = info.connected();
+            async move {
+                tokio::time::timeout(Duration::from_secs(5), async {
+                    while flag.load(Ordering::Acquire) != connected {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                })
+                .await
+            }
+        };
+
+        wait_for(true).await.expect("client was never accepted");
+        // A client killed by SIGHUP just goes away, closing its connection
+        drop(client);
+        wait_for(false).await.expect("client was never let go");
+        std::fs::remove_file(&sock_path).ok();
+
+        assert_eq!(unsafe { libc::kill(pty.pid(), 0) }, 0);
+    }
+
+    /// How many fds the process has open
+    fn open_fds() -> usize {
+        std::fs::read_dir("/proc/self/fd")
+            .expect("failed to list fds")
+            .count()
+    }
+
+    #[tokio::test]
+    async fn attach_detach_cycles_dont_leak_fds() {
+        let pty = Pty::builder("cat")
+            .spawn(&Size::default())
+            .expect("failed to spawn");
+        let sock_path =
+            std::env::temp_dir().join(format!("sesh-test-fds-{}.sock", std::process::id()));
+        std::fs::remove_file(&sock_path).ok();
+        let listener = UnixListener::bind(&sock_path).expect("failed to bind");
+        let info = SessionInfo::new(sock_path.clone(), Size::default());
+        let mut reader = unsafe { File::from_raw_fd(libc::dup(pty.fd())) };
+        let mut writer = reader.try_clone().await.expect("failed to clone pty");
+        let fds = [pty.fd()];
+
+        let before = open_fds();
+        for _ in 0..100 {
+            let (server, client) = tokio::join!(
+                Session::accept(
+                    &listener,
+                    &fds,
+                    &info.connected,
+                    &info.connect_size,
+                    &info.size,
+                    &info.attach_time,
+                ),
+                UnixStream::connect(&sock_path),
+            );
+            let (server, mut client) = (
+                server.expect("failed to accept"),
+                client.expect("failed to connect"),
+            );
+            let (res, _) = tokio::join!(
+                Session::relay(
+                    server,
+                    std::slice::from_mut(&mut reader),
+                    std::slice::from_mut(&mut writer),
+                    RelayHandles {
+                        connected: info.connected(),
+                        client_fd: info.client_fd(),
+                        ..handles()
+                    },
+                ),
+                async move {
+                    client.write_all(b"x\n").await.expect("failed to write");
+                    // Both the echo and cat's copy, so nothing is left to relay once it leaves
+                    read_until(&mut client, &mut String::new(), "x\r\nx\r\n").await;
+                }
+            );
+            res.expect("relay failed");
+            assert!(!info.connected().load(Ordering::Acquire));
+        }
+        std::fs::remove_file(&sock_path).ok();
+
+        // Other tests open and close fds in the same process, so wait for theirs to settle
+        // rather than comparing the count at one instant
+        let settled = async {
+            while open_fds() > before {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), settled)
+            .await
+            .unwrap_or_else(|_| panic!("{} fds open before, {} after", before, open_fds()));
+    }
+
+    /// How fast a relay moves input to a program and its output back, without a pty or a
+    /// program in the way. Run with `cargo test --release -- --ignored relay_throughput
+    /// --nocapture`.
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn relay_throughput() {
+        const BYTES: usize = 256 << 20;
+        // A socket pair stands in for the pty, with its far end echoing like cat does
+        let (pty, far) = std::os::unix::net::UnixStream::pair().expect("failed to create pty pair");
+        std::thread::spawn(move || {
+            let mut far_read = far.try_clone().expect("failed to clone pty pair");
+            let mut far_write = far;
+            std::io::copy(&mut far_read, &mut far_write).ok();
+        });
+        let mut reader = unsafe { File::from_raw_fd(libc::dup(pty.as_raw_fd())) };
+        let mut writer = reader.try_clone().await.expect("failed to clone pty");
+        let (server, client) = UnixStream::pair().expect("failed to create socket pair");
+        tokio::task::spawn(async move {
+            Session::relay(
+                server,
+                std::slice::from_mut(&mut reader),
+                std::slice::from_mut(&mut writer),
+                handles(),
+            )
+            .await
+        });
+
+        let (mut r_client, mut w_client) = client.into_split();
+        let start = std::time::Instant::now();
+        let write = tokio::task::spawn(async move {
+            let chunk = [b'x'; 1 << 16];
+            for _ in 0..BYTES / chunk.len() {
+                w_client.write_all(&chunk).await.expect("failed to write");
+            }
+            w_client
+        });
+        let mut packet = vec![0; 1 << 16];
+        let mut received = 0;
+        while received < BYTES {
+            let n = r_client.read(&mut packet).await.expect("failed to read");
+            assert_ne!(n, 0, "relay closed the connection");
+            received += n;
+        }
+        let elapsed = start.elapsed();
+        drop(write.await.expect("writer panicked"));
+        println!(
+            "relayed {} MiB each way in {:.2}s, {:.1} MiB/s",
+            BYTES >> 20,
+            elapsed.as_secs_f64(),
+            (BYTES >> 20) as f64 / elapsed.as_secs_f64()
+        );
     }
 }
@@ -0,0 +1,244 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use sesh_shared::name;
+use tokio::sync::mpsc::Sender;
+
+use crate::{limits::Limits, Session, SessionList};
+
+/// How long the server waits after its last session goes before exiting, so a session started
+/// right after killing the last one, like `sesh kill a; sesh start b`, keeps it running
+const EXIT_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Default)]
+struct Inner {
+    in_flight: usize,
+    /// Programs of the starts in flight whose session hasn't been added yet. They count towards
+    /// the session limits, so a burst of starts can't all get past them at once.
+    reserved: Vec<String>,
+    /// The last session ended while a start was in flight, so the exit waits for it
+    exit_deferred: bool,
+    /// The server has been asked to exit for having no sessions
+    exiting: bool,
+}
+
+/// Session starts in flight, which keep the server from exiting for having no sessions when
+/// one is about to be added.
+pub struct Starts {
+    inner: Mutex<Inner>,
+    sessions: Arc<SessionList>,
+    exit_signal: Sender<()>,
+    /// Whether the server exits once it has no sessions, which an embedded one may not
+    exit_on_empty: bool,
+}
+
+/// Counts a session start as in flight until it's dropped. Until its session is added, it also
+/// holds a place for it under the session limits, and its name.
+pub struct StartGuard {
+    starts: Arc<Starts>,
+    /// The program whose place is held, until the session is added
+    reserved: Option<String>,
+    /// The name reserved for the session, until it's added
+    name: Option<String>,
+}
+
+impl Starts {
+    pub fn new(sessions: Arc<SessionList>, exit_signal: Sender<()>, exit_on_empty: bool) -> Self {
+        Self {
+            inner: Mutex::default(),
+            sessions,
+            exit_signal,
+            exit_on_empty,
+        }
+    }
+
+    /// Counts a start of `program` as in flight until the guard is dropped, holding a place
+    /// for its session under `limits`. Fails if the server is already exiting, or if the limits
+    /// have been reached, counting the other starts in flight.
+    pub fn begin(self: &Arc<Self>, limits: &Limits, program: &str) -> Result<StartGuard> {
+        let mut inner = self.inner.lock().expect("starts lock poisoned");
+        if inner.exiting {
+            return Err(anyhow!("Server is shutting down"));
+        }
+        let program = name::from_program(program);
+        let sessions = self.sessions.count() + inner.reserved.len();
+        let same_program = self
+            .sessions
+            .iter()
+            .filter(|entry| name::from_program(&entry.program) == program)
+            .count()
+            + inner.reserved.iter().filter(|p| **p == program).count();
+        limits.check(&program, sessions, same_program)?;
+        inner.in_flight += 1;
+        inner.reserved.push(program.clone());
+        Ok(StartGuard {
+            starts: Arc::clone(self),
+            reserved: Some(program),
+            name: None,
+        })
+    }
+
+    /// Asks the server to exit shortly if it has no sessions left. The exit only happens if
+    /// there are still none by then, and no start is in flight. Otherwise it waits until the last
+    /// start in flight finishes, and only happens if no session was added.
+    pub fn exit_if_empty(self: &Arc<Self>) {
+        if !self.exit_on_empty || !self.sessions.is_empty() {
+            return;
+        }
+        let starts = Arc::clone(self);
+        tokio::task::spawn(async move {
+            tokio::time::sleep(EXIT_DELAY).await;
+            let mut inner = starts.inner.lock().expect("starts lock poisoned");
+            if inner.in_flight > 0 {
+                inner.exit_deferred = true;
+            } else if starts.sessions.is_empty() {
+                starts.exit(&mut inner);
+            }
+        });
+    }
+
+    fn finish(&self, reserved: Option<String>) {
+        let mut inner = self.inner.lock().expect("starts lock poisoned");
+        if let Some(program) = reserved {
+            inner.release(&program);
+        }
+        inner.in_flight -= 1;
+        if inner.in_flight == 0
+            && std::mem::take(&mut inner.exit_deferred)
+            && self.sessions.is_empty()
+        {
+            self.exit(&mut inner);
+        }
+    }
+
+    fn exit(&self, inner: &mut Inner) {
+        inner.exiting = true;
+        // The channel only holds one exit, so a full one already has an exit waiting
+        self.exit_signal.try_send(()).ok();
+    }
+}
+
+impl Inner {
+    fn release(&mut self, program: &str) {
+        if let Some(i) = self.reserved.iter().position(|p| p == program) {
+            self.reserved.swap_remove(i);
+        }
+    }
+}
+
+impl StartGuard {
+    /// Picks the session's name from `name`, adding a suffix if it's taken, and reserves it
+    /// until the session is added
+    pub fn name(&mut self, name: &str) -> String {
+        let name = self.starts.sessions.reserve(name);
+        self.name = Some(name.clone());
+        name
+    }
+
+    /// Adds the started session, which takes the place and name held for it
+    pub fn add(&mut self, session: Session) -> Result<()> {
+        let mut inner = self.starts.inner.lock().expect("starts lock poisoned");
+        self.starts.sessions.insert(session.name.clone(), session)?;
+        self.name = None;
+        if let Some(program) = self.reserved.take() {
+            inner.release(&program);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for StartGuard {
+    fn drop(&mut self) {
+        if let Some(name) = self.name.take() {
+            self.starts.sessions.release(&name);
+        }
+        self.starts.finish(self.reserved.take());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn starts() -> (Arc<Starts>, tokio::sync::mpsc::Receiver<()>) {
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        (
+            Arc::new(Starts::new(Arc::new(SessionList::new()), tx, true)),
+            rx,
+        )
+    }
+
+    async fn after_delay() {
+        tokio::time::sleep(EXIT_DELAY + Duration::from_millis(100)).await;
+    }
+
+    #[tokio::test]
+    async fn exits_once_empty() {
+        let (starts, mut exit) = starts();
+        starts.exit_if_empty();
+        assert!(exit.try_recv().is_err());
+        after_delay().await;
+        assert!(exit.try_recv().is_ok());
+        assert!(starts.begin(&Limits::default(), "cat").is_err());
+    }
+
+    #[tokio::test]
+    async fn exit_waits_for_start_in_flight() {
+        let (starts, mut exit) = starts();
+        let start = starts
+            .begin(&Limits::default(), "cat")
+            .expect("failed to begin start");
+        starts.exit_if_empty();
+        after_delay().await;
+        assert!(exit.try_recv().is_err());
+
+        drop(start);
+        assert!(exit.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn start_right_after_last_session_goes() {
+        let (starts, mut exit) = starts();
+        starts.exit_if_empty();
+        // Starting is let through, and the exit waits for it
+        let start = starts
+            .begin(&Limits::default(), "cat")
+            .expect("start was refused");
+        after_delay().await;
+        assert!(exit.try_recv().is_err());
+        drop(start);
+        assert!(exit.try_recv().is_ok());
+    }
+
+    #[test]
+    fn starts_in_flight_count_towards_limits() {
+        let (starts, _exit) = starts();
+        let limits = Limits::new(Some(3), Some(2));
+        let vim = starts
+            .begin(&limits, "/usr/bin/vim")
+            .expect("start was refused");
+        let _other = starts.begin(&limits, "vim").expect("start was refused");
+        // Neither session has been added, but vim already has as many as it may
+        assert!(starts.begin(&limits, "vim").is_err());
+        let _cat = starts.begin(&limits, "cat").expect("start was refused");
+        assert!(starts.begin(&limits, "cat").is_err());
+        // A start that fails gives its place back
+        drop(vim);
+        assert!(starts.begin(&limits, "vim").is_ok());
+    }
+
+    #[tokio::test]
+    async fn failed_start_alone_doesnt_exit() {
+        let (starts, mut exit) = starts();
+        drop(
+            starts
+                .begin(&Limits::default(), "cat")
+                .expect("failed to begin start"),
+        );
+        after_delay().await;
+        assert!(exit.try_recv().is_err());
+    }
+}
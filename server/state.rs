@@ -0,0 +1,161 @@
+use std::{
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sesh_shared::term::Termios;
+
+use crate::{notify::Notify, window::WindowSpec};
+
+/// Everything needed to start a session's program again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub name: String,
+    pub program: String,
+    pub args: Vec<String>,
+    pub pwd: String,
+    pub env: Vec<(String, String)>,
+    /// Whether the program was started as a login shell
+    #[serde(default)]
+    pub login: bool,
+    /// $TERM the program was started with, if it was set explicitly
+    #[serde(default)]
+    pub term: Option<String>,
+    pub start_time: i64,
+    /// Pid of the session's process when the record was written, or 0 if unknown
+    #[serde(default)]
+    pub pid: i32,
+    /// Note set by the user
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Tags the session was started with
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Title for the terminal, if the user gave one
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Temporary script file the program runs
+    #[serde(default)]
+    pub script: Option<PathBuf>,
+    /// How the session is told about clients attaching and detaching
+    #[serde(default)]
+    pub notify: Notify,
+    /// Whether the session stays after its program exits
+    #[serde(default)]
+    pub keep: bool,
+    /// Windows started along with the program
+    #[serde(default)]
+    pub windows: Vec<WindowSpec>,
+    /// Changes made to the terminal settings before the program started
+    #[serde(default)]
+    pub termios: Termios,
+}
+
+impl SessionRecord {
+    /// Returns the pid of the session's process if it is still running without a server.
+    ///
+    /// Its pty died with the old server, so it can't be attached to again, but it can be killed.
+    /// Session processes lead their own unix session, which rules out most unrelated processes
+    /// that have since been given the same pid.
+    pub fn orphan_pid(&self) -> Option<i32> {
+        let alive = self.pid > 0
+            && unsafe { libc::kill(self.pid, 0) == 0 && libc::getsid(self.pid) == self.pid };
+        alive.then_some(self.pid)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct StateFile {
+    sessions: Vec<SessionRecord>,
+}
+
+/// On-disk record of running sessions, used to recover after a crash or reboot.
+///
+/// The file is rewritten whenever a session starts or stops. Any entries left in it when the
+/// server starts belong to sessions that were lost, and are kept around as "dead" sessions until
+/// they are revived.
+pub struct State {
+    path: PathBuf,
+    dead: DashMap<String, SessionRecord>,
+}
+
+impl State {
+    /// Loads leftover session records from the state file at `path`
+    pub fn load(path: PathBuf) -> Self {
+        let dead = DashMap::new();
+        match Self::read(&path) {
+            Ok(state) => {
+                for record in state.sessions {
+                    match record.orphan_pid() {
+                        Some(pid) => info!(
+                            target: "state",
+                            "Found orphaned session {} (pid {})", record.name, pid
+                        ),
+                        None => info!(target: "state", "Found dead session {}", record.name),
+                    }
+                    dead.insert(record.name.clone(), record);
+                }
+            }
+            Err(e) => warn!(target: "state", "Could not read {}: {}", path.display(), e),
+        }
+        Self { path, dead }
+    }
+
+    fn read(path: &Path) -> Result<StateFile> {
+        if !path.exists() {
+            return Ok(StateFile::default());
+        }
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Writes the live sessions, along with any dead sessions that haven't been revived
+    pub fn save(&self, live: Vec<SessionRecord>) -> Result<()> {
+        let mut sessions = live;
+        sessions.extend(self.dead.iter().map(|e| e.value().clone()));
+        let contents = serde_json::to_string_pretty(&StateFile { sessions })?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Write to a temporary file first so a crash mid-write can't corrupt the state
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        Ok(())
+    }
+
+    /// Returns the dead sessions, oldest first
+    pub fn dead(&self) -> Vec<SessionRecord> {
+        let mut dead = self
+            .dead
+            .iter()
+            .map(|e| e.value().clone())
+            .collect::<Vec<_>>();
+        dead.sort_by_key(|r| r.start_time);
+        dead
+    }
+
+    /// Marks a session as dead, so that it can be revived later
+    pub fn insert_dead(&self, record: SessionRecord) {
+        self.dead.insert(record.name.clone(), record);
+    }
+
+    /// Removes a dead session by name, returning its record
+    pub fn take_dead(&self, name: impl AsRef<str>) -> Option<SessionRecord> {
+        self.dead.remove(name.as_ref()).map(|(_, record)| record)
+    }
+}
@@ -0,0 +1,135 @@
+//! The tasks doing a session's IO, like relaying its pty to clients. Their failures would
+//! otherwise only be logged, so the last one is kept to show as the session's health.
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::task::AbortHandle;
+
+/// Owns a session's tasks, which are stopped along with it, and keeps the last error of one
+#[derive(Clone, Default)]
+pub struct Supervisor {
+    tasks: Arc<Mutex<Vec<AbortHandle>>>,
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl Supervisor {
+    /// Runs `future` as one of the session's tasks. If it fails or panics, that's kept as the
+    /// session's error, named after `task`.
+    pub fn spawn<F>(&self, task: &'static str, future: F)
+    where
+        F: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let handle = tokio::task::spawn(future);
+        let mut tasks = self.tasks.lock().expect("tasks lock poisoned");
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(handle.abort_handle());
+        drop(tasks);
+        let supervisor = self.clone();
+        tokio::task::spawn(async move {
+            match handle.await {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => supervisor.fail(task, &e),
+                Err(e) if e.is_panic() => supervisor.fail(task, &anyhow::anyhow!("panicked")),
+                // Stopped along with the session
+                Err(_) => (),
+            }
+        });
+    }
+
+    /// Keeps the error of a task that handled it on its own, like a relay to one client. The
+    /// tasks log their errors themselves.
+    pub fn fail(&self, task: &str, e: &anyhow::Error) {
+        *self.error.lock().expect("error lock poisoned") = Some(format!("{}: {:#}", task, e));
+    }
+
+    /// Forgets the last error, once the task that had it works again
+    pub fn recovered(&self) {
+        *self.error.lock().expect("error lock poisoned") = None;
+    }
+
+    /// The last error of one of the tasks, or `None` if they're all fine
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().expect("error lock poisoned").clone()
+    }
+
+    /// How the session's health is listed: `ok`, or `io-error: ` and the last error
+    pub fn health(&self) -> String {
+        match self.error() {
+            Some(e) => format!("io-error: {}", e),
+            None => "ok".to_owned(),
+        }
+    }
+
+    /// Stops all the tasks
+    pub fn abort(&self) {
+        for task in self.tasks.lock().expect("tasks lock poisoned").drain(..) {
+            task.abort();
+        }
+    }
+
+    /// Stops all the tasks, and waits until none of them is running anymore. A task that's
+    /// aborted while it's running only stops once it next waits.
+    pub async fn stop(&self) {
+        let tasks = std::mem::take(&mut *self.tasks.lock().expect("tasks lock poisoned"));
+        for task in &tasks {
+            task.abort();
+        }
+        while !tasks.iter().all(AbortHandle::is_finished) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn failures_are_kept() {
+        let supervisor = Supervisor::default();
+        assert_eq!(supervisor.health(), "ok");
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        supervisor.spawn("relay", async {
+            rx.await.ok();
+            anyhow::bail!("Failed to read from the pty: I/O error")
+        });
+        supervisor.spawn("replay", async { panic!("oops") });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(supervisor.health(), "io-error: replay: panicked");
+        tx.send(()).unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(
+            supervisor.error().as_deref(),
+            Some("relay: Failed to read from the pty: I/O error")
+        );
+        supervisor.recovered();
+        assert_eq!(supervisor.health(), "ok");
+    }
+
+    #[tokio::test]
+    async fn aborted_tasks_arent_failures() {
+        let supervisor = Supervisor::default();
+        supervisor.spawn("relay", std::future::pending());
+        supervisor.abort();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(supervisor.error(), None);
+    }
+
+    #[tokio::test]
+    async fn stopped_tasks_are_gone() {
+        let supervisor = Supervisor::default();
+        let held = Arc::new(());
+        let task = Arc::clone(&held);
+        supervisor.spawn("relay", async move {
+            let _held = task;
+            std::future::pending().await
+        });
+        supervisor.stop().await;
+        // The task has been dropped, along with what it held
+        assert_eq!(Arc::strong_count(&held), 1);
+    }
+}
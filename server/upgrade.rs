@@ -0,0 +1,481 @@
+use std::{
+    io::{self, Read, Write},
+    mem::size_of,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::net::UnixStream as StdUnixStream,
+    },
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sesh_shared::{pty::Pty, term::Size};
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::{state::SessionRecord, Seshd, Session};
+
+/// Bumped whenever the handover format changes, so mismatched servers refuse to upgrade
+const HANDOVER_VERSION: u32 = 1;
+/// Maximum number of fds sent in a single message
+const MAX_FDS: usize = 200;
+/// How long the old server waits for the new one to connect and acknowledge
+const HANDOVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize)]
+struct HandoverHeader {
+    version: u32,
+    sessions: Vec<HandoverSession>,
+}
+
+/// A session's state, sent along with its pty, listener and (if attached) client fds
+#[derive(Serialize, Deserialize)]
+struct HandoverSession {
+    id: usize,
+    record: SessionRecord,
+    pid: i32,
+    socket: PathBuf,
+    attach_time: i64,
+    connected: bool,
+}
+
+impl HandoverSession {
+    fn fd_count(&self) -> usize {
+        if self.connected {
+            3
+        } else {
+            2
+        }
+    }
+}
+
+/// A session that's no longer served while it's handed over
+struct Paused {
+    name: String,
+    /// Whether a task was waiting for its clients
+    served: bool,
+    /// The client attached to it, if any
+    client: Option<OwnedFd>,
+}
+
+/// Sessions received from the old server, waiting to be adopted
+pub struct Handover {
+    stream: StdUnixStream,
+    listener: RawFd,
+    sessions: Vec<(HandoverSession, Vec<RawFd>)>,
+}
+
+impl Handover {
+    /// Connects to the old server's handover socket and receives its sessions
+    pub fn receive(path: &Path) -> Result<Self> {
+        info!(target: "upgrade", "Receiving sessions from {}", path.display());
+        let mut stream = StdUnixStream::connect(path)
+            .with_context(|| format!("Failed to connect to {}", path.display()))?;
+
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let mut header = vec![0; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut header)?;
+        let header: HandoverHeader = serde_json::from_slice(&header)?;
+        if header.version != HANDOVER_VERSION {
+            anyhow::bail!(
+                "Handover version mismatch (old server: {}, new server: {})",
+                header.version,
+                HANDOVER_VERSION
+            );
+        }
+
+        let count = 1 + header
+            .sessions
+            .iter()
+            .map(HandoverSession::fd_count)
+            .sum::<usize>();
+        let mut fds = recv_fds(stream.as_raw_fd(), count)?.into_iter();
+
+        let listener = fds.next().context("Missing server listener")?;
+        let sessions = header
+            .sessions
+            .into_iter()
+            .map(|session| {
+                let fds = fds.by_ref().take(session.fd_count()).collect();
+                (session, fds)
+            })
+            .collect();
+
+        Ok(Self {
+            stream,
+            listener,
+            sessions,
+        })
+    }
+
+    /// Returns the old server's listening socket
+    pub fn listener(&self) -> Result<UnixListener> {
+        let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(self.listener) };
+        listener.set_nonblocking(true)?;
+        Ok(UnixListener::from_std(listener)?)
+    }
+}
+
+impl Seshd {
+    /// Starts a new server from `seshd_path` and hands all sessions over to it.
+    /// Returns the pid of the new server once it has taken over.
+    pub async fn hand_over(&self, seshd_path: &str) -> Result<i32> {
+        // Only the program's own pty is handed over, so the others would be lost
+        let windowed = self
+            .sessions
+            .iter()
+            .filter(|session| {
+                session
+                    .windows
+                    .iter()
+                    .any(|window| window.exit.status().is_none())
+            })
+            .map(|session| session.name.clone())
+            .collect::<Vec<_>>();
+        if !windowed.is_empty() {
+            anyhow::bail!(
+                "Sessions with windows can't be handed over, kill them first: {}",
+                windowed.join(", ")
+            );
+        }
+        let path = self.runtime_dir.join("upgrade.sock");
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+
+        info!(target: "upgrade", "Starting {}", seshd_path);
+        let server = Pty::builder(seshd_path)
+            .arg("--upgrade")
+            .arg(&path)
+            .arg("--runtime-dir")
+            .arg(&self.runtime_dir)
+            .arg("--socket")
+            .arg(&self.socket_path)
+            .daemonize()
+            .spawn(&Size::detect())?;
+        let pid = server.pid();
+
+        let res = self.hand_over_on(&listener).await;
+        std::fs::remove_file(&path).ok();
+
+        match res {
+            Ok(()) => {
+                info!(target: "upgrade", "Sessions handed over to {}", pid);
+                Ok(pid)
+            }
+            Err(e) => {
+                unsafe { libc::kill(pid, libc::SIGTERM) };
+                Err(e)
+            }
+        }
+    }
+
+    /// Hands all sessions over to the new server connecting on `listener`. Until it has them,
+    /// the sessions are neither served nor can clients connect, so the two servers don't share
+    /// them. If the handover fails, this server goes back to serving them.
+    pub async fn hand_over_on(&self, listener: &UnixListener) -> Result<()> {
+        let (stream, _) = tokio::time::timeout(HANDOVER_TIMEOUT, listener.accept())
+            .await
+            .context("Timed out waiting for the new server")??;
+        let stream = stream.into_std()?;
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(HANDOVER_TIMEOUT))?;
+
+        let paused = self.pause().await;
+        let (header, fds) = self.handover_state(&paused);
+        let res = tokio::task::spawn_blocking(move || send_handover(stream, header, fds))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|res| res);
+        if res.is_err() {
+            self.resume(paused);
+        }
+        res
+    }
+
+    /// Stops accepting clients and serving the sessions, keeping the clients attached to them
+    /// connected
+    async fn pause(&self) -> Vec<Paused> {
+        self.accepting.send_replace(false);
+        let sessions = self
+            .sessions
+            .iter()
+            .map(|session| {
+                (
+                    session.name.clone(),
+                    session.info.serving(),
+                    session.info.connected(),
+                    session.info.client_fd(),
+                    session.info.supervisor.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut paused = Vec::with_capacity(sessions.len());
+        for (name, serving, connected, client_fd, supervisor) in sessions {
+            let served = serving.load(Ordering::Acquire);
+            let client_fd = client_fd.load(Ordering::Acquire);
+            // The relay closes its client when it's stopped, so the client is kept open apart
+            let client = (connected.load(Ordering::Acquire) && client_fd >= 0)
+                .then(|| unsafe { libc::fcntl(client_fd, libc::F_DUPFD_CLOEXEC, 0) })
+                .filter(|fd| *fd >= 0)
+                .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+            supervisor.stop().await;
+            paused.push(Paused {
+                name,
+                served,
+                client,
+            });
+        }
+        paused
+    }
+
+    /// Serves the sessions again after a handover failed
+    fn resume(&self, paused: Vec<Paused>) {
+        self.accepting.send_replace(true);
+        for Paused {
+            name,
+            served,
+            client,
+        } in paused
+        {
+            let Some(session) = self.sessions.get(&name) else {
+                continue;
+            };
+            let client = client.map(|fd| {
+                let client = StdUnixStream::from(fd);
+                client.set_nonblocking(true)?;
+                session.info.connected().store(true, Ordering::Release);
+                UnixStream::from_std(client)
+            });
+            let res = match client.transpose() {
+                Ok(client) if served || client.is_some() => self.serve_clients(&session, client),
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = res {
+                error!(target: &session.log_group(), "Failed to serve again: {}", e);
+            }
+        }
+    }
+
+    /// Collects the session table and the fds that need to be handed over
+    fn handover_state(&self, paused: &[Paused]) -> (HandoverHeader, Vec<RawFd>) {
+        let mut fds = vec![self.listener_fd.load(Ordering::Acquire)];
+        let sessions = paused
+            .iter()
+            .filter_map(|paused| {
+                let session = self.sessions.get(&paused.name)?;
+                fds.push(session.pty.fd());
+                fds.push(session.listener.as_raw_fd());
+                if let Some(client) = &paused.client {
+                    fds.push(client.as_raw_fd());
+                }
+                Some(HandoverSession {
+                    id: session.id,
+                    record: session.record(),
+                    pid: session.pid(),
+                    socket: session.info.sock_path().clone(),
+                    attach_time: session.info.attach_time.load(Ordering::Relaxed),
+                    connected: paused.client.is_some(),
+                })
+            })
+            .collect();
+        (
+            HandoverHeader {
+                version: HANDOVER_VERSION,
+                sessions,
+            },
+            fds,
+        )
+    }
+
+    /// Takes ownership of the sessions received from the old server, and tells it to exit
+    pub fn adopt(&self, handover: Handover) -> Result<()> {
+        let Handover {
+            mut stream,
+            sessions,
+            ..
+        } = handover;
+
+        for (session, fds) in sessions {
+            let HandoverSession {
+                id,
+                record,
+                pid,
+                socket,
+                attach_time,
+                connected,
+            } = session;
+            info!(target: "upgrade", "Adopting {} (pid {})", record.name, pid);
+
+            let pty = unsafe { Pty::from_raw_fd(fds[0], pid) };
+            let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fds[1]) };
+            listener.set_nonblocking(true)?;
+            // Adopted processes are children of the old server, so they can't be waited on
+            let exit = self.reaper.watch_foreign(pid);
+            let session = Session::adopt(
+                id,
+                record,
+                pty,
+                UnixListener::from_std(listener)?,
+                socket,
+                exit.clone(),
+            );
+            session
+                .info
+                .attach_time
+                .store(attach_time, Ordering::Relaxed);
+
+            if connected {
+                let client = unsafe { StdUnixStream::from_raw_fd(fds[2]) };
+                client.set_nonblocking(true)?;
+                let client = UnixStream::from_std(client)?;
+                session.info.connected().store(true, Ordering::Release);
+                self.serve_clients(&session, Some(client))?;
+            }
+
+            self.sessions.insert(session.name.clone(), session)?;
+            self.watch_session(exit);
+        }
+        self.persist();
+
+        // Let the old server know it can exit
+        stream.write_all(&[1])?;
+        Ok(())
+    }
+}
+
+/// Sends the session table and fds, then waits for the new server to acknowledge them
+fn send_handover(mut stream: StdUnixStream, header: HandoverHeader, fds: Vec<RawFd>) -> Result<()> {
+    let header = serde_json::to_vec(&header)?;
+    stream.write_all(&(header.len() as u32).to_be_bytes())?;
+    stream.write_all(&header)?;
+    send_fds(stream.as_raw_fd(), &fds)?;
+
+    let mut ack = [0];
+    stream
+        .read_exact(&mut ack)
+        .context("New server did not acknowledge the handover")?;
+    if ack[0] != 1 {
+        anyhow::bail!("New server rejected the handover");
+    }
+    Ok(())
+}
+
+/// Sends fds over a unix socket with SCM_RIGHTS
+fn send_fds(sock: RawFd, fds: &[RawFd]) -> io::Result<()> {
+    for chunk in fds.chunks(MAX_FDS) {
+        let data_len = std::mem::size_of_val(chunk) as u32;
+        let mut byte = [0u8];
+        let mut iov = libc::iovec {
+            iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+            iov_len: byte.len(),
+        };
+        let space = unsafe { libc::CMSG_SPACE(data_len) } as usize;
+        // u64s keep the buffer aligned for cmsghdr
+        let mut control = vec![0u64; space.div_ceil(size_of::<u64>())];
+
+        unsafe {
+            let mut msg: libc::msghdr = std::mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = space as _;
+
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(data_len) as _;
+            std::ptr::copy_nonoverlapping(
+                chunk.as_ptr(),
+                libc::CMSG_DATA(cmsg) as *mut RawFd,
+                chunk.len(),
+            );
+
+            if libc::sendmsg(sock, &msg, 0) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Receives `count` fds sent with `send_fds`
+fn recv_fds(sock: RawFd, count: usize) -> io::Result<Vec<RawFd>> {
+    let mut fds = Vec::with_capacity(count);
+    while fds.len() < count {
+        let expected = (count - fds.len()).min(MAX_FDS);
+        let mut byte = [0u8];
+        let mut iov = libc::iovec {
+            iov_base: byte.as_mut_ptr() as *mut libc::c_void,
+            iov_len: byte.len(),
+        };
+        let space = unsafe { libc::CMSG_SPACE((expected * size_of::<RawFd>()) as u32) } as usize;
+        let mut control = vec![0u64; space.div_ceil(size_of::<u64>())];
+
+        unsafe {
+            let mut msg: libc::msghdr = std::mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = space as _;
+
+            match libc::recvmsg(sock, &mut msg, 0) {
+                n if n < 0 => return Err(io::Error::last_os_error()),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                _ => {}
+            }
+
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                    let len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                    let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                    for i in 0..len / size_of::<RawFd>() {
+                        let fd = data.add(i).read_unaligned();
+                        // Don't leak the fds into session processes spawned later
+                        libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                        fds.push(fd);
+                    }
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+    }
+    Ok(fds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fds_round_trip() {
+        let (sender, receiver) = StdUnixStream::pair().expect("failed to create socket pair");
+        // More sockets than fit in one message, so they're sent in chunks
+        let sockets = (0..MAX_FDS + 3)
+            .map(|_| StdUnixStream::pair().expect("failed to create socket pair"))
+            .collect::<Vec<_>>();
+        let fds = sockets
+            .iter()
+            .map(|(a, _)| a.as_raw_fd())
+            .collect::<Vec<_>>();
+        send_fds(sender.as_raw_fd(), &fds).expect("failed to send fds");
+        let received = recv_fds(receiver.as_raw_fd(), fds.len()).expect("failed to receive fds");
+        assert_eq!(received.len(), fds.len());
+
+        // Each fd received is a new one for the same socket as the one sent
+        for (fd, (_, other)) in received.into_iter().zip(&sockets) {
+            let mut sent = unsafe { StdUnixStream::from_raw_fd(fd) };
+            sent.write_all(&[7]).expect("failed to write");
+            let mut byte = [0];
+            (&*other).read_exact(&mut byte).expect("failed to read");
+            assert_eq!(byte, [7]);
+        }
+    }
+}
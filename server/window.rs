@@ -0,0 +1,97 @@
+//! Extra windows of a session, started with `sesh start --window NAME:CMD`. Each runs a program
+//! on a pty of its own, and an attached client sees one window at a time.
+
+use serde::{Deserialize, Serialize};
+use sesh_shared::pty::Pty;
+
+use crate::reaper::ExitWatch;
+
+/// What runs in a window, kept to start it again when the session is revived
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowSpec {
+    pub name: String,
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl From<sesh_proto::Window> for WindowSpec {
+    fn from(window: sesh_proto::Window) -> Self {
+        Self {
+            name: window.name,
+            program: window.program,
+            args: window.args,
+        }
+    }
+}
+
+pub struct Window {
+    pub spec: WindowSpec,
+    pub pty: Pty,
+    /// Resolves when the window's process exits
+    pub exit: ExitWatch,
+}
+
+/// Checks that the windows' names can be told apart from each other and from `main`, the name
+/// of the session's own window
+pub fn check_names(main: &str, windows: &[WindowSpec]) -> anyhow::Result<()> {
+    for (i, window) in windows.iter().enumerate() {
+        if window.name.is_empty() {
+            anyhow::bail!("Window names can't be empty");
+        }
+        if window.name == main || windows[..i].iter().any(|w| w.name == window.name) {
+            anyhow::bail!("There's more than one window named {}", window.name);
+        }
+    }
+    Ok(())
+}
+
+/// Index of the window `step` windows away from `from`, going around at either end, among
+/// windows whose program is still `running`. The first window is the session's own, which is
+/// always counted as running, since the session ends along with it.
+pub fn step(from: usize, step: i32, running: &[bool]) -> usize {
+    let open = (0..running.len())
+        .filter(|&i| i == 0 || running[i])
+        .collect::<Vec<_>>();
+    let step = step as i64;
+    let to = match open.binary_search(&from) {
+        Ok(at) => at as i64 + step,
+        // The current window has exited, so it's counted from the gap it left
+        Err(next) if step > 0 => next as i64 + step - 1,
+        Err(next) => next as i64 + step,
+    };
+    open[to.rem_euclid(open.len() as i64) as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_around_exited_windows() {
+        let running = [true, true, false, true];
+        assert_eq!(step(0, 1, &running), 1);
+        assert_eq!(step(1, 1, &running), 3);
+        assert_eq!(step(3, 1, &running), 0);
+        assert_eq!(step(0, -1, &running), 3);
+        assert_eq!(step(3, -2, &running), 0);
+        // Moving on from a window that just exited
+        assert_eq!(step(2, 1, &running), 3);
+        assert_eq!(step(2, -1, &running), 1);
+        // The session's own window is there even once its program has exited
+        assert_eq!(step(0, 1, &[false]), 0);
+        assert_eq!(step(0, 1, &[false, false]), 0);
+    }
+
+    #[test]
+    fn window_names_must_differ() {
+        let spec = |name: &str| WindowSpec {
+            name: name.to_owned(),
+            program: "sh".to_owned(),
+            args: Vec::new(),
+        };
+        assert!(check_names("vim", &[spec("logs"), spec("build")]).is_ok());
+        assert!(check_names("vim", &[spec("logs"), spec("logs")]).is_err());
+        assert!(check_names("vim", &[spec("vim")]).is_err());
+        assert!(check_names("vim", &[spec("")]).is_err());
+    }
+}
@@ -22,3 +22,106 @@ impl CResult<libc::passwd, anyhow::Error> for *mut libc::passwd {
         }
     }
 }
+
+/// Errors the server answers requests with that a client may want to handle, rather than only
+/// show. Anything else the server runs into is sent as an internal error, with its message.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SeshError {
+    #[error("Session not found")]
+    SessionNotFound,
+    #[error("Session already connected")]
+    SessionAlreadyConnected,
+    #[error("A session named {0} already exists")]
+    SessionExists(String),
+    #[error("Invalid size")]
+    InvalidSize,
+    #[error("No session specified")]
+    NoSessionSpecified,
+    /// Too many sessions are running to start another, with which limit was reached
+    #[error("{0}")]
+    SessionLimit(String),
+    /// A name that can't be given to a session, with what's wrong with it
+    #[error("{0}")]
+    InvalidName(String),
+}
+
+impl SeshError {
+    pub fn code(&self) -> tonic::Code {
+        match self {
+            SeshError::SessionNotFound => tonic::Code::NotFound,
+            SeshError::SessionAlreadyConnected => tonic::Code::FailedPrecondition,
+            SeshError::SessionExists(_) => tonic::Code::AlreadyExists,
+            SeshError::InvalidSize | SeshError::NoSessionSpecified | SeshError::InvalidName(_) => {
+                tonic::Code::InvalidArgument
+            }
+            SeshError::SessionLimit(_) => tonic::Code::ResourceExhausted,
+        }
+    }
+
+    /// The error a status from the server stands for, if it's one of these. The kind of error,
+    /// and what goes with it, is sent in the status's details, since codes are shared.
+    pub fn from_status(status: &tonic::Status) -> Option<Self> {
+        let details = std::str::from_utf8(status.details()).ok()?;
+        let (kind, arg) = details.split_once('\0').unwrap_or((details, ""));
+        let error = match kind {
+            "session-not-found" => SeshError::SessionNotFound,
+            "session-already-connected" => SeshError::SessionAlreadyConnected,
+            "session-exists" => SeshError::SessionExists(arg.to_owned()),
+            "invalid-size" => SeshError::InvalidSize,
+            "no-session-specified" => SeshError::NoSessionSpecified,
+            "session-limit" => SeshError::SessionLimit(arg.to_owned()),
+            "invalid-name" => SeshError::InvalidName(arg.to_owned()),
+            _ => return None,
+        };
+        (error.code() == status.code()).then_some(error)
+    }
+
+    fn details(&self) -> String {
+        match self {
+            SeshError::SessionNotFound => "session-not-found".to_owned(),
+            SeshError::SessionAlreadyConnected => "session-already-connected".to_owned(),
+            SeshError::SessionExists(name) => format!("session-exists\0{}", name),
+            SeshError::InvalidSize => "invalid-size".to_owned(),
+            SeshError::NoSessionSpecified => "no-session-specified".to_owned(),
+            SeshError::SessionLimit(limit) => format!("session-limit\0{}", limit),
+            SeshError::InvalidName(problem) => format!("invalid-name\0{}", problem),
+        }
+    }
+}
+
+impl From<SeshError> for tonic::Status {
+    fn from(error: SeshError) -> Self {
+        tonic::Status::with_details(
+            error.code(),
+            error.to_string(),
+            error.details().into_bytes().into(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_survive_a_status() {
+        for error in [
+            SeshError::SessionNotFound,
+            SeshError::SessionAlreadyConnected,
+            SeshError::SessionExists("vim".to_owned()),
+            SeshError::InvalidSize,
+            SeshError::NoSessionSpecified,
+            SeshError::SessionLimit("Session limit (1) reached".to_owned()),
+            SeshError::InvalidName("Invalid session name \"a/b\"".to_owned()),
+        ] {
+            let status = tonic::Status::from(error.clone());
+            assert_eq!(status.message(), error.to_string());
+            assert_eq!(SeshError::from_status(&status), Some(error));
+        }
+        // Other errors are only messages
+        assert_eq!(
+            SeshError::from_status(&tonic::Status::internal("Session not found")),
+            None
+        );
+    }
+}
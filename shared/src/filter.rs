@@ -0,0 +1,101 @@
+//! Which sessions a `sesh list` filter picks out. The server filters the list it sends, and the
+//! client filters it again, since a server from before filters were added sends every session.
+
+use sesh_proto::SeshListFilter;
+
+/// Whether a session with the given name, program, clients and tags matches the filter
+pub fn matches(
+    filter: &SeshListFilter,
+    name: &str,
+    program: &str,
+    connected: bool,
+    tags: &[String],
+) -> bool {
+    (filter.name.is_empty() || glob(&filter.name, name))
+        && program.contains(&filter.program)
+        && filter.connected.is_none_or(|c| c == connected)
+        && filter.tags.iter().all(|tag| tags.contains(tag))
+}
+
+/// Matches text against a glob, where `*` matches any run of characters and `?` any one
+fn glob(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // Where to go on from if matching fails after the last *: the pattern after it, and the
+    // text it's taken up to
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // The * takes up one more character
+                Some((after, taken)) => {
+                    p = after;
+                    t = taken + 1;
+                    star = Some((after, taken + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(glob("vim", "vim"));
+        assert!(!glob("vim", "nvim"));
+        assert!(glob("*vim", "nvim"));
+        assert!(glob("build-*", "build-"));
+        assert!(glob("build-*", "build-release"));
+        assert!(!glob("build-*", "rebuild-x"));
+        assert!(glob("a*b*c", "aXbYbZc"));
+        assert!(!glob("a*b*c", "aXbYbZ"));
+        assert!(glob("t?st", "test"));
+        assert!(!glob("t?st", "tst"));
+        assert!(glob("*", ""));
+        assert!(!glob("?", ""));
+    }
+
+    #[test]
+    fn filters() {
+        let tags = ["web".to_owned(), "dev".to_owned()];
+        let matches =
+            |filter: &SeshListFilter| matches(filter, "api-1", "/usr/bin/node", true, &tags);
+        assert!(matches(&SeshListFilter::default()));
+        assert!(matches(&SeshListFilter {
+            name: "api-*".to_owned(),
+            program: "node".to_owned(),
+            connected: Some(true),
+            tags: vec!["dev".to_owned()],
+        }));
+        assert!(!matches(&SeshListFilter {
+            name: "api".to_owned(),
+            ..Default::default()
+        }));
+        assert!(!matches(&SeshListFilter {
+            program: "python".to_owned(),
+            ..Default::default()
+        }));
+        assert!(!matches(&SeshListFilter {
+            connected: Some(false),
+            ..Default::default()
+        }));
+        assert!(!matches(&SeshListFilter {
+            tags: vec!["web".to_owned(), "prod".to_owned()],
+            ..Default::default()
+        }));
+    }
+}
@@ -1,4 +1,9 @@
 pub mod error;
+pub mod filter;
+pub mod name;
 pub mod pty;
+pub mod runtime_dir;
+pub mod signal;
+pub mod sys;
 pub mod term;
 pub mod user;
@@ -0,0 +1,160 @@
+//! Which session names are allowed. A session's socket is named after it, so a name has to be
+//! a safe file name in the runtime directory, short enough to fit in a socket address.
+
+use crate::error::SeshError;
+
+/// Longest name allowed, in bytes. Socket paths are limited to around 100 bytes, and the runtime
+/// directory and `.sock` take up part of that.
+pub const MAX_LEN: usize = 64;
+
+/// Characters allowed in names besides ASCII letters and digits
+const PUNCTUATION: &[char] = &['-', '_', '.', '+', '@'];
+
+/// Names whose socket would take the place of the server's own in the runtime directory
+const RESERVED: &[&str] = &["server", "upgrade"];
+
+fn allowed(c: char) -> bool {
+    c.is_ascii_alphanumeric() || PUNCTUATION.contains(&c)
+}
+
+/// Checks that `name` can be used as a session name: it's not empty, `.` or `..`, doesn't start
+/// with `-` where it would be taken for a flag, isn't longer than [`MAX_LEN`], and only has ASCII
+/// letters, digits and `-_.+@` in it. Names of the server's own sockets, `server`, `upgrade` and
+/// `client-<pid>`, are reserved.
+pub fn check(name: &str) -> Result<(), SeshError> {
+    let problem = if name.is_empty() {
+        "it's empty".to_owned()
+    } else if name == "." || name == ".." {
+        "it's a directory name".to_owned()
+    } else if name.starts_with('-') {
+        "it starts with -".to_owned()
+    } else if name.len() > MAX_LEN {
+        format!("it's longer than {} bytes", MAX_LEN)
+    } else if let Some(c) = name.chars().find(|c| !allowed(*c)) {
+        format!(
+            "{:?} isn't allowed, only letters, digits and {} are",
+            c,
+            PUNCTUATION.iter().collect::<String>()
+        )
+    } else if RESERVED.contains(&name) || client_sock(name) {
+        "it's reserved for the server's sockets".to_owned()
+    } else {
+        return Ok(());
+    };
+    Err(SeshError::InvalidName(format!(
+        "Invalid session name {:?}: {}",
+        name, problem
+    )))
+}
+
+/// Whether a session named `name` would have the socket an attached client listens on
+fn client_sock(name: &str) -> bool {
+    name.strip_prefix("client-")
+        .is_some_and(|pid| !pid.is_empty() && pid.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Makes a valid name out of a program's path, for sessions started without one: its file name
+/// with characters that aren't allowed replaced by `_`, cut down to [`MAX_LEN`].
+pub fn from_program(program: &str) -> String {
+    let file_name = program.rsplit('/').find(|part| !part.is_empty());
+    let mut name = file_name
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if allowed(c) { c } else { '_' })
+        .collect::<String>();
+    // Only ASCII is left, so any byte is a char boundary
+    name.truncate(MAX_LEN);
+    if name.starts_with('-') {
+        name.replace_range(..1, "_");
+    }
+    match check(&name) {
+        Ok(()) => name,
+        Err(_) if !name.is_empty() && !name.chars().all(|c| c == '.') => format!("{}_", name),
+        Err(_) => "session".to_owned(),
+    }
+}
+
+/// The name to try for a session when `name` is taken, with `-<n>` added. `name` is cut short
+/// first if the suffix would take it over [`MAX_LEN`].
+pub fn with_suffix(name: &str, n: usize) -> String {
+    let suffix = format!("-{}", n);
+    // Names are ASCII, so any byte is a char boundary
+    let len = name.len().min(MAX_LEN.saturating_sub(suffix.len()));
+    format!("{}{}", &name[..len], suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adversarial_names_are_rejected() {
+        for name in [
+            "",
+            ".",
+            "..",
+            "../../etc/passwd",
+            "a/b",
+            "/tmp/x",
+            "nul\0byte",
+            "new\nline",
+            "-rf",
+            "with space",
+            "ünïcode",
+            &"x".repeat(MAX_LEN + 1),
+        ] {
+            assert!(check(name).is_err(), "{:?} was allowed", name);
+        }
+        for name in [
+            "vim",
+            "cargo-watch",
+            "build_2",
+            "v1.2+rc",
+            "me@host",
+            "...",
+            ".hidden",
+        ] {
+            check(name).unwrap_or_else(|e| panic!("{:?} was refused: {}", name, e));
+        }
+        assert!(check(&"x".repeat(MAX_LEN)).is_ok());
+    }
+
+    #[test]
+    fn server_socket_names_are_reserved() {
+        for name in ["server", "upgrade", "client-1", "client-4242"] {
+            let error = check(name).unwrap_err().to_string();
+            assert!(error.contains("reserved"), "{:?}: {}", name, error);
+        }
+        for name in [
+            "servers",
+            "Server",
+            "upgrade2",
+            "client",
+            "client-",
+            "client-vim",
+        ] {
+            check(name).unwrap_or_else(|e| panic!("{:?} was refused: {}", name, e));
+        }
+    }
+
+    #[test]
+    fn program_names() {
+        assert_eq!(from_program("/usr/bin/vim"), "vim");
+        assert_eq!(from_program("./scripts/run tests.sh"), "run_tests.sh");
+        assert_eq!(from_program("bin/"), "bin");
+        assert_eq!(from_program("-weird"), "_weird");
+        assert_eq!(from_program(".."), "session");
+        assert_eq!(from_program("/usr/sbin/server"), "server_");
+        assert_eq!(from_program("/"), "session");
+        assert_eq!(from_program(&"y".repeat(100)).len(), MAX_LEN);
+    }
+
+    #[test]
+    fn suffixes_fit() {
+        assert_eq!(with_suffix("vim", 0), "vim-0");
+        let long = with_suffix(&"z".repeat(MAX_LEN), 12);
+        assert_eq!(long.len(), MAX_LEN);
+        assert!(long.ends_with("z-12"));
+        assert!(check(&long).is_ok());
+    }
+}
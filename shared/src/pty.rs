@@ -6,13 +6,16 @@ use std::{
         io::{FromRawFd, RawFd},
         process::CommandExt,
     },
+    path::Path,
     process::{Command, Stdio},
-    ptr,
-    time::Duration,
 };
 use tokio::fs::File;
 
-use crate::{error::CResult, term::Size};
+use crate::{
+    error::CResult,
+    sys,
+    term::{Size, Termios},
+};
 
 const PTY_ERR: &str = "[pty.rs] Failed to open pty";
 const PRG_ERR: &str = "[pty.rs] Failed to spawn shell";
@@ -30,6 +33,7 @@ pub struct Pty {
 pub struct PtyBuilder {
     inner: Command,
     daemonize: bool,
+    termios: Termios,
 }
 
 impl PtyBuilder {
@@ -71,6 +75,28 @@ impl PtyBuilder {
         self
     }
 
+    /// Starts the program as a login shell, by prefixing its argv[0] with `-` like login(1) does
+    pub fn login(mut self) -> Self {
+        let program = Path::new(self.inner.get_program());
+        let name = program.file_name().unwrap_or(program.as_os_str());
+        let mut arg0 = OsStr::new("-").to_owned();
+        arg0.push(name);
+        self.inner.arg0(arg0);
+        self
+    }
+
+    /// Sets $TERM for the program, instead of whatever it would have inherited
+    pub fn term<T: AsRef<OsStr>>(self, term: T) -> Self {
+        self.env("TERM", term)
+    }
+
+    /// Changes the pty's terminal settings before the program starts, like turning off flow
+    /// control so Ctrl-S doesn't freeze it
+    pub fn termios(mut self, termios: Termios) -> Self {
+        self.termios = termios;
+        self
+    }
+
     pub fn daemonize(mut self) -> Self {
         self.daemonize = true;
         self
@@ -95,12 +121,15 @@ impl PtyBuilder {
 
         let mut cmd = self.inner;
 
-        cmd.stdin(unsafe { Stdio::from_raw_fd(slave) })
-            .stdout(unsafe { Stdio::from_raw_fd(slave) })
+        // Each Stdio takes ownership of its fd, so they each need their own copy
+        cmd.stdin(unsafe { Stdio::from_raw_fd(libc::dup(slave)) })
+            .stdout(unsafe { Stdio::from_raw_fd(libc::dup(slave)) })
             .stderr(unsafe { Stdio::from_raw_fd(slave) });
 
+        let keep_master = self.daemonize.then_some(master);
+        let termios = self.termios;
         unsafe {
-            cmd.pre_exec(Pty::pre_exec);
+            cmd.pre_exec(move || Pty::pre_exec(keep_master, &termios));
         }
         cmd.spawn().map_err(|_| anyhow!(PRG_ERR)).and_then(|e| {
             let pty = Pty {
@@ -122,6 +151,7 @@ impl Pty {
         PtyBuilder {
             inner: Command::new(program.as_ref()),
             daemonize: false,
+            termios: Termios::default(),
         }
     }
 
@@ -129,6 +159,21 @@ impl Pty {
         Pty::builder(program).args(args).spawn(size)
     }
 
+    /// Wraps an existing pty master fd whose child process has the given pid.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open pty master that is not owned by anything else, since the returned
+    /// `Pty` will close it when dropped.
+    pub unsafe fn from_raw_fd(fd: RawFd, pid: i32) -> Pty {
+        Pty {
+            fd,
+            file: File::from_raw_fd(fd),
+            pid,
+            kill_on_drop: true,
+        }
+    }
+
     pub fn daemonize(&mut self) {
         self.kill_on_drop = false;
     }
@@ -145,53 +190,31 @@ impl Pty {
         self.fd
     }
 
-    /// Resizes the child pty.
-    pub fn resize(&self, size: &Size) -> Result<()> {
-        unsafe {
-            libc::ioctl(
-                self.fd,
-                libc::TIOCSWINSZ,
-                &libc::winsize {
-                    ws_row: size.rows,
-                    ws_col: size.cols,
-                    ws_xpixel: 0,
-                    ws_ypixel: 0,
-                },
-            )
+    /// Sends a signal to the child process.
+    /// Once the child has been reaped its pid may be reused, so this must not be called after.
+    pub fn kill(&self, signal: libc::c_int) -> Result<()> {
+        unsafe { libc::kill(self.pid, signal) }
             .to_result()
             .map(|_| ())
-            .context(PTY_ERR)
-        }
+            .context("[pty.rs] Failed to signal child")
+    }
+
+    /// Resizes the child pty.
+    pub fn resize(&self, size: &Size) -> Result<()> {
+        sys::set_winsize(self.fd, size).context(PTY_ERR)
+    }
+
+    /// Gets the size of the child pty.
+    pub fn size(&self) -> Result<Size> {
+        sys::winsize(self.fd).context(PTY_ERR)
     }
 
     /// Creates a pty with the given size and returns the (master, slave)
     /// file descriptors attached to it.
     pub fn open(size: &Size) -> Result<(RawFd, RawFd)> {
-        let mut master = 0;
-        let mut slave = 0;
+        let (master, slave) = sys::openpty(size).context(PTY_ERR)?;
 
         unsafe {
-            #[cfg(target_arch = "aarch64")]
-            libc::openpty(
-                &mut master,
-                &mut slave,
-                ptr::null_mut(),
-                ptr::null_mut(),
-                &mut size.into(),
-            )
-            .to_result()
-            .context(PTY_ERR)?;
-            #[cfg(not(target_arch = "aarch64"))]
-            libc::openpty(
-                &mut master,
-                &mut slave,
-                ptr::null_mut(),
-                ptr::null_mut(),
-                &size.into(),
-            )
-            .to_result()
-            .context(PTY_ERR)?;
-
             // Configure master to be non blocking
             let current_config = libc::fcntl(master, libc::F_GETFL, 0)
                 .to_result()
@@ -206,28 +229,28 @@ impl Pty {
     }
 
     // Runs between fork and exec calls
-    fn pre_exec() -> io::Result<()> {
+    fn pre_exec(keep_master: Option<RawFd>, termios: &Termios) -> io::Result<()> {
         unsafe {
             if libc::getpid() == 0 {
                 std::process::exit(0);
             }
             // Create a new process group, this process being the master
-            libc::setsid().to_result().map_err(|e| {
-                io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Failed to create process group: {}", e),
-                )
-            })?;
-
-            // Set this process as the controling terminal
-            libc::ioctl(0, libc::TIOCSCTTY, 1)
+            libc::setsid()
                 .to_result()
-                .map_err(|e| {
-                    io::Error::new(
-                        io::ErrorKind::Other,
-                        format!("Failed to set controlling terminal: {}", e),
-                    )
-                })?;
+                .map_err(|e| io::Error::other(format!("Failed to create process group: {}", e)))?;
+        }
+        // Set this process as the controling terminal
+        sys::set_controlling_terminal(0)
+            .map_err(|e| io::Error::other(format!("Failed to set controlling terminal: {}", e)))?;
+        sys::set_termios(0, termios)
+            .map_err(|e| io::Error::other(format!("Failed to set terminal settings: {}", e)))?;
+        // Don't leak the parent's sockets and other ptys into the program. Closing them here
+        // would also close the pipe used to report a failed exec, so they're closed on exec
+        sys::cloexec_from(3);
+        // A daemon outlives the process that started it, and the pty hangs up once the last copy
+        // of its master is closed, so the daemon holds on to one itself
+        if let Some(master) = keep_master {
+            unsafe { libc::fcntl(master, libc::F_SETFD, 0) };
         }
 
         Ok(())
@@ -237,27 +260,11 @@ impl Pty {
 /// Handle cleanup automatically
 impl Drop for Pty {
     fn drop(&mut self) {
-        unsafe {
-            if self.kill_on_drop {
-                let fd = self.fd;
-                let pid = self.pid;
-                // Close file descriptor
-                libc::close(fd);
-                // Kill the owned processed when the Pty is dropped
-                libc::kill(pid, libc::SIGTERM);
-                std::thread::sleep(Duration::from_millis(5));
-
-                let mut status = 0;
-                // make sure the process has exited
-                libc::waitpid(pid, &mut status, libc::WNOHANG);
-
-                // if it hasn't exited, force kill it and clean up the zombie process
-                if status <= 0 {
-                    // The process exists but hasn't changed state, or there was an error
-                    libc::kill(pid, libc::SIGKILL);
-                    libc::waitpid(pid, &mut status, 0);
-                }
-            }
+        if self.kill_on_drop {
+            // The file descriptor is closed when `self.file` is dropped
+            // Kill the owned processed when the Pty is dropped. Reaping it is left to the
+            // owner, since waiting here would race with any other waitpid on the same pid.
+            self.kill(libc::SIGTERM).ok();
         }
     }
 }
@@ -0,0 +1,79 @@
+//! Checks on the directory the server and its sessions keep their sockets in.
+//!
+//! When the directory can't be used, binding or connecting to a socket in it only fails with
+//! a bare os error, so these find out why first.
+
+use std::{
+    io::ErrorKind,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::sys;
+
+/// Fails with the reason the current user can't keep sockets in `dir`: it is another user's,
+/// isn't writable, isn't a directory, or doesn't exist and can't be created
+pub fn check(dir: &Path) -> Result<()> {
+    // A trailing slash would make a file in the way fail as "not a directory" instead
+    let dir = &dir.components().collect::<PathBuf>();
+    match std::fs::metadata(dir) {
+        Ok(meta) if !meta.is_dir() => {
+            anyhow::bail!("{} is in the way of the runtime directory", dir.display())
+        }
+        // On a shared host, /tmp/sesh may have been made by whoever ran sesh first
+        Ok(meta) if meta.uid() != unsafe { libc::geteuid() } => anyhow::bail!(
+            "{} belongs to another user, remove it or set $XDG_RUNTIME_DIR to a directory of your own",
+            dir.display()
+        ),
+        Ok(_) if !sys::accessible(dir, libc::W_OK | libc::X_OK) => {
+            anyhow::bail!("Permission denied on {}", dir.display())
+        }
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            // It's created along with any missing parents, under the closest one that exists
+            match dir.ancestors().skip(1).find(|parent| parent.exists()) {
+                Some(parent) if !sys::accessible(parent, libc::W_OK | libc::X_OK) => {
+                    anyhow::bail!("Permission denied creating {}", dir.display())
+                }
+                _ => Ok(()),
+            }
+        }
+        Err(e) => anyhow::bail!("Could not read {}: {}", dir.display(), e),
+    }
+}
+
+/// Makes sure the current user can keep sockets in `dir`, creating it if it's missing
+pub fn prepare(dir: &Path) -> Result<()> {
+    check(dir)?;
+    if !dir.exists() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| anyhow::anyhow!("Could not create {}: {}", dir.display(), e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_dir_is_created() {
+        let dir = std::env::temp_dir()
+            .join(format!("sesh-test-dir-{}", std::process::id()))
+            .join("sesh");
+        prepare(&dir).expect("failed to prepare");
+        assert!(dir.is_dir());
+        std::fs::remove_dir_all(dir.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn file_in_the_way_fails() {
+        let file = std::env::temp_dir().join(format!("sesh-test-file-{}", std::process::id()));
+        std::fs::write(&file, "").expect("failed to write");
+        let err = check(&file).expect_err("a file was accepted");
+        std::fs::remove_file(&file).ok();
+        assert!(err.to_string().contains("in the way"));
+    }
+}
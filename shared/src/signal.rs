@@ -0,0 +1,60 @@
+use anyhow::{anyhow, Result};
+
+const SIGNALS: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("IO", libc::SIGIO),
+    ("SYS", libc::SIGSYS),
+];
+
+/// Parses a signal from its name (`SIGHUP`, `hup`) or number (`1`)
+pub fn parse(signal: &str) -> Result<libc::c_int> {
+    if let Ok(num) = signal.parse::<libc::c_int>() {
+        return if num > 0 {
+            Ok(num)
+        } else {
+            Err(anyhow!("Invalid signal number {}", num))
+        };
+    }
+    let upper = signal.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNALS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, num)| *num)
+        .ok_or_else(|| anyhow!("Unknown signal {}", signal))
+}
+
+/// Returns the name of a signal (e.g. `SIGHUP`), or its number if it has no known name
+pub fn name(signal: libc::c_int) -> String {
+    SIGNALS
+        .iter()
+        .find(|(_, num)| *num == signal)
+        .map(|(name, _)| format!("SIG{}", name))
+        .unwrap_or_else(|| signal.to_string())
+}
@@ -0,0 +1,355 @@
+//! Wrappers around the libc calls that differ between the unix platforms sesh runs on, so the
+//! rest of the code doesn't need to know which one it was built for.
+
+use std::{
+    ffi::CString,
+    os::{fd::RawFd, unix::ffi::OsStrExt},
+    path::{Path, PathBuf},
+    ptr,
+    time::Duration,
+};
+
+use anyhow::Result;
+
+use crate::{
+    error::CResult,
+    term::{Size, Termios},
+};
+
+/// Returns the error number set by the last failed libc call on this thread
+pub fn errno() -> libc::c_int {
+    unsafe { *errno_location() }
+}
+
+#[cfg(any(target_os = "linux", target_os = "dragonfly"))]
+unsafe fn errno_location() -> *mut libc::c_int {
+    libc::__errno_location()
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+unsafe fn errno_location() -> *mut libc::c_int {
+    libc::__error()
+}
+
+#[cfg(any(target_os = "openbsd", target_os = "netbsd", target_os = "android"))]
+unsafe fn errno_location() -> *mut libc::c_int {
+    libc::__errno()
+}
+
+/// Opens a pty with the given size and returns its (master, slave) fds.
+/// On the BSDs openpty lives in libutil, which the libc crate links for us.
+pub fn openpty(size: &Size) -> Result<(RawFd, RawFd)> {
+    let mut master = 0;
+    let mut slave = 0;
+    // Linux takes the size as a const pointer and the BSDs as a mut one, a &mut works for both
+    let mut size = libc::winsize::from(size);
+    #[allow(clippy::unnecessary_mut_passed)]
+    unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &mut size,
+        )
+        .to_result()?;
+    }
+    Ok((master, slave))
+}
+
+/// Sets the size of the terminal behind `fd`
+pub fn set_winsize(fd: RawFd, size: &Size) -> Result<()> {
+    unsafe {
+        libc::ioctl(fd, libc::TIOCSWINSZ, &libc::winsize::from(size))
+            .to_result()
+            .map(|_| ())
+    }
+}
+
+/// Gets the size of the terminal behind `fd`
+pub fn winsize(fd: RawFd) -> Result<Size> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    unsafe {
+        libc::ioctl(fd, libc::TIOCGWINSZ, &mut size).to_result()?;
+    }
+    Ok(Size {
+        cols: size.ws_col,
+        rows: size.ws_row,
+    })
+}
+
+/// Marks every fd from `first` up as close-on-exec, so a program started from this process
+/// doesn't inherit them. Only makes async-signal-safe calls, so it can run between fork and exec.
+pub fn cloexec_from(first: RawFd) {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        if libc::syscall(
+            libc::SYS_close_range,
+            first as libc::c_uint,
+            libc::c_uint::MAX,
+            libc::CLOSE_RANGE_CLOEXEC,
+        ) == 0
+        {
+            return;
+        }
+    }
+    // Kernels without close_range, and the BSDs, need every fd that could be open marked by hand.
+    // The limit can be huge, and the server never has that many open, so it's capped
+    let max = match unsafe { libc::sysconf(libc::_SC_OPEN_MAX) } {
+        max if max > 0 => max.min(65536) as RawFd,
+        _ => 1024,
+    };
+    for fd in first..max {
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+}
+
+/// Whether a process has exited but not been reaped yet. Only known where there's /proc.
+pub fn zombie(pid: i32) -> bool {
+    std::fs::read_to_string(format!("/proc/{}/status", pid))
+        .is_ok_and(|status| status.contains("State:\tZ"))
+}
+
+/// How many more fds this process can open before reaching its soft limit
+pub fn fd_headroom() -> Result<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) }.to_result()?;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let dir = "/proc/self/fd";
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let dir = "/dev/fd";
+    // Reading the directory takes an fd of its own, which is listed too
+    let open = std::fs::read_dir(dir)?.count().saturating_sub(1) as u64;
+    // rlim_t is signed on some of the BSDs
+    #[allow(clippy::unnecessary_cast)]
+    let limit = limit.rlim_cur as u64;
+    Ok(limit.saturating_sub(open))
+}
+
+/// Makes the changes in `termios` to the settings of the terminal behind `fd`
+pub fn set_termios(fd: RawFd, termios: &Termios) -> Result<()> {
+    if *termios == Termios::default() {
+        return Ok(());
+    }
+    let mut settings = unsafe { std::mem::zeroed::<libc::termios>() };
+    unsafe { libc::tcgetattr(fd, &mut settings).to_result()? };
+    if termios.no_flow_control {
+        settings.c_iflag &= !(libc::IXON | libc::IXOFF);
+    }
+    if termios.no_echo {
+        settings.c_lflag &= !(libc::ECHO | libc::ECHOE | libc::ECHOK | libc::ECHONL);
+    }
+    unsafe {
+        libc::tcsetattr(fd, libc::TCSANOW, &settings)
+            .to_result()
+            .map(|_| ())
+    }
+}
+
+/// Makes the terminal behind `fd` the controlling terminal of the calling process, which must
+/// be a session leader
+pub fn set_controlling_terminal(fd: RawFd) -> Result<()> {
+    // Linux only steals the terminal from another session if asked to, the BSDs ignore the arg
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let steal: libc::c_int = 1;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let steal: libc::c_int = 0;
+    unsafe {
+        libc::ioctl(fd, libc::TIOCSCTTY, steal)
+            .to_result()
+            .map(|_| ())
+    }
+}
+
+/// Gets the working directory of another process
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn process_cwd(pid: libc::pid_t) -> Result<PathBuf> {
+    Ok(std::fs::read_link(format!("/proc/{}/cwd", pid))?)
+}
+
+/// Gets the working directory of another process
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn process_cwd(_pid: libc::pid_t) -> Result<PathBuf> {
+    anyhow::bail!("Finding another process's working directory isn't supported on this platform")
+}
+
+/// CPU time another process has used so far, in user and kernel mode together
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn process_cpu_time(pid: libc::pid_t) -> Result<Duration> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid))?;
+    // The command name can hold spaces and parentheses, so fields are counted after its last `)`.
+    // utime and stime are the 14th and 15th fields, and the state, 3rd, comes first.
+    let fields = stat
+        .rsplit_once(')')
+        .map(|(_, fields)| fields.split_whitespace().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let ticks = |field: usize| {
+        fields
+            .get(field - 3)
+            .and_then(|ticks| ticks.parse::<u64>().ok())
+            .ok_or_else(|| anyhow::anyhow!("Could not read the CPU time of process {}", pid))
+    };
+    let per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+    Ok(Duration::from_secs_f64(
+        (ticks(14)? + ticks(15)?) as f64 / per_second,
+    ))
+}
+
+/// CPU time another process has used so far, in user and kernel mode together
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn process_cpu_time(_pid: libc::pid_t) -> Result<Duration> {
+    anyhow::bail!("Finding another process's CPU time isn't supported on this platform")
+}
+
+/// Checks whether the current user can access a path with the given `libc::access` mode
+pub fn accessible(path: &Path, mode: libc::c_int) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), mode) == 0 }
+}
+
+/// Watches a directory for entries being added to it, so a file can be waited for without
+/// polling. Uses inotify on Linux and kqueue on the BSDs and macOS.
+pub struct DirWatch {
+    /// The inotify instance or kqueue that events are read from
+    fd: RawFd,
+    /// The watched directory, which kqueue needs kept open
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    dir: RawFd,
+}
+
+impl DirWatch {
+    /// Starts watching `dir`. Entries added from here on wake up [`DirWatch::wait`].
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn new(dir: &Path) -> Result<Self> {
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK).to_result()? };
+        let watch = Self { fd };
+        unsafe {
+            libc::inotify_add_watch(fd, path.as_ptr(), libc::IN_CREATE | libc::IN_MOVED_TO)
+                .to_result()?;
+        }
+        Ok(watch)
+    }
+
+    /// Starts watching `dir`. Entries added from here on wake up [`DirWatch::wait`].
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn new(dir: &Path) -> Result<Self> {
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        let dir = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC) };
+        let dir = dir.to_result()?;
+        let fd = match unsafe { libc::kqueue() }.to_result() {
+            Ok(fd) => fd,
+            Err(e) => {
+                unsafe { libc::close(dir) };
+                return Err(e);
+            }
+        };
+        let watch = Self { fd, dir };
+        // A directory counts as written to when an entry is added to it
+        let mut change: libc::kevent = unsafe { std::mem::zeroed() };
+        change.ident = dir as _;
+        change.filter = libc::EVFILT_VNODE as _;
+        change.flags = (libc::EV_ADD | libc::EV_CLEAR) as _;
+        change.fflags = libc::NOTE_WRITE as _;
+        unsafe {
+            libc::kevent(fd, &change, 1, ptr::null_mut(), 0, ptr::null()).to_result()?;
+        }
+        Ok(watch)
+    }
+
+    /// Waits up to `timeout` for an entry to be added to the directory, and returns whether one
+    /// was. Blocks the thread, and may also return early on other changes to the directory.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn wait(&self, timeout: std::time::Duration) -> Result<bool> {
+        let mut events = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+        if unsafe { libc::poll(&mut events, 1, millis) }.to_result()? == 0 {
+            return Ok(false);
+        }
+        // Which entries were added doesn't matter, the caller looks for its own, so the events
+        // are only read to clear them for the next wait
+        let mut buf = [0u8; 4096];
+        while unsafe { libc::read(self.fd, buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+        Ok(true)
+    }
+
+    /// Waits up to `timeout` for an entry to be added to the directory, and returns whether one
+    /// was. Blocks the thread, and may also return early on other changes to the directory.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn wait(&self, timeout: std::time::Duration) -> Result<bool> {
+        let timeout = libc::timespec {
+            tv_sec: timeout.as_secs() as _,
+            tv_nsec: timeout.subsec_nanos() as _,
+        };
+        let mut event: libc::kevent = unsafe { std::mem::zeroed() };
+        let n = unsafe { libc::kevent(self.fd, ptr::null(), 0, &mut event, 1, &timeout) };
+        Ok(n.to_result()? > 0)
+    }
+}
+
+impl Drop for DirWatch {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        unsafe {
+            libc::close(self.dir)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn dir_watch_sees_new_entries() {
+        let dir = std::env::temp_dir().join(format!("sesh-test-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("failed to create dir");
+        let watch = DirWatch::new(&dir).expect("failed to watch dir");
+        assert!(!watch.wait(Duration::from_millis(10)).unwrap());
+
+        std::fs::write(dir.join("server.sock"), "").expect("failed to write");
+        assert!(watch.wait(Duration::from_secs(1)).unwrap());
+        // The event was used up by the last wait
+        assert!(!watch.wait(Duration::from_millis(10)).unwrap());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn termios_turns_off_flow_control() {
+        let (master, slave) = openpty(&Size::default()).expect("failed to open pty");
+        let settings = || {
+            let mut settings = unsafe { std::mem::zeroed::<libc::termios>() };
+            unsafe { libc::tcgetattr(slave, &mut settings) };
+            settings
+        };
+        set_termios(slave, &Termios::default()).unwrap();
+        assert_ne!(settings().c_iflag & libc::IXON, 0);
+        let termios = Termios {
+            no_flow_control: true,
+            no_echo: false,
+        };
+        set_termios(slave, &termios).unwrap();
+        assert_eq!(settings().c_iflag & libc::IXON, 0);
+        assert_ne!(settings().c_lflag & libc::ECHO, 0);
+        unsafe {
+            libc::close(master);
+            libc::close(slave);
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use std::{future::Future, time::Duration};
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sesh_proto::WinSize;
 use termion;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Size {
     /// Number of columns
     pub cols: u16,
@@ -15,6 +18,63 @@ impl Size {
         let (cols, rows) = termion::terminal_size()?;
         Ok(Size { cols, rows })
     }
+
+    /// The size of this process's terminal. Without one, as in a daemon, it's the size in
+    /// $COLUMNS and $LINES, and 80x24 for whichever of them isn't set.
+    pub fn detect() -> Size {
+        Size::detect_from(Size::term_size, |var| std::env::var(var).ok())
+    }
+
+    /// Like [`Size::detect`], with the terminal's size from `term_size` and the variables from
+    /// `var`
+    pub fn detect_from(
+        term_size: impl FnOnce() -> Result<Size>,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Size {
+        // A pty nobody has sized yet reports 0x0, which is no more use than none
+        if let Some(size) = term_size()
+            .ok()
+            .filter(|size| size.cols > 0 && size.rows > 0)
+        {
+            return size;
+        }
+        let default = Size::default();
+        let dimension = |name: &str| {
+            var(name)
+                .and_then(|value| value.trim().parse::<u16>().ok())
+                .filter(|&value| value > 0)
+        };
+        Size {
+            cols: dimension("COLUMNS").unwrap_or(default.cols),
+            rows: dimension("LINES").unwrap_or(default.rows),
+        }
+    }
+}
+
+/// 80x24, the size used when there's no terminal to ask
+impl Default for Size {
+    fn default() -> Self {
+        Size { cols: 80, rows: 24 }
+    }
+}
+
+/// Sizes too big for a terminal are clamped to the largest one it can have
+impl From<&WinSize> for Size {
+    fn from(val: &WinSize) -> Self {
+        Size {
+            cols: val.cols.try_into().unwrap_or(u16::MAX),
+            rows: val.rows.try_into().unwrap_or(u16::MAX),
+        }
+    }
+}
+
+impl From<&Size> for WinSize {
+    fn from(val: &Size) -> Self {
+        WinSize {
+            cols: val.cols.into(),
+            rows: val.rows.into(),
+        }
+    }
 }
 
 impl From<&Size> for libc::winsize {
@@ -28,6 +88,37 @@ impl From<&Size> for libc::winsize {
     }
 }
 
+/// Changes to a pty's terminal settings, made before its program starts. The defaults leave
+/// them as the system sets up a new pty.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Termios {
+    /// Turn off XON/XOFF flow control, so Ctrl-S and Ctrl-Q reach the program instead of
+    /// pausing and resuming its output
+    #[serde(default)]
+    pub no_flow_control: bool,
+    /// Turn off echoing input back to the terminal
+    #[serde(default)]
+    pub no_echo: bool,
+}
+
+impl From<&sesh_proto::Termios> for Termios {
+    fn from(val: &sesh_proto::Termios) -> Self {
+        Termios {
+            no_flow_control: val.no_flow_control,
+            no_echo: val.no_echo,
+        }
+    }
+}
+
+impl From<&Termios> for sesh_proto::Termios {
+    fn from(val: &Termios) -> Self {
+        sesh_proto::Termios {
+            no_flow_control: val.no_flow_control,
+            no_echo: val.no_echo,
+        }
+    }
+}
+
 /// Future that checks if a process exists and resolves when it doesn't.
 struct ExitFuture {
     pid: i32,
@@ -44,13 +135,8 @@ impl Future for ExitFuture {
         unsafe {
             // This doesn't actually kill the process, it just checks if it exists
             if libc::kill(self.pid, 0) == -1 {
-                // TODO: Figure out why this doesn't work on M1/M2 macs
-                #[cfg(target_arch = "aarch64")]
-                let errno = *libc::__error();
-                #[cfg(not(target_arch = "aarch64"))]
-                let errno = *libc::__errno_location();
                 // process doesn't exist / has exited
-                if errno == libc::ESRCH {
+                if crate::sys::errno() == libc::ESRCH {
                     return std::task::Poll::Ready(());
                 }
             }
@@ -64,6 +150,7 @@ impl Future for ExitFuture {
 
 /// Wait for the given process to exit, polling every 20ms.
 /// Resolves immediately if the process doesn't exist.
+/// A child of this process is only seen as exited once something else has reaped it.
 pub async fn process_exit(pid: i32) {
     ExitFuture {
         pid,
@@ -71,3 +158,65 @@ pub async fn process_exit(pid: i32) {
     }
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_falls_back_without_a_terminal() {
+        let no_terminal = || Err(anyhow::anyhow!("Inappropriate ioctl for device"));
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        let size = |cols, rows| Size { cols, rows };
+        assert_eq!(
+            Size::detect_from(no_terminal, env(&[("COLUMNS", "132"), ("LINES", "43")])),
+            size(132, 43)
+        );
+        assert_eq!(
+            Size::detect_from(no_terminal, env(&[("COLUMNS", "100")])),
+            size(100, 24)
+        );
+        assert_eq!(
+            Size::detect_from(no_terminal, env(&[("COLUMNS", "wide"), ("LINES", "0")])),
+            size(80, 24)
+        );
+        assert_eq!(
+            Size::detect_from(|| Ok(size(0, 0)), env(&[("LINES", "50")])),
+            size(80, 50)
+        );
+        assert_eq!(
+            Size::detect_from(|| Ok(size(200, 60)), env(&[("COLUMNS", "132")])),
+            size(200, 60)
+        );
+    }
+
+    #[test]
+    fn winsize_round_trips() {
+        let size = Size {
+            cols: 120,
+            rows: 40,
+        };
+        assert_eq!(Size::from(&WinSize::from(&size)), size);
+    }
+
+    #[test]
+    fn oversized_winsize_saturates() {
+        let size = Size::from(&WinSize {
+            cols: u32::MAX,
+            rows: u16::MAX as u32 + 1,
+        });
+        assert_eq!(
+            size,
+            Size {
+                cols: u16::MAX,
+                rows: u16::MAX
+            }
+        );
+    }
+}
@@ -1,5 +1,5 @@
-use anyhow::Result;
-use std::{env, ffi};
+use anyhow::{anyhow, Result};
+use std::{env, ffi, ptr};
 
 use crate::error::CResult;
 
@@ -7,16 +7,48 @@ struct Passwd {
     pub shell: String,
 }
 
+/// Largest buffer getpwuid_r is given before giving up on the entry
+const MAX_PASSWD_BUF: usize = 1 << 20;
+
 fn get_passwd() -> Result<Passwd> {
-    unsafe {
-        let passwd = libc::getpwuid(libc::getuid()).to_result()?;
-        let shell = ffi::CStr::from_ptr(passwd.pw_shell).to_str()?.to_string();
-        Ok(Passwd { shell })
+    // getpwuid returns a static buffer that other threads could overwrite, so use getpwuid_r
+    let mut buf = vec![0 as libc::c_char; 1024];
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = ptr::null_mut();
+    loop {
+        let err = unsafe {
+            libc::getpwuid_r(
+                libc::getuid(),
+                &mut entry,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        match err {
+            0 => break,
+            libc::ERANGE if buf.len() < MAX_PASSWD_BUF => buf.resize(buf.len() * 2, 0),
+            err => return Err(anyhow!(std::io::Error::from_raw_os_error(err))),
+        }
     }
+    // The entry's strings point into `buf`, so they're copied out before it's dropped
+    let passwd = result.to_result()?;
+    let shell = unsafe { ffi::CStr::from_ptr(passwd.pw_shell) }
+        .to_str()?
+        .to_string();
+    Ok(Passwd { shell })
 }
 
+/// Returns the user's shell, from $SHELL or else their passwd entry
 pub fn get_shell() -> String {
     env::var("SHELL")
-        .or_else(|_| get_passwd().map(|passwd| passwd.shell))
-        .unwrap_or_else(|_| "/bin/sh".to_string())
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .or_else(|| {
+            get_passwd()
+                .ok()
+                .map(|passwd| passwd.shell)
+                .filter(|shell| !shell.is_empty())
+        })
+        .unwrap_or_else(|| "/bin/sh".to_string())
 }